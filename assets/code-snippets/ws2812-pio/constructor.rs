@@ -1,5 +1,5 @@
 let mut neopixels = Ws2812::new(
-    pins.d5.into_mode(),
+    pins.{{pin}}.into_mode(),
     &mut pio,
     sm0,
     clocks.peripheral_clock.freq(),
@@ -0,0 +1,39 @@
+//! Blinks the LED on a Adafruit Feather RP2040 board
+//!
+//! This will blink on-board LED.
+#![no_std]
+#![no_main]
+use adafruit_feather_rp2040::entry;
+use adafruit_feather_rp2040::{
+    Pins, XOSC_CRYSTAL_FREQ,
+    hal::{
+        self, Sio,
+        clocks::{Clock, init_clocks_and_plls},
+        fugit::RateExtU32,
+        pac,
+        watchdog::Watchdog,
+    },
+};
+use common_hal_interface::*;
+use embedded_hal::spi::{Mode, Phase, Polarity};
+use panic_halt as _;
+
+#[entry]
+fn main() -> ! {
+    rp2040_setup!(pac, core, clocks, sio, pins);
+    let mut delay = new_delay!(core, clocks);
+
+    // PIN_DEFINITIONS
+    let i2c_sda = pins.gpio2;
+    let i2c_scl = pins.gpio3;
+
+    // INTERFACE_DEFINITIONS
+    let i2c_peripheral = pac.I2C1;
+    let mut i2c = setup_i2c!(pac, clocks, 100_000, i2c_peripheral, i2c_sda, i2c_scl);
+    let i2c_bus = shared_bus::BusManagerSimple::new(i2c);
+    let mut oled_featherwing_128x64 = i2c_bus.acquire_i2c();
+
+    loop {
+        delay.delay_ms(100);
+    }
+}
\ No newline at end of file
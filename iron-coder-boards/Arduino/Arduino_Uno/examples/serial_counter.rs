@@ -0,0 +1,30 @@
+//! Counts up once a second over the serial port
+//!
+//! Open the serial monitor at 57600 baud to watch the count.
+#![allow(warnings)]
+#![no_std]
+#![no_main]
+
+use arduino_hal::prelude::*;
+use panic_halt as _;
+use ufmt::uwriteln;
+
+use common_hal_interface::*;
+
+#[arduino_hal::entry]
+fn main() -> ! {
+    arduino_setup!(dp, pins);
+    let mut serial = setup_serial!(dp, pins, 57600);
+    uwriteln!(serial, "Starting up...").unwrap();
+
+    // PIN_DEFINITIONS
+
+    // INTERFACE_DEFINITIONS
+
+    let mut count: u32 = 0;
+    loop {
+        uwriteln!(serial, "count: {}", count).unwrap();
+        count = count.wrapping_add(1);
+        arduino_hal::delay_ms(1000);
+    }
+}
@@ -0,0 +1,39 @@
+#![allow(warnings)]
+#![no_std]
+#![no_main]
+
+use arduino_hal::prelude::*;
+use panic_halt as _;
+use ufmt::uwriteln;
+
+use common_hal_interface::*;
+
+#[arduino_hal::entry]
+fn main() -> ! {
+    arduino_setup!(dp, pins);
+    let mut serial = setup_serial!(dp, pins, 57600);
+    uwriteln!(serial, "Starting up...").unwrap();
+
+    /*
+     * For examples (and inspiration), head to
+     *
+     *     https://github.com/Rahix/avr-hal/tree/main/examples
+     *
+     * NOTE: Not all examples were ported to all boards!  There is a good chance though, that code
+     * for a different board can be adapted for yours.  The Arduino Uno currently has the most
+     * examples available.
+     */
+
+    // PIN_DEFINITIONS
+    let i2c_sda = pins.a4.into_floating_input().into_pull_up_input();
+    let i2c_scl = pins.a5.into_floating_input().into_pull_up_input();
+
+    // INTERFACE_DEFINITIONS
+    let mut i2c = setup_i2c!(dp, i2c_sda, i2c_scl, 10_000);
+    let i2c_bus = core::cell::RefCell::new(i2c);
+    let mut oled_featherwing_128x64 = embedded_hal_bus::i2c::RefCellDevice::new(&i2c_bus);
+
+    loop {
+        arduino_hal::delay_ms(100);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,390 @@
+//! Cargo builds run as a managed process, with their compiler diagnostics collected
+//!
+//! Cargo is run with `--message-format=json`, so compiler messages arrive on stdout as JSON
+//! while cargo's own progress ("Compiling ...") arrives on stderr as text. Both streams are
+//! read on a background task and handed to the UI as lines, which `Build::poll` sorts into
+//! the build log and the list of diagnostics.
+use log::{info, warn};
+use serde::Deserialize;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::mpsc::{Receiver, channel};
+
+use crate::app::size_report::{MemoryCapacity, SizeReport, USAGE_WARNING_THRESHOLD};
+use crate::app::tasks::{Task, TaskContext, TaskError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticLevel {
+    Error,
+    Warning,
+}
+
+/// A compiler error or warning, located at its primary span when it has one
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub level: DiagnosticLevel,
+    pub message: String,
+    /// Error code such as E0308 or lint name such as unused_variables
+    pub code: Option<String>,
+    /// Absolute path of the file the diagnostic points at
+    pub file: Option<PathBuf>,
+    /// 1-based line and column
+    pub line: usize,
+    pub column: usize,
+    /// The diagnostic as the compiler would have printed it
+    pub rendered: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildStatus {
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+/// A cargo build in progress or finished, with everything it has reported so far
+pub struct Build {
+    pub crate_dir: PathBuf,
+    pub status: BuildStatus,
+    /// Cargo's progress and the rendered diagnostics, in the order they arrived
+    pub output: Vec<String>,
+    pub diagnostics: Vec<Diagnostic>,
+    /// The firmware image, once it's linked
+    pub executable: Option<PathBuf>,
+    /// Memory of the board the build is for, to size the image against
+    pub capacity: MemoryCapacity,
+    /// What the image takes up, read once the build succeeds
+    pub size_report: Option<Result<SizeReport, String>>,
+    lines: Receiver<String>,
+    /// Reads cargo's output, finishing with whether cargo succeeded
+    task: Task<bool>,
+}
+
+impl Build {
+    /// Start cargo in a crate directory
+    /// Cargo and rustup pick up their configuration from the working directory, so cargo is
+    /// run from inside the crate rather than pointed at its manifest
+    pub fn start(crate_dir: &Path, cargo_args: &[&str]) -> io::Result<Build> {
+        let handle = duct::cmd("cargo", cargo_args)
+            .dir(crate_dir)
+            .stderr_to_stdout()
+            .unchecked()
+            .reader()?;
+        let handle = Arc::new(handle);
+        let (tx, rx) = channel();
+        let task = Task::spawn("Build", move |context: &TaskContext| {
+            let killer = Arc::clone(&handle);
+            context.on_cancel(move || {
+                if let Err(e) = killer.kill() {
+                    warn!("couldn't stop cargo: {}", e);
+                }
+            });
+            let mut lines = BufReader::new(&*handle);
+            let mut buf = Vec::new();
+            loop {
+                buf.clear();
+                match lines.read_until(b'\n', &mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(_) => {
+                        let line = String::from_utf8_lossy(&buf).trim_end().to_string();
+                        let progress = line.trim_start();
+                        if progress.starts_with("Compiling ") || progress.starts_with("Checking ") {
+                            context.set_progress(None, progress);
+                        }
+                        if tx.send(line).is_err() {
+                            return false;
+                        }
+                    }
+                }
+            }
+            matches!(handle.try_wait(), Ok(Some(output)) if output.status.success())
+        });
+        info!("started cargo {} in {}", cargo_args.join(" "), crate_dir.display());
+
+        Ok(Build {
+            // diagnostics hold canonical paths, so the crate's is too
+            crate_dir: crate_dir.canonicalize().unwrap_or_else(|_| crate_dir.to_path_buf()),
+            status: BuildStatus::Running,
+            output: vec![format!("cargo {}", cargo_args.join(" "))],
+            diagnostics: vec![],
+            executable: None,
+            capacity: MemoryCapacity::default(),
+            size_report: None,
+            lines: rx,
+            task,
+        })
+    }
+
+    /// Take in what cargo reported since the last poll
+    /// Returns true if anything changed
+    pub fn poll(&mut self) -> bool {
+        // every line is sent before the task finishes, so its result is taken first
+        let finished = self.task.poll();
+        let mut changed = finished.is_some();
+        while let Ok(line) = self.lines.try_recv() {
+            changed = true;
+            self.add_line(&line);
+        }
+        let Some(result) = finished else {
+            return changed;
+        };
+        if self.status == BuildStatus::Running {
+            self.status = match result {
+                Ok(true) => BuildStatus::Succeeded,
+                Ok(false) => BuildStatus::Failed,
+                Err(TaskError::Cancelled) => BuildStatus::Cancelled,
+                Err(e) => {
+                    self.output.push(format!("Reading cargo's output {}", e));
+                    BuildStatus::Failed
+                }
+            };
+        }
+        if self.status == BuildStatus::Succeeded {
+            self.report_size();
+        }
+        self.output.push(match self.status {
+            BuildStatus::Succeeded => "Build succeeded".to_string(),
+            BuildStatus::Cancelled => "Build cancelled".to_string(),
+            _ => format!(
+                "Build failed with {} error(s), {} warning(s)",
+                self.count(DiagnosticLevel::Error),
+                self.count(DiagnosticLevel::Warning)
+            ),
+        });
+        true
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.status == BuildStatus::Running
+    }
+
+    pub fn cancel(&mut self) {
+        if !self.is_running() {
+            return;
+        }
+        self.task.cancel();
+        self.status = BuildStatus::Cancelled;
+    }
+
+    /// Get the build's progress and cancellation, to list it with the other tasks
+    pub fn context(&self) -> &TaskContext {
+        self.task.context()
+    }
+
+    pub fn count(&self, level: DiagnosticLevel) -> usize {
+        self.diagnostics.iter().filter(|d| d.level == level).count()
+    }
+
+    /// Get the diagnostics pointing into a file
+    pub fn diagnostics_for<'a>(&'a self, file: &'a Path) -> impl Iterator<Item = &'a Diagnostic> + 'a {
+        self.diagnostics
+            .iter()
+            .filter(move |d| d.file.as_deref() == Some(file))
+    }
+
+    /// Read the image's size and note it in the output, warning if the board is nearly full
+    fn report_size(&mut self) {
+        let Some(executable) = &self.executable else {
+            return;
+        };
+        match SizeReport::read(executable) {
+            Ok(report) => {
+                self.output.extend(report.summary(self.capacity));
+                for warning in report.warnings(self.capacity, USAGE_WARNING_THRESHOLD) {
+                    self.output.push(format!("warning: {}", warning));
+                }
+                self.size_report = Some(Ok(report));
+            }
+            Err(e) => {
+                warn!("couldn't read the size of {}: {}", executable.display(), e);
+                self.size_report = Some(Err(e.to_string()));
+            }
+        }
+    }
+
+    fn add_line(&mut self, line: &str) {
+        match parse_line(line, &self.crate_dir) {
+            ParsedLine::Text(text) => self.output.push(text),
+            ParsedLine::Diagnostic(diagnostic) => {
+                self.output.extend(diagnostic.rendered.lines().map(str::to_string));
+                // the same message is reported again for every target that includes the file
+                if !self.diagnostics.contains(&diagnostic) {
+                    self.diagnostics.push(diagnostic);
+                }
+            }
+            ParsedLine::Rendered(text) => self.output.extend(text.lines().map(str::to_string)),
+            ParsedLine::Executable(path) => self.executable = Some(path),
+            ParsedLine::Ignored => {}
+        }
+    }
+}
+
+impl Drop for Build {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+/// What a line of cargo's output turned out to be
+#[derive(Debug, PartialEq)]
+pub enum ParsedLine {
+    /// Plain text, such as cargo's progress
+    Text(String),
+    Diagnostic(Diagnostic),
+    /// A compiler message that isn't worth listing, such as the error count summary
+    Rendered(String),
+    /// A binary cargo finished linking
+    Executable(PathBuf),
+    /// A JSON message with nothing to show, such as a finished library
+    Ignored,
+}
+
+#[derive(Deserialize)]
+struct CargoMessage {
+    reason: String,
+    message: Option<CompilerMessage>,
+    executable: Option<PathBuf>,
+}
+
+#[derive(Deserialize)]
+struct CompilerMessage {
+    message: String,
+    level: String,
+    code: Option<DiagnosticCode>,
+    #[serde(default)]
+    spans: Vec<DiagnosticSpan>,
+    rendered: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DiagnosticCode {
+    code: String,
+}
+
+#[derive(Deserialize)]
+struct DiagnosticSpan {
+    file_name: String,
+    line_start: usize,
+    column_start: usize,
+    is_primary: bool,
+}
+
+/// Sort a line of `cargo --message-format=json` output
+/// Span paths are relative to the workspace root, which may be above the crate directory
+pub fn parse_line(line: &str, crate_dir: &Path) -> ParsedLine {
+    if !line.starts_with('{') {
+        return ParsedLine::Text(line.to_string());
+    }
+    let Ok(message) = serde_json::from_str::<CargoMessage>(line) else {
+        return ParsedLine::Text(line.to_string());
+    };
+    if message.reason == "compiler-artifact" {
+        if let Some(executable) = message.executable {
+            return ParsedLine::Executable(executable);
+        }
+    }
+    if message.reason != "compiler-message" {
+        return ParsedLine::Ignored;
+    }
+    let Some(message) = message.message else {
+        return ParsedLine::Ignored;
+    };
+    let rendered = message.rendered.clone().unwrap_or_else(|| message.message.clone());
+
+    let level = match message.level.as_str() {
+        "error" | "error: internal compiler error" => DiagnosticLevel::Error,
+        "warning" => DiagnosticLevel::Warning,
+        _ => return ParsedLine::Rendered(rendered),
+    };
+    let span = message.spans.iter().find(|s| s.is_primary);
+    // summaries like "aborting due to 2 previous errors" point nowhere and repeat the count
+    if span.is_none()
+        && (message.message.starts_with("aborting due to") || message.message.ends_with("emitted"))
+    {
+        return ParsedLine::Rendered(rendered);
+    }
+
+    ParsedLine::Diagnostic(Diagnostic {
+        level,
+        message: message.message,
+        code: message.code.map(|c| c.code),
+        file: span.map(|s| resolve_span_file(&s.file_name, crate_dir)),
+        line: span.map_or(0, |s| s.line_start),
+        column: span.map_or(0, |s| s.column_start),
+        rendered,
+    })
+}
+
+fn resolve_span_file(file_name: &str, crate_dir: &Path) -> PathBuf {
+    let path = Path::new(file_name);
+    let found = if path.is_absolute() {
+        Some(path.to_path_buf())
+    } else {
+        crate_dir
+            .ancestors()
+            .map(|dir| dir.join(path))
+            .find(|candidate| candidate.exists())
+    };
+    let path = found.unwrap_or_else(|| crate_dir.join(path));
+    // file tabs hold canonical paths
+    path.canonicalize().unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_build_diagnostics_are_parsed_and_located() {
+        use crate::app::build::{Build, BuildStatus, DiagnosticLevel, ParsedLine, parse_line};
+
+        let temp = crate::test::temp_dir();
+        let dir = temp.path().to_path_buf();
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::write(
+            dir.join("Cargo.toml"),
+            "[package]\nname = \"broken\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("src/main.rs"),
+            "fn main() {\n\n    let x: u8 = \"no\";\n}\n",
+        )
+        .unwrap();
+        let main_rs = dir.join("src/main.rs").canonicalize().unwrap();
+
+        assert_eq!(
+            parse_line("   Compiling broken v0.1.0", &dir),
+            ParsedLine::Text("   Compiling broken v0.1.0".to_string())
+        );
+        assert_eq!(
+            parse_line(r#"{"reason":"compiler-artifact","package_id":"broken"}"#, &dir),
+            ParsedLine::Ignored
+        );
+        let summary = r#"{"reason":"compiler-message","message":{"message":"aborting due to 1 previous error","level":"error","code":null,"spans":[],"rendered":"error: aborting due to 1 previous error\n"}}"#;
+        assert!(matches!(parse_line(summary, &dir), ParsedLine::Rendered(_)));
+        let error = r#"{"reason":"compiler-message","message":{"message":"mismatched types","level":"error","code":{"code":"E0308"},"spans":[{"file_name":"src/main.rs","line_start":3,"column_start":17,"is_primary":true}],"rendered":"error[E0308]: mismatched types\n"}}"#;
+        let ParsedLine::Diagnostic(diagnostic) = parse_line(error, &dir.join("src")) else {
+            panic!("expected a diagnostic");
+        };
+        // spans are relative to the workspace root, found above the crate directory if need be
+        assert_eq!(diagnostic.file.as_deref(), Some(main_rs.as_path()));
+        assert_eq!((diagnostic.line, diagnostic.column), (3, 17));
+        assert_eq!(diagnostic.code.as_deref(), Some("E0308"));
+
+        // a real build reports the error against the file
+        let mut build = Build::start(&dir, &["build", "--message-format=json"]).unwrap();
+        let started = std::time::Instant::now();
+        while build.is_running() && started.elapsed() < std::time::Duration::from_secs(120) {
+            build.poll();
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }
+        assert_eq!(build.status, BuildStatus::Failed);
+        assert_eq!(build.count(DiagnosticLevel::Error), 1);
+        let in_main: Vec<_> = build.diagnostics_for(&main_rs).collect();
+        assert_eq!(in_main.len(), 1);
+        assert_eq!((in_main[0].level, in_main[0].line), (DiagnosticLevel::Error, 3));
+        assert!(build.output.iter().any(|l| l.contains("Compiling broken")));
+        drop(build);
+    }
+}
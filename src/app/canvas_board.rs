@@ -36,6 +36,9 @@ pub struct CanvasBoard {
     #[serde(skip)]
     pub pin_locations: HashMap<u32, Rect>,
     pub canvas_pos: Vec2,
+    /// Crate holding this board's firmware, relative to the project directory
+    /// Only set for main boards after the first, whose firmware is a workspace member
+    pub firmware_dir: Option<String>,
     #[serde(skip)]
     pub connections: Vec<Rc<RefCell<CanvasConnection>>>,
     connection_ids: Vec<Uuid>,
@@ -56,6 +59,7 @@ impl Default for CanvasBoard {
             image_rect: Rect::ZERO,
             pin_locations: HashMap::new(),
             canvas_pos: Vec2::ZERO,
+            firmware_dir: None,
             connection_ids: Vec::new(),
             connections: Vec::new(),
             canvas_rect: Rect::ZERO,
@@ -94,6 +98,7 @@ impl CanvasBoard {
                 image_rect,
                 pin_locations,
                 canvas_pos: Vec2::new(0.0, 0.0),
+                firmware_dir: None,
                 connections: Vec::new(),
                 connection_ids: Vec::new(),
                 canvas_rect,
@@ -123,6 +128,9 @@ impl CanvasBoard {
         println!("LOOKING FOR BOARD: {}", self.board_name);
         if let Some(kb_board) = kb.iter().find(|b| b.get_name() == self.board_name) {
             self.board = kb_board.clone();
+        } else {
+            // keep the board as a named placeholder until the user repairs it
+            self.board = Rc::new(Board::placeholder(&self.board_name));
         }
 
         self.init_pins();
@@ -354,6 +362,15 @@ impl CanvasBoard {
         self.pin_locations.get(pin_num).map(|rect| rect.center())
     }
 
+    pub fn board_name(&self) -> &str {
+        &self.board_name
+    }
+
+    /// Point this board at a different library board, call `init_refs` afterwards to load it
+    pub fn set_board_name(&mut self, name: &str) {
+        self.board_name = name.to_string();
+    }
+
     pub fn get_canvas_position(&self) -> Vec2 {
         return self.canvas_pos;
     }
@@ -358,6 +358,14 @@ impl CanvasConnection {
         &self.start_board
     }
 
+    pub fn start_board_id(&self) -> Uuid {
+        self.start_board_id
+    }
+
+    pub fn end_board_id(&self) -> Uuid {
+        self.end_board_id
+    }
+
     pub fn get_start_pin(&self) -> u32 {
         return self.start_pin.clone();
     }
@@ -5,17 +5,46 @@ use std::cell::RefCell;
 use std::rc::Rc;
 use uuid::Uuid;
 
+/// A peripheral board attached to a shared bus
+/// Each device gets its own bus handle in generated code, so every driver can own one
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BusDevice {
+    pub board_id: Uuid,
+    /// Name used for the device's generated variables
+    pub name: String,
+    /// Bus address selected for the device (I2C only)
+    #[serde(default)]
+    pub address: Option<u8>,
+}
+
+impl BusDevice {
+    /// Get the Rust identifier generated code uses for this device
+    pub fn variable_name(&self) -> String {
+        to_identifier(&self.name)
+    }
+}
+
 /// Represents a group of connections that form a complete protocol (I2C, SPI, UART, etc.)
 /// This allows us to create, undo, and redo protocol connections as a single unit
 #[derive(Clone, Serialize, Deserialize)]
 pub struct CanvasProtocol {
     pub id: Uuid,
     pub protocol_type: WizardType,
+    /// User-editable name of the group, also used for generated variable names
+    #[serde(default)]
+    pub name: String,
+    /// Peripheral block driving this bus (e.g. I2C0, SPI2), taken from the main board manifest
+    #[serde(default)]
+    pub peripheral_instance: Option<String>,
+    /// Peripherals sharing this bus, in the order they were attached
+    #[serde(default)]
+    pub devices: Vec<BusDevice>,
     /// All the individual wire connections that make up this protocol
-    /// For I2C: 2 connections (SDA, SCL)
-    /// For SPI: 4 connections (MOSI, MISO, SCK, CS)
+    /// For I2C: 2 connections (SDA, SCL) per device
+    /// For SPI: 4 connections (MOSI, MISO, SCK, CS) per device
     /// For UART: 2 connections (TX, RX)
-    #[serde(with = "crate::project::project::rc_refcell_vec")]
+    /// Saved as connection ids only, the connections themselves live in the project
+    #[serde(rename = "connection_ids", with = "connection_ids")]
     pub connections: Vec<Rc<RefCell<CanvasConnection>>>,
 }
 
@@ -25,10 +54,33 @@ impl CanvasProtocol {
         Self {
             id: Uuid::new_v4(),
             protocol_type,
+            name: String::new(),
+            peripheral_instance: None,
+            devices: Vec::new(),
             connections: Vec::new(),
         }
     }
 
+    /// Default group name for a protocol type, used before the user renames the group
+    pub fn default_name(protocol_type: &WizardType) -> String {
+        match protocol_type {
+            WizardType::None => "bus".to_string(),
+            WizardType::I2C => "i2c".to_string(),
+            WizardType::SPI => "spi".to_string(),
+            WizardType::UART => "uart".to_string(),
+        }
+    }
+
+    /// Get the Rust identifier generated code uses for this group
+    pub fn variable_name(&self) -> String {
+        let name = if self.name.trim().is_empty() {
+            Self::default_name(&self.protocol_type)
+        } else {
+            self.name.clone()
+        };
+        to_identifier(&name)
+    }
+
     /// Add a connection to this protocol group
     pub fn add_connection(&mut self, connection: Rc<RefCell<CanvasConnection>>) {
         self.connections.push(connection);
@@ -42,7 +94,49 @@ impl CanvasProtocol {
             WizardType::SPI => 4,   // MOSI + MISO + SCK + CS
             WizardType::UART => 2,  // TX + RX
         };
-        self.connections.len() == expected_count
+        self.connections.len() == expected_count * self.devices.len().max(1)
+    }
+
+    /// Get the device attached to the bus from the given peripheral board
+    pub fn get_device(&self, board_id: &Uuid) -> Option<&BusDevice> {
+        self.devices.iter().find(|d| d.board_id == *board_id)
+    }
+
+    /// Get the addresses claimed by more than one device on this bus, with the devices claiming them
+    pub fn address_conflicts(&self) -> Vec<(u8, Vec<String>)> {
+        let mut conflicts: Vec<(u8, Vec<String>)> = Vec::new();
+        for device in &self.devices {
+            let Some(address) = device.address else {
+                continue;
+            };
+            match conflicts.iter_mut().find(|(a, _)| *a == address) {
+                Some((_, names)) => names.push(device.name.clone()),
+                None => conflicts.push((address, vec![device.name.clone()])),
+            }
+        }
+        conflicts.retain(|(_, names)| names.len() > 1);
+        conflicts
+    }
+
+    /// Get the connections wiring one device to the bus
+    pub fn device_connections(&self, board_id: &Uuid) -> Vec<Rc<RefCell<CanvasConnection>>> {
+        self.connections
+            .iter()
+            .filter(|c| {
+                c.borrow()
+                    .get_end_board()
+                    .is_some_and(|b| b.borrow().id == *board_id)
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Get the first connection filling a main board role (e.g. the SDA line)
+    pub fn connection_for_role(&self, role: &str) -> Option<Rc<RefCell<CanvasConnection>>> {
+        self.connections
+            .iter()
+            .find(|c| c.borrow().role.as_deref() == Some(role))
+            .cloned()
     }
 
     /// Get all connections in this protocol
@@ -91,3 +185,58 @@ impl CanvasProtocol {
         self.assign_to_connections();
     }
 }
+
+/// (De)serialize a group's connections by id
+/// Deserialized connections are placeholders holding only the id until `init_refs` swaps in the real ones
+mod connection_ids {
+    use super::CanvasConnection;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::{cell::RefCell, rc::Rc};
+    use uuid::Uuid;
+
+    pub fn serialize<S>(v: &[Rc<RefCell<CanvasConnection>>], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let ids: Vec<Uuid> = v.iter().map(|c| c.borrow().id).collect();
+        ids.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<Rc<RefCell<CanvasConnection>>>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let ids: Vec<Uuid> = Vec::deserialize(deserializer)?;
+        Ok(ids
+            .into_iter()
+            .map(|id| {
+                let mut placeholder = CanvasConnection::default();
+                placeholder.id = id;
+                Rc::new(RefCell::new(placeholder))
+            })
+            .collect())
+    }
+}
+
+/// Format a bus address the way datasheets write it, e.g. 0x3C
+pub fn format_address(address: u8) -> String {
+    format!("0x{:02X}", address)
+}
+
+/// Convert a user-provided name into a snake_case Rust identifier
+pub fn to_identifier(name: &str) -> String {
+    let mut ident = String::new();
+    for c in name.trim().chars() {
+        if c.is_ascii_alphanumeric() {
+            ident.push(c.to_ascii_lowercase());
+        } else if !ident.ends_with('_') {
+            ident.push('_');
+        }
+    }
+    let ident = ident.trim_matches('_').to_string();
+    match ident.chars().next() {
+        None => "bus".to_string(),
+        Some(c) if c.is_ascii_digit() => format!("bus_{}", ident),
+        _ => ident,
+    }
+}
@@ -35,9 +35,21 @@ impl Command for AddProtocolConnectionCommand {
         for conn in &self.protocol_connection.connections {
             project.add_connection(conn);
         }
+        if !project.add_protocol_group(self.protocol_connection.clone()) {
+            // the board ran out of peripherals for another bus since, so take the wires back out
+            for conn in &self.protocol_connection.connections {
+                project.remove_connection(conn);
+            }
+        }
     }
 
     fn undo(&mut self, project: &mut Project) {
+        // Keep any rename or instance change made since the group was added
+        if let Some(group) = project.get_protocol_group(&self.protocol_connection.id) {
+            self.protocol_connection.name = group.name.clone();
+            self.protocol_connection.peripheral_instance = group.peripheral_instance.clone();
+            self.protocol_connection.devices = group.devices.clone();
+        }
         // Remove all connections that were part of this protocol
         println!("Protocol undo: Removing {} connections from {:?}",
                  self.protocol_connection.connections.len(),
@@ -45,6 +57,7 @@ impl Command for AddProtocolConnectionCommand {
         for conn in &self.protocol_connection.connections {
             project.remove_connection(conn);
         }
+        project.remove_protocol_group(&self.protocol_connection.id);
     }
 
     fn description(&self) -> String {
@@ -69,6 +82,7 @@ impl Command for RemoveProtocolConnectionCommand {
         for conn in &self.protocol_connection.connections {
             project.remove_connection(conn);
         }
+        project.remove_protocol_group(&self.protocol_connection.id);
     }
 
     fn undo(&mut self, project: &mut Project) {
@@ -76,6 +90,12 @@ impl Command for RemoveProtocolConnectionCommand {
         for conn in &self.protocol_connection.connections {
             project.add_connection(conn);
         }
+        if !project.add_protocol_group(self.protocol_connection.clone()) {
+            // the board ran out of peripherals for another bus since, so take the wires back out
+            for conn in &self.protocol_connection.connections {
+                project.remove_connection(conn);
+            }
+        }
     }
 
     fn description(&self) -> String {
@@ -28,6 +28,25 @@ impl WizardType {
         }
     }
 
+    /// Name of the board manifest interface this protocol uses
+    pub fn interface_name(&self) -> &'static str {
+        match self {
+            WizardType::None => "",
+            WizardType::I2C => "I2C",
+            WizardType::SPI => "SPI",
+            WizardType::UART => "UART",
+        }
+    }
+
+    /// Main board roles whose pins are shared by every device on the bus
+    pub fn shared_roles(&self) -> Vec<String> {
+        match self {
+            WizardType::I2C => vec!["SDA".to_string(), "SCL".to_string()],
+            WizardType::SPI => vec!["SCK".to_string(), "MOSI".to_string(), "MISO".to_string()],
+            _ => vec![],
+        }
+    }
+
     pub fn required_roles(&self) -> Vec<(String, String)> {
         match self {
             WizardType::None => vec![],
@@ -167,7 +186,7 @@ impl ConnectionWizard {
             match &mut self.state {
                 WizardState::SelectingRole { .. } => {
                     if let Some(roles) = board.pinout.get_pin_roles(&pin_number) {
-                        if roles.contains(&current_role) {
+                        if Self::pin_fills_role(roles, &current_role) {
                             let pin_name = board
                                 .pinout
                                 .get_pin_name(&pin_number)
@@ -199,6 +218,11 @@ impl ConnectionWizard {
         }
     }
 
+    /// Chip selects are plain outputs, so any GPIO (or the hardware SS pin) can drive one
+    fn pin_fills_role(roles: &HashSet<String>, role: &str) -> bool {
+        roles.contains(role) || (role == "CS" && (roles.contains("SS") || roles.contains("GPIO")))
+    }
+
     /// Given a selected pin, return true if the pin is valid and was accepted
     /// Returns true and the wizard advances, returns false and shows error if invalid
     /// board_id is the UUID of the CanvasBoard that this pin belongs to
@@ -224,7 +248,7 @@ impl ConnectionWizard {
                             .map(|s| s.as_str())
                             .unwrap_or("unknown");
 
-                        if roles.contains(&current_role) {
+                        if Self::pin_fills_role(roles, &current_role) {
                             selected_pins.push((
                                 current_role.clone(),
                                 pin_number,
@@ -244,16 +268,11 @@ impl ConnectionWizard {
                                 // Check if completed all roles
                                 if *current_role_index >= self.required_roles.len() {
                                     // Move created_connections into the Complete state
+                                    // Bus code is generated when the protocol group is added to the project
                                     let conns = created_connections.clone();
-                                    let is_completing_existing = conns
-                                        .iter()
-                                        .any(|c| c.borrow().protocol_group_id.is_some());
                                     self.state = WizardState::Complete {
                                         created_connections: conns,
                                     };
-                                    if !is_completing_existing {
-                                        project.add_connection_bus(&self.wizard_type);
-                                    }
                                 } else {
                                     *pins_left_to_connect = 2;
                                 }
@@ -0,0 +1,529 @@
+//! Flashing firmware onto a board as a managed process, and finding the boards to flash
+//!
+//! Flashing runs `cargo run`, which builds the firmware and hands it to the board's runner.
+//! Runners redraw their progress bars with carriage returns, so output is split on `\r` as
+//! well as `\n`, and an unfinished line is handed over too so a bar drawn a character at a
+//! time (as avrdude's is) still moves. `parse_progress` reads a stage and a fraction done out
+//! of whatever the build or the runner prints.
+use log::{info, warn};
+use serialport::{SerialPortInfo, SerialPortType};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+use std::sync::mpsc::{Receiver, channel};
+use std::time::Instant;
+
+use crate::app::tasks::{Task, TaskContext, TaskError};
+use crate::board::{BuildProfile, UsbId};
+
+/// A serial port on this computer, and the board it belongs to if its USB ID says so
+#[derive(Debug, Clone, PartialEq)]
+pub struct DetectedDevice {
+    pub port_name: String,
+    pub usb_id: Option<UsbId>,
+    /// Product or manufacturer name the port reports
+    pub description: Option<String>,
+    /// Name of the board whose manifest lists the port's USB ID
+    pub board: Option<String>,
+}
+
+impl DetectedDevice {
+    pub fn label(&self) -> String {
+        let mut label = self.port_name.clone();
+        if let Some(board) = &self.board {
+            label.push_str(&format!(" ({})", board));
+        } else if let Some(description) = &self.description {
+            label.push_str(&format!(" ({})", description));
+        }
+        label
+    }
+}
+
+/// List the serial ports on this computer, matched against the boards' USB IDs
+pub fn detect_devices(boards: &[(String, BuildProfile)]) -> Vec<DetectedDevice> {
+    match serialport::available_ports() {
+        Ok(ports) => match_devices(ports, boards),
+        Err(e) => {
+            warn!("couldn't list serial ports: {}", e);
+            vec![]
+        }
+    }
+}
+
+/// Match serial ports against the boards' USB IDs, putting ports that belong to a board first
+pub fn match_devices(ports: Vec<SerialPortInfo>, boards: &[(String, BuildProfile)]) -> Vec<DetectedDevice> {
+    let mut devices: Vec<DetectedDevice> = ports
+        .into_iter()
+        .map(|port| {
+            let usb = match port.port_type {
+                SerialPortType::UsbPort(usb) => Some(usb),
+                _ => None,
+            };
+            let usb_id = usb.as_ref().map(|usb| UsbId {
+                vid: usb.vid,
+                pid: usb.pid,
+            });
+            let board = usb_id.and_then(|id| {
+                boards
+                    .iter()
+                    .find(|(_, profile)| profile.usb_ids.contains(&id))
+                    .map(|(name, _)| name.clone())
+            });
+            DetectedDevice {
+                port_name: port.port_name,
+                usb_id,
+                description: usb.and_then(|usb| usb.product.or(usb.manufacturer)),
+                board,
+            }
+        })
+        .collect();
+    devices.sort_by(|a, b| (a.board.is_none(), &a.port_name).cmp(&(b.board.is_none(), &b.port_name)));
+    devices
+}
+
+/// A debug probe probe-rs can flash through
+#[derive(Debug, Clone, PartialEq)]
+pub struct Probe {
+    /// VID:PID[:serial], as `--probe` takes it
+    pub selector: String,
+    pub name: String,
+}
+
+/// List the debug probes attached to this computer
+pub fn list_probes() -> Vec<Probe> {
+    match Command::new("probe-rs").arg("list").output() {
+        Ok(output) => parse_probe_list(&String::from_utf8_lossy(&output.stdout)),
+        Err(e) => {
+            warn!("couldn't run probe-rs list: {}", e);
+            vec![]
+        }
+    }
+}
+
+/// Read the probes out of `probe-rs list`, whose lines look like
+/// `[0]: STLink V2 -- 0483:3748:066DFF (ST-LINK)`
+pub fn parse_probe_list(output: &str) -> Vec<Probe> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (_, rest) = line.trim().strip_prefix('[')?.split_once("]:")?;
+            let (name, rest) = rest.split_once(" -- ")?;
+            let selector = rest.split_whitespace().next()?;
+            Some(Probe {
+                selector: selector.to_string(),
+                name: name.trim().to_string(),
+            })
+        })
+        .collect()
+}
+
+/// What a flash is doing and how far along it is
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlashProgress {
+    pub stage: String,
+    /// Fraction of the stage done, when the output says
+    pub fraction: Option<f32>,
+}
+
+/// Stages, by a word their output lines contain
+const STAGES: &[(&str, &str)] = &[
+    ("compiling", "Building"),
+    ("building", "Building"),
+    ("running `", "Starting flasher"),
+    ("connecting", "Connecting"),
+    ("erasing", "Erasing"),
+    ("writing", "Writing"),
+    ("flashing", "Writing"),
+    ("programming", "Writing"),
+    ("transfer", "Writing"),
+    ("reading", "Reading"),
+    ("verifying", "Verifying"),
+];
+/// avrdude's bar is 50 `#`s long
+const AVRDUDE_BAR_LENGTH: f32 = 50.0;
+
+/// Read a stage and fraction done out of a line of build or flasher output
+/// Either may be missing: espflash prints its bar on a line of its own, for one
+pub fn parse_progress(line: &str) -> (Option<&'static str>, Option<f32>) {
+    let lower = line.to_lowercase();
+    let stage = STAGES
+        .iter()
+        .find(|(word, _)| lower.contains(word))
+        .map(|(_, stage)| *stage);
+    (stage, parse_fraction(line))
+}
+
+fn parse_fraction(line: &str) -> Option<f32> {
+    // a percentage, as in "| 100% 0.20s"
+    if let Some(percent) = line.find('%') {
+        let digits = line[..percent]
+            .rsplit(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .next()
+            .unwrap_or_default();
+        if let Ok(percent) = digits.parse::<f32>() {
+            return Some((percent / 100.0).clamp(0.0, 1.0));
+        }
+    }
+    // a count, as in "218/218" or "16.00 KiB/32.00 KiB"
+    for (slash, _) in line.match_indices('/') {
+        let before = line[..slash].trim_end();
+        let before = before.trim_end_matches(|c: char| c.is_alphabetic()).trim_end();
+        let done = before.rsplit(' ').next().unwrap_or_default();
+        let after = line[slash + 1..].trim_start();
+        let total = after.split(|c: char| !(c.is_ascii_digit() || c == '.')).next().unwrap_or_default();
+        if let (Ok(done), Ok(total)) = (done.parse::<f32>(), total.parse::<f32>()) {
+            if total > 0.0 {
+                return Some((done / total).clamp(0.0, 1.0));
+            }
+        }
+    }
+    // avrdude's bar, as in "Writing | ################"
+    if let Some((_, bar)) = line.split_once("| ") {
+        let hashes = bar.chars().take_while(|c| *c == '#').count();
+        if hashes > 0 {
+            return Some((hashes as f32 / AVRDUDE_BAR_LENGTH).min(1.0));
+        }
+    }
+    None
+}
+
+/// Whether a line says the flasher couldn't open the port because something else has it
+pub fn is_port_busy(line: &str) -> bool {
+    let lower = line.to_lowercase();
+    ["resource busy", "port is busy", "already in use", "access is denied", "failed to open serial port"]
+        .iter()
+        .any(|message| lower.contains(message))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlashStatus {
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+enum FlashEvent {
+    Line(String),
+    /// Text of a line still being drawn
+    Partial(String),
+}
+
+/// A flash in progress or finished, with everything it has printed so far
+pub struct Flash {
+    pub crate_dir: PathBuf,
+    /// Serial port or probe being flashed through, if one was picked
+    pub device: Option<String>,
+    pub status: FlashStatus,
+    pub output: Vec<String>,
+    pub progress: Option<FlashProgress>,
+    /// Set when the flasher couldn't open the port because another program had it
+    pub port_busy: bool,
+    /// When the flasher exited
+    pub finished_at: Option<Instant>,
+    events: Receiver<FlashEvent>,
+    /// Reads the flasher's output, finishing with whether it succeeded
+    task: Task<bool>,
+}
+
+impl Flash {
+    /// Start a flasher in a crate directory, such as `cargo run` with the board's runner
+    pub fn start(crate_dir: &Path, program: &str, args: &[String], device: Option<String>) -> io::Result<Flash> {
+        let handle = duct::cmd(program, args)
+            .dir(crate_dir)
+            .stderr_to_stdout()
+            .unchecked()
+            .reader()?;
+        let handle = Arc::new(handle);
+        let (tx, rx) = channel();
+        let task = Task::spawn("Flash", move |context: &TaskContext| {
+            let killer = Arc::clone(&handle);
+            context.on_cancel(move || {
+                if let Err(e) = killer.kill() {
+                    warn!("couldn't stop the flasher: {}", e);
+                }
+            });
+            let mut buf = [0u8; 4096];
+            let mut line = Vec::new();
+            loop {
+                let n = match (&*handle).read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => n,
+                };
+                for &byte in &buf[..n] {
+                    if byte == b'\n' || byte == b'\r' {
+                        if !line.is_empty() {
+                            let text = String::from_utf8_lossy(&line).to_string();
+                            if let (Some(stage), fraction) = parse_progress(&strip_ansi(&text)) {
+                                context.set_progress(fraction, stage);
+                            }
+                            if tx.send(FlashEvent::Line(text)).is_err() {
+                                return false;
+                            }
+                            line.clear();
+                        }
+                    } else {
+                        line.push(byte);
+                    }
+                }
+                if !line.is_empty() {
+                    tx.send(FlashEvent::Partial(String::from_utf8_lossy(&line).to_string())).ok();
+                }
+            }
+            if !line.is_empty() {
+                tx.send(FlashEvent::Line(String::from_utf8_lossy(&line).to_string())).ok();
+            }
+            matches!(handle.try_wait(), Ok(Some(output)) if output.status.success())
+        });
+        let command = format!("{} {}", program, args.join(" "));
+        info!("started {} in {}", command, crate_dir.display());
+
+        Ok(Flash {
+            crate_dir: crate_dir.to_path_buf(),
+            device,
+            status: FlashStatus::Running,
+            output: vec![command],
+            progress: None,
+            port_busy: false,
+            finished_at: None,
+            events: rx,
+            task,
+        })
+    }
+
+    /// Take in what the flasher printed since the last poll
+    /// Returns true if anything changed
+    pub fn poll(&mut self) -> bool {
+        // every line is sent before the task finishes, so its result is taken first
+        let finished = self.task.poll();
+        let mut changed = finished.is_some();
+        while let Ok(event) = self.events.try_recv() {
+            changed = true;
+            match event {
+                FlashEvent::Line(line) => {
+                    let line = strip_ansi(&line);
+                    self.update_progress(&line);
+                    self.port_busy |= is_port_busy(&line);
+                    // a progress bar redrawn on a line of its own replaces its last drawing
+                    let (stage, fraction) = parse_progress(&line);
+                    let replaces_last = fraction.is_some()
+                        && self.output.last().is_some_and(|last| match parse_progress(last) {
+                            (last_stage, Some(_)) => last_stage == stage || stage.is_none(),
+                            _ => false,
+                        });
+                    if replaces_last {
+                        self.output.pop();
+                    }
+                    self.output.push(line);
+                }
+                FlashEvent::Partial(line) => self.update_progress(&strip_ansi(&line)),
+            }
+        }
+        let Some(result) = finished else {
+            return changed;
+        };
+        self.finished_at = Some(Instant::now());
+        if self.status == FlashStatus::Running {
+            self.status = match result {
+                Ok(true) => FlashStatus::Succeeded,
+                Ok(false) => FlashStatus::Failed,
+                Err(TaskError::Cancelled) => FlashStatus::Cancelled,
+                Err(e) => {
+                    self.output.push(format!("Reading the flasher's output {}", e));
+                    FlashStatus::Failed
+                }
+            };
+        }
+        self.output.push(
+            match self.status {
+                FlashStatus::Succeeded => "Flashing succeeded",
+                FlashStatus::Cancelled => "Flashing cancelled",
+                _ => "Flashing failed",
+            }
+            .to_string(),
+        );
+        if self.status == FlashStatus::Succeeded {
+            self.progress = Some(FlashProgress {
+                stage: "Done".to_string(),
+                fraction: Some(1.0),
+            });
+        }
+        true
+    }
+
+    fn update_progress(&mut self, line: &str) {
+        let (stage, fraction) = parse_progress(line);
+        match (&mut self.progress, stage) {
+            (Some(progress), None) => {
+                if fraction.is_some() {
+                    progress.fraction = fraction;
+                }
+            }
+            (_, Some(stage)) => {
+                self.progress = Some(FlashProgress {
+                    stage: stage.to_string(),
+                    fraction,
+                })
+            }
+            (None, None) => {}
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.status == FlashStatus::Running
+    }
+
+    pub fn cancel(&mut self) {
+        if !self.is_running() {
+            return;
+        }
+        self.task.cancel();
+        self.status = FlashStatus::Cancelled;
+        self.finished_at = Some(Instant::now());
+    }
+
+    /// Get the flash's progress and cancellation, to list it with the other tasks
+    pub fn context(&self) -> &TaskContext {
+        self.task.context()
+    }
+}
+
+/// Drop the colour and cursor escapes flashers print
+fn strip_ansi(line: &str) -> String {
+    let mut text = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // CSI sequences end with a letter; anything else is a single character
+            if chars.next() == Some('[') {
+                for c in chars.by_ref() {
+                    if c.is_ascii_alphabetic() {
+                        break;
+                    }
+                }
+            }
+        } else {
+            text.push(c);
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::board::get_boards;
+    use std::path::Path;
+
+    #[test]
+    fn test_flashing_finds_boards_and_follows_flasher_progress() {
+        use crate::app::flash::{self, match_devices, parse_probe_list, parse_progress};
+        use crate::board::UsbId;
+        use serialport::{SerialPortInfo, SerialPortType, UsbPortInfo};
+
+        let boards: Vec<(String, crate::board::BuildProfile)> = get_boards(Path::new("./iron-coder-boards"))
+            .iter()
+            .filter(|b| b.is_main_board())
+            .map(|b| (b.get_name().to_string(), b.build_profile().clone()))
+            .collect();
+        let usb_port = |name: &str, vid: u16, pid: u16| SerialPortInfo {
+            port_name: name.to_string(),
+            port_type: SerialPortType::UsbPort(UsbPortInfo {
+                vid,
+                pid,
+                serial_number: None,
+                manufacturer: Some("QinHeng Electronics".to_string()),
+                product: None,
+            }),
+        };
+        let ports = vec![
+            SerialPortInfo {
+                port_name: "/dev/ttyS0".to_string(),
+                port_type: SerialPortType::Unknown,
+            },
+            usb_port("/dev/ttyUSB1", 0x0403, 0x6001),
+            usb_port("/dev/ttyUSB0", 0x1a86, 0x7523),
+            usb_port("/dev/ttyACM0", 0x303a, 0x1001),
+        ];
+        let devices = match_devices(ports, &boards);
+        let found: Vec<_> = devices.iter().map(|d| (d.port_name.as_str(), d.board.as_deref())).collect();
+        assert_eq!(
+            found,
+            vec![
+                ("/dev/ttyACM0", Some("ESP32-C6-DevKitM-1")),
+                ("/dev/ttyUSB0", Some("Arduino Uno")),
+                ("/dev/ttyS0", None),
+                ("/dev/ttyUSB1", None),
+            ]
+        );
+        assert_eq!(devices[1].usb_id, Some(UsbId { vid: 0x1a86, pid: 0x7523 }));
+        assert_eq!(devices[3].label(), "/dev/ttyUSB1 (QinHeng Electronics)");
+
+        let probes = parse_probe_list(
+            "The following debug probes were found:\n[0]: STLink V2 -- 0483:3748:066DFF (ST-LINK)\n[1]: CMSIS-DAP -- 2e8a:000c:E6614C311B (CMSIS-DAP)\n",
+        );
+        assert_eq!(probes.len(), 2);
+        assert_eq!((probes[1].name.as_str(), probes[1].selector.as_str()), ("CMSIS-DAP", "2e8a:000c:E6614C311B"));
+
+        assert_eq!(parse_progress("   Compiling blinky v0.1.0 (/home/me/blinky)"), (Some("Building"), None));
+        assert_eq!(parse_progress("Writing | #########################"), (Some("Writing"), Some(0.5)));
+        assert_eq!(parse_progress("Reading | ################################################## | 100% 0.20s"), (Some("Reading"), Some(1.0)));
+        assert_eq!(parse_progress("[00:00:02] [=========>            ]      54/218     0x10000"), (None, Some(54.0 / 218.0)));
+        assert_eq!(parse_progress("      Erasing ✔ [00:00:00] [####] 8.00 KiB/32.00 KiB @ 40 KiB/s"), (Some("Erasing"), Some(0.25)));
+        assert!(flash::is_port_busy("Error: espflash::serial_error\n  × Device or resource busy"));
+        assert!(!flash::is_port_busy("Flashing has completed!"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_flash_runs_as_a_managed_process_and_pauses_the_serial_monitor() {
+        use crate::app::flash::{Flash, FlashStatus};
+        use crate::app::serial::SerialMonitor;
+        use serialport::{SerialPort, TTYPort};
+        use std::io::Write;
+        use std::time::{Duration, Instant};
+
+        // a flasher drawing its bar with carriage returns, then finding the port taken
+        let script = "printf 'Connecting...\\n'; printf 'Writing | #####\\rWriting | ##########'; sleep 0.2; \
+                      printf '\\n\\033[31mError: Device or resource busy\\033[0m\\n'; exit 1";
+        let dir = std::env::temp_dir();
+        let mut flash = Flash::start(&dir, "sh", &["-c".to_string(), script.to_string()], Some("/dev/ttyACM0".to_string()))
+            .unwrap();
+        let start = Instant::now();
+        let mut saw_progress = false;
+        while flash.is_running() {
+            assert!(start.elapsed() < Duration::from_secs(10), "the flasher never finished");
+            flash.poll();
+            saw_progress |= flash.progress.as_ref().is_some_and(|p| p.stage == "Writing" && p.fraction == Some(0.2));
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(saw_progress);
+        assert_eq!(flash.status, FlashStatus::Failed);
+        assert!(flash.port_busy);
+        assert!(flash.finished_at.is_some());
+        assert_eq!(flash.device.as_deref(), Some("/dev/ttyACM0"));
+        // the redrawn bar is kept once, and the escapes are gone
+        assert_eq!(
+            &flash.output[1..],
+            &["Connecting...", "Writing | ##########", "Error: Device or resource busy", "Flashing failed"]
+        );
+
+        let (mut board, host) = TTYPort::pair().unwrap();
+        let host_name = host.name().unwrap();
+        let mut monitor = SerialMonitor::attach(Box::new(host), &host_name).unwrap();
+        monitor.pause();
+        assert!(monitor.is_paused() && !monitor.is_open());
+        assert!(monitor.send("ping", crate::app::serial::LineEnding::Lf).is_err());
+        monitor.resume().unwrap();
+        assert!(monitor.is_open() && !monitor.is_paused());
+        board.write_all(b"back\n").unwrap();
+        let start = Instant::now();
+        while monitor.lines.last().is_none_or(|l| l.text != "back") {
+            assert!(start.elapsed() < Duration::from_secs(5), "nothing arrived after resuming");
+            monitor.poll();
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let notes: Vec<_> = monitor.lines.iter().filter(|l| l.direction == crate::app::serial::Direction::Note).collect();
+        assert_eq!(notes.len(), 2);
+    }
+}
@@ -3,11 +3,13 @@ use std::fs;
 use std::path::PathBuf;
 
 #[derive(Serialize, Deserialize)]
+#[serde(default)]
 pub struct IDE_Settings {
     pub colorscheme_file: Option<String>,
     pub syntect_highlighting_file: Option<String>,
     pub last_opened_project: Option<PathBuf>,
-    pub opened_files: Vec<PathBuf>,
+    /// Recently opened project directories, most recent first
+    pub recent_projects: Vec<PathBuf>,
     pub default_terminal: Option<PathBuf>,
 }
 
@@ -32,7 +34,7 @@ impl Default for IDE_Settings {
             syntect_highlighting_file: Some("base16-ocean".to_string()),
             colorscheme_file: None,
             last_opened_project: None,
-            opened_files: Vec::new(),
+            recent_projects: Vec::new(),
             default_terminal: None,
         }
     }
@@ -1,8 +1,16 @@
 #![allow(warnings)]
 mod colorschemes;
+pub mod build;
+pub mod flash;
 pub mod icons;
 mod ide_settings;
 mod keybinding;
+pub mod plotter;
+pub mod recovery;
+pub mod serial;
+pub mod session;
+pub mod size_report;
+pub mod tasks;
 mod shared_state;
 mod syntax_highlighting;
 pub use shared_state::SharedState;
@@ -28,3 +36,6 @@ pub mod command;
 pub use command::{Command, CommandHistory, AddProtocolConnectionCommand, RemoveProtocolConnectionCommand};
 
 mod canvas_element;
+
+/// Name the app runs under, which also names the directory eframe keeps its state in
+pub const APP_NAME: &str = "IRON CODER";
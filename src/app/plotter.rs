@@ -0,0 +1,229 @@
+//! Numeric telemetry parsed out of serial output, for the Serial Plotter
+//!
+//! Boards print one sample per line, either as named values (`temp:23.4,hum:40`, or with `=`)
+//! or as bare comma separated numbers. A CSV header line such as `temp,hum` names the columns
+//! of the lines after it; otherwise unnamed columns are called by their position.
+use chrono::{DateTime, Local};
+
+use std::fmt::Write;
+
+use crate::app::serial::{Direction, SerialLine, SerialMonitor};
+
+/// Samples kept; older ones are dropped
+const MAX_SAMPLES: usize = 20_000;
+
+/// One line's worth of values, in the order they were printed
+/// Values without a name are keyed by their column, counting from 1
+pub fn parse_sample(line: &str) -> Option<Vec<(String, f64)>> {
+    // fields are separated by commas, semicolons or tabs, or by spaces if there are none of those
+    let fields: Vec<&str> = if line.contains([',', ';', '\t']) {
+        line.split([',', ';', '\t']).map(str::trim).filter(|f| !f.is_empty()).collect()
+    } else {
+        line.split_whitespace().collect()
+    };
+    if fields.is_empty() {
+        return None;
+    }
+    let mut values = vec![];
+    for (column, field) in fields.iter().enumerate() {
+        let (name, value) = match field.split_once([':', '=']) {
+            Some((name, value)) => (name.trim().to_string(), value.trim()),
+            None => ((column + 1).to_string(), *field),
+        };
+        match value.parse::<f64>() {
+            Ok(value) if value.is_finite() => values.push((name, value)),
+            // one bad field means the line isn't telemetry, such as a log message with a number in it
+            _ => return None,
+        }
+    }
+    Some(values)
+}
+
+/// Column names from a CSV header line, if the line is one
+fn parse_header(line: &str) -> Option<Vec<String>> {
+    let names: Vec<String> = line.split(',').map(|f| f.trim().to_string()).collect();
+    let is_name = |n: &String| {
+        n.starts_with(|c: char| c.is_alphabetic() || c == '_')
+            && n.chars().all(|c| c.is_alphanumeric() || "_-()%/.".contains(c))
+    };
+    (names.len() > 1 && names.iter().all(is_name)).then_some(names)
+}
+
+/// A named stream of values, one per sample (None where a sample didn't include it)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Series {
+    pub name: String,
+    pub values: Vec<Option<f64>>,
+    pub visible: bool,
+}
+
+/// Every sample parsed from a serial monitor's output
+#[derive(Debug, Default)]
+pub struct Plotter {
+    /// Seconds since the first sample, for each sample
+    pub times: Vec<f64>,
+    pub series: Vec<Series>,
+    start: Option<DateTime<Local>>,
+    header: Option<Vec<String>>,
+    /// Monitor lines already looked at, counted the way `SerialMonitor::total_lines` counts
+    seen: usize,
+    /// Port the lines came from, so a new connection starts a new plot
+    port_name: Option<String>,
+}
+
+impl Plotter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take in the lines the monitor received since the last call
+    pub fn update(&mut self, monitor: &SerialMonitor) {
+        if self.port_name.as_deref() != Some(monitor.port_name.as_str()) || monitor.total_lines() < self.seen {
+            self.clear();
+            self.port_name = Some(monitor.port_name.clone());
+        }
+        for line in monitor.lines_since(self.seen) {
+            if line.direction == Direction::Received {
+                self.push(line);
+            }
+        }
+        self.seen = monitor.total_lines();
+    }
+
+    /// Add a line of output, if it holds a sample or names the columns of later ones
+    pub fn push(&mut self, line: &SerialLine) {
+        if let Some(header) = parse_header(&line.text) {
+            self.header = Some(header);
+            return;
+        }
+        let Some(values) = parse_sample(&line.text) else {
+            return;
+        };
+        let start = *self.start.get_or_insert(line.time);
+        let time = (line.time - start).num_microseconds().unwrap_or(0) as f64 / 1e6;
+        self.times.push(time);
+        for (column, (name, value)) in values.into_iter().enumerate() {
+            let name = match (&self.header, name.parse::<usize>()) {
+                (Some(header), Ok(_)) => header.get(column).cloned().unwrap_or(name),
+                _ => name,
+            };
+            let index = match self.series.iter().position(|s| s.name == name) {
+                Some(index) => index,
+                None => {
+                    self.series.push(Series {
+                        name,
+                        values: vec![None; self.times.len() - 1],
+                        visible: true,
+                    });
+                    self.series.len() - 1
+                }
+            };
+            let series = &mut self.series[index];
+            // a name repeated on one line keeps its last value
+            if series.values.len() == self.times.len() {
+                series.values.pop();
+            }
+            series.values.push(Some(value));
+        }
+        for series in &mut self.series {
+            series.values.resize(self.times.len(), None);
+        }
+        if self.times.len() > MAX_SAMPLES {
+            let excess = self.times.len() - MAX_SAMPLES;
+            self.times.drain(..excess);
+            for series in &mut self.series {
+                series.values.drain(..excess);
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.times.clear();
+        self.series.clear();
+        self.start = None;
+        self.header = None;
+    }
+
+    /// Time of the newest sample
+    pub fn latest_time(&self) -> Option<f64> {
+        self.times.last().copied()
+    }
+
+    /// Every sample as CSV, with a time column in seconds and a column per series
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("time_s");
+        for series in &self.series {
+            csv.push(',');
+            csv.push_str(&csv_field(&series.name));
+        }
+        csv.push('\n');
+        for (i, time) in self.times.iter().enumerate() {
+            write!(csv, "{:.3}", time).ok();
+            for series in &self.series {
+                csv.push(',');
+                if let Some(value) = series.values[i] {
+                    write!(csv, "{}", value).ok();
+                }
+            }
+            csv.push('\n');
+        }
+        csv
+    }
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_serial_plotter_parses_named_and_csv_samples() {
+        use crate::app::plotter::{Plotter, parse_sample};
+        use crate::app::serial::{Direction, SerialLine};
+        use chrono::{Local, TimeDelta};
+
+        assert_eq!(
+            parse_sample("temp:23.4,hum:40"),
+            Some(vec![("temp".to_string(), 23.4), ("hum".to_string(), 40.0)])
+        );
+        assert_eq!(
+            parse_sample("x = 1; y = -2.5e1"),
+            Some(vec![("x".to_string(), 1.0), ("y".to_string(), -25.0)])
+        );
+        assert_eq!(parse_sample("1 2"), Some(vec![("1".to_string(), 1.0), ("2".to_string(), 2.0)]));
+        assert_eq!(parse_sample("Booting in 3 seconds"), None);
+        assert_eq!(parse_sample(""), None);
+
+        let start = Local::now();
+        let line = |ms: i64, text: &str| SerialLine {
+            time: start + TimeDelta::milliseconds(ms),
+            direction: Direction::Received,
+            text: text.to_string(),
+        };
+        let mut plotter = Plotter::new();
+        plotter.push(&line(0, "temp:20,hum:40"));
+        plotter.push(&line(100, "ready"));
+        plotter.push(&line(500, "temp:21.5"));
+        // a header names the unnamed columns after it
+        plotter.push(&line(600, "temp,light"));
+        plotter.push(&line(1000, "22,300"));
+        assert_eq!(plotter.times, vec![0.0, 0.5, 1.0]);
+        let names: Vec<_> = plotter.series.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["temp", "hum", "light"]);
+        assert_eq!(plotter.series[0].values, vec![Some(20.0), Some(21.5), Some(22.0)]);
+        assert_eq!(plotter.series[1].values, vec![Some(40.0), None, None]);
+        assert_eq!(plotter.series[2].values, vec![None, None, Some(300.0)]);
+        assert_eq!(plotter.latest_time(), Some(1.0));
+        assert_eq!(
+            plotter.to_csv(),
+            "time_s,temp,hum,light\n0.000,20,40,\n0.500,21.5,,\n1.000,22,,300\n"
+        );
+        plotter.clear();
+        assert!(plotter.times.is_empty() && plotter.series.is_empty());
+    }
+}
@@ -0,0 +1,246 @@
+//! Crash recovery for unsaved work
+//!
+//! Dirty project and file buffers are periodically stashed in the user's recovery directory,
+//! each running instance in a directory of its own. Saving a file discards its stash, and a
+//! clean exit discards the instance's directory, so a directory whose instance stopped checking
+//! in holds work that was lost in a crash.
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::app::APP_NAME;
+use crate::project::atomic_write::write_atomic;
+
+/// How often dirty buffers are stashed
+pub const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// An instance that hasn't checked in for this long is taken to have crashed
+const ABANDONED_AFTER: Duration = Duration::from_secs(3 * AUTOSAVE_INTERVAL.as_secs());
+
+const RECOVERY_DIR_NAME: &str = "recovery";
+
+/// Touched by every autosave to show the instance is still running
+const HEARTBEAT_FILE: &str = "heartbeat";
+
+/// Get the per-user directory machine-specific IDE state goes in, alongside eframe's own
+/// Falls back to the resources directory on platforms without one
+pub(crate) fn user_data_dir() -> PathBuf {
+    eframe::storage_dir(APP_NAME).unwrap_or_else(|| PathBuf::from("./resources"))
+}
+
+/// Name this process' stashes are kept under; the start time tells apart processes that
+/// were given the same id after a crash
+fn instance_id() -> &'static str {
+    static ID: OnceLock<String> = OnceLock::new();
+    ID.get_or_init(|| format!("{}-{}", std::process::id(), millis_since_epoch(SystemTime::now())))
+}
+
+/// A stashed copy of an unsaved buffer
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RecoveryEntry {
+    /// The file the buffer belongs to
+    pub original: PathBuf,
+    /// When the buffer was stashed, in milliseconds since the Unix epoch
+    pub saved_at: u64,
+    pub contents: String,
+}
+
+impl RecoveryEntry {
+    /// Check if the stash holds work newer than the file on disk
+    pub fn is_newer_than_original(&self) -> bool {
+        match fs::metadata(&self.original).and_then(|m| m.modified()) {
+            Ok(modified) => self.saved_at > millis_since_epoch(modified),
+            Err(_) => true,
+        }
+    }
+}
+
+pub struct RecoveryStore {
+    /// Directory holding the stashes of every instance
+    root: PathBuf,
+    /// This instance's stashes
+    dir: PathBuf,
+}
+
+impl Default for RecoveryStore {
+    fn default() -> Self {
+        Self::new(&user_data_dir().join(RECOVERY_DIR_NAME))
+    }
+}
+
+impl RecoveryStore {
+    pub fn new(root: &Path) -> Self {
+        Self::for_instance(root, instance_id())
+    }
+
+    fn for_instance(root: &Path, instance: &str) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            dir: root.join(instance),
+        }
+    }
+
+    /// Record that this instance is still running, so others leave its stashes alone
+    pub fn heartbeat(&self) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        write_atomic(&self.dir.join(HEARTBEAT_FILE), &[])
+    }
+
+    /// Stash the current contents of an unsaved buffer
+    pub fn stash(&self, original: &Path, contents: &str) -> io::Result<()> {
+        self.heartbeat()?;
+        let entry = RecoveryEntry {
+            original: original.to_path_buf(),
+            saved_at: millis_since_epoch(SystemTime::now()),
+            contents: contents.to_string(),
+        };
+        let toml_str = toml::to_string(&entry).map_err(io::Error::other)?;
+        write_atomic(&self.entry_path(original), toml_str.as_bytes())
+    }
+
+    /// Forget the stash of a buffer, e.g. once it has been saved
+    pub fn discard(&self, original: &Path) {
+        let path = self.entry_path(original);
+        if path.exists() {
+            if let Err(e) = fs::remove_file(&path) {
+                warn!("couldn't remove recovery file {}: {:?}", path.display(), e);
+            }
+        }
+    }
+
+    /// Forget every stash of this instance
+    pub fn discard_all(&self) {
+        if self.dir.exists() {
+            if let Err(e) = fs::remove_dir_all(&self.dir) {
+                warn!("couldn't remove recovery directory {}: {:?}", self.dir.display(), e);
+            }
+        }
+    }
+
+    /// Forget the stashes of crashed instances, once they were recovered or declined
+    pub fn discard_abandoned(&self) {
+        for dir in self.abandoned_dirs() {
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+
+    /// Get the stashes crashed instances left holding work newer than their files on disk
+    /// Stashes that are older than their files were superseded by a save and are discarded
+    pub fn pending(&self) -> Vec<RecoveryEntry> {
+        let mut entries = Vec::new();
+        for path in self.abandoned_dirs().iter().flat_map(|dir| entry_paths(dir)) {
+            let entry: Option<RecoveryEntry> = fs::read_to_string(&path)
+                .ok()
+                .and_then(|s| toml::from_str(&s).ok());
+            match entry {
+                Some(entry) if entry.is_newer_than_original() => entries.push(entry),
+                _ => {
+                    info!("discarding stale recovery file {}", path.display());
+                    let _ = fs::remove_file(path);
+                }
+            }
+        }
+        entries.sort_by(|a, b| a.original.cmp(&b.original));
+        entries
+    }
+
+    /// Directories of other instances that stopped checking in
+    fn abandoned_dirs(&self) -> Vec<PathBuf> {
+        let Ok(read_dir) = fs::read_dir(&self.root) else {
+            return vec![];
+        };
+        read_dir
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|dir| dir.is_dir() && *dir != self.dir)
+            .filter(|dir| {
+                let last_seen = fs::metadata(dir.join(HEARTBEAT_FILE)).and_then(|m| m.modified());
+                match last_seen.map(|t| t.elapsed()) {
+                    Ok(Ok(elapsed)) => elapsed > ABANDONED_AFTER,
+                    // a heartbeat from the future belongs to a running instance with a skewed clock
+                    Ok(Err(_)) => false,
+                    Err(_) => true,
+                }
+            })
+            .collect()
+    }
+
+    /// Stashes are named after a hash of the original path, so each file has at most one
+    fn entry_path(&self, original: &Path) -> PathBuf {
+        self.dir.join(format!("{:016x}.toml", path_hash(original)))
+    }
+}
+
+fn entry_paths(dir: &Path) -> Vec<PathBuf> {
+    let Ok(read_dir) = fs::read_dir(dir) else {
+        return vec![];
+    };
+    read_dir
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|ext| ext == "toml"))
+        .collect()
+}
+
+/// Hash a path with FNV-1a, which unlike the std hasher is stable between builds
+pub(crate) fn path_hash(path: &Path) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in path.to_string_lossy().bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+fn millis_since_epoch(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_recovery_stash_is_pending_until_the_file_is_saved() {
+        let temp = crate::test::temp_dir();
+        let original = temp.path().join("main.rs");
+        std::fs::write(&original, "fn main() {}\n").unwrap();
+        let root = temp.path().join("recovery");
+
+        let set_modified = |path: &Path, time: SystemTime| {
+            std::fs::File::options().write(true).open(path).unwrap().set_modified(time).unwrap();
+        };
+        let crashed = RecoveryStore::for_instance(&root, "crashed");
+        let current = RecoveryStore::for_instance(&root, "current");
+        set_modified(&original, SystemTime::now() - Duration::from_secs(60));
+        crashed.stash(&original, "fn main() { unsaved(); }").unwrap();
+        current.stash(&original, "fn main() { mine(); }").unwrap();
+
+        // nothing to recover while the other instance keeps checking in
+        assert!(current.pending().is_empty());
+        set_modified(&root.join("crashed").join(HEARTBEAT_FILE), SystemTime::now() - ABANDONED_AFTER * 2);
+        let pending = current.pending();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].original, original);
+        assert_eq!(pending[0].contents, "fn main() { unsaved(); }");
+
+        // a clean exit only takes this instance's stashes with it
+        current.discard_all();
+        assert!(!root.join("current").exists());
+        assert_eq!(current.pending().len(), 1);
+
+        // saved since the stash was taken, so there is nothing to recover
+        set_modified(&original, SystemTime::now() + Duration::from_secs(60));
+        assert!(current.pending().is_empty());
+
+        current.discard_abandoned();
+        assert!(!root.join("crashed").exists());
+    }
+}
@@ -0,0 +1,477 @@
+//! A serial connection to a board, with everything it has said since it was opened
+//!
+//! The port is read on a background thread, which hands whatever arrives to the UI in chunks.
+//! `SerialMonitor::poll` splits those into lines, stamped with the time their first byte
+//! arrived, and appends them to the log file if one is being written.
+use chrono::{DateTime, Local};
+use log::{info, warn};
+use serialport::SerialPort;
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, channel};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+pub const BAUD_RATES: &[u32] = &[9600, 19200, 38400, 57600, 115200, 230400, 460800, 921600];
+/// Baud rate for boards that don't say which they use
+pub const DEFAULT_BAUD_RATE: u32 = 115200;
+/// Lines kept in memory; older ones are dropped, though the log file keeps them
+const MAX_LINES: usize = 10_000;
+const READ_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// What ends a line sent to the board
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    None,
+    Lf,
+    Cr,
+    CrLf,
+}
+
+impl LineEnding {
+    pub const ALL: [LineEnding; 4] = [LineEnding::None, LineEnding::Lf, LineEnding::Cr, LineEnding::CrLf];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::None => "",
+            LineEnding::Lf => "\n",
+            LineEnding::Cr => "\r",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            LineEnding::None => "No line ending",
+            LineEnding::Lf => "Newline (LF)",
+            LineEnding::Cr => "Carriage return (CR)",
+            LineEnding::CrLf => "Both (CR LF)",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Received,
+    Sent,
+    /// Written by the monitor itself, such as when the connection pauses
+    Note,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SerialLine {
+    pub time: DateTime<Local>,
+    pub direction: Direction,
+    pub text: String,
+}
+
+impl SerialLine {
+    /// The line as it is written to the log file
+    pub fn to_log_line(&self, timestamps: bool) -> String {
+        let marker = match self.direction {
+            Direction::Received | Direction::Note => "",
+            Direction::Sent => "> ",
+        };
+        if timestamps {
+            format!("[{}] {}{}", self.time.format("%H:%M:%S%.3f"), marker, self.text)
+        } else {
+            format!("{}{}", marker, self.text)
+        }
+    }
+}
+
+enum SerialEvent {
+    Data(Vec<u8>),
+    Closed(String),
+}
+
+/// An open serial port being read in the background
+pub struct SerialMonitor {
+    pub port_name: String,
+    pub baud_rate: u32,
+    pub lines: Vec<SerialLine>,
+    /// Why the connection ended, if it ended by itself
+    pub error: Option<String>,
+    /// Text of a line that hasn't ended yet, and when it started
+    partial: Option<(DateTime<Local>, Vec<u8>)>,
+    /// Lines pushed since the port was opened, including ones since dropped from `lines`
+    total_lines: usize,
+    /// The port, while it's open
+    writer: Option<Box<dyn SerialPort>>,
+    events: Receiver<SerialEvent>,
+    stop: Arc<AtomicBool>,
+    reader: Option<JoinHandle<()>>,
+    open: bool,
+    /// Closed for now so another program can use the port, to be reopened later
+    paused: bool,
+    log: Option<(PathBuf, File)>,
+    log_timestamps: bool,
+}
+
+impl SerialMonitor {
+    /// Open a serial port by name
+    pub fn open(port_name: &str, baud_rate: u32) -> io::Result<Self> {
+        let port = serialport::new(port_name, baud_rate)
+            .timeout(READ_TIMEOUT)
+            .open()
+            .map_err(io::Error::from)?;
+        Self::attach(port, port_name)
+    }
+
+    /// Monitor a port that is already open, such as one end of a pseudo-terminal
+    pub fn attach(port: Box<dyn SerialPort>, port_name: &str) -> io::Result<Self> {
+        let baud_rate = port.baud_rate().unwrap_or(DEFAULT_BAUD_RATE);
+        let (_, events) = channel();
+        let mut monitor = SerialMonitor {
+            port_name: port_name.to_string(),
+            baud_rate,
+            lines: vec![],
+            error: None,
+            partial: None,
+            total_lines: 0,
+            writer: None,
+            events,
+            stop: Arc::new(AtomicBool::new(false)),
+            reader: None,
+            open: false,
+            paused: false,
+            log: None,
+            log_timestamps: true,
+        };
+        monitor.start_reading(port)?;
+        info!("opened serial port {} at {} baud", port_name, baud_rate);
+        Ok(monitor)
+    }
+
+    /// Read the port on a background thread and keep it for writing
+    fn start_reading(&mut self, mut port: Box<dyn SerialPort>) -> io::Result<()> {
+        port.set_timeout(READ_TIMEOUT).map_err(io::Error::from)?;
+        let mut reader = port.try_clone().map_err(io::Error::from)?;
+        let (tx, rx) = channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_reader = Arc::clone(&stop);
+        let handle = thread::spawn(move || {
+            let mut buf = [0u8; 1024];
+            while !stop_reader.load(Ordering::Relaxed) {
+                match reader.read(&mut buf) {
+                    Ok(0) => {}
+                    Ok(n) => {
+                        if tx.send(SerialEvent::Data(buf[..n].to_vec())).is_err() {
+                            return;
+                        }
+                    }
+                    Err(e) if matches!(e.kind(), io::ErrorKind::TimedOut | io::ErrorKind::Interrupted) => {}
+                    Err(e) => {
+                        tx.send(SerialEvent::Closed(e.to_string())).ok();
+                        return;
+                    }
+                }
+            }
+        });
+        self.writer = Some(port);
+        self.events = rx;
+        self.stop = stop;
+        self.reader = Some(handle);
+        self.open = true;
+        self.paused = false;
+        self.error = None;
+        Ok(())
+    }
+
+    /// Stop the reader and let go of the port
+    fn release(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // the reader gives up its handle on the port within a read timeout
+        if let Some(reader) = self.reader.take() {
+            reader.join().ok();
+        }
+        self.writer = None;
+        self.open = false;
+    }
+
+    /// Take in what arrived since the last poll
+    /// Returns true if anything changed
+    pub fn poll(&mut self) -> bool {
+        let mut changed = false;
+        while let Ok(event) = self.events.try_recv() {
+            changed = true;
+            match event {
+                SerialEvent::Data(data) => self.receive(&data),
+                SerialEvent::Closed(e) => {
+                    warn!("serial port {} closed: {}", self.port_name, e);
+                    self.error = Some(e);
+                    self.open = false;
+                }
+            }
+        }
+        changed
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn close(&mut self) {
+        self.release();
+        self.paused = false;
+    }
+
+    /// Let go of the port so another program, such as a flasher, can open it
+    /// Everything received so far is kept for when the port is reopened with `resume`
+    pub fn pause(&mut self) {
+        if self.open {
+            self.release();
+            self.paused = true;
+            self.push_note("paused while the port is in use");
+        }
+    }
+
+    /// Reopen a paused port
+    pub fn resume(&mut self) -> io::Result<()> {
+        let port = serialport::new(&self.port_name, self.baud_rate)
+            .timeout(READ_TIMEOUT)
+            .open()
+            .map_err(io::Error::from)?;
+        self.start_reading(port)?;
+        self.push_note("resumed");
+        Ok(())
+    }
+
+    /// Send text to the board, followed by the line ending
+    pub fn send(&mut self, text: &str, ending: LineEnding) -> io::Result<()> {
+        let writer = self
+            .writer
+            .as_mut()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "the port isn't open"))?;
+        writer.write_all(text.as_bytes())?;
+        writer.write_all(ending.as_str().as_bytes())?;
+        writer.flush()?;
+        self.push_line(SerialLine {
+            time: Local::now(),
+            direction: Direction::Sent,
+            text: text.to_string(),
+        });
+        Ok(())
+    }
+
+    /// Text received since the last complete line
+    pub fn partial_line(&self) -> Option<String> {
+        self.partial
+            .as_ref()
+            .map(|(_, bytes)| String::from_utf8_lossy(bytes).to_string())
+    }
+
+    /// Count of lines since the port was opened, so readers can tell which lines are new
+    /// even after old ones are dropped or cleared
+    pub fn total_lines(&self) -> usize {
+        self.total_lines
+    }
+
+    /// Lines that arrived after the first `seen` of `total_lines`, as far as they are still kept
+    pub fn lines_since(&self, seen: usize) -> &[SerialLine] {
+        let new = self.total_lines.saturating_sub(seen).min(self.lines.len());
+        &self.lines[self.lines.len() - new..]
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+    }
+
+    /// Append every line from now on to a file
+    pub fn start_log(&mut self, path: &Path, timestamps: bool) -> io::Result<()> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        self.log = Some((path.to_path_buf(), file));
+        self.log_timestamps = timestamps;
+        Ok(())
+    }
+
+    pub fn stop_log(&mut self) {
+        self.log = None;
+    }
+
+    pub fn log_path(&self) -> Option<&Path> {
+        self.log.as_ref().map(|(path, _)| path.as_path())
+    }
+
+    fn receive(&mut self, data: &[u8]) {
+        for &byte in data {
+            let (_, line) = self.partial.get_or_insert_with(|| (Local::now(), vec![]));
+            if byte != b'\n' {
+                line.push(byte);
+                continue;
+            }
+            let (time, mut line) = self.partial.take().unwrap_or_default();
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+            self.push_line(SerialLine {
+                time,
+                direction: Direction::Received,
+                text: String::from_utf8_lossy(&line).to_string(),
+            });
+        }
+    }
+
+    /// Note something about the connection among the lines, such as it pausing
+    fn push_note(&mut self, note: &str) {
+        self.push_line(SerialLine {
+            time: Local::now(),
+            direction: Direction::Note,
+            text: format!("-- {} {} --", self.port_name, note),
+        });
+    }
+
+    fn push_line(&mut self, line: SerialLine) {
+        if let Some((path, file)) = &mut self.log {
+            if let Err(e) = writeln!(file, "{}", line.to_log_line(self.log_timestamps)) {
+                warn!("stopped logging serial output to {}: {}", path.display(), e);
+                self.log = None;
+            }
+        }
+        self.lines.push(line);
+        self.total_lines += 1;
+        if self.lines.len() > MAX_LINES {
+            let excess = self.lines.len() - MAX_LINES;
+            self.lines.drain(..excess);
+        }
+    }
+}
+
+impl Drop for SerialMonitor {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+/// Previously sent lines, walked through with the arrow keys
+#[derive(Debug, Default)]
+pub struct SendHistory {
+    entries: Vec<String>,
+    /// Entry being shown, counted back from the newest
+    position: Option<usize>,
+}
+
+impl SendHistory {
+    const MAX_ENTRIES: usize = 100;
+
+    pub fn push(&mut self, text: &str) {
+        self.position = None;
+        if text.is_empty() || self.entries.last().is_some_and(|last| last == text) {
+            return;
+        }
+        self.entries.push(text.to_string());
+        if self.entries.len() > Self::MAX_ENTRIES {
+            self.entries.remove(0);
+        }
+    }
+
+    /// Step back to an older entry
+    pub fn previous(&mut self) -> Option<&str> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        let position = self.position.map_or(0, |p| (p + 1).min(self.entries.len() - 1));
+        self.position = Some(position);
+        self.entries.get(self.entries.len() - 1 - position).map(String::as_str)
+    }
+
+    /// Step forward to a newer entry, or to an empty line past the newest
+    pub fn next(&mut self) -> Option<&str> {
+        match self.position? {
+            0 => {
+                self.position = None;
+                Some("")
+            }
+            p => {
+                self.position = Some(p - 1);
+                self.entries.get(self.entries.len() - p).map(String::as_str)
+            }
+        }
+    }
+}
+
+/// Get the names of the serial ports on this computer
+pub fn available_ports() -> Vec<String> {
+    match serialport::available_ports() {
+        Ok(ports) => {
+            let mut names: Vec<String> = ports.into_iter().map(|p| p.port_name).collect();
+            names.sort();
+            names
+        }
+        Err(e) => {
+            warn!("couldn't list serial ports: {}", e);
+            vec![]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(unix)]
+    #[test]
+    fn test_serial_monitor_reads_lines_sends_and_logs_over_a_pty() {
+        use crate::app::serial::{Direction, LineEnding, SendHistory, SerialMonitor};
+        use serialport::{SerialPort, TTYPort};
+        use std::io::{Read, Write};
+        use std::time::{Duration, Instant};
+
+        let (mut board, host) = TTYPort::pair().expect("couldn't open a pseudo-terminal pair");
+        board.set_timeout(Duration::from_secs(2)).unwrap();
+        let mut monitor = SerialMonitor::attach(Box::new(host), "pty").unwrap();
+        let log_dir = crate::test::temp_dir();
+        let log_path = log_dir.path().join("serial.log");
+        monitor.start_log(&log_path, false).unwrap();
+        assert_eq!(monitor.log_path(), Some(log_path.as_path()));
+
+        let wait_for = |monitor: &mut SerialMonitor, done: &dyn Fn(&SerialMonitor) -> bool| {
+            let start = Instant::now();
+            while !done(monitor) {
+                assert!(start.elapsed() < Duration::from_secs(5), "timed out waiting for serial data");
+                monitor.poll();
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        };
+        board.write_all(b"hello\r\nwor").unwrap();
+        wait_for(&mut monitor, &|m| m.partial_line().as_deref() == Some("wor"));
+        assert_eq!(monitor.lines.len(), 1);
+        assert_eq!(monitor.lines[0].text, "hello");
+        board.write_all(b"ld\n").unwrap();
+        wait_for(&mut monitor, &|m| m.lines.len() == 2);
+        assert_eq!(monitor.lines[1].text, "world");
+        assert_eq!(monitor.total_lines(), 2);
+        assert_eq!(monitor.lines_since(1), &monitor.lines[1..]);
+        assert_eq!(monitor.partial_line(), None);
+
+        monitor.send("ping", LineEnding::CrLf).unwrap();
+        let mut sent = [0u8; 6];
+        board.read_exact(&mut sent).unwrap();
+        assert_eq!(&sent, b"ping\r\n");
+        assert_eq!(monitor.lines[2].direction, Direction::Sent);
+        assert!(monitor.lines[2].to_log_line(true).ends_with("] > ping"));
+
+        monitor.stop_log();
+        monitor.close();
+        assert!(!monitor.is_open());
+        assert_eq!(std::fs::read_to_string(&log_path).unwrap(), "hello\nworld\n> ping\n");
+
+        let mut history = SendHistory::default();
+        history.push("first");
+        history.push("second");
+        history.push("second");
+        assert_eq!(history.previous(), Some("second"));
+        assert_eq!(history.previous(), Some("first"));
+        // the oldest entry stays put
+        assert_eq!(history.previous(), Some("first"));
+        assert_eq!(history.next(), Some("second"));
+        assert_eq!(history.next(), Some(""));
+        assert_eq!(history.next(), None);
+    }
+}
@@ -0,0 +1,150 @@
+//! Per-project workspace sessions, restored when a project is opened again
+//!
+//! A session records the open files with their cursor and scroll positions, the dock
+//! layout and the canvas view. Sessions are machine-specific, so they are kept in the user's
+//! data directory rather than in the project directory, one file per project path.
+use egui_dock::DockState;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::app::recovery::{path_hash, user_data_dir};
+use crate::project::atomic_write::write_atomic;
+
+const SESSION_DIR_NAME: &str = "sessions";
+
+/// An open file and where the user was in it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileSession {
+    pub path: PathBuf,
+    /// Cursor position as a character index
+    pub cursor: Option<usize>,
+    pub scroll: [f32; 2],
+}
+
+/// Zoom and pan of the canvas
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CanvasView {
+    pub zoom: f32,
+    pub offset: [f32; 2],
+}
+
+/// The workspace of a project as it was last left
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct Session {
+    pub open_files: Vec<FileSession>,
+    /// Name of the tab that had focus
+    pub active_tab: Option<String>,
+    /// Dock layout, holding tab names; file tabs are named by their path
+    pub layout: Option<DockState<String>>,
+    pub canvas: Option<CanvasView>,
+}
+
+/// Where sessions are kept
+pub struct SessionStore {
+    dir: PathBuf,
+}
+
+impl Default for SessionStore {
+    fn default() -> Self {
+        Self::new(&user_data_dir().join(SESSION_DIR_NAME))
+    }
+}
+
+impl SessionStore {
+    pub fn new(dir: &Path) -> Self {
+        SessionStore {
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    /// Save the session of the project in a directory, replacing the previous one
+    pub fn save(&self, project_dir: &Path, session: &Session) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        // RON rather than JSON, as the dock layout holds infinite rects that JSON can't represent
+        let text = ron::ser::to_string_pretty(session, ron::ser::PrettyConfig::default())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        write_atomic(&self.session_path(project_dir), text.as_bytes())?;
+        info!("saved session for {}", project_dir.display());
+        Ok(())
+    }
+
+    /// Load the last session of the project in a directory, if there is one
+    pub fn load(&self, project_dir: &Path) -> Option<Session> {
+        let path = self.session_path(project_dir);
+        let text = fs::read_to_string(&path).ok()?;
+        match ron::from_str(&text) {
+            Ok(session) => Some(session),
+            Err(e) => {
+                warn!("ignoring unreadable session {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    /// Forget the session of a project, such as after it was moved
+    pub fn discard(&self, project_dir: &Path) {
+        let _ = fs::remove_file(self.session_path(project_dir));
+    }
+
+    fn session_path(&self, project_dir: &Path) -> PathBuf {
+        let project_dir = project_dir.canonicalize().unwrap_or_else(|_| project_dir.to_path_buf());
+        self.dir.join(format!("{:016x}.ron", path_hash(&project_dir)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::tabs::file_tab::FileTab;
+    use egui_dock::DockState;
+
+    #[test]
+    fn test_session_round_trip() {
+        let temp = crate::test::temp_dir();
+        let project_dir = temp.path().join("project");
+        std::fs::create_dir_all(project_dir.join("src")).unwrap();
+        let main_rs = project_dir.join("src/main.rs");
+        std::fs::write(&main_rs, "fn main() {\n    loop {}\n}\n").unwrap();
+
+        let store = SessionStore::new(&temp.path().join("sessions"));
+        assert!(store.load(&project_dir).is_none());
+
+        let main_rs_name = main_rs.display().to_string();
+        let session = Session {
+            open_files: vec![FileSession {
+                path: main_rs.clone(),
+                cursor: Some(12),
+                scroll: [0.0, 40.0],
+            }],
+            active_tab: Some(main_rs_name.clone()),
+            layout: Some(DockState::new(vec!["Canvas".to_string(), main_rs_name.clone()])),
+            canvas: Some(CanvasView {
+                zoom: 1.5,
+                offset: [10.0, -20.0],
+            }),
+        };
+        store.save(&project_dir, &session).unwrap();
+
+        let loaded = store.load(&project_dir).unwrap();
+        assert_eq!(loaded.open_files, session.open_files);
+        assert_eq!(loaded.active_tab, session.active_tab);
+        assert_eq!(loaded.canvas, session.canvas);
+        let tabs: Vec<String> = loaded.layout.unwrap().iter_all_tabs().map(|(_, t)| t.clone()).collect();
+        assert_eq!(tabs, vec!["Canvas".to_string(), main_rs_name]);
+
+        // the cursor is clamped to the file and reported back as it was restored
+        let mut tab = FileTab::default();
+        tab.load_from_file(&main_rs).unwrap();
+        tab.restore_view(Some(1000), egui::vec2(0.0, 40.0));
+        let (cursor, scroll) = tab.view();
+        assert_eq!(cursor, Some(std::fs::read_to_string(&main_rs).unwrap().chars().count()));
+        assert_eq!(scroll, egui::vec2(0.0, 40.0));
+
+        store.discard(&project_dir);
+        assert!(store.load(&project_dir).is_none());
+    }
+}
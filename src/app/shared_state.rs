@@ -1,22 +1,34 @@
+use crate::app::build::Build;
+use crate::app::flash::{self, Flash};
+use crate::app::serial::{self, SerialMonitor};
 use crate::app::canvas_board::CanvasBoard;
 use crate::app::colorschemes::{self, colorscheme};
 use crate::app::command::CommandHistory;
 use crate::app::connection_wizard::ConnectionWizard;
 use crate::app::ide_settings::{self, IDE_Settings};
 use crate::app::keybinding::{Keybinding, Keybindings};
+use crate::app::session::CanvasView;
+use crate::app::size_report::MemoryCapacity;
 use crate::app::syntax_highlighting::SyntaxHighlighter;
-use crate::board::{self, Board};
+use crate::app::tabs::start_tab::{self, StartPageRequest};
+use crate::app::tasks::{TaskContext, TaskError, TaskManager};
+use crate::board::{self, Board, BuildProfile, DeviceKind};
+use crate::dependencies::checker::{self, DependencyChecker, DependencyReport};
+use crate::dependencies::redist::ToolchainManager;
 use crate::project::Project;
+use crate::snippets::{self, Snippet};
 
 use crate::app::CanvasConnection;
 use eframe::glow::LINE;
 use egui_term::{BackendCommand, TerminalBackend};
+use log::warn;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::ffi::OsStr;
 use std::io::BufRead;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::time::Duration;
 use uuid::Uuid;
 
 #[cfg(target_os = "windows")]
@@ -24,6 +36,12 @@ pub const LINE_ENDING: &str = "\r\n";
 #[cfg(not(target_os = "windows"))]
 pub const LINE_ENDING: &str = "\n";
 
+/// How long to keep trying to reopen the serial monitor's port after flashing
+const MONITOR_RESUME_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub const LOADING_BOARDS: &str = "Loading boards";
+pub const CHECKING_DEPENDENCIES: &str = "Checking dependencies";
+
 pub struct SharedState {
     pub default_terminal: Option<PathBuf>,
     
@@ -35,6 +53,7 @@ pub struct SharedState {
     pub known_boards: Vec<Rc<Board>>,
     pub output_terminal_backend: Option<Rc<RefCell<TerminalBackend>>>,
     pub connection_wizard: Option<ConnectionWizard>,
+    pub snippets: Vec<Snippet>,
     
     // tab flags & buffers
     pub requested_file_to_open: Option<PathBuf>,
@@ -42,24 +61,55 @@ pub struct SharedState {
     pub reset_canvas: bool,
     pub sync_file_explorer: bool,
     pub did_activate_colorscheme: bool,
+    pub snippet_to_insert: Option<String>,
+    pub last_focused_file: Option<PathBuf>,
+    /// Board example to create a new project from
+    pub requested_example: Option<(Rc<Board>, PathBuf)>,
+    /// Canvas zoom and pan from a restored session, applied by the canvas tab
+    pub canvas_view_to_restore: Option<CanvasView>,
+    /// Recently opened project directories, most recent first
+    pub recent_projects: Vec<PathBuf>,
+    /// What the start page asked for
+    pub start_page_request: Option<StartPageRequest>,
+    /// Main board that Build and Flash act on; the project's first main board when unset
+    pub build_target: Option<Uuid>,
+    /// The last build, which may still be running
+    pub build: Option<Build>,
+    /// Open serial connection of the Serial Monitor, polled every frame
+    pub serial_monitor: Option<SerialMonitor>,
+    /// The last flash, which may still be running
+    pub flash: Option<Flash>,
+    /// Serial port or probe to flash through; found from the board's USB IDs when unset
+    pub flash_device: Option<String>,
+    /// File position to open, as a 1-based line and column, such as a build diagnostic's
+    pub requested_file_position: Option<(PathBuf, usize, usize)>,
+    /// Work running off the UI thread, listed in the status bar
+    pub tasks: TaskManager,
+    /// Project the IDE was left with, opened once the boards have loaded
+    pub project_to_open: Option<PathBuf>,
+    /// Set when a project finishes loading in the background, for the window to reopen its files
+    pub project_loaded: bool,
+    /// What the last dependency check found, or None until one has finished
+    pub dependency_reports: Option<Vec<DependencyReport>>,
+    dependency_check: Option<TaskContext>,
 }
 
 impl SharedState {
     pub fn default() -> Self {
         use egui::epaint::color;
 
-        let boards_dir = Path::new("./iron-coder-boards");
-        let known_boards = board::get_boards(boards_dir);
+        let snippets = snippets::load_snippets(Path::new("./assets/code-snippets"), "Iron Coder");
 
-        let mut project = Project::default();
+        let project = Project::default();
         let last_settings = ide_settings::load_ide_settings();
-        if let Some(location) = last_settings.last_opened_project {
-            project.location = Some(location.clone());
-            match project.load_from(&location, &known_boards) {
-                Ok(_) => (),
-                Err(e) => println!("Error loading project! {:?}", e),
-            }
-        }
+        let recent_projects = last_settings.recent_projects;
+        // the last project can only be opened once the boards it uses are known
+        let mut tasks = TaskManager::new();
+        tasks.spawn(
+            LOADING_BOARDS,
+            |_| board::load_boards(Path::new("./iron-coder-boards")),
+            |state: &mut SharedState, boards| state.boards_loaded(boards),
+        );
         let mut colorschemes = colorscheme::default();
 
         if let Some(scheme_name) = last_settings.colorscheme_file {
@@ -104,13 +154,31 @@ impl SharedState {
             syntax_highlighter,
             project,
             requested_file_to_open: None,
-            known_boards,
+            known_boards: vec![],
             output_terminal_backend: None,
             default_terminal: Some(default_terminal),
             reset_canvas: false,
             sync_file_explorer: false,
             connection_wizard: None,
             command_history: CommandHistory::new(),
+            snippets,
+            snippet_to_insert: None,
+            last_focused_file: None,
+            requested_example: None,
+            canvas_view_to_restore: None,
+            recent_projects,
+            start_page_request: None,
+            build_target: None,
+            build: None,
+            serial_monitor: None,
+            flash: None,
+            flash_device: None,
+            requested_file_position: None,
+            tasks,
+            project_to_open: last_settings.last_opened_project,
+            project_loaded: false,
+            dependency_reports: None,
+            dependency_check: None,
         };
 
         // Update all wire colors to match the loaded colorscheme
@@ -119,6 +187,69 @@ impl SharedState {
         state
     }
 
+    fn boards_loaded(&mut self, boards: Result<Vec<Board>, TaskError>) {
+        match boards {
+            Ok(boards) => self.known_boards = boards.into_iter().map(Rc::new).collect(),
+            Err(e) => {
+                warn!("loading boards {}", e);
+                return;
+            }
+        }
+        let Some(location) = self.project_to_open.take() else {
+            return;
+        };
+        self.project.location = Some(location.clone());
+        match self.project.load_from(&location, &self.known_boards) {
+            Ok(_) => {
+                start_tab::remember_project(&mut self.recent_projects, &location);
+                self.update_all_wire_colors_to_match_colorscheme();
+                self.project_loaded = true;
+            }
+            Err(e) => warn!("couldn't load project {}: {}", location.display(), e),
+        }
+    }
+
+    /// Apply the results of background tasks that have finished
+    /// Returns true while any are still running
+    pub fn poll_tasks(&mut self) -> bool {
+        for completion in self.tasks.take_finished() {
+            completion(self);
+        }
+        self.tasks.is_busy()
+    }
+
+    /// Check what the project's main boards need in the background, replacing a check still running
+    pub fn check_dependencies(&mut self) {
+        if let Some(check) = self.dependency_check.take() {
+            check.cancel();
+        }
+        let boards = self.main_board_profiles();
+        let check = self.tasks.spawn(
+            CHECKING_DEPENDENCIES,
+            move |context| {
+                let requirements = checker::requirements_for(&boards, ToolchainManager::locate().as_ref());
+                let total = requirements.len().max(1);
+                let mut checked = 0;
+                let mut dependency_checker = DependencyChecker::new();
+                dependency_checker.check_with(requirements, |program, args| {
+                    if context.is_cancelled() {
+                        return None;
+                    }
+                    context.set_progress(Some(checked as f32 / total as f32), program.display().to_string());
+                    checked += 1;
+                    checker::run_command(program, args)
+                });
+                dependency_checker.reports
+            },
+            |state: &mut SharedState, reports| match reports {
+                Ok(reports) => state.dependency_reports = Some(reports),
+                Err(TaskError::Cancelled) => {}
+                Err(e) => warn!("checking dependencies {}", e),
+            },
+        );
+        self.dependency_check = Some(check);
+    }
+
     pub fn term_open_project_dir(&mut self) {
         if let Some(term_ref) = &self.output_terminal_backend {
             let mut term = term_ref.borrow_mut();
@@ -174,43 +305,136 @@ impl SharedState {
         }
     }
 
+    /// Get the main board Build and Flash act on
+    pub fn build_target_board(&self) -> Option<Rc<RefCell<CanvasBoard>>> {
+        self.build_target
+            .and_then(|id| self.project.main_boards_iter().find(|b| b.borrow().id == id).cloned())
+            .or_else(|| self.project.main_board.clone())
+    }
+
+    /// Get the firmware crate of the board Build and Flash act on
+    pub fn build_target_dir(&self) -> Option<PathBuf> {
+        let target = self.build_target_board()?.borrow().id;
+        self.project.firmware_crate_dir(&target)
+    }
+
+    /// Start building the build target's crate, replacing any build still running
     pub fn build_project(&mut self) {
-        if let Some(term_ref) = &self.output_terminal_backend {
-            let mut term = term_ref.borrow_mut();
-            self.project.update_toolchain_location();
-            term.process_command(BackendCommand::Write(vec![0x03]));
-            term.process_command(BackendCommand::Write(
-                format!("\n{}{}", "cargo +nightly build", LINE_ENDING)
-                    .as_bytes()
-                    .to_vec(),
-            ));
+        self.project.update_toolchain_location();
+        let (Some(board), Some(crate_dir)) = (self.build_target_board(), self.build_target_dir()) else {
+            println!("Open a project with a main board to build it");
+            return;
+        };
+        if let Some(build) = self.build.as_mut() {
+            build.cancel();
+        }
+        let args = board.borrow().board.build_profile().build_args();
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        match Build::start(&crate_dir, &args) {
+            Ok(mut build) => {
+                let board = &board.borrow().board;
+                build.capacity = MemoryCapacity::from_kb(board.flash(), board.ram());
+                self.tasks.track(build.context().clone());
+                self.build = Some(build);
+            }
+            Err(e) => println!("Failed to start cargo: {}", e),
+        }
+    }
+
+    /// Build the build target's crate and flash it, replacing any flash still running
+    /// The serial monitor lets go of its port while the flasher runs, and takes it back after
+    pub fn flash_project(&mut self) {
+        self.project.update_toolchain_location();
+        let (Some(board), Some(crate_dir)) = (self.build_target_board(), self.build_target_dir()) else {
+            println!("Open a project with a main board to flash it");
+            return;
+        };
+        if let Some(flash) = self.flash.as_mut() {
+            flash.cancel();
+        }
+        let profile = board.borrow().board.build_profile().clone();
+        let board_name = board.borrow().board.get_name().to_string();
+        let device = match profile.runner.and_then(|r| r.device_kind()) {
+            // without a choice, a port the board's USB ID was found on; otherwise the runner looks
+            Some(DeviceKind::SerialPort) => self.flash_device.clone().or_else(|| {
+                flash::detect_devices(&[(board_name, profile.clone())])
+                    .into_iter()
+                    .find(|d| d.board.is_some())
+                    .map(|d| d.port_name)
+            }),
+            Some(DeviceKind::Probe) => self.flash_device.clone(),
+            None => None,
+        };
+        if let Some(monitor) = self.serial_monitor.as_mut() {
+            monitor.pause();
+        }
+        let args = profile.run_args(device.as_deref());
+        match Flash::start(&crate_dir, "cargo", &args, device) {
+            Ok(flash) => {
+                self.tasks.track(flash.context().clone());
+                self.flash = Some(flash);
+            }
+            Err(e) => println!("Failed to start cargo: {}", e),
+        }
+    }
+
+    /// Take in the flasher's output, reopening the serial monitor's port once it's done
+    /// Returns true while there's still something to wait for
+    pub fn poll_flash(&mut self) -> bool {
+        let Some(flash) = self.flash.as_mut() else {
+            return false;
+        };
+        flash.poll();
+        if flash.is_running() {
+            return true;
+        }
+        let Some(monitor) = self.serial_monitor.as_mut().filter(|m| m.is_paused()) else {
+            return false;
+        };
+        // a board that resets after flashing takes a moment to bring its port back
+        let waited = flash.finished_at.map_or(Duration::ZERO, |t| t.elapsed());
+        match monitor.resume() {
+            Ok(()) => false,
+            Err(_) if waited < MONITOR_RESUME_TIMEOUT => true,
+            Err(e) => {
+                monitor.error = Some(format!("couldn't reopen {} after flashing: {}", monitor.port_name, e));
+                monitor.close();
+                false
+            }
         }
     }
 
-    pub fn run_project(&mut self) {
+    /// Interrupt whatever the output terminal is running and run a command in it instead
+    pub fn run_in_terminal(&self, command: &str) {
         if let Some(term_ref) = &self.output_terminal_backend {
             let mut term = term_ref.borrow_mut();
-            // if project uses main board arduino, select --target atmega328p.json
-            // if let Some(board) = self.project.main_board.as_ref() {
-            //     if board.borrow().board.cpu.as_deref() == Some("arduino-uno") {
-            //         term.process_command(BackendCommand::Write(
-            //             format!("cargo +nightly run {}", LINE_ENDING).as_bytes().to_vec(),
-            //         ));
-            //     } else {
-            //         term.process_command(BackendCommand::Write(
-            //             format!("cargo +nightly run --target avr-atmega328p.json{}", LINE_ENDING).as_bytes().to_vec(),
-            //         ));
-            //     }
-            // }
             term.process_command(BackendCommand::Write(vec![0x03]));
             term.process_command(BackendCommand::Write(
-                format!("cargo +nightly run{}", LINE_ENDING)
+                format!("{}{}", command, LINE_ENDING)
                     .as_bytes()
                     .to_vec(),
             ));
         }
     }
 
+    /// Get the baud rate the build target's firmware talks over serial at
+    pub fn default_baud_rate(&self) -> u32 {
+        self.build_target_board()
+            .and_then(|b| b.borrow().board.build_profile().baud_rate)
+            .unwrap_or(serial::DEFAULT_BAUD_RATE)
+    }
+
+    /// Get the name and build profile of every main board in the project
+    pub fn main_board_profiles(&self) -> Vec<(String, BuildProfile)> {
+        self.project
+            .main_boards_iter()
+            .map(|b| {
+                let b = b.borrow();
+                (b.board.get_name().to_string(), b.board.build_profile().clone())
+            })
+            .collect()
+    }
+
     pub fn save_settings(&self) {
         let settings = IDE_Settings {
             syntect_highlighting_file: Some(
@@ -218,11 +442,19 @@ impl SharedState {
             ),
             colorscheme_file: Some(self.colorschemes.name.clone()),
             last_opened_project: self.project.location.clone(),
-            opened_files: Vec::new(), // Future feature
+            recent_projects: self.recent_projects.clone(),
             default_terminal: self.default_terminal.clone(),
         };
         ide_settings::save_ide_settings(&settings);
     }
+    /// Put the open project at the top of the recent projects and save the settings
+    pub fn remember_project(&mut self) {
+        if let Some(location) = self.project.get_location_path() {
+            start_tab::remember_project(&mut self.recent_projects, &location);
+        }
+        self.save_settings();
+    }
+
     pub fn get_ide_installation_path() -> PathBuf {
         if let Some(proj_dir) = std::env::current_exe()
             .ok()
@@ -0,0 +1,533 @@
+//! How much of a board's flash and RAM a built firmware image uses, read from its ELF file
+//!
+//! Sizes are counted the way `cargo size` and `avr-size` count them: flash holds every
+//! allocated section with contents (code, read-only data and the initial values of `.data`),
+//! and RAM holds every writable allocated section (`.data`, `.bss` and the like). Only the
+//! section and symbol tables are read, so 32 and 64-bit files of either byte order will do.
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Usage above this fraction of a board's capacity is warned about
+pub const USAGE_WARNING_THRESHOLD: f32 = 0.85;
+
+const SHT_SYMTAB: u32 = 2;
+const SHT_NOBITS: u32 = 8;
+const SHF_WRITE: u64 = 0x1;
+const SHF_ALLOC: u64 = 0x2;
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+
+#[derive(Debug)]
+pub enum ElfError {
+    Io(io::Error),
+    /// The file doesn't start with the ELF magic number
+    NotElf,
+    /// A header or table runs past the end of the file
+    Truncated,
+}
+
+impl fmt::Display for ElfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ElfError::Io(e) => write!(f, "{}", e),
+            ElfError::NotElf => write!(f, "not an ELF file"),
+            ElfError::Truncated => write!(f, "ELF file is truncated"),
+        }
+    }
+}
+
+impl From<io::Error> for ElfError {
+    fn from(e: io::Error) -> Self {
+        ElfError::Io(e)
+    }
+}
+
+/// A section of the image that takes up flash, RAM or both
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section {
+    pub name: String,
+    pub size: u64,
+    pub in_flash: bool,
+    pub in_ram: bool,
+}
+
+/// A function or static, with its name demangled
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub size: u64,
+    pub in_flash: bool,
+    pub in_ram: bool,
+}
+
+/// A board's memory, in bytes, as its manifest gives it
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MemoryCapacity {
+    pub flash: Option<u64>,
+    pub ram: Option<u64>,
+}
+
+impl MemoryCapacity {
+    /// Capacity from the KB figures in a board manifest
+    pub fn from_kb(flash: Option<isize>, ram: Option<isize>) -> Self {
+        let bytes = |kb: Option<isize>| kb.filter(|kb| *kb > 0).map(|kb| kb as u64 * 1024);
+        MemoryCapacity {
+            flash: bytes(flash),
+            ram: bytes(ram),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SizeReport {
+    pub sections: Vec<Section>,
+    /// Largest first
+    pub symbols: Vec<Symbol>,
+    pub flash_used: u64,
+    pub ram_used: u64,
+}
+
+impl SizeReport {
+    pub fn read(path: &Path) -> Result<SizeReport, ElfError> {
+        SizeReport::parse(&fs::read(path)?)
+    }
+
+    pub fn parse(data: &[u8]) -> Result<SizeReport, ElfError> {
+        let elf = Elf::new(data)?;
+        let sections = elf.section_headers()?;
+        let names = sections.get(elf.shstrndx).map(|s| s.contents(data)).transpose()?;
+        let name_of = |offset: u32, table: Option<&[u8]>| {
+            table
+                .and_then(|t| t.get(offset as usize..))
+                .map(|t| String::from_utf8_lossy(t.split(|b| *b == 0).next().unwrap_or_default()).to_string())
+                .unwrap_or_default()
+        };
+
+        let mut report = SizeReport {
+            sections: vec![],
+            symbols: vec![],
+            flash_used: 0,
+            ram_used: 0,
+        };
+        for header in &sections {
+            let (in_flash, in_ram) = header.placement();
+            if (in_flash || in_ram) && header.size > 0 {
+                report.flash_used += if in_flash { header.size } else { 0 };
+                report.ram_used += if in_ram { header.size } else { 0 };
+                report.sections.push(Section {
+                    name: name_of(header.name, names),
+                    size: header.size,
+                    in_flash,
+                    in_ram,
+                });
+            }
+        }
+
+        for symtab in sections.iter().filter(|s| s.kind == SHT_SYMTAB) {
+            let strings = sections.get(symtab.link as usize).map(|s| s.contents(data)).transpose()?;
+            for symbol in elf.symbols(symtab)? {
+                let Some(section) = sections.get(symbol.section as usize) else {
+                    continue;
+                };
+                let (in_flash, in_ram) = section.placement();
+                if symbol.size == 0 || !matches!(symbol.kind, STT_OBJECT | STT_FUNC) || !(in_flash || in_ram) {
+                    continue;
+                }
+                report.symbols.push(Symbol {
+                    name: demangle(&name_of(symbol.name, strings)),
+                    size: symbol.size,
+                    in_flash,
+                    in_ram,
+                });
+            }
+        }
+        report.symbols.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+        // aliases of one symbol show up once
+        report.symbols.dedup();
+        Ok(report)
+    }
+
+    /// Fraction of the board's flash and RAM used, where the board says how much it has
+    pub fn usage(&self, capacity: MemoryCapacity) -> (Option<f32>, Option<f32>) {
+        let fraction = |used: u64, capacity: Option<u64>| capacity.map(|c| used as f32 / c as f32);
+        (fraction(self.flash_used, capacity.flash), fraction(self.ram_used, capacity.ram))
+    }
+
+    /// Warnings for memory used past the threshold
+    pub fn warnings(&self, capacity: MemoryCapacity, threshold: f32) -> Vec<String> {
+        let (flash, ram) = self.usage(capacity);
+        [("flash", flash), ("RAM", ram)]
+            .into_iter()
+            .filter_map(|(memory, usage)| {
+                let usage = usage.filter(|u| *u >= threshold)?;
+                Some(if usage > 1.0 {
+                    format!("the firmware doesn't fit: it needs {:.0}% of the board's {}", usage * 100.0, memory)
+                } else {
+                    format!("the firmware uses {:.0}% of the board's {}", usage * 100.0, memory)
+                })
+            })
+            .collect()
+    }
+
+    /// One line per memory, such as "Flash: 1.2 KB of 32 KB (4%)"
+    pub fn summary(&self, capacity: MemoryCapacity) -> Vec<String> {
+        vec![
+            format!("Flash: {}", describe_usage(self.flash_used, capacity.flash)),
+            format!("RAM: {}", describe_usage(self.ram_used, capacity.ram)),
+        ]
+    }
+}
+
+/// Bytes used, out of the capacity if it's known, such as "1.2 KB of 32 KB (4%)"
+pub fn describe_usage(used: u64, capacity: Option<u64>) -> String {
+    match capacity {
+        Some(capacity) => format!(
+            "{} of {} ({:.0}%)",
+            format_bytes(used),
+            format_bytes(capacity),
+            used as f32 / capacity as f32 * 100.0
+        ),
+        None => format_bytes(used),
+    }
+}
+
+/// Bytes as B, KB or MB, whichever reads best
+pub fn format_bytes(bytes: u64) -> String {
+    match bytes {
+        0..1024 => format!("{} B", bytes),
+        1024..1_048_576 => format!("{:.1} KB", bytes as f64 / 1024.0),
+        _ => format!("{:.2} MB", bytes as f64 / 1_048_576.0),
+    }
+}
+
+/// Turn a legacy Rust symbol such as `_ZN4core3fmt5write17h0123456789abcdefE` into
+/// `core::fmt::write`; anything else comes back as it was
+pub fn demangle(name: &str) -> String {
+    let Some(mut rest) = name.strip_prefix("_ZN").or_else(|| name.strip_prefix("__ZN")) else {
+        return name.to_string();
+    };
+    let mut parts = vec![];
+    while !rest.starts_with('E') {
+        let digits = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+        let Ok(length) = rest[..digits].parse::<usize>() else {
+            return name.to_string();
+        };
+        rest = &rest[digits..];
+        let Some(part) = rest.get(..length) else {
+            return name.to_string();
+        };
+        parts.push(part);
+        rest = &rest[length..];
+    }
+    if parts
+        .last()
+        .is_some_and(|p| p.len() == 17 && p.starts_with('h') && p[1..].chars().all(|c| c.is_ascii_hexdigit()))
+    {
+        parts.pop();
+    }
+    const ESCAPES: &[(&str, &str)] = &[
+        ("$LT$", "<"),
+        ("$GT$", ">"),
+        ("$RF$", "&"),
+        ("$BP$", "*"),
+        ("$LP$", "("),
+        ("$RP$", ")"),
+        ("$C$", ","),
+        ("$u20$", " "),
+        ("$u27$", "'"),
+        ("$u5b$", "["),
+        ("$u5d$", "]"),
+        ("$u7b$", "{"),
+        ("$u7d$", "}"),
+        ("$u7e$", "~"),
+        ("..", "::"),
+    ];
+    parts
+        .iter()
+        .map(|part| {
+            let mut part = part.strip_prefix("_$").map_or(part.to_string(), |p| format!("${}", p));
+            for (escape, text) in ESCAPES {
+                part = part.replace(escape, text);
+            }
+            part
+        })
+        .collect::<Vec<_>>()
+        .join("::")
+}
+
+/// The parts of an ELF file's layout the report needs
+struct Elf<'a> {
+    data: &'a [u8],
+    is_64: bool,
+    little_endian: bool,
+    shoff: u64,
+    shentsize: usize,
+    shnum: usize,
+    shstrndx: usize,
+}
+
+struct SectionHeader {
+    name: u32,
+    kind: u32,
+    flags: u64,
+    offset: u64,
+    size: u64,
+    link: u32,
+    entsize: u64,
+}
+
+impl SectionHeader {
+    /// Whether the section takes up flash and RAM
+    fn placement(&self) -> (bool, bool) {
+        let alloc = self.flags & SHF_ALLOC != 0;
+        (
+            alloc && self.kind != SHT_NOBITS,
+            alloc && self.flags & SHF_WRITE != 0,
+        )
+    }
+
+    fn contents<'a>(&self, data: &'a [u8]) -> Result<&'a [u8], ElfError> {
+        data.get(self.offset as usize..(self.offset + self.size) as usize)
+            .ok_or(ElfError::Truncated)
+    }
+}
+
+struct ElfSymbol {
+    name: u32,
+    kind: u8,
+    section: u16,
+    size: u64,
+}
+
+impl<'a> Elf<'a> {
+    fn new(data: &'a [u8]) -> Result<Elf<'a>, ElfError> {
+        if data.get(..4) != Some(b"\x7fELF") {
+            return Err(ElfError::NotElf);
+        }
+        let mut elf = Elf {
+            data,
+            is_64: data.get(4) == Some(&2),
+            little_endian: data.get(5) != Some(&2),
+            shoff: 0,
+            shentsize: 0,
+            shnum: 0,
+            shstrndx: 0,
+        };
+        let fields = if elf.is_64 { 0x28 } else { 0x20 };
+        elf.shoff = elf.word(fields)?;
+        let halves = fields + if elf.is_64 { 0x12 } else { 0x0e };
+        elf.shentsize = elf.u16(halves)? as usize;
+        elf.shnum = elf.u16(halves + 2)? as usize;
+        elf.shstrndx = elf.u16(halves + 4)? as usize;
+        Ok(elf)
+    }
+
+    fn section_headers(&self) -> Result<Vec<SectionHeader>, ElfError> {
+        (0..self.shnum)
+            .map(|i| {
+                let at = self.shoff as usize + i * self.shentsize;
+                Ok(if self.is_64 {
+                    SectionHeader {
+                        name: self.u32(at)?,
+                        kind: self.u32(at + 4)?,
+                        flags: self.u64(at + 8)?,
+                        offset: self.u64(at + 24)?,
+                        size: self.u64(at + 32)?,
+                        link: self.u32(at + 40)?,
+                        entsize: self.u64(at + 56)?,
+                    }
+                } else {
+                    SectionHeader {
+                        name: self.u32(at)?,
+                        kind: self.u32(at + 4)?,
+                        flags: self.u32(at + 8)? as u64,
+                        offset: self.u32(at + 16)? as u64,
+                        size: self.u32(at + 20)? as u64,
+                        link: self.u32(at + 24)?,
+                        entsize: self.u32(at + 36)? as u64,
+                    }
+                })
+            })
+            .collect()
+    }
+
+    fn symbols(&self, symtab: &SectionHeader) -> Result<Vec<ElfSymbol>, ElfError> {
+        let entsize = match symtab.entsize {
+            0 if self.is_64 => 24,
+            0 => 16,
+            size => size,
+        };
+        (0..symtab.size / entsize)
+            .map(|i| {
+                let at = (symtab.offset + i * entsize) as usize;
+                Ok(if self.is_64 {
+                    ElfSymbol {
+                        name: self.u32(at)?,
+                        kind: self.u8(at + 4)? & 0xf,
+                        section: self.u16(at + 6)?,
+                        size: self.u64(at + 16)?,
+                    }
+                } else {
+                    ElfSymbol {
+                        name: self.u32(at)?,
+                        kind: self.u8(at + 12)? & 0xf,
+                        section: self.u16(at + 14)?,
+                        size: self.u32(at + 8)? as u64,
+                    }
+                })
+            })
+            .collect()
+    }
+
+    fn bytes<const N: usize>(&self, at: usize) -> Result<[u8; N], ElfError> {
+        let bytes: [u8; N] = self
+            .data
+            .get(at..at + N)
+            .and_then(|b| b.try_into().ok())
+            .ok_or(ElfError::Truncated)?;
+        Ok(bytes)
+    }
+
+    fn u8(&self, at: usize) -> Result<u8, ElfError> {
+        self.data.get(at).copied().ok_or(ElfError::Truncated)
+    }
+
+    fn u16(&self, at: usize) -> Result<u16, ElfError> {
+        let bytes = self.bytes(at)?;
+        Ok(if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    }
+
+    fn u32(&self, at: usize) -> Result<u32, ElfError> {
+        let bytes = self.bytes(at)?;
+        Ok(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    fn u64(&self, at: usize) -> Result<u64, ElfError> {
+        let bytes = self.bytes(at)?;
+        Ok(if self.little_endian {
+            u64::from_le_bytes(bytes)
+        } else {
+            u64::from_be_bytes(bytes)
+        })
+    }
+
+    /// An address-sized field
+    fn word(&self, at: usize) -> Result<u64, ElfError> {
+        if self.is_64 { self.u64(at) } else { self.u32(at).map(u64::from) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    #[test]
+    fn test_size_report_reads_elf_sections_and_symbols() {
+        use crate::app::build::{ParsedLine, parse_line};
+        use crate::app::size_report::{ElfError, MemoryCapacity, SizeReport, USAGE_WARNING_THRESHOLD, demangle};
+
+        // a little-endian ELF32 image laid out like an AVR one: sections, then their headers
+        let strings = b"\0.text\0.data\0.bss\0.debug_info\0.symtab\0.strtab\0.shstrtab\0".to_vec();
+        let name = |strings: &[u8], n: &str| {
+            strings.windows(n.len()).position(|w| w == n.as_bytes()).unwrap() as u32
+        };
+        let symbol_names = b"\0main\0_ZN6blinky6BUFFER17h0123456789abcdefE\0_ZN4core3fmt5write17hfedcba9876543210E\0";
+        let mut symtab = vec![0u8; 16];
+        for (symbol, size, kind, section) in [
+            ("main", 600u32, 2u8, 1u16),
+            ("_ZN6blinky6BUFFER17h0123456789abcdefE", 256, 1, 3),
+            ("_ZN4core3fmt5write17hfedcba9876543210E", 900, 2, 1),
+        ] {
+            symtab.extend(name(symbol_names, symbol).to_le_bytes());
+            symtab.extend(0u32.to_le_bytes());
+            symtab.extend(size.to_le_bytes());
+            symtab.extend([kind, 0]);
+            symtab.extend(section.to_le_bytes());
+        }
+        let mut elf = vec![0u8; 0x34];
+        elf[..6].copy_from_slice(b"\x7fELF\x01\x01");
+        let text_at = elf.len();
+        elf.extend(vec![0u8; 1600]);
+        let data_at = elf.len();
+        elf.extend(vec![0u8; 40]);
+        let symtab_at = elf.len();
+        elf.extend(&symtab);
+        let symbol_names_at = elf.len();
+        elf.extend(symbol_names);
+        let strings_at = elf.len();
+        elf.extend(&strings);
+        let headers_at = elf.len();
+        // name, type, flags, offset, size, link, entsize
+        let sections: [(&str, u32, u32, usize, usize, u32, u32); 8] = [
+            ("", 0, 0, 0, 0, 0, 0),
+            (".text", 1, 0x6, text_at, 1600, 0, 0),
+            (".data", 1, 0x3, data_at, 40, 0, 0),
+            (".bss", 8, 0x3, 0, 300, 0, 0),
+            (".debug_info", 1, 0, 0, 5000, 0, 0),
+            (".symtab", 2, 0, symtab_at, symtab.len(), 6, 16),
+            (".strtab", 3, 0, symbol_names_at, symbol_names.len(), 0, 0),
+            (".shstrtab", 3, 0, strings_at, strings.len(), 0, 0),
+        ];
+        for (section, kind, flags, offset, size, link, entsize) in sections {
+            let name = if section.is_empty() { 0 } else { name(&strings, section) };
+            for field in [name, kind, flags, 0, offset as u32, size as u32, link, 0, 0, entsize] {
+                elf.extend(field.to_le_bytes());
+            }
+        }
+        elf[0x20..0x24].copy_from_slice(&(headers_at as u32).to_le_bytes());
+        elf[0x2e..0x30].copy_from_slice(&40u16.to_le_bytes());
+        elf[0x30..0x32].copy_from_slice(&8u16.to_le_bytes());
+        elf[0x32..0x34].copy_from_slice(&7u16.to_le_bytes());
+
+        let report = SizeReport::parse(&elf).unwrap();
+        let sections: Vec<_> = report.sections.iter().map(|s| (s.name.as_str(), s.size, s.in_flash, s.in_ram)).collect();
+        assert_eq!(
+            sections,
+            vec![(".text", 1600, true, false), (".data", 40, true, true), (".bss", 300, false, true)]
+        );
+        assert_eq!((report.flash_used, report.ram_used), (1640, 340));
+        let symbols: Vec<_> = report.symbols.iter().map(|s| (s.name.as_str(), s.size)).collect();
+        assert_eq!(symbols, vec![("core::fmt::write", 900), ("main", 600), ("blinky::BUFFER", 256)]);
+        assert!(report.symbols[2].in_ram && !report.symbols[2].in_flash);
+
+        // against an Uno's 32 KB of flash and 2 KB of RAM
+        let uno = MemoryCapacity::from_kb(Some(32), Some(2));
+        assert_eq!(uno, MemoryCapacity { flash: Some(32768), ram: Some(2048) });
+        assert_eq!(report.summary(uno), vec!["Flash: 1.6 KB of 32.0 KB (5%)", "RAM: 340 B of 2.0 KB (17%)"]);
+        assert!(report.warnings(uno, USAGE_WARNING_THRESHOLD).is_empty());
+        let tiny = MemoryCapacity::from_kb(Some(1), None);
+        assert_eq!(
+            report.warnings(tiny, USAGE_WARNING_THRESHOLD),
+            vec!["the firmware doesn't fit: it needs 160% of the board's flash"]
+        );
+        assert_eq!(report.usage(tiny).1, None);
+
+        assert_eq!(demangle("_ZN53_$LT$core..fmt..Error$u20$as$u20$core..fmt..Debug$GT$3fmt17h0123456789abcdefE"), "<core::fmt::Error as core::fmt::Debug>::fmt");
+        assert_eq!(demangle("__vector_1"), "__vector_1");
+        assert!(matches!(SizeReport::parse(b"#!/bin/sh"), Err(ElfError::NotElf)));
+        assert!(matches!(SizeReport::parse(&elf[..0x40]), Err(ElfError::Truncated)));
+
+        // a real, 64-bit image: the test binary itself
+        #[cfg(target_os = "linux")]
+        {
+            let report = SizeReport::read(&std::env::current_exe().unwrap()).unwrap();
+            assert!(report.flash_used > 0 && !report.symbols.is_empty());
+        }
+
+        let artifact = r#"{"reason":"compiler-artifact","package_id":"blinky","executable":"/tmp/blinky/target/avr-none/debug/blinky.elf"}"#;
+        assert_eq!(
+            parse_line(artifact, Path::new("/tmp/blinky")),
+            ParsedLine::Executable("/tmp/blinky/target/avr-none/debug/blinky.elf".into())
+        );
+    }
+}
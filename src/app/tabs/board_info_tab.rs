@@ -34,17 +34,24 @@ impl BaseTab for BoardInfoTab {
         egui::containers::scroll_area::ScrollArea::vertical().show(ui, |ui| {
             ui.columns(num_cols, |columns| {
                 for (i, board) in state.known_boards.clone().into_iter().enumerate() {
-                    if state.project.has_main_board() {
-                        if board.as_ref().is_main_board() {
-                            continue;
-                        }
-                    }
                     let col = i % num_cols;
-                    if columns[col]
+                    let response = columns[col]
                         .add(board::display::BoardSelectorWidget(board.clone()))
-                        .clicked()
-                    {
-                        state.project.add_board(&board);
+                        .on_hover_text("Right-click for details and examples");
+                    response.context_menu(|ui| {
+                        ui.add(board.as_ref().clone());
+                    });
+                    if let Some(example) = display::take_requested_example(columns[col].ctx()) {
+                        state.requested_example = Some((board.clone(), example));
+                    }
+                    if response.clicked() {
+                        // another main board brings its own firmware crate along, once the project is on disk
+                        let adds_firmware = board.is_main_board()
+                            && state.project.has_main_board()
+                            && state.project.location.is_some();
+                        if state.project.add_board(&board).is_some() && adds_firmware {
+                            state.sync_file_explorer = true;
+                        }
                     }
                 }
             });
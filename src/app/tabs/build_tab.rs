@@ -0,0 +1,215 @@
+use crate::app::SharedState;
+use crate::app::build::{BuildStatus, Diagnostic, DiagnosticLevel};
+use crate::app::size_report::{MemoryCapacity, SizeReport, USAGE_WARNING_THRESHOLD, describe_usage, format_bytes};
+use crate::app::tabs::base_tab::BaseTab;
+
+use egui::{Color32, RichText};
+
+const ERROR_COLOR: Color32 = Color32::from_rgb(230, 60, 60);
+const WARNING_COLOR: Color32 = Color32::from_rgb(230, 180, 40);
+const OK_COLOR: Color32 = Color32::from_rgb(80, 190, 90);
+const LARGEST_SYMBOLS_SHOWN: usize = 20;
+
+/// Output and diagnostics of the last cargo build
+pub struct BuildTab {
+    show_warnings: bool,
+}
+
+impl BuildTab {
+    pub fn new() -> Self {
+        Self { show_warnings: true }
+    }
+
+    fn diagnostic_row(ui: &mut egui::Ui, diagnostic: &Diagnostic, crate_dir: &std::path::Path) -> bool {
+        let (label, color) = match diagnostic.level {
+            DiagnosticLevel::Error => ("error", ERROR_COLOR),
+            DiagnosticLevel::Warning => ("warning", WARNING_COLOR),
+        };
+        let code = diagnostic
+            .code
+            .as_ref()
+            .map(|c| format!("[{}]", c))
+            .unwrap_or_default();
+        let location = match &diagnostic.file {
+            Some(file) => {
+                let shown = file.strip_prefix(crate_dir).unwrap_or(file);
+                format!("{}:{}:{}", shown.display(), diagnostic.line, diagnostic.column)
+            }
+            None => String::new(),
+        };
+
+        let mut clicked = false;
+        ui.horizontal(|ui| {
+            ui.label(RichText::new(format!("{}{}", label, code)).color(color).monospace());
+            let text = format!("{}  {}", diagnostic.message, location);
+            if diagnostic.file.is_some() {
+                clicked = ui
+                    .link(text)
+                    .on_hover_text(RichText::new(&diagnostic.rendered).monospace())
+                    .clicked();
+            } else {
+                ui.label(text);
+            }
+        });
+        clicked
+    }
+}
+
+impl BuildTab {
+    fn memory_usage(ui: &mut egui::Ui, report: &SizeReport, capacity: MemoryCapacity) {
+        let (flash, ram) = report.usage(capacity);
+        let memories = [
+            ("Flash", report.flash_used, capacity.flash, flash),
+            ("RAM", report.ram_used, capacity.ram, ram),
+        ];
+        for (memory, used, capacity, usage) in memories {
+            ui.horizontal(|ui| {
+                ui.add_sized([40.0, 0.0], egui::Label::new(memory));
+                let text = describe_usage(used, capacity);
+                match usage {
+                    Some(usage) => {
+                        let color = if usage >= USAGE_WARNING_THRESHOLD {
+                            ERROR_COLOR
+                        } else {
+                            OK_COLOR
+                        };
+                        ui.add(
+                            egui::ProgressBar::new(usage.min(1.0))
+                                .desired_width(240.0)
+                                .fill(color)
+                                .text(text),
+                        );
+                    }
+                    None => {
+                        ui.label(format!("{} (the board doesn't say how much it has)", text));
+                    }
+                }
+            });
+        }
+        for warning in report.warnings(capacity, USAGE_WARNING_THRESHOLD) {
+            ui.label(RichText::new(format!("Warning: {}", warning)).color(WARNING_COLOR));
+        }
+        egui::CollapsingHeader::new(format!("Largest symbols ({} in all)", report.symbols.len()))
+            .id_salt("largest_symbols")
+            .show(ui, |ui| {
+                egui::Grid::new("largest_symbols_grid").striped(true).show(ui, |ui| {
+                    ui.strong("Size");
+                    ui.strong("In");
+                    ui.strong("Symbol");
+                    ui.end_row();
+                    for symbol in report.symbols.iter().take(LARGEST_SYMBOLS_SHOWN) {
+                        ui.label(format_bytes(symbol.size));
+                        ui.label(match (symbol.in_flash, symbol.in_ram) {
+                            (true, true) => "Flash + RAM",
+                            (true, false) => "Flash",
+                            _ => "RAM",
+                        });
+                        ui.label(RichText::new(&symbol.name).monospace());
+                        ui.end_row();
+                    }
+                });
+            });
+    }
+}
+
+impl BaseTab for BuildTab {
+    fn draw(&mut self, ui: &mut egui::Ui, state: &mut SharedState) {
+        let running = state.build.as_ref().is_some_and(|b| b.is_running());
+        ui.horizontal(|ui| {
+            if ui.add_enabled(!running, egui::Button::new("Build")).clicked() {
+                state.build_project();
+            }
+            if ui.add_enabled(running, egui::Button::new("Cancel")).clicked() {
+                if let Some(build) = state.build.as_mut() {
+                    build.cancel();
+                }
+            }
+            ui.checkbox(&mut self.show_warnings, "Show warnings");
+            ui.separator();
+            match &state.build {
+                None => {
+                    ui.label("No build yet");
+                }
+                Some(build) => {
+                    let status = match build.status {
+                        BuildStatus::Running => RichText::new("Building..."),
+                        BuildStatus::Succeeded => RichText::new("Build succeeded").color(OK_COLOR),
+                        BuildStatus::Failed => RichText::new("Build failed").color(ERROR_COLOR),
+                        BuildStatus::Cancelled => RichText::new("Build cancelled"),
+                    };
+                    if running {
+                        ui.spinner();
+                    }
+                    ui.label(status);
+                    ui.label(RichText::new(format!("{} errors", build.count(DiagnosticLevel::Error))).color(ERROR_COLOR));
+                    ui.label(
+                        RichText::new(format!("{} warnings", build.count(DiagnosticLevel::Warning))).color(WARNING_COLOR),
+                    );
+                }
+            }
+        });
+        ui.separator();
+
+        let Some(build) = &state.build else {
+            return;
+        };
+        let mut requested = None;
+        if !build.diagnostics.is_empty() {
+            egui::CollapsingHeader::new("Problems")
+                .default_open(true)
+                .show(ui, |ui| {
+                    egui::ScrollArea::vertical()
+                        .id_salt("build_problems")
+                        .max_height(ui.available_height() / 2.0)
+                        .auto_shrink([false, true])
+                        .show(ui, |ui| {
+                            for diagnostic in &build.diagnostics {
+                                if diagnostic.level == DiagnosticLevel::Warning && !self.show_warnings {
+                                    continue;
+                                }
+                                if Self::diagnostic_row(ui, diagnostic, &build.crate_dir) {
+                                    if let Some(file) = &diagnostic.file {
+                                        requested = Some((file.clone(), diagnostic.line, diagnostic.column));
+                                    }
+                                }
+                            }
+                        });
+                });
+            ui.separator();
+        }
+
+        if let Some(report) = &build.size_report {
+            egui::CollapsingHeader::new("Memory Usage")
+                .default_open(true)
+                .show(ui, |ui| match report {
+                    Ok(report) => Self::memory_usage(ui, report, build.capacity),
+                    Err(e) => {
+                        ui.label(RichText::new(format!("Couldn't read the firmware image: {}", e)).color(ERROR_COLOR));
+                    }
+                });
+            ui.separator();
+        }
+
+        egui::ScrollArea::both()
+            .id_salt("build_output")
+            .auto_shrink([false; 2])
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                for line in &build.output {
+                    ui.label(RichText::new(line).monospace());
+                }
+            });
+
+        if requested.is_some() {
+            state.requested_file_position = requested;
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
@@ -2,6 +2,8 @@ use crate::app::canvas_board::CanvasBoard;
 use crate::app::canvas_element::CanvasSelection;
 use crate::app::colorschemes::debug_once;
 use crate::app::tabs::base_tab::BaseTab;
+use crate::app::session::CanvasView;
+use crate::app::canvas_protocol::format_address;
 use crate::app::{AddProtocolConnectionCommand, CanvasProtocol};
 use crate::app::{SharedState, connection_wizard};
 use crate::app::{canvas_board, canvas_connection::CanvasConnection};
@@ -15,6 +17,7 @@ use syntect::highlighting::Color;
 
 use std::cell::RefCell;
 use std::rc::Rc;
+use uuid::Uuid;
 
 use crate::app::connection_wizard::{ConnectionWizard, WizardResult, WizardState, WizardType};
 use egui_extras::RetainedImage;
@@ -26,6 +29,12 @@ pub struct CanvasTab {
     connection_in_progress: Option<Rc<RefCell<CanvasConnection>>>,
     selection: Option<CanvasSelection>,
     pin_tooltip: Option<(Rc<RefCell<CanvasBoard>>, u32)>,
+    /// Edit buffer for the name of the selected protocol group
+    group_name_edit: Option<(Uuid, String)>,
+    /// Edit buffers for the names of the selected group's bus devices, keyed by board
+    device_name_edits: HashMap<Uuid, String>,
+    /// Main board the connection wizard checks pins against
+    wizard_board: Option<Rc<RefCell<CanvasBoard>>>,
 }
 
 impl CanvasTab {
@@ -36,6 +45,23 @@ impl CanvasTab {
             connection_in_progress: None,
             selection: None,
             pin_tooltip: None,
+            group_name_edit: None,
+            device_name_edits: HashMap::new(),
+            wizard_board: None,
+        }
+    }
+
+    /// Main board a new connection wizard should run against: the selected board when it is a
+    /// main board, the main board of the selected bus, otherwise the project's first main board
+    fn wizard_main_board(&self, state: &SharedState) -> Option<Rc<RefCell<CanvasBoard>>> {
+        match &self.selection {
+            Some(CanvasSelection::Board(b)) if b.borrow().board.is_main_board() => Some(b.clone()),
+            Some(CanvasSelection::ProtocolGroup { group_id, .. })
+            | Some(CanvasSelection::WithinProtocolGroup { group_id, .. }) => state
+                .project
+                .get_protocol_group(group_id)
+                .and_then(|g| state.project.group_controller(g)),
+            _ => state.project.main_board.clone(),
         }
     }
 }
@@ -46,6 +72,9 @@ impl BaseTab for CanvasTab {
             self.reset_canvas();
             state.reset_canvas = false;
         }
+        if let Some(view) = state.canvas_view_to_restore.take() {
+            self.set_view(view);
+        }
 
         // grab mouse location
         let mouse_screen = ui.input(|i| i.pointer.hover_pos()).unwrap_or_default();
@@ -220,7 +249,7 @@ impl BaseTab for CanvasTab {
                 if selected.len() % 2 == 1 {
                     // We started a connection but didn't finish it (odd number)
                     // Just undo one selection to clean up
-                    if let Some(main_board) = &state.project.main_board {
+                    if let Some(main_board) = self.wizard_board.as_ref().or(state.project.main_board.as_ref()) {
                         wizard.handle_undo(&main_board.borrow().board);
                     }
                 }
@@ -337,14 +366,16 @@ impl BaseTab for CanvasTab {
 
                 // Draw the pins for the appropriate roles
                 if sb.board.is_main_board() {
-                    for b in state.project.peripheral_boards.iter() {
+                    // peripherals, or another main board to talk to
+                    let others = state.project.main_boards_iter().filter(|b| b.borrow().id != sb.id);
+                    for b in state.project.peripheral_boards.iter().chain(others) {
                         let cb = b.borrow();
                         for role in &roles_to_show {
                             cb.draw_pins_from_role(ui, &to_screen, role);
                         }
                     }
                 } else {
-                    if let Some(b) = &state.project.main_board {
+                    for b in state.project.main_boards_iter() {
                         for role in &roles_to_show {
                             let cb = b.borrow();
                             cb.draw_pins_from_role(ui, &to_screen, role);
@@ -507,9 +538,8 @@ impl BaseTab for CanvasTab {
                                             WizardType::I2C
                                             | WizardType::SPI
                                             | WizardType::UART => {
-                                                if let Some(main_board_rc) =
-                                                    state.project.main_board.as_mut()
-                                                {
+                                                self.wizard_board = self.wizard_main_board(state);
+                                                if let Some(main_board_rc) = &self.wizard_board {
                                                     let main_board = main_board_rc.borrow();
                                                     // Starts the wizard
                                                     state.connection_wizard =
@@ -641,6 +671,11 @@ impl BaseTab for CanvasTab {
                             }
                         }
 
+                        // Protocol group settings: name and peripheral instance
+                        if state.connection_wizard.is_none() {
+                            self.show_group_settings(ui, state);
+                        }
+
                         // Handle deferred wizard mutations (after borrow ends)
                         if should_exit_review {
                             state.connection_wizard = None;
@@ -648,7 +683,7 @@ impl BaseTab for CanvasTab {
                         }
 
                         if should_start_completing {
-                            if let Some(main_board_rc) = state.project.main_board.as_mut() {
+                            if let Some(main_board_rc) = self.wizard_board.as_ref().or(state.project.main_board.as_ref()) {
                                 let main_board = main_board_rc.borrow();
                                 if let Some(wiz) = state.connection_wizard.as_mut() {
                                     wiz.start_completing_missing(&main_board.board);
@@ -784,13 +819,18 @@ impl BaseTab for CanvasTab {
                             self.pin_tooltip = Some((canvas_board_rc.clone(), pin));
                         } else if button == PointerButton::Primary {
                             clicked_pin = Some(pin);
-                            if self.check_pin_use(canvas_board_rc, &pin, &state.project.connections)
+                            let board_id = canvas_board_rc.borrow().id;
+
+                            // Shared bus lines may fan out to another peripheral during a wizard
+                            let shared_bus_pin = state.connection_wizard.as_ref().is_some_and(|cw| {
+                                state.project.is_shared_bus_pin(&board_id, pin, &cw.wizard_type)
+                            });
+                            if !shared_bus_pin
+                                && self.check_pin_use(canvas_board_rc, &pin, &state.project.connections)
                             {
                                 break;
                             }
 
-                            let board_id = canvas_board_rc.borrow().id;
-
                             // If wizard is active, validate the pin selection first
                             if let Some(mut cw) = state.connection_wizard.take() {
                                 if !cw.handle_pin_selected(
@@ -1126,6 +1166,19 @@ impl CanvasTab {
         }
     }
 
+    /// Get the zoom and pan, for saving in the project's session
+    pub fn view(&self) -> CanvasView {
+        CanvasView {
+            zoom: self.canvas_zoom,
+            offset: [self.canvas_offset.x, self.canvas_offset.y],
+        }
+    }
+
+    pub fn set_view(&mut self, view: CanvasView) {
+        self.canvas_zoom = view.zoom;
+        self.canvas_offset = Vec2::new(view.offset[0], view.offset[1]);
+    }
+
     pub fn reset_canvas(&mut self) {
         self.canvas_zoom = 5.0;
         self.canvas_offset = Vec2::new(0.0, 0.0);
@@ -1225,6 +1278,107 @@ impl CanvasTab {
         println!("Select All: Canvas operation");
     }
 
+    /// Show the name and peripheral instance editors for the selected protocol group
+    fn show_group_settings(&mut self, ui: &mut Ui, state: &mut SharedState) {
+        let Some(CanvasSelection::ProtocolGroup { group_id, .. }) = &self.selection else {
+            self.group_name_edit = None;
+            return;
+        };
+        let group_id = *group_id;
+        let Some(group) = state.project.get_protocol_group(&group_id).cloned() else {
+            return;
+        };
+
+        if self.group_name_edit.as_ref().map(|(id, _)| *id) != Some(group_id) {
+            self.group_name_edit = Some((group_id, group.name.clone()));
+            self.device_name_edits.clear();
+        }
+
+        ui.label(format!("{} Group:", group.protocol_type.interface_name()));
+        if let Some((_, name)) = self.group_name_edit.as_mut() {
+            let response = ui.add(egui::TextEdit::singleline(name).desired_width(120.0));
+            if response.lost_focus() && *name != group.name {
+                if let Some(applied) = state.project.rename_protocol_group(&group_id, name) {
+                    *name = applied;
+                }
+            }
+        }
+
+        let instances = state.project.protocol_instances(&group);
+        if !instances.is_empty() {
+            let current = group
+                .peripheral_instance
+                .clone()
+                .unwrap_or_else(|| instances[0].clone());
+            ui.label("Peripheral:");
+            egui::ComboBox::from_id_source("protocol_group_instance_combo")
+                .selected_text(current.clone())
+                .show_ui(ui, |cb_ui| {
+                    for instance in &instances {
+                        if cb_ui
+                            .selectable_label(*instance == current, instance)
+                            .clicked()
+                        {
+                            state
+                                .project
+                                .set_protocol_group_instance(&group_id, instance);
+                        }
+                    }
+                });
+        }
+
+        if !group.devices.is_empty() {
+            ui.menu_button(format!("Devices ({})", group.devices.len()), |menu_ui| {
+                for device in &group.devices {
+                    let name = self
+                        .device_name_edits
+                        .entry(device.board_id)
+                        .or_insert_with(|| device.name.clone());
+                    menu_ui.horizontal(|row| {
+                        let response = row.add(egui::TextEdit::singleline(name).desired_width(140.0));
+                        if response.lost_focus() && *name != device.name {
+                            if let Some(applied) =
+                                state.project.rename_bus_device(&group_id, &device.board_id, name)
+                            {
+                                *name = applied;
+                            }
+                        }
+
+                        let addresses = state
+                            .project
+                            .device_addresses(&device.board_id, &group.protocol_type);
+                        if let (false, Some(current)) = (addresses.is_empty(), device.address) {
+                            egui::ComboBox::from_id_source(("bus_device_address", device.board_id))
+                                .selected_text(format_address(current))
+                                .show_ui(row, |cb_ui| {
+                                    for address in addresses {
+                                        if cb_ui
+                                            .selectable_label(address == current, format_address(address))
+                                            .clicked()
+                                        {
+                                            state.project.set_bus_device_address(
+                                                &group_id,
+                                                &device.board_id,
+                                                address,
+                                            );
+                                        }
+                                    }
+                                });
+                        }
+                    });
+                }
+            });
+        }
+
+        for (address, names) in group.address_conflicts() {
+            ui.colored_label(
+                Color32::RED,
+                format!("Address conflict: {} used by {}", format_address(address), names.join(", ")),
+            );
+        }
+        ui.separator();
+    }
+
     /// Handle wizard completion by grouping all created connections for undo/redo
     /// This is called when the wizard state becomes Complete
     /// Connections are already created and added to the project during the wizard flow
@@ -1235,6 +1389,29 @@ impl CanvasTab {
                 created_connections,
             } = wizard.state
             {
+                // Completing missing roles of an existing group: extend that group instead
+                let existing_group_id = created_connections
+                    .iter()
+                    .find_map(|c| c.borrow().protocol_group_id)
+                    .filter(|id| state.project.get_protocol_group(id).is_some());
+                if let Some(group_id) = existing_group_id {
+                    state
+                        .project
+                        .add_connections_to_protocol_group(&group_id, created_connections);
+                    return;
+                }
+
+                // Wired to the shared lines of an existing bus: attach as another device on it
+                if let Some(group_id) = state
+                    .project
+                    .find_shared_bus_group(&wizard.wizard_type, &created_connections)
+                {
+                    state
+                        .project
+                        .add_connections_to_protocol_group(&group_id, created_connections);
+                    return;
+                }
+
                 // Create a ProtocolConnection to group all these connections
                 let mut protocol_conn = CanvasProtocol::new(wizard.wizard_type);
 
@@ -1246,8 +1423,20 @@ impl CanvasTab {
                 //   Assign group ID to all connections
                 protocol_conn.assign_to_connections();
 
-                //   Store the protocol group in the project
-                state.project.add_protocol_group(protocol_conn.clone());
+                //   Store the protocol group in the project (this names the group and emits its bus code)
+                let group_id = protocol_conn.get_id();
+                if !state.project.add_protocol_group(protocol_conn.clone()) {
+                    // no peripheral left to drive another bus, so the new wires go again
+                    for conn in &protocol_conn.connections {
+                        state.project.remove_connection(conn);
+                    }
+                    return;
+                }
+                let protocol_conn = state
+                    .project
+                    .get_protocol_group(&group_id)
+                    .cloned()
+                    .unwrap_or(protocol_conn);
 
                 // Add the grouped protocol connection to command history for undo/redo
                 // Note: The connections are already in the project, so we don't execute the command
@@ -1,58 +1,109 @@
 use crate::app::SharedState;
+use crate::app::shared_state::CHECKING_DEPENDENCIES;
 use crate::app::tabs::base_tab::BaseTab;
-use crate::dependencies::checker::DependencyChecker;
+use crate::dependencies::checker::{DependencyReport, DependencyStatus};
+
+use egui::{Color32, RichText};
+
+const OK_COLOR: Color32 = Color32::from_rgb(80, 190, 90);
+const WARNING_COLOR: Color32 = Color32::from_rgb(230, 180, 40);
+const ERROR_COLOR: Color32 = Color32::from_rgb(230, 60, 60);
 
 pub struct DependencyCheckerTab {
-    checker: DependencyChecker,
+    /// Main boards the reports were made for, so they are redone when the boards change
+    checked_boards: Option<Vec<String>>,
 }
 
 impl DependencyCheckerTab {
     pub fn new() -> Self {
-        let mut checker = DependencyChecker::new();
-        checker.check_all();
-        
-        Self {
-            checker,
-        }
+        Self { checked_boards: None }
     }
-    
-    fn refresh(&mut self) {
-        self.checker.check_all();
+
+    fn status_label(report: &DependencyReport) -> RichText {
+        let text = RichText::new(report.status.status_text());
+        match &report.status {
+            DependencyStatus::Installed => text.color(OK_COLOR),
+            DependencyStatus::Outdated => text.color(WARNING_COLOR),
+            DependencyStatus::NotFound | DependencyStatus::Error(_) => text.color(ERROR_COLOR),
+        }
     }
 }
 
 impl BaseTab for DependencyCheckerTab {
-    fn draw(&mut self, ui: &mut egui::Ui, _state: &mut SharedState) {
+    fn draw(&mut self, ui: &mut egui::Ui, state: &mut SharedState) {
+        let boards: Vec<String> = state.main_board_profiles().into_iter().map(|(name, _)| name).collect();
+        if self.checked_boards.as_ref() != Some(&boards) {
+            state.check_dependencies();
+            self.checked_boards = Some(boards.clone());
+        }
+
         ui.heading("Dependency Checker");
-        ui.separator();
-        
-        ui.vertical(|ui| {
-            ui.horizontal(|ui| {
-                ui.label("cargo-generate:");
-                ui.label(self.checker.cargo_generate.status_text());
-            });
-            
-            ui.horizontal(|ui| {
-                ui.label("espflash:");
-                ui.label(self.checker.espflash.status_text());
-            });
-            
-            ui.horizontal(|ui| {
-                ui.label("ravedude:");
-                ui.label(self.checker.ravedude.status_text());
-            });
-            
+        if boards.is_empty() {
+            ui.label("Add a main board to the project to see what it needs");
+        } else {
+            ui.label(format!("Checking what {} need(s)", boards.join(", ")));
+        }
+        if let Some(check) = state.tasks.running().find(|t| t.name() == CHECKING_DEPENDENCIES) {
             ui.horizontal(|ui| {
-                ui.label("probe-rs:");
-                ui.label(self.checker.ravedude.status_text());
+                ui.spinner();
+                ui.label(format!("Running {}", check.progress().message));
             });
-            
+        }
+        ui.separator();
+
+        let mut fix_to_run = None;
+        egui::ScrollArea::vertical().auto_shrink([false; 2]).show(ui, |ui| {
+            egui::Grid::new("dependency_reports")
+                .striped(true)
+                .num_columns(5)
+                .show(ui, |ui| {
+                    ui.strong("Dependency");
+                    ui.strong("Status");
+                    ui.strong("Version");
+                    ui.strong("Needed by");
+                    ui.strong("Fix");
+                    ui.end_row();
+                    for report in state.dependency_reports.iter().flatten() {
+                        let requirement = &report.requirement;
+                        ui.label(&requirement.name);
+                        let status = ui.label(Self::status_label(report));
+                        if let DependencyStatus::Error(e) = &report.status {
+                            status.on_hover_text(e);
+                        }
+                        let version = report.version.clone().unwrap_or_else(|| "-".to_string());
+                        match &requirement.minimum {
+                            Some(minimum) => ui.label(format!("{} (needs {})", version, minimum)),
+                            None => ui.label(version),
+                        };
+                        ui.label(requirement.required_by.join(", "));
+                        match (&requirement.fix, report.status.is_available()) {
+                            (Some(fix), false) => {
+                                ui.horizontal(|ui| {
+                                    if ui.button("Run in Terminal").on_hover_text(fix).clicked() {
+                                        fix_to_run = Some(fix.clone());
+                                    }
+                                    if ui.button("Copy").clicked() {
+                                        ui.ctx().copy_text(fix.clone());
+                                    }
+                                    ui.label(RichText::new(fix).monospace());
+                                });
+                            }
+                            _ => {
+                                ui.label("");
+                            }
+                        }
+                        ui.end_row();
+                    }
+                });
+
             ui.separator();
-            
             if ui.button("Refresh").clicked() {
-                self.refresh();
+                self.checked_boards = None;
             }
         });
+        if let Some(fix) = fix_to_run {
+            state.run_in_terminal(&fix);
+        }
     }
 
     fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
@@ -62,4 +113,4 @@ impl BaseTab for DependencyCheckerTab {
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
-}
\ No newline at end of file
+}
@@ -48,7 +48,9 @@ impl FileExplorerTab {
 impl BaseTab for FileExplorerTab {
     fn draw(&mut self, ui: &mut egui::Ui, _state: &mut SharedState) {
         if _state.sync_file_explorer {
-            self.set_root_dir(_state.project.location.clone().unwrap());
+            if let Some(location) = _state.project.location.clone() {
+                self.set_root_dir(location);
+            }
             _state.sync_file_explorer = false;
         }
         egui::ScrollArea::vertical().show(ui, |ui| {
@@ -1,8 +1,12 @@
 use crate::app::SharedState;
+use crate::app::build::DiagnosticLevel;
+use crate::app::recovery::RecoveryStore;
 use crate::app::tabs::base_tab::BaseTab;
+use crate::project::atomic_write::write_atomic;
+use crate::snippets;
 
 use egui::ScrollArea;
-use log::info;
+use log::{info, warn};
 use std::fs::{File, OpenOptions};
 use std::io::{Read, Seek, Write};
 use std::path::{Path, PathBuf};
@@ -10,6 +14,7 @@ use std::path::{Path, PathBuf};
 use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
 use std::sync::{
     Arc, Mutex,
+    atomic::{AtomicBool, Ordering},
     mpsc::{Receiver, channel},
 };
 use std::thread;
@@ -27,10 +32,17 @@ pub struct FileTab {
 
     watcher_rx: Option<Receiver<Event>>,
     watcher_handle: Option<thread::JoinHandle<()>>,
+    watcher_stop: Arc<AtomicBool>,
     file_changed_externally: Arc<Mutex<bool>>,
 
     last_cursor_range: Option<egui::text::CCursorRange>,
     should_request_focus: bool,
+    /// Cursor placed by a restored session, applied once the editor has been laid out
+    should_restore_cursor: bool,
+    scroll_offset: egui::Vec2,
+    scroll_to_restore: Option<egui::Vec2>,
+    /// Bring the cursor into view once the editor has been laid out
+    scroll_to_cursor: bool,
 }
 
 impl FileTab {
@@ -46,11 +58,16 @@ impl FileTab {
 
             watcher_rx: None,
             watcher_handle: None,
+            watcher_stop: Arc::new(AtomicBool::new(false)),
             file_changed_externally: Arc::new(Mutex::new(false)),
 
             // Cursor restoration logic
             last_cursor_range: None,
             should_request_focus: false,
+            should_restore_cursor: false,
+            scroll_offset: egui::Vec2::ZERO,
+            scroll_to_restore: None,
+            scroll_to_cursor: false,
         }
     }
 
@@ -69,11 +86,11 @@ impl FileTab {
     }
 
     pub fn save(&mut self) -> std::io::Result<()> {
-        if let Some(file) = &mut self.file {
-            file.rewind()?;
-            file.set_len(0)?;
-            file.write_all(self.code.as_bytes())?;
-            file.sync_all()?;
+        if let Some(path) = self.path.clone() {
+            write_atomic(&path, self.code.as_bytes())?;
+            // the rename replaced the file, so the old handle points at the previous contents
+            self.file = Some(OpenOptions::new().read(true).write(true).open(&path)?);
+            RecoveryStore::default().discard(&path);
             self.synced = true;
             // Must request focus after synced changes due to title name change
             self.should_request_focus = true;
@@ -85,18 +102,83 @@ impl FileTab {
         self.synced
     }
 
+    pub fn path(&self) -> Option<&PathBuf> {
+        self.path.as_ref()
+    }
+
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    /// Replace the buffer with recovered unsaved work, leaving the file on disk untouched
+    pub fn restore_unsaved(&mut self, contents: String) {
+        self.code = contents;
+        self.synced = false;
+        self.cached_layout_job = None;
+        self.last_highlighted_text.clear();
+    }
+
+    /// Get the cursor position, as a character index, and the scroll offset
+    pub fn view(&self) -> (Option<usize>, egui::Vec2) {
+        let cursor = self.last_cursor_range.map(|r| r.primary.index);
+        (cursor, self.scroll_offset)
+    }
+
+    /// Put the cursor and scroll position back where a previous session left them
+    pub fn restore_view(&mut self, cursor: Option<usize>, scroll: egui::Vec2) {
+        if let Some(cursor) = cursor {
+            let cursor = cursor.min(self.code.chars().count());
+            self.last_cursor_range = Some(egui::text::CCursorRange::one(egui::text::CCursor::new(
+                cursor,
+            )));
+            self.should_restore_cursor = true;
+        }
+        self.scroll_offset = scroll;
+        self.scroll_to_restore = Some(scroll);
+    }
+
+    /// Move the cursor to a 1-based line and column and scroll it into view
+    pub fn go_to(&mut self, line: usize, column: usize) {
+        let index = char_index_of(&self.code, line, column);
+        self.last_cursor_range = Some(egui::text::CCursorRange::one(egui::text::CCursor::new(index)));
+        self.should_request_focus = true;
+        self.scroll_to_cursor = true;
+    }
+
+    /// Point the tab at the file's new location after it was moved, keeping any unsaved edits
+    pub fn relocate(&mut self, new_path: &Path) -> std::io::Result<()> {
+        self.stop_watching();
+        self.path = Some(new_path.canonicalize()?);
+        self.file = Some(OpenOptions::new().read(true).write(true).open(new_path)?);
+        self.start_watching();
+        Ok(())
+    }
+
+    fn stop_watching(&mut self) {
+        self.watcher_stop.store(true, Ordering::Relaxed);
+        self.watcher_rx = None;
+        self.watcher_handle = None;
+    }
+
     fn start_watching(&mut self) {
         if let Some(path) = &self.path {
             let (tx, rx) = channel();
             let path = path.clone();
+            let stop = Arc::new(AtomicBool::new(false));
+            self.watcher_stop = Arc::clone(&stop);
             let file_changed_flag = Arc::new(Mutex::new(false));
             let flag_clone = Arc::clone(&file_changed_flag);
 
             let handle = thread::spawn(move || {
+                // watch the directory, since saves (ours or an editor's) replace the file by renaming
+                let dir = path.parent().map(Path::to_path_buf).unwrap_or_else(|| path.clone());
+                let watched = path.clone();
                 let mut watcher: RecommendedWatcher = notify::recommended_watcher(
                     move |res: Result<notify::Event, notify::Error>| {
                         if let Ok(event) = res {
-                            if matches!(event.kind, EventKind::Modify(_)) {
+                            if matches!(event.kind, EventKind::Modify(_) | EventKind::Create(_))
+                                && event.paths.iter().any(|p| *p == watched)
+                            {
                                 tx.send(event).ok();
                             }
                         }
@@ -104,11 +186,13 @@ impl FileTab {
                 )
                 .expect("Failed to create file watcher");
 
-                watcher
-                    .watch(&path, RecursiveMode::NonRecursive)
-                    .expect("Failed to start watching file");
+                // the file may have been moved away before the watcher got going
+                if let Err(e) = watcher.watch(&dir, RecursiveMode::NonRecursive) {
+                    warn!("couldn't watch {}: {}", dir.display(), e);
+                    return;
+                }
 
-                loop {
+                while !stop.load(Ordering::Relaxed) {
                     std::thread::sleep(std::time::Duration::from_secs(1));
                 }
             });
@@ -119,6 +203,25 @@ impl FileTab {
         }
     }
 
+    /// Insert text at the last known cursor position, replacing any selection
+    /// Without a cursor position the text is appended to the end of the file
+    pub fn insert_at_cursor(&mut self, text: &str) {
+        let (start, end) = match self.last_cursor_range {
+            Some(range) => (range.primary.index, range.secondary.index),
+            None => {
+                let len = self.code.chars().count();
+                (len, len)
+            }
+        };
+        let (code, cursor) = snippets::insert_snippet(&self.code, start, end, text);
+        self.code = code;
+        self.last_cursor_range = Some(egui::text::CCursorRange::one(egui::text::CCursor::new(
+            cursor,
+        )));
+        self.synced = false;
+        self.should_request_focus = true;
+    }
+
     fn reload_from_disk_if_not_dirty(&mut self) {
         if self.synced {
             if let Some(path) = &self.path {
@@ -136,6 +239,78 @@ impl FileTab {
     }
 }
 
+impl FileTab {
+    /// Underline the lines the last build reported errors or warnings on, with the message on hover
+    fn draw_diagnostic_markers(
+        &self,
+        ui: &mut egui::Ui,
+        state: &SharedState,
+        galley: &egui::Galley,
+        galley_pos: egui::Pos2,
+    ) {
+        let (Some(path), Some(build)) = (&self.path, &state.build) else {
+            return;
+        };
+        for diagnostic in build.diagnostics_for(path) {
+            if diagnostic.line == 0 {
+                continue;
+            }
+            let start = char_index_of(&self.code, diagnostic.line, diagnostic.column);
+            let line_end = char_index_of(&self.code, diagnostic.line + 1, 1).max(start + 1) - 1;
+            let start_rect = galley
+                .pos_from_ccursor(egui::text::CCursor::new(start))
+                .translate(galley_pos.to_vec2());
+            let end_rect = galley
+                .pos_from_ccursor(egui::text::CCursor::new(line_end.max(start)))
+                .translate(galley_pos.to_vec2());
+            let color = match diagnostic.level {
+                DiagnosticLevel::Error => egui::Color32::from_rgb(230, 60, 60),
+                DiagnosticLevel::Warning => egui::Color32::from_rgb(230, 180, 40),
+            };
+            let y = start_rect.max.y;
+            let right = end_rect.max.x.max(start_rect.min.x + 8.0);
+            ui.painter().line_segment(
+                [egui::pos2(start_rect.min.x, y), egui::pos2(right, y)],
+                egui::Stroke::new(2.0, color),
+            );
+
+            let row_rect = egui::Rect::from_min_max(
+                egui::pos2(galley_pos.x, start_rect.min.y),
+                egui::pos2(right, start_rect.max.y),
+            );
+            if ui.rect_contains_pointer(row_rect) {
+                egui::show_tooltip_at_pointer(
+                    ui.ctx(),
+                    ui.layer_id(),
+                    egui::Id::new(("diagnostic", diagnostic.line, diagnostic.column)),
+                    |ui| {
+                        ui.colored_label(color, &diagnostic.message);
+                    },
+                );
+            }
+        }
+    }
+}
+
+/// Get the character index of a 1-based line and column, clamped to the text
+fn char_index_of(text: &str, line: usize, column: usize) -> usize {
+    let mut index = 0;
+    for (i, text_line) in text.split('\n').enumerate() {
+        let line_len = text_line.chars().count();
+        if i + 1 == line.max(1) {
+            return index + column.saturating_sub(1).min(line_len);
+        }
+        index += line_len + 1;
+    }
+    text.chars().count()
+}
+
+impl Drop for FileTab {
+    fn drop(&mut self) {
+        self.stop_watching();
+    }
+}
+
 impl BaseTab for FileTab {
     fn draw(&mut self, ui: &mut egui::Ui, state: &mut SharedState) {
         let mut file_changed = false;
@@ -154,7 +329,11 @@ impl BaseTab for FileTab {
             ui.label(format!("File: {}", path.display()));
         }
 
-        ScrollArea::both().auto_shrink([false; 2]).show(ui, |ui| {
+        let mut scroll_area = ScrollArea::both().auto_shrink([false; 2]);
+        if let Some(offset) = self.scroll_to_restore.take() {
+            scroll_area = scroll_area.scroll_offset(offset);
+        }
+        let output = scroll_area.show(ui, |ui| {
             let former_contents = self.code.clone();
 
             // did the text change?
@@ -174,32 +353,41 @@ impl BaseTab for FileTab {
                 self.last_highlighted_text = self.code.clone();
             }
 
-            let response = if self.use_syntax_highlighting && !self.code.is_empty() {
-                ui.add(
-                    egui::TextEdit::multiline(&mut self.code)
-                        .font(egui::TextStyle::Monospace)
-                        .lock_focus(true)
-                        .desired_width(f32::INFINITY)
-                        .frame(false)
-                        .layouter(&mut |ui, string, _wrap_width| {
-                            let language = self
-                                .path
-                                .as_ref()
-                                .and_then(|p| state.syntax_highlighter.detect_language(p));
-                            let job = state.syntax_highlighter.highlight_code(string, language);
-                            ui.fonts(|f| f.layout_job(job))
-                        }),
-                )
+            let output = if self.use_syntax_highlighting && !self.code.is_empty() {
+                egui::TextEdit::multiline(&mut self.code)
+                    .font(egui::TextStyle::Monospace)
+                    .lock_focus(true)
+                    .desired_width(f32::INFINITY)
+                    .frame(false)
+                    .layouter(&mut |ui, string, _wrap_width| {
+                        let language = self
+                            .path
+                            .as_ref()
+                            .and_then(|p| state.syntax_highlighter.detect_language(p));
+                        let job = state.syntax_highlighter.highlight_code(string, language);
+                        ui.fonts(|f| f.layout_job(job))
+                    })
+                    .show(ui)
             } else {
-                ui.add(
-                    egui::TextEdit::multiline(&mut self.code)
-                        .font(egui::TextStyle::Monospace)
-                        .code_editor()
-                        .lock_focus(true)
-                        .desired_width(f32::INFINITY)
-                        .frame(false),
-                )
+                egui::TextEdit::multiline(&mut self.code)
+                    .font(egui::TextStyle::Monospace)
+                    .code_editor()
+                    .lock_focus(true)
+                    .desired_width(f32::INFINITY)
+                    .frame(false)
+                    .show(ui)
             };
+            self.draw_diagnostic_markers(ui, state, &output.galley, output.galley_pos);
+            if std::mem::take(&mut self.scroll_to_cursor) {
+                if let Some(cursor_range) = self.last_cursor_range {
+                    let cursor_rect = output
+                        .galley
+                        .pos_from_ccursor(cursor_range.primary)
+                        .translate(output.galley_pos.to_vec2());
+                    ui.scroll_to_rect(cursor_rect, Some(egui::Align::Center));
+                }
+            }
+            let response = output.response;
 
             // Store cursor position whenever the text edit has focus
             if response.has_focus() {
@@ -208,6 +396,7 @@ impl BaseTab for FileTab {
                         self.last_cursor_range = Some(cursor_range);
                     }
                 }
+                state.last_focused_file = self.path.clone();
             }
 
             if self.should_request_focus {
@@ -219,7 +408,12 @@ impl BaseTab for FileTab {
                     }
                 }
                 self.should_request_focus = false;
+            } else if self.should_restore_cursor {
+                let mut edit_state = egui::TextEdit::load_state(ui.ctx(), response.id).unwrap_or_default();
+                edit_state.cursor.set_char_range(self.last_cursor_range);
+                edit_state.store(ui.ctx(), response.id);
             }
+            self.should_restore_cursor = false;
 
             if self.synced && self.code != former_contents {
                 self.synced = false;
@@ -228,16 +422,27 @@ impl BaseTab for FileTab {
             }
 
             // See if a code snippet was released over the editor.
-            ui.ctx().memory_mut(|mem| {
+            let released = ui.ctx().memory_mut(|mem| {
                 let id = egui::Id::new("released_code_snippet");
                 let data: Option<String> = mem.data.get_temp(id);
-                if let Some(value) = data {
+                if data.is_some() {
                     info!("found a released code snippet!");
                     mem.data.remove::<String>(id);
-                    self.code += &value;
                 }
+                data
             });
+            if let Some(value) = released {
+                self.insert_at_cursor(&value);
+            }
+
+            // Insert a snippet chosen in the snippets panel if this was the last edited file
+            if self.path.is_some() && state.last_focused_file == self.path {
+                if let Some(value) = state.snippet_to_insert.take() {
+                    self.insert_at_cursor(&value);
+                }
+            }
         });
+        self.scroll_offset = output.state.offset;
     }
 
     fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
@@ -0,0 +1,179 @@
+use crate::app::SharedState;
+use crate::app::flash::{self, DetectedDevice, FlashStatus, Probe};
+use crate::app::tabs::base_tab::BaseTab;
+use crate::board::DeviceKind;
+
+use egui::{Color32, RichText};
+
+const OK_COLOR: Color32 = Color32::from_rgb(80, 190, 90);
+const WARNING_COLOR: Color32 = Color32::from_rgb(230, 180, 40);
+const ERROR_COLOR: Color32 = Color32::from_rgb(230, 60, 60);
+
+/// Picks the port or probe to flash through, and shows how the last flash went
+pub struct FlashTab {
+    devices: Vec<DetectedDevice>,
+    probes: Vec<Probe>,
+    /// Devices are looked for when the tab opens, and again on request
+    detected: bool,
+}
+
+impl FlashTab {
+    pub fn new() -> Self {
+        Self {
+            devices: vec![],
+            probes: vec![],
+            detected: false,
+        }
+    }
+
+    fn detect(&mut self, state: &SharedState, kind: Option<DeviceKind>) {
+        self.devices = flash::detect_devices(&state.main_board_profiles());
+        self.probes = match kind {
+            Some(DeviceKind::Probe) => flash::list_probes(),
+            _ => vec![],
+        };
+        self.detected = true;
+    }
+
+    fn draw_device_picker(&mut self, ui: &mut egui::Ui, state: &mut SharedState, kind: Option<DeviceKind>) {
+        let options: Vec<(String, String)> = match kind {
+            Some(DeviceKind::SerialPort) => self.devices.iter().map(|d| (d.port_name.clone(), d.label())).collect(),
+            Some(DeviceKind::Probe) => self
+                .probes
+                .iter()
+                .map(|p| (p.selector.clone(), format!("{} ({})", p.name, p.selector)))
+                .collect(),
+            None => {
+                ui.label("The board is flashed in its USB bootloader mode; hold BOOTSEL while plugging it in");
+                return;
+            }
+        };
+        let automatic = match kind {
+            Some(DeviceKind::Probe) => "First probe found",
+            _ => "Detect from USB ID",
+        };
+        let selected = match &state.flash_device {
+            Some(device) => options
+                .iter()
+                .find(|(value, _)| value == device)
+                .map_or(device.clone(), |(_, label)| label.clone()),
+            None => automatic.to_string(),
+        };
+        ui.label(match kind {
+            Some(DeviceKind::Probe) => "Probe:",
+            _ => "Port:",
+        });
+        egui::ComboBox::from_id_salt("flash_device")
+            .selected_text(selected)
+            .width(280.0)
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut state.flash_device, None, automatic);
+                for (value, label) in options {
+                    ui.selectable_value(&mut state.flash_device, Some(value), label);
+                }
+            });
+        if ui.button("⟳").on_hover_text("Look for boards again").clicked() {
+            self.detect(state, kind);
+        }
+    }
+}
+
+impl BaseTab for FlashTab {
+    fn draw(&mut self, ui: &mut egui::Ui, state: &mut SharedState) {
+        let profile = state
+            .build_target_board()
+            .map(|b| (b.borrow().board.get_name().to_string(), b.borrow().board.build_profile().clone()));
+        let Some((board_name, profile)) = profile else {
+            ui.label("Add a main board to the project to flash it");
+            return;
+        };
+        let kind = profile.runner.and_then(|r| r.device_kind());
+        if !self.detected {
+            self.detect(state, kind);
+        }
+
+        let running = state.flash.as_ref().is_some_and(|f| f.is_running());
+        ui.horizontal(|ui| {
+            match profile.runner {
+                Some(runner) => ui.label(format!("{} with {}", board_name, runner)),
+                None => ui.label(RichText::new(format!("{} doesn't say how to flash it", board_name)).color(ERROR_COLOR)),
+            };
+            ui.separator();
+            self.draw_device_picker(ui, state, kind);
+            ui.separator();
+            if ui
+                .add_enabled(!running && profile.runner.is_some(), egui::Button::new("Flash"))
+                .clicked()
+            {
+                state.flash_project();
+            }
+            if ui.add_enabled(running, egui::Button::new("Cancel")).clicked() {
+                if let Some(flash) = state.flash.as_mut() {
+                    flash.cancel();
+                }
+            }
+        });
+        let board_found = self.devices.iter().any(|d| d.board.as_deref() == Some(board_name.as_str()));
+        if kind == Some(DeviceKind::SerialPort) && !board_found && state.flash_device.is_none() {
+            ui.label(
+                RichText::new(format!("No {} found over USB; check the cable, or pick its port", board_name))
+                    .color(WARNING_COLOR),
+            );
+        }
+        ui.separator();
+
+        let Some(flash) = &state.flash else {
+            ui.label("Nothing flashed yet");
+            return;
+        };
+        let (stage, fraction) = match &flash.progress {
+            Some(progress) => (progress.stage.clone(), progress.fraction),
+            None => ("Starting".to_string(), None),
+        };
+        match flash.status {
+            FlashStatus::Running => {
+                let bar = egui::ProgressBar::new(fraction.unwrap_or(0.0)).text(match fraction {
+                    Some(fraction) => format!("{} {:.0}%", stage, fraction * 100.0),
+                    None => format!("{}...", stage),
+                });
+                ui.add(bar.animate(fraction.is_none()));
+            }
+            FlashStatus::Succeeded => {
+                ui.add(egui::ProgressBar::new(1.0).text("Done"));
+                ui.label(RichText::new("Flashing succeeded").color(OK_COLOR));
+            }
+            FlashStatus::Failed => {
+                ui.label(RichText::new(format!("Flashing failed while {}", stage.to_lowercase())).color(ERROR_COLOR));
+            }
+            FlashStatus::Cancelled => {
+                ui.label("Flashing cancelled");
+            }
+        }
+        if flash.port_busy {
+            ui.label(
+                RichText::new("The port is in use by another program; close it, or pick another port").color(ERROR_COLOR),
+            );
+        }
+        if let Some(device) = &flash.device {
+            ui.label(format!("Flashing through {}", device));
+        }
+
+        ui.separator();
+        egui::ScrollArea::vertical()
+            .auto_shrink([false; 2])
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                for line in &flash.output {
+                    ui.label(RichText::new(line).monospace());
+                }
+            });
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
@@ -1,20 +1,34 @@
 pub mod base_tab;
 
 pub mod board_info_tab;
+pub mod build_tab;
 pub mod canvas_tab;
 pub mod debug_tab;
 pub mod dependency_checker_tab;
 pub mod file_explorer_tab;
 pub mod file_tab;
+pub mod flash_tab;
+pub mod serial_monitor_tab;
+pub mod serial_plotter_tab;
 pub mod settings_tab;
+pub mod snippets_tab;
+pub mod start_tab;
 pub mod terminal_tab;
+pub mod toolchains_tab;
 
 pub use base_tab::BaseTab;
 pub use board_info_tab::BoardInfoTab;
+pub use build_tab::BuildTab;
 pub use canvas_tab::CanvasTab;
 pub use debug_tab::DebugTab;
 pub use dependency_checker_tab::DependencyCheckerTab;
 pub use file_explorer_tab::FileExplorerTab;
 pub use file_tab::FileTab;
+pub use flash_tab::FlashTab;
+pub use serial_monitor_tab::SerialMonitorTab;
+pub use serial_plotter_tab::SerialPlotterTab;
 pub use settings_tab::SettingsTab;
+pub use snippets_tab::SnippetsTab;
+pub use start_tab::StartTab;
 pub use terminal_tab::TerminalTab;
+pub use toolchains_tab::ToolchainsTab;
@@ -0,0 +1,242 @@
+use crate::app::SharedState;
+use crate::app::serial::{self, Direction, LineEnding, SendHistory, SerialMonitor};
+use crate::app::tabs::base_tab::BaseTab;
+
+use egui::{Color32, RichText};
+use rfd::FileDialog;
+
+const SENT_COLOR: Color32 = Color32::from_rgb(90, 160, 230);
+const ERROR_COLOR: Color32 = Color32::from_rgb(230, 60, 60);
+
+/// Talks to a board over a serial port
+pub struct SerialMonitorTab {
+    ports: Vec<String>,
+    selected_port: Option<String>,
+    /// Chosen baud rate; follows the build target's until the user picks one
+    baud_rate: Option<u32>,
+    line_ending: LineEnding,
+    input: String,
+    history: SendHistory,
+    show_timestamps: bool,
+    autoscroll: bool,
+    message: Option<String>,
+}
+
+impl SerialMonitorTab {
+    pub fn new() -> Self {
+        let ports = serial::available_ports();
+        Self {
+            selected_port: ports.first().cloned(),
+            ports,
+            baud_rate: None,
+            line_ending: LineEnding::Lf,
+            input: String::new(),
+            history: SendHistory::default(),
+            show_timestamps: true,
+            autoscroll: true,
+            message: None,
+        }
+    }
+
+    fn connect(&mut self, state: &mut SharedState) {
+        let Some(port) = self.selected_port.clone() else {
+            return;
+        };
+        let baud_rate = self.baud_rate.unwrap_or_else(|| state.default_baud_rate());
+        match SerialMonitor::open(&port, baud_rate) {
+            Ok(monitor) => {
+                state.serial_monitor = Some(monitor);
+                self.message = None;
+            }
+            Err(e) => self.message = Some(format!("Couldn't open {}: {}", port, e)),
+        }
+    }
+
+    fn send(&mut self, state: &mut SharedState) {
+        let Some(monitor) = state.serial_monitor.as_mut().filter(|m| m.is_open()) else {
+            return;
+        };
+        match monitor.send(&self.input, self.line_ending) {
+            Ok(()) => {
+                self.history.push(&self.input);
+                self.input.clear();
+            }
+            Err(e) => self.message = Some(format!("Couldn't send: {}", e)),
+        }
+    }
+
+    fn draw_connection_bar(&mut self, ui: &mut egui::Ui, state: &mut SharedState) {
+        let connected = state.serial_monitor.as_ref().is_some_and(|m| m.is_open());
+        let paused = state.serial_monitor.as_ref().is_some_and(|m| m.is_paused());
+        ui.horizontal(|ui| {
+            ui.add_enabled_ui(!connected && !paused, |ui| {
+                egui::ComboBox::from_id_salt("serial_port")
+                    .selected_text(self.selected_port.as_deref().unwrap_or("No ports found"))
+                    .show_ui(ui, |ui| {
+                        for port in &self.ports {
+                            ui.selectable_value(&mut self.selected_port, Some(port.clone()), port);
+                        }
+                    });
+                if ui.button("⟳").on_hover_text("Look for ports again").clicked() {
+                    self.ports = serial::available_ports();
+                    if self.selected_port.as_ref().is_none_or(|p| !self.ports.contains(p)) {
+                        self.selected_port = self.ports.first().cloned();
+                    }
+                }
+                let default_baud_rate = state.default_baud_rate();
+                let mut baud_rate = self.baud_rate.unwrap_or(default_baud_rate);
+                egui::ComboBox::from_id_salt("serial_baud_rate")
+                    .selected_text(format!("{} baud", baud_rate))
+                    .show_ui(ui, |ui| {
+                        for rate in serial::BAUD_RATES {
+                            let label = if *rate == default_baud_rate {
+                                format!("{} (board default)", rate)
+                            } else {
+                                rate.to_string()
+                            };
+                            ui.selectable_value(&mut baud_rate, *rate, label);
+                        }
+                    });
+                if baud_rate != self.baud_rate.unwrap_or(default_baud_rate) {
+                    self.baud_rate = Some(baud_rate);
+                }
+            });
+
+            if paused {
+                ui.label(RichText::new("Paused while flashing").weak());
+                if ui.button("Disconnect").clicked() {
+                    if let Some(monitor) = state.serial_monitor.as_mut() {
+                        monitor.close();
+                    }
+                }
+            } else if connected {
+                if ui.button("Disconnect").clicked() {
+                    if let Some(monitor) = state.serial_monitor.as_mut() {
+                        monitor.close();
+                    }
+                }
+            } else if ui
+                .add_enabled(self.selected_port.is_some(), egui::Button::new("Connect"))
+                .clicked()
+            {
+                self.connect(state);
+            }
+
+            ui.separator();
+            ui.checkbox(&mut self.show_timestamps, "Timestamps");
+            ui.checkbox(&mut self.autoscroll, "Autoscroll");
+            if ui.button("Clear").clicked() {
+                if let Some(monitor) = state.serial_monitor.as_mut() {
+                    monitor.clear();
+                }
+            }
+            let Some(monitor) = state.serial_monitor.as_mut() else {
+                return;
+            };
+            match monitor.log_path().map(|p| p.display().to_string()) {
+                Some(path) => {
+                    if ui.button("Stop Logging").on_hover_text(path).clicked() {
+                        monitor.stop_log();
+                    }
+                }
+                None => {
+                    if ui.button("Log to File...").clicked() {
+                        let path = FileDialog::new()
+                            .add_filter("Log files", &["log", "txt"])
+                            .set_file_name("serial.log")
+                            .save_file();
+                        if let Some(path) = path {
+                            if let Err(e) = monitor.start_log(&path, self.show_timestamps) {
+                                self.message = Some(format!("Couldn't log to {}: {}", path.display(), e));
+                            }
+                        }
+                    }
+                }
+            }
+        });
+    }
+}
+
+impl BaseTab for SerialMonitorTab {
+    fn draw(&mut self, ui: &mut egui::Ui, state: &mut SharedState) {
+        self.draw_connection_bar(ui, state);
+        if let Some(message) = &self.message {
+            ui.label(RichText::new(message).color(ERROR_COLOR));
+        }
+        if let Some(error) = state.serial_monitor.as_ref().and_then(|m| m.error.as_ref()) {
+            ui.label(RichText::new(format!("Connection lost: {}", error)).color(ERROR_COLOR));
+        }
+        ui.separator();
+
+        // the send line sits below the output, so the output gets what's left above it
+        let send_height = ui.spacing().interact_size.y + ui.spacing().item_spacing.y * 2.0;
+        egui::ScrollArea::vertical()
+            .id_salt("serial_output")
+            .max_height((ui.available_height() - send_height).max(0.0))
+            .auto_shrink([false; 2])
+            .stick_to_bottom(self.autoscroll)
+            .show(ui, |ui| {
+                let Some(monitor) = &state.serial_monitor else {
+                    ui.label("Not connected");
+                    return;
+                };
+                for line in &monitor.lines {
+                    let mut text = RichText::new(line.to_log_line(self.show_timestamps)).monospace();
+                    match line.direction {
+                        Direction::Sent => text = text.color(SENT_COLOR),
+                        Direction::Note => text = text.weak(),
+                        Direction::Received => {}
+                    }
+                    ui.label(text);
+                }
+                if let Some(partial) = monitor.partial_line() {
+                    ui.label(RichText::new(partial).monospace());
+                }
+            });
+
+        ui.separator();
+        let connected = state.serial_monitor.as_ref().is_some_and(|m| m.is_open());
+        ui.add_enabled_ui(connected, |ui| {
+            ui.horizontal(|ui| {
+                let input = ui.add(
+                    egui::TextEdit::singleline(&mut self.input)
+                        .hint_text("Send to the board")
+                        .desired_width(ui.available_width() - 260.0),
+                );
+                if input.has_focus() {
+                    let (up, down) = ui.input(|i| (i.key_pressed(egui::Key::ArrowUp), i.key_pressed(egui::Key::ArrowDown)));
+                    let recalled = if up {
+                        self.history.previous()
+                    } else if down {
+                        self.history.next()
+                    } else {
+                        None
+                    };
+                    if let Some(text) = recalled {
+                        self.input = text.to_string();
+                    }
+                }
+                let enter = input.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                egui::ComboBox::from_id_salt("serial_line_ending")
+                    .selected_text(self.line_ending.label())
+                    .show_ui(ui, |ui| {
+                        for ending in LineEnding::ALL {
+                            ui.selectable_value(&mut self.line_ending, ending, ending.label());
+                        }
+                    });
+                if ui.button("Send").clicked() || enter {
+                    self.send(state);
+                    input.request_focus();
+                }
+            });
+        });
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
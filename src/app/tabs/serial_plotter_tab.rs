@@ -0,0 +1,265 @@
+use crate::app::SharedState;
+use crate::app::plotter::Plotter;
+use crate::app::tabs::base_tab::BaseTab;
+
+use egui::{Align2, Color32, FontId, Pos2, RichText, Sense, Shape, Stroke, Vec2};
+use rfd::FileDialog;
+
+const SERIES_COLORS: [Color32; 8] = [
+    Color32::from_rgb(230, 80, 70),
+    Color32::from_rgb(70, 150, 230),
+    Color32::from_rgb(90, 190, 90),
+    Color32::from_rgb(230, 170, 40),
+    Color32::from_rgb(170, 100, 220),
+    Color32::from_rgb(50, 190, 190),
+    Color32::from_rgb(230, 110, 180),
+    Color32::from_rgb(150, 150, 150),
+];
+const ERROR_COLOR: Color32 = Color32::from_rgb(230, 60, 60);
+/// Seconds shown until the user zooms
+const DEFAULT_SPAN: f64 = 10.0;
+const MIN_SPAN: f64 = 0.1;
+const MAX_SPAN: f64 = 3600.0;
+const GRID_LINES: usize = 5;
+
+/// Plots numbers the board prints over the serial monitor's connection
+pub struct SerialPlotterTab {
+    plotter: Plotter,
+    /// Seconds of samples across the plot
+    span: f64,
+    /// Time at the right edge while paused; the plot follows the newest sample otherwise
+    paused_at: Option<f64>,
+    message: Option<String>,
+}
+
+impl SerialPlotterTab {
+    pub fn new() -> Self {
+        Self {
+            plotter: Plotter::new(),
+            span: DEFAULT_SPAN,
+            paused_at: None,
+            message: None,
+        }
+    }
+
+    fn export_csv(&mut self) {
+        let Some(path) = FileDialog::new()
+            .add_filter("CSV files", &["csv"])
+            .set_file_name("serial_plot.csv")
+            .save_file()
+        else {
+            return;
+        };
+        self.message = match std::fs::write(&path, self.plotter.to_csv()) {
+            Ok(()) => Some(format!("Exported {} samples to {}", self.plotter.times.len(), path.display())),
+            Err(e) => Some(format!("Couldn't export to {}: {}", path.display(), e)),
+        };
+    }
+
+    fn draw_toolbar(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            let latest = self.plotter.latest_time().unwrap_or(0.0);
+            match self.paused_at {
+                Some(_) => {
+                    if ui.button("▶ Resume").clicked() {
+                        self.paused_at = None;
+                    }
+                }
+                None => {
+                    if ui.button("⏸ Pause").clicked() {
+                        self.paused_at = Some(latest);
+                    }
+                }
+            }
+            if ui.button("➖").on_hover_text("Zoom out").clicked() {
+                self.span = (self.span * 2.0).min(MAX_SPAN);
+            }
+            if ui.button("➕").on_hover_text("Zoom in").clicked() {
+                self.span = (self.span / 2.0).max(MIN_SPAN);
+            }
+            if ui.button("Show All").clicked() {
+                self.span = latest.clamp(MIN_SPAN, MAX_SPAN);
+                self.paused_at = self.paused_at.map(|_| latest);
+            }
+            ui.label(format!("{:.1} s", self.span));
+            ui.separator();
+            if ui.button("Clear").clicked() {
+                self.plotter.clear();
+                self.paused_at = None;
+            }
+            if ui
+                .add_enabled(!self.plotter.times.is_empty(), egui::Button::new("Export CSV..."))
+                .clicked()
+            {
+                self.export_csv();
+            }
+        });
+        ui.horizontal_wrapped(|ui| {
+            for (i, series) in self.plotter.series.iter_mut().enumerate() {
+                let color = SERIES_COLORS[i % SERIES_COLORS.len()];
+                let latest = series.values.iter().rev().flatten().next();
+                let label = match latest {
+                    Some(value) => format!("{} ({})", series.name, value),
+                    None => series.name.clone(),
+                };
+                ui.checkbox(&mut series.visible, RichText::new(label).color(color));
+            }
+        });
+    }
+
+    fn draw_plot(&mut self, ui: &mut egui::Ui) {
+        let (response, painter) = ui.allocate_painter(ui.available_size(), Sense::drag());
+        let frame = response.rect;
+        painter.rect_filled(frame, 2.0, ui.visuals().extreme_bg_color);
+        let plot = frame.shrink2(Vec2::new(8.0, 8.0)).with_min_x(frame.left() + 60.0);
+
+        let latest = self.plotter.latest_time().unwrap_or(0.0);
+        if response.hovered() {
+            let scroll = ui.input(|i| i.smooth_scroll_delta.y);
+            if scroll != 0.0 {
+                self.span = (self.span * (-scroll as f64 / 200.0).exp()).clamp(MIN_SPAN, MAX_SPAN);
+            }
+        }
+        // dragging scrolls back through time, which pauses the plot where it's left
+        if response.dragged() && plot.width() > 0.0 {
+            let seconds = -response.drag_delta().x as f64 * self.span / plot.width() as f64;
+            let end = self.paused_at.unwrap_or(latest) + seconds;
+            self.paused_at = Some(end.clamp(self.span.min(latest), latest));
+        }
+        if response.double_clicked() {
+            self.paused_at = None;
+            self.span = DEFAULT_SPAN;
+        }
+
+        let end = self.paused_at.unwrap_or(latest).max(self.span);
+        let start = end - self.span;
+        let visible: Vec<usize> = (0..self.plotter.times.len())
+            .filter(|&i| (start..=end).contains(&self.plotter.times[i]))
+            .collect();
+        let (mut min, mut max) = (f64::INFINITY, f64::NEG_INFINITY);
+        for series in self.plotter.series.iter().filter(|s| s.visible) {
+            for value in visible.iter().filter_map(|&i| series.values[i]) {
+                min = min.min(value);
+                max = max.max(value);
+            }
+        }
+        let text_color = ui.visuals().weak_text_color();
+        if !min.is_finite() {
+            let text = if self.plotter.series.is_empty() {
+                "Waiting for numbers, such as \"temp:23.4,hum:40\" or \"23.4,40\", one sample per line"
+            } else {
+                "No samples in view"
+            };
+            painter.text(frame.center(), Align2::CENTER_CENTER, text, FontId::proportional(14.0), text_color);
+            return;
+        }
+        // leave some room above and below, and give a flat line some height
+        let margin = ((max - min) * 0.05).max(0.5);
+        let (min, max) = (min - margin, max + margin);
+
+        let to_screen = |time: f64, value: f64| {
+            Pos2::new(
+                plot.left() + ((time - start) / self.span) as f32 * plot.width(),
+                plot.bottom() - ((value - min) / (max - min)) as f32 * plot.height(),
+            )
+        };
+        let grid = Stroke::new(1.0, ui.visuals().widgets.noninteractive.bg_stroke.color);
+        for i in 0..=GRID_LINES {
+            let fraction = i as f64 / GRID_LINES as f64;
+            let value = min + fraction * (max - min);
+            let y = to_screen(start, value).y;
+            painter.line_segment([Pos2::new(plot.left(), y), Pos2::new(plot.right(), y)], grid);
+            painter.text(
+                Pos2::new(plot.left() - 4.0, y),
+                Align2::RIGHT_CENTER,
+                format!("{:.2}", value),
+                FontId::monospace(10.0),
+                text_color,
+            );
+            let time = start + fraction * self.span;
+            let x = to_screen(time, min).x;
+            painter.line_segment([Pos2::new(x, plot.top()), Pos2::new(x, plot.bottom())], grid);
+            painter.text(
+                Pos2::new(x, plot.bottom()),
+                Align2::CENTER_BOTTOM,
+                format!("{:.1}s", time),
+                FontId::monospace(10.0),
+                text_color,
+            );
+        }
+
+        let clip = painter.with_clip_rect(plot.expand(1.0));
+        for (s, series) in self.plotter.series.iter().enumerate().filter(|(_, s)| s.visible) {
+            let stroke = Stroke::new(1.5, SERIES_COLORS[s % SERIES_COLORS.len()]);
+            // a sample that didn't include the series breaks its line
+            let mut points = vec![];
+            for &i in &visible {
+                match series.values[i] {
+                    Some(value) => points.push(to_screen(self.plotter.times[i], value)),
+                    None => draw_line(&clip, std::mem::take(&mut points), stroke),
+                }
+            }
+            draw_line(&clip, points, stroke);
+        }
+
+        if let Some(pointer) = response.hover_pos().filter(|p| plot.contains(*p)) {
+            let time = start + ((pointer.x - plot.left()) / plot.width()) as f64 * self.span;
+            let nearest = visible
+                .iter()
+                .min_by(|&&a, &&b| (self.plotter.times[a] - time).abs().total_cmp(&(self.plotter.times[b] - time).abs()));
+            if let Some(&i) = nearest {
+                let x = to_screen(self.plotter.times[i], min).x;
+                painter.line_segment([Pos2::new(x, plot.top()), Pos2::new(x, plot.bottom())], Stroke::new(1.0, text_color));
+                let mut text = format!("{:.3}s", self.plotter.times[i]);
+                for series in self.plotter.series.iter().filter(|s| s.visible) {
+                    if let Some(value) = series.values[i] {
+                        text.push_str(&format!("\n{}: {}", series.name, value));
+                    }
+                }
+                response.on_hover_text_at_pointer(text);
+            }
+        }
+    }
+}
+
+fn draw_line(painter: &egui::Painter, points: Vec<Pos2>, stroke: Stroke) {
+    match points.len() {
+        0 => {}
+        1 => {
+            painter.circle_filled(points[0], stroke.width, stroke.color);
+        }
+        _ => {
+            painter.add(Shape::line(points, stroke));
+        }
+    }
+}
+
+impl BaseTab for SerialPlotterTab {
+    fn draw(&mut self, ui: &mut egui::Ui, state: &mut SharedState) {
+        match &state.serial_monitor {
+            Some(monitor) => self.plotter.update(monitor),
+            None => {
+                ui.label("Connect to a board in the Serial Monitor to plot what it prints");
+            }
+        }
+        self.draw_toolbar(ui);
+        if let Some(message) = &self.message {
+            let color = if message.starts_with("Couldn't") {
+                ERROR_COLOR
+            } else {
+                ui.visuals().text_color()
+            };
+            ui.label(RichText::new(message).color(color));
+        }
+        ui.separator();
+        self.draw_plot(ui);
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
@@ -0,0 +1,151 @@
+use crate::app::SharedState;
+use crate::app::tabs::base_tab::BaseTab;
+use crate::snippets::{self, Snippet};
+
+use std::collections::HashMap;
+
+pub struct SnippetsTab {
+    search: String,
+    selected: Option<(String, String)>,
+    values: HashMap<String, String>,
+}
+
+impl SnippetsTab {
+    pub fn new() -> Self {
+        Self {
+            search: String::new(),
+            selected: None,
+            values: HashMap::new(),
+        }
+    }
+
+    /// Gather the built-in snippets along with those shipped by boards in the project
+    fn available_snippets(state: &SharedState) -> Vec<Snippet> {
+        let mut available = state.snippets.clone();
+        let mut seen_boards = Vec::new();
+        for b in state.project.boards_iter() {
+            let b = b.borrow();
+            if seen_boards.contains(&b.board.name) {
+                continue;
+            }
+            seen_boards.push(b.board.name.clone());
+            available.extend(b.board.snippets().iter().cloned());
+        }
+        available
+    }
+}
+
+impl BaseTab for SnippetsTab {
+    fn draw(&mut self, ui: &mut egui::Ui, state: &mut SharedState) {
+        ui.heading("Snippets");
+        ui.horizontal(|ui| {
+            ui.label("Search:");
+            ui.text_edit_singleline(&mut self.search);
+        });
+        ui.separator();
+
+        let available = Self::available_snippets(state);
+        let bindings = snippets::project_bindings(&state.project);
+
+        egui::ScrollArea::vertical()
+            .id_salt("snippet_list")
+            .max_height(ui.available_height() / 2.0)
+            .auto_shrink([false, true])
+            .show(ui, |ui| {
+                for snippet in available.iter().filter(|s| s.matches(&self.search)) {
+                    let key = (snippet.source.clone(), snippet.name.clone());
+                    let is_selected = self.selected.as_ref() == Some(&key);
+                    let response = ui
+                        .selectable_label(is_selected, format!("{}  ({})", snippet.name, snippet.source))
+                        .on_hover_text(&snippet.description);
+                    if response.clicked() && !is_selected {
+                        self.selected = Some(key);
+                        self.values.clear();
+                    }
+                }
+            });
+        ui.separator();
+
+        let Some(snippet) = available
+            .iter()
+            .find(|s| self.selected.as_ref() == Some(&(s.source.clone(), s.name.clone())))
+        else {
+            ui.label("Select a snippet to preview it.");
+            return;
+        };
+
+        if !snippet.description.is_empty() {
+            ui.label(&snippet.description);
+        }
+
+        // Bind each placeholder to project data where possible, otherwise let the user type a value
+        egui::Grid::new("snippet_placeholders").show(ui, |ui| {
+            for name in snippet.placeholders() {
+                ui.label(&name);
+                let value = self.values.entry(name.clone()).or_default();
+                match bindings.get(&name) {
+                    Some(options) => {
+                        if value.is_empty() {
+                            if let Some(first) = options.first() {
+                                *value = first.clone();
+                            }
+                        }
+                        egui::ComboBox::from_id_salt(format!("snippet_binding_{}", name))
+                            .selected_text(value.clone())
+                            .show_ui(ui, |ui| {
+                                for option in options {
+                                    ui.selectable_value(value, option.clone(), option);
+                                }
+                            });
+                        if options.is_empty() {
+                            ui.label("nothing in the project to bind to");
+                        }
+                    }
+                    None => {
+                        ui.text_edit_singleline(value);
+                    }
+                }
+                ui.end_row();
+            }
+        });
+
+        let rendered = snippet.render(&self.values);
+        egui::ScrollArea::both()
+            .id_salt("snippet_preview")
+            .max_height(ui.available_height() - 40.0)
+            .show(ui, |ui| {
+                ui.add(
+                    egui::TextEdit::multiline(&mut rendered.as_str())
+                        .font(egui::TextStyle::Monospace)
+                        .desired_width(f32::INFINITY),
+                );
+            });
+
+        ui.horizontal(|ui| {
+            let target = state
+                .last_focused_file
+                .as_ref()
+                .and_then(|p| p.file_name())
+                .map(|n| n.to_string_lossy().to_string());
+            let insert = ui.add_enabled(target.is_some(), egui::Button::new("Insert"));
+            if insert.clicked() {
+                state.snippet_to_insert = Some(rendered.clone());
+            }
+            match target {
+                Some(name) => ui.label(format!("at the cursor in {}", name)),
+                None => ui.label("click into a file to choose where to insert"),
+            };
+            if ui.button("Copy").clicked() {
+                ui.ctx().copy_text(rendered.clone());
+            }
+        });
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
@@ -0,0 +1,105 @@
+use crate::app::SharedState;
+use crate::app::tabs::base_tab::BaseTab;
+
+use std::path::{Path, PathBuf};
+
+/// Most projects kept in the recent list
+pub const MAX_RECENT_PROJECTS: usize = 10;
+
+/// Something picked on the start page, carried out by the main window
+#[derive(Debug, Clone, PartialEq)]
+pub enum StartPageRequest {
+    NewProject,
+    OpenProject,
+    OpenRecent(PathBuf),
+}
+
+/// Move a project to the front of a recent list, dropping the oldest beyond the limit
+pub fn remember_project(recent: &mut Vec<PathBuf>, location: &Path) {
+    recent.retain(|p| p != location);
+    recent.insert(0, location.to_path_buf());
+    recent.truncate(MAX_RECENT_PROJECTS);
+}
+
+pub struct StartTab;
+
+impl StartTab {
+    pub fn new() -> Self {
+        StartTab
+    }
+}
+
+impl BaseTab for StartTab {
+    fn draw(&mut self, ui: &mut egui::Ui, state: &mut SharedState) {
+        ui.heading("Iron Coder");
+        ui.horizontal(|ui| {
+            if ui.button("New Project").clicked() {
+                state.start_page_request = Some(StartPageRequest::NewProject);
+            }
+            if ui.button("Open Project...").clicked() {
+                state.start_page_request = Some(StartPageRequest::OpenProject);
+            }
+        });
+        ui.separator();
+
+        ui.label(egui::RichText::new("Recent Projects").strong());
+        if state.recent_projects.is_empty() {
+            ui.label("No recent projects");
+        }
+        let mut forget = None;
+        egui::ScrollArea::vertical().auto_shrink([false, true]).show(ui, |ui| {
+            for location in &state.recent_projects {
+                let name = location
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_else(|| location.display().to_string());
+                ui.horizontal(|ui| {
+                    if location.exists() {
+                        let is_open = state.project.get_location_path().as_ref() == Some(location);
+                        if ui.add_enabled(!is_open, egui::Link::new(&name)).clicked() {
+                            state.start_page_request = Some(StartPageRequest::OpenRecent(location.clone()));
+                        }
+                    } else {
+                        ui.label(egui::RichText::new(&name).strikethrough())
+                            .on_hover_text("This project folder no longer exists");
+                    }
+                    ui.label(egui::RichText::new(location.display().to_string()).weak());
+                    if ui.small_button("✖").on_hover_text("Remove from the list").clicked() {
+                        forget = Some(location.clone());
+                    }
+                });
+            }
+        });
+        if let Some(location) = forget {
+            state.recent_projects.retain(|p| *p != location);
+            state.save_settings();
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recent_projects_are_deduplicated_and_capped() {
+        let dir = Path::new("/projects");
+        let mut recent: Vec<PathBuf> = vec![];
+        for i in 0..MAX_RECENT_PROJECTS + 2 {
+            remember_project(&mut recent, &dir.join(i.to_string()));
+        }
+        remember_project(&mut recent, &dir.join("5"));
+        assert_eq!(recent.len(), MAX_RECENT_PROJECTS);
+        assert_eq!(recent[0], dir.join("5"));
+        assert_eq!(recent[1], dir.join((MAX_RECENT_PROJECTS + 1).to_string()));
+        assert_eq!(recent.iter().filter(|p| **p == dir.join("5")).count(), 1);
+    }
+}
@@ -0,0 +1,221 @@
+use crate::app::SharedState;
+use crate::app::tabs::base_tab::BaseTab;
+use crate::dependencies::redist::{
+    self, InstalledToolchain, ToolchainArchive, ToolchainManager, Verification,
+};
+
+use egui::{Color32, RichText};
+use rfd::FileDialog;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, channel};
+use std::thread;
+
+const OK_COLOR: Color32 = Color32::from_rgb(80, 190, 90);
+const ERROR_COLOR: Color32 = Color32::from_rgb(230, 60, 60);
+
+/// Toolchains bundled in Redist, with their versions and checksums
+pub struct ToolchainsTab {
+    manager: Option<ToolchainManager>,
+    installed: Vec<InstalledToolchain>,
+    available: Vec<ToolchainArchive>,
+    /// Verification results by toolchain directory; checksumming runs off the UI thread
+    verifications: HashMap<PathBuf, Result<Verification, String>>,
+    pending: Vec<(PathBuf, Receiver<Result<Verification, String>>)>,
+    message: Option<(String, bool)>,
+}
+
+impl ToolchainsTab {
+    pub fn new() -> Self {
+        let mut tab = Self {
+            manager: ToolchainManager::locate(),
+            installed: vec![],
+            available: vec![],
+            verifications: HashMap::new(),
+            pending: vec![],
+            message: None,
+        };
+        tab.refresh();
+        tab
+    }
+
+    fn refresh(&mut self) {
+        let Some(manager) = &self.manager else {
+            return;
+        };
+        self.installed = manager.installed();
+        match manager.available() {
+            Ok(available) => self.available = available,
+            Err(e) => {
+                self.available.clear();
+                self.message = Some((e.to_string(), false));
+            }
+        }
+    }
+
+    fn verify(&mut self, toolchain: &InstalledToolchain) {
+        let Some(manager) = self.manager.clone() else {
+            return;
+        };
+        let (tx, rx) = channel();
+        let toolchain = toolchain.clone();
+        self.pending.push((toolchain.dir.clone(), rx));
+        self.verifications.remove(&toolchain.dir);
+        thread::spawn(move || {
+            tx.send(manager.verify(&toolchain).map_err(|e| e.to_string())).ok();
+        });
+    }
+
+    fn install(&mut self, archive: &Path) {
+        let Some(manager) = &self.manager else {
+            return;
+        };
+        self.message = Some(match manager.install_archive(archive) {
+            Ok(toolchain) => (format!("Installed {} for {}", toolchain.name, toolchain.platform), true),
+            Err(e) => (format!("Couldn't install {}: {}", archive.display(), e), false),
+        });
+        self.refresh();
+    }
+
+    fn poll_verifications(&mut self) {
+        self.pending.retain(|(dir, rx)| match rx.try_recv() {
+            Ok(result) => {
+                self.verifications.insert(dir.clone(), result);
+                false
+            }
+            Err(std::sync::mpsc::TryRecvError::Empty) => true,
+            Err(std::sync::mpsc::TryRecvError::Disconnected) => false,
+        });
+    }
+
+    fn verification_label(&self, toolchain: &InstalledToolchain) -> Option<RichText> {
+        if self.pending.iter().any(|(dir, _)| *dir == toolchain.dir) {
+            return Some(RichText::new("Verifying..."));
+        }
+        Some(match self.verifications.get(&toolchain.dir)? {
+            Ok(Verification::Verified { files }) => RichText::new(format!("{} files verified", files)).color(OK_COLOR),
+            Ok(Verification::NoChecksums) => RichText::new("No checksums to verify against"),
+            Ok(Verification::Mismatched { changed, missing }) => RichText::new(format!(
+                "{} file(s) changed, {} missing",
+                changed.len(),
+                missing.len()
+            ))
+            .color(ERROR_COLOR),
+            Err(e) => RichText::new(e).color(ERROR_COLOR),
+        })
+    }
+}
+
+impl BaseTab for ToolchainsTab {
+    fn draw(&mut self, ui: &mut egui::Ui, _state: &mut SharedState) {
+        self.poll_verifications();
+        if !self.pending.is_empty() {
+            ui.ctx().request_repaint_after(std::time::Duration::from_millis(200));
+        }
+
+        ui.heading("Toolchains");
+        let Some(root) = self.manager.as_ref().map(|m| m.root.clone()) else {
+            ui.label(format!(
+                "Couldn't find the {} directory. Set {} to where it is.",
+                redist::REDIST_DIR_NAME,
+                redist::REDIST_ENV
+            ));
+            return;
+        };
+        ui.label(format!("Redist directory: {}", root.display()));
+        ui.horizontal(|ui| {
+            if ui.button("Refresh").clicked() {
+                self.refresh();
+            }
+            if ui.button("Install from Archive...").clicked() {
+                if let Some(archive) = FileDialog::new()
+                    .add_filter("Toolchain archives", &["gz", "tgz", "zip"])
+                    .pick_file()
+                {
+                    self.install(&archive);
+                }
+            }
+        });
+        if let Some((message, ok)) = &self.message {
+            ui.label(RichText::new(message).color(if *ok { OK_COLOR } else { ERROR_COLOR }));
+        }
+        ui.separator();
+
+        let this_platform = redist::platform_dir();
+        let mut to_verify = None;
+        egui::ScrollArea::vertical().auto_shrink([false; 2]).show(ui, |ui| {
+            ui.label(RichText::new("Installed").strong());
+            if self.installed.is_empty() {
+                ui.label("No toolchains installed");
+            }
+            for toolchain in &self.installed {
+                let current = Some(toolchain.platform.as_str()) == this_platform;
+                egui::CollapsingHeader::new(format!("{} ({})", toolchain.name, toolchain.platform))
+                    .id_salt(&toolchain.dir)
+                    .default_open(current)
+                    .show(ui, |ui| {
+                        if current {
+                            ui.label("Used on this computer");
+                        }
+                        egui::Grid::new(("toolchain_versions", &toolchain.dir)).show(ui, |ui| {
+                            for (tool, version) in &toolchain.versions {
+                                ui.label(tool);
+                                ui.label(RichText::new(version).monospace());
+                                ui.end_row();
+                            }
+                        });
+                        ui.horizontal(|ui| {
+                            if ui.button("Verify").clicked() {
+                                to_verify = Some(toolchain.clone());
+                            }
+                            if let Some(label) = self.verification_label(toolchain) {
+                                ui.label(label);
+                            }
+                        });
+                    });
+            }
+
+            ui.separator();
+            ui.label(RichText::new("Available").strong());
+            if self.available.is_empty() {
+                ui.label("No toolchain archives listed in toolchains.toml");
+            }
+            let mut to_install = None;
+            for archive in &self.available {
+                let path = if archive.archive.is_absolute() {
+                    archive.archive.clone()
+                } else {
+                    root.join(&archive.archive)
+                };
+                let installed = self
+                    .installed
+                    .iter()
+                    .any(|t| t.name == archive.name && t.platform == archive.platform);
+                ui.horizontal(|ui| {
+                    ui.label(format!("{} ({})", archive.name, archive.platform));
+                    if !archive.description.is_empty() {
+                        ui.label(RichText::new(&archive.description).weak());
+                    }
+                    let label = if installed { "Reinstall" } else { "Install" };
+                    if ui.add_enabled(path.exists(), egui::Button::new(label)).on_disabled_hover_text(format!("{} is missing", path.display())).clicked() {
+                        to_install = Some(path);
+                    }
+                });
+            }
+            if let Some(path) = to_install {
+                self.install(&path);
+            }
+        });
+        if let Some(toolchain) = to_verify {
+            self.verify(&toolchain);
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
@@ -0,0 +1,335 @@
+//! Work run off the UI thread, with progress and cancellation
+//!
+//! A `Task` runs a closure on a thread of its own. The closure is handed a `TaskContext` to
+//! report progress through and to check for cancellation; work blocked on something else,
+//! such as a child process, registers a hook to stop it with `on_cancel`. The `TaskManager`
+//! in `SharedState` keeps the tasks the status bar lists, and delivers each one's result
+//! back into the state on the UI thread once it's done.
+use log::warn;
+use std::any::Any;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, TryRecvError, channel};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::app::SharedState;
+
+/// Why a task has no result
+#[derive(Debug, Clone, PartialEq)]
+pub enum TaskError {
+    Cancelled,
+    /// The work panicked, with the panic's message
+    Panicked(String),
+}
+
+impl fmt::Display for TaskError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TaskError::Cancelled => write!(f, "cancelled"),
+            TaskError::Panicked(message) => write!(f, "failed unexpectedly: {}", message),
+        }
+    }
+}
+
+/// How far along a task is
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TaskProgress {
+    /// Fraction done, when the work can tell
+    pub fraction: Option<f32>,
+    /// What the work is doing now
+    pub message: String,
+}
+
+struct TaskShared {
+    name: String,
+    progress: Mutex<TaskProgress>,
+    cancelled: AtomicBool,
+    finished: AtomicBool,
+    on_cancel: Mutex<Vec<Box<dyn FnOnce() + Send>>>,
+}
+
+/// A task's progress and cancellation, shared between the work and whoever is watching it
+#[derive(Clone)]
+pub struct TaskContext {
+    shared: Arc<TaskShared>,
+}
+
+impl TaskContext {
+    fn new(name: &str) -> Self {
+        TaskContext {
+            shared: Arc::new(TaskShared {
+                name: name.to_string(),
+                progress: Mutex::new(TaskProgress::default()),
+                cancelled: AtomicBool::new(false),
+                finished: AtomicBool::new(false),
+                on_cancel: Mutex::new(vec![]),
+            }),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.shared.name
+    }
+
+    pub fn set_progress(&self, fraction: Option<f32>, message: impl Into<String>) {
+        if let Ok(mut progress) = self.shared.progress.lock() {
+            progress.fraction = fraction.map(|f| f.clamp(0.0, 1.0));
+            progress.message = message.into();
+        }
+    }
+
+    pub fn progress(&self) -> TaskProgress {
+        self.shared.progress.lock().map(|p| p.clone()).unwrap_or_default()
+    }
+
+    /// Ask the work to stop, running any hooks it registered to stop what it's waiting on
+    pub fn cancel(&self) {
+        if self.is_finished() || self.shared.cancelled.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let hooks = self.shared.on_cancel.lock().map(|mut h| std::mem::take(&mut *h)).unwrap_or_default();
+        for hook in hooks {
+            hook();
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.shared.cancelled.load(Ordering::SeqCst)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.shared.finished.load(Ordering::SeqCst)
+    }
+
+    /// Run `hook` when the task is cancelled, or straight away if it already has been
+    pub fn on_cancel(&self, hook: impl FnOnce() + Send + 'static) {
+        if self.is_cancelled() {
+            hook();
+            return;
+        }
+        if let Ok(mut hooks) = self.shared.on_cancel.lock() {
+            hooks.push(Box::new(hook));
+        }
+    }
+}
+
+/// Work running on a thread of its own, whose result is collected with `poll`
+pub struct Task<T> {
+    context: TaskContext,
+    result: Receiver<thread::Result<T>>,
+    done: bool,
+}
+
+impl<T: Send + 'static> Task<T> {
+    pub fn spawn(name: &str, work: impl FnOnce(&TaskContext) -> T + Send + 'static) -> Task<T> {
+        let context = TaskContext::new(name);
+        let worker = context.clone();
+        let (tx, rx) = channel();
+        thread::spawn(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| work(&worker)));
+            worker.shared.finished.store(true, Ordering::SeqCst);
+            tx.send(result).ok();
+        });
+        Task {
+            context,
+            result: rx,
+            done: false,
+        }
+    }
+}
+
+impl<T> Task<T> {
+    /// Take the result if the work is done; a cancelled task's result is thrown away
+    /// Returns None while it's still running, and after the result has been taken
+    pub fn poll(&mut self) -> Option<Result<T, TaskError>> {
+        if self.done {
+            return None;
+        }
+        let result = match self.result.try_recv() {
+            Err(TryRecvError::Empty) => return None,
+            Err(TryRecvError::Disconnected) => Err(TaskError::Panicked("the task's thread went away".to_string())),
+            Ok(Err(panic)) => Err(TaskError::Panicked(panic_message(panic))),
+            Ok(Ok(_)) if self.context.is_cancelled() => Err(TaskError::Cancelled),
+            Ok(Ok(value)) => Ok(value),
+        };
+        self.done = true;
+        if let Err(e) = &result {
+            warn!("task \"{}\" {}", self.context.name(), e);
+        }
+        Some(result)
+    }
+
+    pub fn is_running(&self) -> bool {
+        !self.done && !self.context.is_finished()
+    }
+
+    pub fn cancel(&self) {
+        self.context.cancel();
+    }
+
+    pub fn context(&self) -> &TaskContext {
+        &self.context
+    }
+}
+
+fn panic_message(panic: Box<dyn Any + Send>) -> String {
+    panic
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "panicked".to_string())
+}
+
+/// Applies a finished task's result to the state
+type Completion = Box<dyn FnOnce(&mut SharedState)>;
+
+struct ManagedTask {
+    context: TaskContext,
+    /// Returns the completion once the task is done; tasks watched elsewhere have none
+    poll: Option<Box<dyn FnMut() -> Option<Completion>>>,
+}
+
+/// The tasks running for the IDE, listed in the status bar
+#[derive(Default)]
+pub struct TaskManager {
+    tasks: Vec<ManagedTask>,
+}
+
+impl TaskManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run work in the background, and `done` with its result on the UI thread once it's finished
+    pub fn spawn<T: Send + 'static>(
+        &mut self,
+        name: &str,
+        work: impl FnOnce(&TaskContext) -> T + Send + 'static,
+        done: impl FnOnce(&mut SharedState, Result<T, TaskError>) + 'static,
+    ) -> TaskContext {
+        let mut task = Task::spawn(name, work);
+        let context = task.context().clone();
+        let mut done = Some(done);
+        self.tasks.push(ManagedTask {
+            context: context.clone(),
+            poll: Some(Box::new(move || {
+                let result = task.poll()?;
+                let done = done.take()?;
+                Some(Box::new(move |state: &mut SharedState| done(state, result)))
+            })),
+        });
+        context
+    }
+
+    /// List a task that something else collects the result of, such as a build
+    pub fn track(&mut self, context: TaskContext) {
+        self.tasks.push(ManagedTask { context, poll: None });
+    }
+
+    /// Tasks still running
+    pub fn running(&self) -> impl Iterator<Item = &TaskContext> {
+        self.tasks.iter().map(|t| &t.context).filter(|c| !c.is_finished())
+    }
+
+    /// Whether any task is still running, or has a result waiting to be applied
+    pub fn is_busy(&self) -> bool {
+        !self.tasks.is_empty()
+    }
+
+    /// Whether a task with the given name is still running
+    pub fn is_running(&self, name: &str) -> bool {
+        self.running().any(|c| c.name() == name)
+    }
+
+    /// Drop finished tasks, returning what their results are to be applied with
+    pub fn take_finished(&mut self) -> Vec<Completion> {
+        let mut completions = vec![];
+        self.tasks.retain_mut(|task| match &mut task.poll {
+            Some(poll) => match poll() {
+                Some(completion) => {
+                    completions.push(completion);
+                    false
+                }
+                None => true,
+            },
+            None => !task.context.is_finished(),
+        });
+        completions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_background_tasks_report_progress_cancel_and_deliver_results() {
+        use crate::app::SharedState;
+        use crate::app::tasks::{Task, TaskError};
+        use std::path::PathBuf;
+        use std::sync::mpsc::channel;
+        use std::time::{Duration, Instant};
+
+        fn wait_for<T>(task: &mut Task<T>) -> Result<T, TaskError> {
+            let start = Instant::now();
+            loop {
+                assert!(start.elapsed() < Duration::from_secs(10), "the task never finished");
+                if let Some(result) = task.poll() {
+                    return result;
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        }
+
+        // progress is seen while the work waits, and its result once it's done
+        let (go, wait) = channel::<()>();
+        let mut task = Task::spawn("Counting", move |context| {
+            context.set_progress(Some(1.5), "halfway");
+            wait.recv().ok();
+            42
+        });
+        let start = Instant::now();
+        while task.context().progress().message.is_empty() {
+            assert!(start.elapsed() < Duration::from_secs(10), "no progress was reported");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(task.context().progress().fraction, Some(1.0));
+        assert!(task.is_running() && task.poll().is_none());
+        go.send(()).unwrap();
+        assert_eq!(wait_for(&mut task), Ok(42));
+        assert!(task.poll().is_none() && !task.is_running());
+
+        // cancelling kills the process the work is blocked on, and its result is thrown away
+        let mut task = Task::spawn("Sleeping", |context| {
+            let handle = std::sync::Arc::new(duct::cmd("sleep", ["30"]).reader().unwrap());
+            let killer = handle.clone();
+            context.on_cancel(move || {
+                killer.kill().ok();
+            });
+            std::io::Read::read_to_end(&mut &*handle, &mut vec![]).ok();
+            "woke up"
+        });
+        std::thread::sleep(Duration::from_millis(100));
+        let start = Instant::now();
+        task.cancel();
+        assert_eq!(wait_for(&mut task), Err(TaskError::Cancelled));
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        let mut task = Task::spawn("Failing", |_| -> u32 { panic!("boom") });
+        assert_eq!(wait_for(&mut task), Err(TaskError::Panicked("boom".to_string())));
+
+        // boards load in the background, and results are applied to the state once polled
+        let mut state = SharedState::default();
+        state.tasks.spawn("Naming", |_| PathBuf::from("somewhere"), |state: &mut SharedState, result| {
+            state.requested_file_to_open = result.ok();
+        });
+        let start = Instant::now();
+        while state.poll_tasks() {
+            assert!(start.elapsed() < Duration::from_secs(30), "the tasks never finished");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(!state.known_boards.is_empty());
+        assert_eq!(state.requested_file_to_open, Some(PathBuf::from("somewhere")));
+        assert_eq!(state.tasks.running().count(), 0);
+    }
+}
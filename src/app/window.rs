@@ -2,13 +2,22 @@ use super::tabs::file_tab::FileTab;
 use super::tabs::*;
 use crate::app::SharedState;
 use crate::app::colorschemes::colorscheme;
+use crate::app::recovery::{self, RecoveryEntry, RecoveryStore};
+use crate::app::session::{FileSession, Session, SessionStore};
+use crate::app::tabs::start_tab::StartPageRequest;
 use crate::board::{Board, get_boards};
+use crate::project::integrity::{self, Fix, Issue};
+use crate::project::{relocate, template};
 use eframe::egui::Ui;
+use log::{info, warn};
 use egui::util::undoer::Settings;
 use egui_dock::{DockArea, DockState, NodeIndex, Style};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Instant;
+use uuid::Uuid;
 
 use rfd::FileDialog;
 
@@ -17,6 +26,8 @@ struct NewProjectDialog {
     name: String,
     path: String,
     selected_board_index: usize,
+    /// Starter of the selected board to create the project from, or an empty project if unset
+    selected_starter: Option<usize>,
 }
 
 impl NewProjectDialog {
@@ -24,9 +35,17 @@ impl NewProjectDialog {
         self.name.clear();
         self.path.clear();
         self.selected_board_index = 0;
+        self.selected_starter = None;
     }
 }
 
+/// Where the Rename/Move Project dialog will put the project
+#[derive(Default)]
+struct RelocateProjectDialog {
+    name: String,
+    parent: String,
+}
+
 static OPENABLE_TABS: &'static [&'static str] = &[
     "Settings",
     "Canvas",
@@ -35,6 +54,13 @@ static OPENABLE_TABS: &'static [&'static str] = &[
     "Board Info",
     "Debug",
     "Dependencies",
+    "Snippets",
+    "Start",
+    "Build",
+    "Toolchains",
+    "Serial Monitor",
+    "Serial Plotter",
+    "Flash",
 ];
 
 struct WindowContext<'a> {
@@ -103,22 +129,34 @@ pub struct MainWindow {
     active_tab: Option<String>,
     show_new_project_dialog: bool,
     new_project_dialog: NewProjectDialog,
+    show_relocate_dialog: bool,
+    relocate_dialog: RelocateProjectDialog,
     show_save_prompt: bool,
     pending_action: Option<PendingAction>,
     should_exit: bool,
+    last_autosave: Instant,
+    recovery_entries: Vec<RecoveryEntry>,
 }
 
 #[derive(Debug, Clone)]
 enum PendingAction {
     OpenProject,
     NewProject,
+    OpenRecent(PathBuf),
+    OpenExample(Rc<Board>, PathBuf),
     Exit,
 }
 
 impl Default for MainWindow {
     fn default() -> Self {
         let mut state = SharedState::default();
-        let mut tree = DockState::new(vec!["Canvas".to_owned(), "Settings".to_owned()]);
+        let mut center_tabs = vec!["Canvas".to_owned(), "Settings".to_owned()];
+        // without a project to go back to, start on the recent projects
+        let show_start_page = state.project.location.is_none() && state.project_to_open.is_none();
+        if show_start_page {
+            center_tabs.insert(0, "Start".to_owned());
+        }
+        let mut tree = DockState::new(center_tabs);
 
         let [a, b] = tree.main_surface_mut().split_left(
             NodeIndex::root(),
@@ -150,6 +188,9 @@ impl Default for MainWindow {
             Box::new(FileExplorerTab::new()),
         );
         tabs.insert("Output".to_string(), Box::new(TerminalTab::new()));
+        if show_start_page {
+            tabs.insert("Start".to_string(), Box::new(StartTab::new()));
+        }
 
         let mut window = Self {
             tree: tree,
@@ -158,17 +199,15 @@ impl Default for MainWindow {
             active_tab: None,
             show_new_project_dialog: false,
             new_project_dialog: NewProjectDialog::default(),
+            show_relocate_dialog: false,
+            relocate_dialog: RelocateProjectDialog::default(),
             show_save_prompt: false,
             pending_action: None,
             should_exit: false,
+            last_autosave: Instant::now(),
+            recovery_entries: RecoveryStore::default().pending(),
         };
 
-        // open main.rs during startup project open
-        let node = window.find_file_node();
-        if window.state.project.location.is_some() {
-            window.auto_open_main_rs(node);
-        }
-
         window
     }
 }
@@ -201,6 +240,14 @@ impl MainWindow {
                         }
                         ui.close_menu();
                     }
+                    let has_location = self.state.project.get_location_path().is_some();
+                    if ui
+                        .add_enabled(has_location, egui::Button::new("Rename/Move Project..."))
+                        .clicked()
+                    {
+                        self.open_relocate_dialog();
+                        ui.close_menu();
+                    }
                     ui.separator();
                     if ui.button("Open").clicked() {
                         self.open_file_dialog();
@@ -242,14 +289,33 @@ impl MainWindow {
                 });
 
                 ui.menu_button("Build", |ui| {
+                    // with several programmable boards, pick which one's firmware to build and flash
+                    let main_boards: Vec<(Uuid, String)> = self
+                        .state
+                        .project
+                        .main_boards_iter()
+                        .map(|b| (b.borrow().id, b.borrow().board_name().to_string()))
+                        .collect();
+                    if main_boards.len() > 1 {
+                        let current = self.state.build_target.unwrap_or(main_boards[0].0);
+                        ui.menu_button("Target Board", |ui| {
+                            for (id, name) in &main_boards {
+                                if ui.radio(current == *id, name).clicked() {
+                                    self.state.build_target = Some(*id);
+                                    ui.close_menu();
+                                }
+                            }
+                        });
+                        ui.separator();
+                    }
                     if ui.button("Build Project").clicked() {
                         // self.state.stop_board();
-                        self.state.build_project();
+                        self.build_project();
                         ui.close_menu();
                     }
                     if ui.button("Flash to Board").clicked() {
                         // self.state.stop_board();
-                        self.state.run_project();
+                        self.flash_project();
                         ui.close_menu();
                     }
                 });
@@ -258,39 +324,54 @@ impl MainWindow {
     }
 
     pub fn add_tab(&mut self, tab_name: String) {
-        match tab_name.as_str() {
-            "Settings" => {
-                self.tabs
-                    .insert(tab_name.clone(), Box::new(SettingsTab::new()));
-            }
-            "Canvas" => {
-                self.tabs
-                    .insert(tab_name.clone(), Box::new(CanvasTab::new()));
-            }
-            "Terminal" => {
-                self.tabs
-                    .insert(tab_name.clone(), Box::new(TerminalTab::new()));
-            }
-            "File Explorer" => {
-                self.tabs
-                    .insert(tab_name.clone(), Box::new(FileExplorerTab::new()));
-            }
-            "Board Info" => {
-                self.tabs
-                    .insert(tab_name.clone(), Box::new(BoardInfoTab::new()));
-            }
-            "Debug" => {
-                self.tabs.insert(tab_name.clone(), Box::new(DebugTab {}));
-            }
-            "Dependencies" => {
-                self.tabs
-                    .insert(tab_name.clone(), Box::new(DependencyCheckerTab::new()));
-            }
-            _ => {}
+        if let Some(tab) = Self::create_tab(&tab_name) {
+            self.tabs.insert(tab_name.clone(), tab);
         }
         self.tree.push_to_focused_leaf(tab_name);
     }
 
+    /// Create one of the openable tabs by name
+    fn create_tab(tab_name: &str) -> Option<Box<dyn BaseTab>> {
+        let tab: Box<dyn BaseTab> = match tab_name {
+            "Settings" => Box::new(SettingsTab::new()),
+            "Canvas" => Box::new(CanvasTab::new()),
+            "Terminal" => Box::new(TerminalTab::new()),
+            "File Explorer" => Box::new(FileExplorerTab::new()),
+            "Board Info" => Box::new(BoardInfoTab::new()),
+            "Debug" => Box::new(DebugTab {}),
+            "Dependencies" => Box::new(DependencyCheckerTab::new()),
+            "Snippets" => Box::new(SnippetsTab::new()),
+            "Start" => Box::new(StartTab::new()),
+            "Build" => Box::new(BuildTab::new()),
+            "Toolchains" => Box::new(ToolchainsTab::new()),
+            "Serial Monitor" => Box::new(SerialMonitorTab::new()),
+            "Serial Plotter" => Box::new(SerialPlotterTab::new()),
+            "Flash" => Box::new(FlashTab::new()),
+            _ => return None,
+        };
+        Some(tab)
+    }
+
+    /// Start a build and bring the Build panel to the front to follow it
+    fn build_project(&mut self) {
+        self.state.build_project();
+        self.show_tab("Build");
+    }
+
+    fn flash_project(&mut self) {
+        self.state.flash_project();
+        self.show_tab("Flash");
+    }
+
+    /// Focus a tab, opening it first if it isn't open
+    fn show_tab(&mut self, tab_name: &str) {
+        let tab_name = tab_name.to_string();
+        match self.tree.find_tab(&tab_name) {
+            Some(location) => self.tree.set_active_tab(location),
+            None => self.add_tab(tab_name),
+        }
+    }
+
     fn save_current_file(&mut self) {
         // Use the tracked active tab
         if let Some(active_tab_name) = &self.active_tab.clone() {
@@ -430,6 +511,127 @@ impl MainWindow {
         }
     }
 
+    fn open_relocate_dialog(&mut self) {
+        let Some(location) = self.state.project.get_location_path() else {
+            return;
+        };
+        self.relocate_dialog.name = self.state.project.name.clone();
+        self.relocate_dialog.parent = location
+            .parent()
+            .map(|p| p.display().to_string())
+            .unwrap_or_default();
+        self.show_relocate_dialog = true;
+    }
+
+    fn display_relocate_dialog(&mut self, ctx: &egui::Context) {
+        let mut should_relocate = false;
+        let mut should_close_dialog = false;
+
+        egui::Window::new("Rename/Move Project")
+            .open(&mut self.show_relocate_dialog)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Project Name:");
+                    ui.text_edit_singleline(&mut self.relocate_dialog.name);
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Location:");
+                    ui.text_edit_singleline(&mut self.relocate_dialog.parent);
+
+                    if ui.button("Browse...").clicked() {
+                        if let Some(folder) = FileDialog::new().pick_folder() {
+                            self.relocate_dialog.parent = folder.display().to_string();
+                        }
+                    }
+                });
+
+                let destination = PathBuf::from(&self.relocate_dialog.parent).join(self.relocate_dialog.name.trim());
+                ui.label(format!("The project will be at {}", destination.display()));
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    let valid = !self.relocate_dialog.name.trim().is_empty() && !self.relocate_dialog.parent.is_empty();
+                    if ui.add_enabled(valid, egui::Button::new("Rename/Move")).clicked() {
+                        should_relocate = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        should_close_dialog = true;
+                    }
+                });
+            });
+
+        if should_relocate {
+            self.relocate_project();
+            should_close_dialog = true;
+        }
+        if should_close_dialog {
+            self.show_relocate_dialog = false;
+        }
+    }
+
+    /// Rename and move the project, then re-point everything holding paths into its old directory
+    fn relocate_project(&mut self) {
+        let new_name = self.relocate_dialog.name.trim().to_string();
+        let destination = PathBuf::from(&self.relocate_dialog.parent).join(&new_name);
+        // the session is keyed by the project's path, which can't be resolved once it has moved
+        let old_session_key = self.state.project.get_location_path().and_then(|l| l.canonicalize().ok());
+        let old_location = match relocate::relocate(&mut self.state.project, &new_name, &destination) {
+            Ok(old_location) => old_location,
+            Err(e) => {
+                println!("Error renaming/moving project: {}", e);
+                return;
+            }
+        };
+        if let Some(key) = old_session_key {
+            SessionStore::default().discard(&key);
+        }
+
+        let mut renamed_tabs = HashMap::new();
+        for (tab_name, tab) in self.tabs.iter_mut() {
+            let Some(file_tab) = tab.as_any_mut().downcast_mut::<FileTab>() else {
+                continue;
+            };
+            let Some(new_path) = relocate::relocated_path(Path::new(tab_name), &old_location, &destination) else {
+                continue;
+            };
+            match file_tab.relocate(&new_path) {
+                Ok(()) => {
+                    // unsaved work is stashed under the new path from now on
+                    RecoveryStore::default().discard(Path::new(tab_name));
+                    renamed_tabs.insert(tab_name.clone(), new_path.display().to_string());
+                }
+                Err(e) => println!("Error reopening '{}': {}", new_path.display(), e),
+            }
+        }
+        for (old_name, new_name) in &renamed_tabs {
+            if let Some(tab) = self.tabs.remove(old_name) {
+                self.tabs.insert(new_name.clone(), tab);
+            }
+        }
+        for (_, tab_name) in self.tree.iter_all_tabs_mut() {
+            if let Some(new_name) = renamed_tabs.get(tab_name) {
+                *tab_name = new_name.clone();
+            }
+        }
+        if let Some(new_name) = self.active_tab.as_ref().and_then(|t| renamed_tabs.get(t)) {
+            self.active_tab = Some(new_name.clone());
+        }
+        if let Some(file) = &self.state.last_focused_file {
+            self.state.last_focused_file = relocate::relocated_path(file, &old_location, &destination);
+        }
+
+        self.refocus_file_explorer_to_project();
+        self.state.term_open_project_dir();
+        self.state.recent_projects.retain(|p| *p != old_location);
+        self.state.remember_project();
+        self.save_session();
+        println!("Project is now '{}' at {}.", new_name, destination.display());
+    }
+
     fn display_new_project_dialog(&mut self, ctx: &egui::Context) {
         let mut should_create_project = false;
         let mut should_close_dialog = false;
@@ -478,11 +680,17 @@ impl MainWindow {
                             .selected_text(selected_board_name)
                             .show_ui(ui, |ui| {
                                 for (i, board) in main_boards.iter().enumerate() {
-                                    ui.selectable_value(
-                                        &mut self.new_project_dialog.selected_board_index,
-                                        i,
-                                        board.get_name(),
-                                    );
+                                    if ui
+                                        .selectable_value(
+                                            &mut self.new_project_dialog.selected_board_index,
+                                            i,
+                                            board.get_name(),
+                                        )
+                                        .changed()
+                                    {
+                                        // starters belong to a board
+                                        self.new_project_dialog.selected_starter = None;
+                                    }
                                 }
                             });
                     } else {
@@ -490,6 +698,42 @@ impl MainWindow {
                     }
                 });
 
+                let starters = self
+                    .state
+                    .known_boards
+                    .iter()
+                    .filter(|b| b.is_main_board())
+                    .nth(self.new_project_dialog.selected_board_index)
+                    .map(|b| b.starters().to_vec())
+                    .unwrap_or_default();
+                if !starters.is_empty() {
+                    ui.horizontal(|ui| {
+                        ui.label("Starter:");
+                        let selected_starter_name = self
+                            .new_project_dialog
+                            .selected_starter
+                            .and_then(|i| starters.get(i))
+                            .map(|s| s.name.clone())
+                            .unwrap_or("Empty".to_string());
+                        egui::ComboBox::from_id_source("new_project_starter_combo")
+                            .selected_text(selected_starter_name)
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut self.new_project_dialog.selected_starter, None, "Empty");
+                                for (i, starter) in starters.iter().enumerate() {
+                                    ui.selectable_value(
+                                        &mut self.new_project_dialog.selected_starter,
+                                        Some(i),
+                                        &starter.name,
+                                    )
+                                    .on_hover_text(&starter.description);
+                                }
+                            });
+                    });
+                    if let Some(starter) = self.new_project_dialog.selected_starter.and_then(|i| starters.get(i)) {
+                        ui.label(&starter.description);
+                    }
+                }
+
                 ui.separator();
 
                 ui.horizontal(|ui| {
@@ -505,13 +749,6 @@ impl MainWindow {
 
         if should_create_project {
             self.create_new_project();
-            self.state.term_open_project_dir();
-
-            let source_node = self.find_file_node();
-            self.rebuild_tabs();
-            self.auto_open_main_rs(source_node);
-
-            self.state.reset_canvas = true;
         }
 
         if should_close_dialog {
@@ -536,6 +773,7 @@ impl MainWindow {
             .clone_from(&self.new_project_dialog.name);
 
         // select board for the project
+        let mut starter = None;
         if self.new_project_dialog.selected_board_index < self.state.known_boards.len() {
             let main_boards: Vec<_> = self
                 .state
@@ -547,6 +785,10 @@ impl MainWindow {
             if self.new_project_dialog.selected_board_index < main_boards.len() {
                 let board = main_boards[self.new_project_dialog.selected_board_index].clone();
                 new_project.add_board(&board);
+                starter = self
+                    .new_project_dialog
+                    .selected_starter
+                    .and_then(|i| board.starters().get(i).cloned());
             }
         }
 
@@ -556,55 +798,95 @@ impl MainWindow {
         // create the project by saving it to the specified location
         let project_folder = project_path.join(&self.new_project_dialog.name);
 
+        // a directory made here is removed again if generating the project doesn't finish
+        let created = !project_folder.exists();
         match std::fs::create_dir_all(&project_folder) {
             Ok(()) => {
-                // Set the location for saving
                 new_project.set_location(project_folder);
-
-                // .ironcoder.toml
-                match new_project.save() {
-                    Ok(()) => {
-                        // Get cargo-generate template
+                // Expand the main board's project template
+                let templates = match new_project.cargo_templates() {
+                    Ok(templates) => templates,
+                    Err(e) => {
                         if new_project.main_board.is_some() {
-                            match new_project.generate_cargo_template() {
-                                Ok(()) => {
-                                    println!("Project template generated.");
+                            warn!("couldn't generate the project template: {}", e);
+                        }
+                        vec![]
+                    }
+                };
+                let location = new_project.get_location_path().unwrap();
+                let project_name = self.new_project_dialog.name.clone();
+                let has_starter = starter.is_some();
+                // what was already in a directory that existed stays when cleaning up
+                let existing = if created { HashSet::new() } else { template::list_entries(&location) };
+                // the templates and starter are copied in the background, and the project
+                // file written and the project opened once they're there
+                self.state.tasks.spawn(
+                    "Generating project",
+                    {
+                        let location = location.clone();
+                        let project_name = project_name.clone();
+                        move |context| {
+                            for (i, template) in templates.iter().enumerate() {
+                                if context.is_cancelled() {
+                                    return Ok(());
                                 }
-                                Err(e) => {
-                                    println!(
-                                        "Is cargo-generate installed? Try: cargo install cargo-generate"
-                                    );
+                                context.set_progress(Some(i as f32 / templates.len() as f32), &template.crate_name);
+                                template
+                                    .expand()
+                                    .map_err(|e| format!("couldn't expand the {} template: {}", template.crate_name, e))?;
+                            }
+                            // lay the starter's wiring and sources over the template
+                            if let Some(starter) = starter.filter(|_| !context.is_cancelled()) {
+                                starter
+                                    .apply(&location, &project_name)
+                                    .map_err(|e| format!("couldn't apply starter \"{}\": {}", starter.name, e))?;
+                            }
+                            Ok(())
+                        }
+                    },
+                    move |state: &mut SharedState, result| {
+                        let generated = match result {
+                            Ok(Ok(())) => {
+                                // a starter brings its own project file
+                                if has_starter {
+                                    Ok(())
+                                } else {
+                                    new_project.save().map_err(|e| format!("couldn't save the project: {}", e))
                                 }
                             }
+                            Ok(Err(e)) => Err(e),
+                            Err(e) => Err(e.to_string()),
+                        };
+                        if let Err(e) = generated {
+                            warn!("generating project '{}' {}", project_name, e);
+                            let cleaned = if created {
+                                fs::remove_dir_all(&location)
+                            } else {
+                                template::remove_added(&location, &existing)
+                            };
+                            if let Err(e) = cleaned {
+                                warn!("couldn't clean up {}: {}", location.display(), e);
+                            }
+                            return;
                         }
-
                         // open the project
-                        let project_location = new_project.get_location_path();
-                        match self
-                            .state
-                            .project
-                            .load_from(&project_location.unwrap(), &self.state.known_boards)
-                        {
+                        match state.project.load_from(&location, &state.known_boards) {
                             Ok(()) => {
-                                self.refocus_file_explorer_to_project();
-                                self.show_new_project_dialog = false;
-                                let project_name = self.new_project_dialog.name.clone();
-                                self.new_project_dialog.reset();
-                                println!("Project '{}' created and opened.", project_name);
-
-                                // open main.rs during new project open
-                                let node = self.find_file_node();
-                                self.auto_open_main_rs(node);
+                                if has_starter {
+                                    state.project.add_wired_driver_dependencies();
+                                }
+                                state.project_loaded = true;
+                                info!("project '{}' created and opened", project_name);
+                                state.remember_project();
                             }
                             Err(e) => {
-                                println!("Project created but failed to open: {:?}", e);
+                                warn!("project '{}' created but couldn't be opened: {}", project_name, e);
                             }
                         }
-                    }
-                    Err(e) => {
-                        println!("Error creating project: {}", e);
-                    }
-                }
+                    },
+                );
+                self.show_new_project_dialog = false;
+                self.new_project_dialog.reset();
             }
             Err(e) => {
                 println!("Error creating project directory: {}", e);
@@ -612,6 +894,31 @@ impl MainWindow {
         }
     }
 
+    /// List the tasks running in the background, with their progress and a way to cancel them
+    fn display_task_bar(&mut self, ctx: &egui::Context) {
+        if !self.state.tasks.is_busy() {
+            return;
+        }
+        egui::TopBottomPanel::bottom("task_bar").show(ctx, |ui| {
+            for task in self.state.tasks.running() {
+                ui.horizontal(|ui| {
+                    let progress = task.progress();
+                    match progress.fraction {
+                        Some(fraction) => ui.add(egui::ProgressBar::new(fraction).desired_width(120.0).show_percentage()),
+                        None => ui.spinner(),
+                    };
+                    ui.label(task.name());
+                    if !progress.message.is_empty() {
+                        ui.weak(&progress.message);
+                    }
+                    if ui.add_enabled(!task.is_cancelled(), egui::Button::new("Cancel")).clicked() {
+                        task.cancel();
+                    }
+                });
+            }
+        });
+    }
+
     fn has_unsaved_changes(&self) -> bool {
         // Check if project has unsaved changes
         if self.state.project.has_unsaved_changes() {
@@ -673,6 +980,166 @@ impl MainWindow {
         }
     }
 
+    fn display_integrity_issues(&mut self, ctx: &egui::Context) {
+        let mut chosen_fix: Option<(Issue, Fix)> = None;
+
+        egui::Window::new("Project Problems")
+            .collapsible(false)
+            .resizable(true)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.label("Some parts of this project reference things that no longer exist.");
+                ui.separator();
+
+                egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                    for (i, issue) in self.state.project.integrity_issues.iter().enumerate() {
+                        ui.label(issue.to_string());
+                        ui.horizontal(|ui| {
+                            let fixes = issue.fixes(&self.state.project, &self.state.known_boards);
+                            let substitutes: Vec<&Fix> = fixes
+                                .iter()
+                                .filter(|f| matches!(f, Fix::Substitute(_)))
+                                .collect();
+                            if !substitutes.is_empty() {
+                                egui::ComboBox::from_id_salt(("integrity_substitute", i))
+                                    .selected_text("Substitute with...")
+                                    .show_ui(ui, |ui| {
+                                        for fix in substitutes {
+                                            if let Fix::Substitute(name) = fix {
+                                                if ui.selectable_label(false, name).clicked() {
+                                                    chosen_fix = Some((issue.clone(), fix.clone()));
+                                                }
+                                            }
+                                        }
+                                    });
+                            }
+                            if fixes.contains(&Fix::Drop) && ui.button("Drop").clicked() {
+                                chosen_fix = Some((issue.clone(), Fix::Drop));
+                            }
+                            if fixes.contains(&Fix::Keep) && ui.button("Keep as placeholder").clicked() {
+                                chosen_fix = Some((issue.clone(), Fix::Keep));
+                            }
+                        });
+                        ui.separator();
+                    }
+                });
+            });
+
+        if let Some((issue, fix)) = chosen_fix {
+            let known_boards = self.state.known_boards.clone();
+            integrity::apply_fix(&mut self.state.project, &issue, &fix, &known_boards);
+            self.state.reset_canvas = true;
+        }
+    }
+
+    /// Stash dirty project and file buffers so they survive a crash
+    fn autosave(&mut self) {
+        let store = RecoveryStore::default();
+        if let Err(e) = store.heartbeat() {
+            println!("Error updating the recovery heartbeat: {}", e);
+        }
+        if self.state.project.has_unsaved_changes() {
+            if let (Some(project_file), Ok(contents)) =
+                (self.state.project.project_file(), self.state.project.to_toml())
+            {
+                if let Err(e) = store.stash(&project_file, &contents) {
+                    println!("Error autosaving project: {}", e);
+                }
+            }
+        }
+        for tab in self.tabs.values() {
+            if let Some(file_tab) = tab.as_any().downcast_ref::<FileTab>() {
+                if let (false, Some(path)) = (file_tab.is_synced(), file_tab.path()) {
+                    if let Err(e) = store.stash(path, file_tab.code()) {
+                        println!("Error autosaving file '{}': {}", path.display(), e);
+                    }
+                }
+            }
+        }
+        self.save_session();
+        self.last_autosave = Instant::now();
+    }
+
+    fn display_recovery_prompt(&mut self, ctx: &egui::Context) {
+        let mut should_recover = false;
+        let mut should_discard = false;
+
+        egui::Window::new("Recover Unsaved Work")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.label("Iron Coder didn't close cleanly. These files have unsaved changes:");
+                ui.separator();
+                for entry in &self.recovery_entries {
+                    ui.label(entry.original.display().to_string());
+                }
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    if ui.button("Recover").clicked() {
+                        should_recover = true;
+                    }
+                    if ui.button("Discard").clicked() {
+                        should_discard = true;
+                    }
+                });
+            });
+
+        if should_recover {
+            for entry in std::mem::take(&mut self.recovery_entries) {
+                self.recover_entry(entry);
+            }
+        }
+        if should_recover || should_discard {
+            RecoveryStore::default().discard_abandoned();
+            self.recovery_entries.clear();
+        }
+    }
+
+    /// Restore one stashed buffer, leaving it unsaved so the user decides whether to keep it
+    fn recover_entry(&mut self, entry: RecoveryEntry) {
+        let is_project_file = entry.original.file_name().is_some_and(|n| n == ".ironcoder.toml");
+        if is_project_file {
+            let Some(project_directory) = entry.original.parent() else {
+                return;
+            };
+            match self.state.project.load_from_str(
+                project_directory,
+                &entry.contents,
+                &self.state.known_boards,
+            ) {
+                Ok(()) => {
+                    self.state.project.mark_unsaved();
+                    self.state.reset_canvas = true;
+                    self.refocus_file_explorer_to_project();
+                }
+                Err(e) => println!("Error recovering project: {}", e),
+            }
+            return;
+        }
+
+        if !entry.original.exists() {
+            // the file itself was lost, so recreate it from the stash
+            if let Err(e) = fs::write(&entry.original, &entry.contents) {
+                println!("Error recovering file '{}': {}", entry.original.display(), e);
+                return;
+            }
+        }
+        let node = self.find_file_node();
+        self.open_file(&entry.original, node);
+
+        let original = entry.original.canonicalize().unwrap_or(entry.original.clone());
+        for tab in self.tabs.values_mut() {
+            if let Some(file_tab) = tab.as_any_mut().downcast_mut::<FileTab>() {
+                if file_tab.path() == Some(&original) {
+                    file_tab.restore_unsaved(entry.contents);
+                    return;
+                }
+            }
+        }
+    }
+
     fn save_project_and_files(&mut self) {
         // Save the project
         match self.state.project.save() {
@@ -716,13 +1183,21 @@ impl MainWindow {
 
     fn execute_pending_action(&mut self) {
         if let Some(action) = self.pending_action.clone() {
+            // every action leaves the current project, so keep its workspace for next time
+            self.save_session();
             match action {
                 PendingAction::OpenProject => {
                     self.open_project_immediately();
                 }
+                PendingAction::OpenRecent(location) => {
+                    self.open_recent_project(&location);
+                }
                 PendingAction::NewProject => {
                     self.show_new_project_dialog = true;
                 }
+                PendingAction::OpenExample(board, example) => {
+                    self.open_example(&board, &example);
+                }
                 PendingAction::Exit => {
                     self.should_exit = true;
                 }
@@ -738,16 +1213,160 @@ impl MainWindow {
                 self.refocus_file_explorer_to_project();
                 println!("Project opened successfully.");
 
-                let source_node = self.find_file_node();
-
                 self.rebuild_tabs();
                 println!("{:?}", self.tabs.keys());
 
-                // open main.rs during project open
-                self.auto_open_main_rs(source_node);
+                self.restore_session();
+                self.state.remember_project();
+            }
+            Err(e) => {
+                println!("Failed to open project: {}", e);
+            }
+        }
+    }
+
+    fn open_recent_project(&mut self, location: &Path) {
+        self.state.clear_terminal();
+        match self.state.project.load_from(location, &self.state.known_boards) {
+            Ok(()) => {
+                self.refocus_file_explorer_to_project();
+                self.state.term_open_project_dir();
+                self.rebuild_tabs();
+                self.state.reset_canvas = true;
+                self.restore_session();
+                self.state.remember_project();
+                println!("Project opened successfully.");
+            }
+            Err(e) => {
+                println!("Failed to open project: {}", e);
+            }
+        }
+    }
+
+    /// Save the open files, dock layout and canvas view of the current project
+    fn save_session(&self) {
+        let Some(location) = self.state.project.get_location_path() else {
+            return;
+        };
+        let mut session = Session {
+            active_tab: self.active_tab.clone(),
+            layout: Some(self.tree.clone()),
+            ..Default::default()
+        };
+        for (_, tab_name) in self.tree.iter_all_tabs() {
+            let Some(tab) = self.tabs.get(tab_name) else {
+                continue;
+            };
+            if let Some(file_tab) = tab.as_any().downcast_ref::<FileTab>() {
+                let (cursor, scroll) = file_tab.view();
+                session.open_files.push(FileSession {
+                    path: PathBuf::from(tab_name),
+                    cursor,
+                    scroll: [scroll.x, scroll.y],
+                });
+            } else if let Some(canvas) = tab.as_any().downcast_ref::<CanvasTab>() {
+                session.canvas = Some(canvas.view());
+            }
+        }
+        if let Err(e) = SessionStore::default().save(&location, &session) {
+            println!("Error saving session: {}", e);
+        }
+    }
+
+    /// Reopen the files, dock layout and canvas view the current project was left with
+    /// Projects without a session just get their main.rs opened
+    fn restore_session(&mut self) {
+        let session = self
+            .state
+            .project
+            .get_location_path()
+            .and_then(|location| SessionStore::default().load(&location));
+        let Some(session) = session else {
+            let node = self.find_file_node();
+            self.auto_open_main_rs(node);
+            return;
+        };
+
+        if let Some(mut layout) = session.layout {
+            let tab_names: Vec<String> = layout.iter_all_tabs().map(|(_, name)| name.clone()).collect();
+            for tab_name in tab_names {
+                if self.tabs.contains_key(&tab_name) {
+                    continue;
+                }
+                if let Some(tab) = Self::create_tab(&tab_name) {
+                    self.tabs.insert(tab_name, tab);
+                } else if Path::new(&tab_name).is_file() {
+                    let mut file_tab = FileTab::default();
+                    match file_tab.load_from_file(Path::new(&tab_name)) {
+                        Ok(()) => {
+                            self.tabs.insert(tab_name, Box::new(file_tab));
+                        }
+                        Err(e) => println!("Error reopening '{}': {}", tab_name, e),
+                    }
+                }
+            }
+            // files that were deleted since can't come back
+            layout.retain_tabs(|tab_name| self.tabs.contains_key(tab_name));
+            let keys: Vec<String> = self.tabs.keys().cloned().collect();
+            for key in keys {
+                if !OPENABLE_TABS.contains(&key.as_str()) && layout.find_tab(&key).is_none() {
+                    self.tabs.remove(&key);
+                }
+            }
+            self.tree = layout;
+        } else {
+            let node = self.find_file_node();
+            for file in &session.open_files {
+                self.open_file(&file.path, node);
+            }
+        }
+
+        for file in &session.open_files {
+            let tab_name = file.path.display().to_string();
+            if let Some(file_tab) = self
+                .tabs
+                .get_mut(&tab_name)
+                .and_then(|t| t.as_any_mut().downcast_mut::<FileTab>())
+            {
+                file_tab.restore_view(file.cursor, egui::vec2(file.scroll[0], file.scroll[1]));
+            }
+        }
+        if let Some(active_tab) = session.active_tab {
+            if let Some(tab_location) = self.tree.find_tab(&active_tab) {
+                self.tree.set_active_tab(tab_location);
+                self.active_tab = Some(active_tab);
+            }
+        }
+        self.state.canvas_view_to_restore = session.canvas;
+    }
+
+    /// Copy a board example into a new project in a folder the user picks, and open it
+    fn open_example(&mut self, board: &Rc<Board>, example: &Path) {
+        let Some(parent) = FileDialog::new()
+            .set_title("Choose where to create the example project")
+            .pick_folder()
+        else {
+            return;
+        };
+        let name = example
+            .file_stem()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| "example".to_string());
+        let destination = parent.join(name);
+        match crate::project::example::create_from_example(board, example, &destination, &self.state.known_boards) {
+            Ok(project) => {
+                self.state.project = project;
+                self.refocus_file_explorer_to_project();
+                self.state.term_open_project_dir();
+                println!("Project created from example {}.", example.display());
+
+                self.rebuild_tabs();
+                self.restore_session();
+                self.state.reset_canvas = true;
+                self.state.remember_project();
             }
             Err(e) => {
-                println!("Failed to open project.");
+                println!("Error creating project from example: {}", e);
             }
         }
     }
@@ -829,7 +1448,10 @@ impl MainWindow {
 impl eframe::App for MainWindow {
     fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
         println!("Exiting application, saving settings...");
+        self.save_session();
         self.state.save_settings();
+        // a clean exit means nothing of this instance needs recovering
+        RecoveryStore::default().discard_all();
     }
     fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
         // Check for close request
@@ -854,10 +1476,25 @@ impl eframe::App for MainWindow {
             self.display_new_project_dialog(ctx);
         }
 
+        if self.show_relocate_dialog {
+            self.display_relocate_dialog(ctx);
+        }
+
         if self.show_save_prompt {
             self.display_save_prompt(ctx);
         }
 
+        if !self.state.project.integrity_issues.is_empty() {
+            self.display_integrity_issues(ctx);
+        }
+
+        if !self.recovery_entries.is_empty() {
+            self.display_recovery_prompt(ctx);
+        } else if self.last_autosave.elapsed() >= recovery::AUTOSAVE_INTERVAL {
+            // only autosave once any leftover work has been recovered or discarded
+            self.autosave();
+        }
+
         // process keybindings only when terminal is not active
         if !self.is_terminal_tab_active() {
             if self.state.keybindings.is_pressed(ctx, "save_file") {
@@ -920,11 +1557,11 @@ impl eframe::App for MainWindow {
 
             // Build operations ================
             if self.state.keybindings.is_pressed(ctx, "build_project") {
-                self.state.build_project();
+                self.build_project();
             }
 
             if self.state.keybindings.is_pressed(ctx, "run_project") {
-                self.state.run_project();
+                self.flash_project();
             }
 
             // Tab navigation ================
@@ -1067,6 +1704,72 @@ impl eframe::App for MainWindow {
             self.open_file(&file_path, node);
         }
 
+        if let Some(build) = self.state.build.as_mut() {
+            build.poll();
+            if build.is_running() {
+                ctx.request_repaint_after(std::time::Duration::from_millis(100));
+            }
+        }
+
+        if self.state.poll_tasks() {
+            ctx.request_repaint_after(std::time::Duration::from_millis(100));
+        }
+
+        // put the workspace back the way a project opened in the background was left
+        if self.state.project_loaded {
+            self.state.project_loaded = false;
+            self.state.term_open_project_dir();
+            self.rebuild_tabs();
+            self.refocus_file_explorer_to_project();
+            self.restore_session();
+            self.state.reset_canvas = true;
+        }
+
+        if self.state.poll_flash() {
+            ctx.request_repaint_after(std::time::Duration::from_millis(100));
+        }
+
+        if let Some(monitor) = self.state.serial_monitor.as_mut() {
+            monitor.poll();
+            if monitor.is_open() {
+                ctx.request_repaint_after(std::time::Duration::from_millis(100));
+            }
+        }
+
+        if let Some((file_path, line, column)) = self.state.requested_file_position.take() {
+            let node = self.find_file_node();
+            self.open_file(&file_path, node);
+            let tab_name = file_path.display().to_string();
+            if let Some(file_tab) = self
+                .tabs
+                .get_mut(&tab_name)
+                .and_then(|t| t.as_any_mut().downcast_mut::<FileTab>())
+            {
+                file_tab.go_to(line, column);
+            }
+            self.show_tab(&tab_name);
+        }
+
+        if let Some((board, example)) = self.state.requested_example.take() {
+            self.prompt_save_if_needed(PendingAction::OpenExample(board, example));
+        }
+
+        match self.state.start_page_request.take() {
+            Some(StartPageRequest::NewProject) => self.prompt_save_if_needed(PendingAction::NewProject),
+            Some(StartPageRequest::OpenProject) => {
+                self.state.clear_terminal();
+                self.prompt_save_if_needed(PendingAction::OpenProject);
+                self.state.term_open_project_dir();
+                self.state.reset_canvas = true;
+            }
+            Some(StartPageRequest::OpenRecent(location)) => {
+                self.prompt_save_if_needed(PendingAction::OpenRecent(location));
+            }
+            None => {}
+        }
+
+        self.display_task_bar(ctx);
+
         let mut context = WindowContext {
             tabs: &mut self.tabs,
             state: &mut self.state,
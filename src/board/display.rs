@@ -5,9 +5,18 @@ use egui::text::{LayoutJob, TextFormat};
 use egui::widgets::Widget;
 use egui::{Color32, FontFamily, FontId, Response, Ui};
 use egui_extras::RetainedImage;
-use log::{debug, info};
+use log::debug;
+use std::path::PathBuf;
 use std::rc::Rc;
 
+/// Key of the example a board widget's link asked to open, in egui's temporary data
+const REQUESTED_EXAMPLE_ID: &str = "requested_example";
+
+/// Take the example a board widget's link was clicked for, if any
+pub fn take_requested_example(ctx: &egui::Context) -> Option<PathBuf> {
+    ctx.data_mut(|d| d.remove_temp(egui::Id::new(REQUESTED_EXAMPLE_ID)))
+}
+
 /// Construct a LayoutJob with a bold heading, followed by a colon,
 /// followed by some content, all with custom colors.
 fn make_field_widget_text(
@@ -111,8 +120,10 @@ impl Widget for Board {
                     });
                     for e in self.examples {
                         ui.horizontal(|ui| {
-                            if ui.link(e.file_name().unwrap().to_str().unwrap()).clicked() {
-                                info!("TODO - open the example!")
+                            let link = ui.link(e.file_name().unwrap().to_str().unwrap());
+                            if link.on_hover_text("Open as a new project").clicked() {
+                                ui.ctx().data_mut(|d| d.insert_temp(egui::Id::new(REQUESTED_EXAMPLE_ID), e.clone()));
+                                ui.close_menu();
                             };
                         });
                     }
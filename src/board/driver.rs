@@ -0,0 +1,45 @@
+//! Driver crates that generated code uses to talk to a peripheral board
+use serde::Deserialize;
+
+use crate::board::BoardStandards;
+
+/// A driver crate declared in a peripheral board's manifest, along with the code that
+/// constructs it once the peripheral is wired to a main board
+#[derive(Debug, Clone, Deserialize)]
+pub struct Driver {
+    /// Name of the crate on crates.io
+    #[serde(rename = "crate")]
+    pub crate_name: String,
+    pub version: String,
+    #[serde(default)]
+    pub features: Vec<String>,
+    /// Interface the driver talks over (I2C, SPI or GPIO)
+    pub interface: String,
+    /// Peripheral pin the driver is attached to, for GPIO drivers
+    #[serde(default)]
+    pub pin: Option<u32>,
+    /// Main board standard the constructor is written for, any standard if unset
+    #[serde(default)]
+    pub standard: Option<BoardStandards>,
+    /// Statements constructing the driver, using the {{name}}, {{bus}}, {{address}}, {{cs}} and {{pin}} placeholders
+    pub constructor: String,
+}
+
+impl Driver {
+    /// Whether the constructor refers to a placeholder, e.g. `address`
+    pub fn uses_placeholder(&self, key: &str) -> bool {
+        self.constructor.contains(&format!("{{{{{}}}}}", key))
+    }
+
+    /// Fill in the constructor's placeholders, returning one string per line of code
+    pub fn instantiate(&self, placeholders: &[(&str, String)]) -> Vec<String> {
+        let mut code = self.constructor.clone();
+        for (key, value) in placeholders {
+            code = code.replace(&format!("{{{{{}}}}}", key), value);
+        }
+        code.lines()
+            .map(|l| l.trim_end().to_string())
+            .filter(|l| !l.trim().is_empty())
+            .collect()
+    }
+}
@@ -22,14 +22,22 @@ pub mod display;
 pub mod pinout;
 pub use pinout::{GPIODirection, Pin, Pinout};
 
+pub mod driver;
+pub use driver::Driver;
+
+pub mod profile;
+pub use profile::{BuildProfile, DeviceKind, Runner, UsbId};
+
 use std::cell::RefCell;
 use std::rc::Rc;
 
 use crate::board::pinout::RoleAssignment;
+use crate::project::starter::{self, Starter};
+use crate::snippets::{self, Snippet};
 
 /// These are the various standard development board form factors
 #[non_exhaustive]
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum BoardStandards {
     Feather,
     Arduino,
@@ -76,6 +84,12 @@ pub struct Board {
     /// A list of examples
     #[serde(skip)]
     examples: Vec<PathBuf>,
+    /// Code snippets shipped in the board's snippets directory
+    #[serde(skip)]
+    snippets: Vec<Snippet>,
+    /// Starter projects shipped in the board's starters directory
+    #[serde(skip)]
+    starters: Vec<Starter>,
     /// An local path of a project template
     #[serde(skip)]
     template_dir: Option<PathBuf>,
@@ -86,6 +100,12 @@ pub struct Board {
     required_crates: Option<Vec<String>>,
     /// A list of related, optional crates
     related_crates: Option<Vec<String>>,
+    /// Driver crates generated code uses for this peripheral
+    #[serde(rename = "driver")]
+    drivers: Vec<Driver>,
+    /// How a main board's firmware is built and flashed
+    #[serde(rename = "build")]
+    build_profile: BuildProfile,
 }
 
 impl fmt::Debug for Board {
@@ -93,6 +113,8 @@ impl fmt::Debug for Board {
         write!(f, "Board {}\n", self.name)?;
         write!(f, "  is main board? {}\n", self.is_main_board)?;
         write!(f, "  num examples: {}\n", self.examples.len())?;
+        write!(f, "  num snippets: {}\n", self.snippets.len())?;
+        write!(f, "  num starters: {}\n", self.starters.len())?;
         write!(
             f,
             "  num required crates: {}\n",
@@ -157,19 +179,54 @@ impl Board {
 
         // See if there are any examples
         if let Ok(examples_path) = path.parent().unwrap().join("examples").canonicalize() {
-            for (_i, e) in examples_path.read_dir().unwrap().enumerate() {
-                let example_path = e.unwrap().path();
-                b.examples.push(example_path);
+            if let Ok(entries) = examples_path.read_dir() {
+                b.examples = entries.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+                b.examples.sort();
             }
         }
 
+        // Load any snippets shipped alongside the board
+        let snippets_path = path.parent().unwrap().join("snippets");
+        b.snippets = snippets::load_snippets(&snippets_path, b.get_name());
+
+        // Load any starter projects shipped alongside the board
+        let starters_path = path.parent().unwrap().join("starters");
+        b.starters = starter::load_starters(&starters_path);
+
         return Ok(b);
     }
 
+    /// A board with only a name, standing in for one missing from the board library
+    pub fn placeholder(name: &str) -> Self {
+        Board {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
     pub fn get_name(&self) -> &str {
         self.name.as_str()
     }
 
+    pub fn snippets(&self) -> &[Snippet] {
+        &self.snippets
+    }
+
+    pub fn starters(&self) -> &[Starter] {
+        &self.starters
+    }
+
+    pub fn examples(&self) -> &[PathBuf] {
+        &self.examples
+    }
+
+    /// Find an example by its file or directory name, with or without the .rs extension
+    pub fn find_example(&self, name: &str) -> Option<&PathBuf> {
+        self.examples.iter().find(|e| {
+            e.file_name().is_some_and(|n| n == name) || e.file_stem().is_some_and(|n| n == name)
+        })
+    }
+
     pub fn required_crates(&self) -> Option<Vec<String>> {
         self.required_crates.clone()
     }
@@ -177,6 +234,27 @@ impl Board {
     pub fn related_crates(&self) -> Option<Vec<String>> {
         self.related_crates.clone()
     }
+    /// Get the driver this peripheral declares for an interface, for use with the given main board standard
+    pub fn get_driver(&self, interface: &str, standard: Option<&BoardStandards>) -> Option<&Driver> {
+        self.drivers.iter().find(|d| {
+            d.interface == interface && (d.standard.is_none() || d.standard.as_ref() == standard)
+        })
+    }
+
+    /// RAM in KB
+    pub fn ram(&self) -> Option<isize> {
+        self.ram
+    }
+
+    /// Flash in KB
+    pub fn flash(&self) -> Option<isize> {
+        self.flash
+    }
+
+    pub fn build_profile(&self) -> &BuildProfile {
+        &self.build_profile
+    }
+
     pub fn is_main_board(&self) -> bool {
         self.is_main_board
     }
@@ -203,7 +281,12 @@ impl Board {
 
 /// Iteratively gather the Boards from the filesystem.
 pub fn get_boards(boards_dir: &Path) -> Vec<Rc<Board>> {
-    let mut r = Vec::<Rc<Board>>::new();
+    load_boards(boards_dir).into_iter().map(Rc::new).collect()
+}
+
+/// Gather the Boards from the filesystem without sharing them, so it can be done off the UI thread
+pub fn load_boards(boards_dir: &Path) -> Vec<Board> {
+    let mut r = Vec::<Board>::new();
     if let Ok(manufacturers) = fs::read_dir(boards_dir) {
         // first tier of organization is by manufacturer
         for manufacturer in manufacturers {
@@ -255,7 +338,7 @@ pub fn get_boards(boards_dir: &Path) -> Vec<Rc<Board>> {
                                     );
                                 }
                                 board.pinout.populate_pins(board.is_main_board());
-                                r.push(Rc::new(board));
+                                r.push(board);
                             }
                             Err(e) => {
                                 println!(
@@ -58,6 +58,15 @@ pub struct Interface {
     pub roles: Vec<InterfaceRole>,
     #[serde(default)]
     pub alias_fmt: Option<String>,
+    /// Hardware peripheral blocks that can drive this interface (e.g. I2C0, I2C1)
+    #[serde(default)]
+    pub instances: Vec<String>,
+    /// Bus address the peripheral answers on out of the box (I2C only)
+    #[serde(default)]
+    pub default_address: Option<u8>,
+    /// Other bus addresses the peripheral can be configured for, e.g. via jumpers (I2C only)
+    #[serde(default)]
+    pub addresses: Vec<u8>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -211,6 +220,29 @@ impl Pinout {
         self.pins.iter().find(|&p| p.physical == *physical)
     }
 
+    /// Get the peripheral instances (e.g. SPI2, SPI3) declared for an interface
+    pub fn get_interface_instances(&self, interface: &str) -> Vec<String> {
+        self.interfaces
+            .iter()
+            .find(|i| i.name == interface)
+            .map(|i| i.instances.clone())
+            .unwrap_or_default()
+    }
+
+    /// Get the addresses a peripheral can use on an interface's bus, default address first
+    pub fn get_interface_addresses(&self, interface: &str) -> Vec<u8> {
+        let Some(interface) = self.interfaces.iter().find(|i| i.name == interface) else {
+            return vec![];
+        };
+        let mut addresses: Vec<u8> = interface.default_address.into_iter().collect();
+        for address in &interface.addresses {
+            if !addresses.contains(address) {
+                addresses.push(*address);
+            }
+        }
+        addresses
+    }
+
     pub fn ui_show_pin_info(&self, physical: &u32, ui: &mut egui::Ui) {
         if let Some(pin) = self.get_pin(physical) {
             ui.heading(format!("Pin {}", pin.silkscreen));
@@ -0,0 +1,210 @@
+//! How a main board's firmware is built and flashed, as declared in its manifest
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Program that puts a built binary onto the board
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Runner {
+    Ravedude,
+    Espflash,
+    ProbeRs,
+    Elf2uf2,
+}
+
+impl Runner {
+    /// The executable and the arguments it is always run with
+    pub fn command(&self) -> &'static [&'static str] {
+        match self {
+            Runner::Ravedude => &["ravedude"],
+            Runner::Espflash => &["espflash", "flash"],
+            Runner::ProbeRs => &["probe-rs", "run"],
+            Runner::Elf2uf2 => &["elf2uf2-rs"],
+        }
+    }
+
+    pub fn executable(&self) -> &'static str {
+        self.command()[0]
+    }
+
+    /// What the runner is pointed at to reach the board
+    pub fn device_kind(&self) -> Option<DeviceKind> {
+        match self {
+            Runner::Ravedude | Runner::Espflash => Some(DeviceKind::SerialPort),
+            Runner::ProbeRs => Some(DeviceKind::Probe),
+            // copies to whichever RP2040 is mounted in bootloader mode
+            Runner::Elf2uf2 => None,
+        }
+    }
+
+    /// Arguments that point the runner at a serial port or debug probe
+    pub fn device_args(&self, device: &str) -> Vec<String> {
+        let flag = match self {
+            Runner::Ravedude => "-P",
+            Runner::Espflash => "--port",
+            Runner::ProbeRs => "--probe",
+            Runner::Elf2uf2 => return vec![],
+        };
+        vec![flag.to_string(), device.to_string()]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceKind {
+    SerialPort,
+    /// A debug probe, picked by its VID:PID[:serial] selector
+    Probe,
+}
+
+/// USB vendor and product ID a board shows up with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct UsbId {
+    pub vid: u16,
+    pub pid: u16,
+}
+
+impl fmt::Display for UsbId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04x}:{:04x}", self.vid, self.pid)
+    }
+}
+
+impl fmt::Display for Runner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.executable())
+    }
+}
+
+/// The `[build]` table of a main board's manifest
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct BuildProfile {
+    /// Target triple the firmware is compiled for
+    pub target: Option<String>,
+    /// Rust toolchain channel, left to the crate's rust-toolchain.toml if unset
+    pub channel: Option<String>,
+    /// Standard library crates to build from source, for targets without a prebuilt one
+    pub build_std: Vec<String>,
+    /// Flags for rustc, which belong in the crate's cargo config rather than on the command line
+    pub rustflags: Vec<String>,
+    pub runner: Option<Runner>,
+    /// Arguments passed to the runner ahead of the binary
+    pub flash_args: Vec<String>,
+    /// Toolchain shipped in the IDE's Redist directory that the board needs
+    pub redist: Option<String>,
+    /// Linker to take from the Redist toolchain
+    pub linker: Option<String>,
+    /// Baud rate the board's firmware talks over serial at
+    pub baud_rate: Option<u32>,
+    /// USB IDs the board, its serial bridge or its bootloader shows up with
+    pub usb_ids: Vec<UsbId>,
+    /// Oldest versions of the tools above that work with the board, by tool name
+    pub min_versions: BTreeMap<String, String>,
+}
+
+impl BuildProfile {
+    /// Arguments for a cargo subcommand that honour the profile
+    fn cargo_args(&self, subcommand: &str) -> Vec<String> {
+        let mut args = vec![];
+        if let Some(channel) = &self.channel {
+            args.push(format!("+{}", channel));
+        }
+        args.push(subcommand.to_string());
+        if let Some(target) = &self.target {
+            args.push("--target".to_string());
+            args.push(target.clone());
+        }
+        if !self.build_std.is_empty() {
+            args.push("-Z".to_string());
+            args.push(format!("build-std={}", self.build_std.join(",")));
+        }
+        args
+    }
+
+    /// Arguments of the cargo command that builds the firmware
+    pub fn build_args(&self) -> Vec<String> {
+        let mut args = self.cargo_args("build");
+        args.push("--message-format=json".to_string());
+        args
+    }
+
+    /// Arguments of the cargo command that builds the firmware and flashes it with the runner
+    /// `device` is the serial port or probe to flash through; the runner looks for one if unset
+    pub fn run_args(&self, device: Option<&str>) -> Vec<String> {
+        let mut args = self.cargo_args("run");
+        if let (Some(target), Some(runner)) = (&self.target, self.runner) {
+            let runner: Vec<String> = runner
+                .command()
+                .iter()
+                .map(|s| s.to_string())
+                .chain(self.flash_args.iter().cloned())
+                .chain(device.map(|d| runner.device_args(d)).unwrap_or_default())
+                // TOML literal strings, so the value survives being double-quoted on a command line
+                .map(|s| format!("'{}'", s))
+                .collect();
+            // overrides whatever runner the crate's .cargo/config.toml names
+            args.push("--config".to_string());
+            args.push(format!("target.{}.runner=[{}]", target, runner.join(", ")));
+        }
+        args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::board::get_boards;
+    use crate::project::Project;
+    use std::path::Path;
+
+    #[test]
+    fn test_build_profiles_drive_cargo_and_toolchain_config() {
+        use crate::board::Runner;
+
+        let known_boards = get_boards(Path::new("./iron-coder-boards"));
+        let find = |name: &str| known_boards.iter().find(|b| b.get_name() == name).unwrap().clone();
+
+        let uno = find("Arduino Uno");
+        let profile = uno.build_profile();
+        assert_eq!(profile.runner, Some(Runner::Ravedude));
+        assert_eq!(
+            profile.build_args(),
+            vec!["+nightly-2025-04-27", "build", "--target", "avr-none", "-Z", "build-std=core", "--message-format=json"]
+        );
+        let esp = find("ESP32-C6-DevKitM-1");
+        assert_eq!(
+            esp.build_profile().run_args(Some("/dev/ttyACM0")),
+            vec![
+                "+stable",
+                "run",
+                "--target",
+                "riscv32imac-unknown-none-elf",
+                "--config",
+                "target.riscv32imac-unknown-none-elf.runner=['espflash', 'flash', '--chip', 'esp32c6', '--port', '/dev/ttyACM0']",
+            ]
+        );
+        // every main board says how to build and flash it
+        for board in known_boards.iter().filter(|b| b.is_main_board()) {
+            assert!(board.build_profile().target.is_some(), "{} has no target", board.get_name());
+            assert!(board.build_profile().runner.is_some(), "{} has no runner", board.get_name());
+        }
+
+        // only the board whose profile takes a linker from Redist gets one
+        let temp = crate::test::temp_dir();
+        let dir = temp.path().to_path_buf();
+        let mut project = Project::default();
+        project.add_board(&uno).unwrap();
+        project.set_location(dir.clone());
+        project.generate_cargo_template().unwrap();
+        let esp = project.add_board(&esp).unwrap();
+        let member_dir = project.firmware_crate_dir(&esp.borrow().id).unwrap();
+        project.update_toolchain_location();
+        let config: toml::Table = std::fs::read_to_string(dir.join(".cargo/config.toml")).unwrap().parse().unwrap();
+        // the template's cfg(target_arch = "avr") table is kept rather than adding a second one
+        assert!(config["target"].get("avr-none").is_none());
+        let linker = config["target"]["cfg(target_arch = \"avr\")"]["linker"].as_str().unwrap();
+        assert!(Path::new(linker).file_stem().is_some_and(|s| s == "avr-gcc"));
+        let member_config = std::fs::read_to_string(member_dir.join(".cargo/config.toml")).unwrap();
+        assert!(!member_config.contains("linker"));
+    }
+}
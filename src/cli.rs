@@ -0,0 +1,126 @@
+//! Commands that run without opening the editor window
+use std::path::Path;
+
+use crate::board;
+use crate::project::example;
+use crate::project::integrity::{self, Fix};
+
+const USAGE: &str = "usage:
+    iron_coder                                   open the editor
+    iron_coder check <project dir> [--fix drop|keep]
+                                                 report dangling references in a project
+    iron_coder example <board> [<example> <destination dir>]
+                                                 list a board's examples, or create a project from one";
+
+/// Run the command given on the command line, if any
+/// Returns the process exit code, or None when the editor should be opened instead
+pub fn run(args: &[String]) -> Option<i32> {
+    let command = args.first()?;
+    let code = match command.as_str() {
+        "check" => check(&args[1..]),
+        "example" => example(&args[1..]),
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            0
+        }
+        other => {
+            eprintln!("unknown command \"{}\"\n{}", other, USAGE);
+            2
+        }
+    };
+    Some(code)
+}
+
+fn check(args: &[String]) -> i32 {
+    let mut project_dir = None;
+    let mut fix = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--fix" => {
+                fix = match args.next().map(|s| s.as_str()) {
+                    Some("drop") => Some(Fix::Drop),
+                    Some("keep") => Some(Fix::Keep),
+                    _ => {
+                        eprintln!("--fix takes \"drop\" or \"keep\"\n{}", USAGE);
+                        return 2;
+                    }
+                }
+            }
+            dir if project_dir.is_none() => project_dir = Some(dir.to_string()),
+            other => {
+                eprintln!("unexpected argument \"{}\"\n{}", other, USAGE);
+                return 2;
+            }
+        }
+    }
+    let Some(project_dir) = project_dir else {
+        eprintln!("{}", USAGE);
+        return 2;
+    };
+
+    let known_boards = board::get_boards(Path::new("./iron-coder-boards"));
+    match integrity::check_headless(Path::new(&project_dir), &known_boards, fix.clone()) {
+        Ok(issues) if issues.is_empty() => {
+            println!("no issues found");
+            0
+        }
+        Ok(issues) => {
+            for issue in &issues {
+                println!("{}", issue);
+            }
+            match fix {
+                Some(fix) => {
+                    println!("applied \"{}\" to {} issue(s)", fix, issues.len());
+                    0
+                }
+                None => 1,
+            }
+        }
+        Err(e) => {
+            eprintln!("couldn't load project: {}", e);
+            2
+        }
+    }
+}
+
+fn example(args: &[String]) -> i32 {
+    let (board_name, example_and_destination) = match args {
+        [board_name] => (board_name, None),
+        [board_name, example_name, destination] => (board_name, Some((example_name, destination))),
+        _ => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+
+    let known_boards = board::get_boards(Path::new("./iron-coder-boards"));
+    let Some(board) = known_boards.iter().find(|b| b.get_name() == board_name) else {
+        eprintln!("unknown board \"{}\"", board_name);
+        return 2;
+    };
+    let Some((example_name, destination)) = example_and_destination else {
+        if board.examples().is_empty() {
+            println!("{} has no examples", board.get_name());
+        }
+        for e in board.examples() {
+            println!("{}", e.file_stem().unwrap_or_default().to_string_lossy());
+        }
+        return 0;
+    };
+    let Some(example_path) = board.find_example(example_name) else {
+        eprintln!("{} has no example \"{}\"", board.get_name(), example_name);
+        return 2;
+    };
+
+    match example::create_from_example(board, example_path, Path::new(destination), &known_boards) {
+        Ok(_) => {
+            println!("created project {} from example \"{}\"", destination, example_name);
+            0
+        }
+        Err(e) => {
+            eprintln!("couldn't create project: {}", e);
+            1
+        }
+    }
+}
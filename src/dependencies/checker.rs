@@ -1,9 +1,23 @@
+//! Checks that the tools the open project's boards are built and flashed with are installed
+//!
+//! What to check comes from each main board's build profile: its rustup toolchain, target or
+//! `rust-src` for targets built from source, its runner and any linker from Redist. Every
+//! requirement knows the command that reports on it and the command that installs it.
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
 use which::which;
 
+use crate::board::{BuildProfile, Runner};
+use crate::dependencies::redist::ToolchainManager;
+use crate::project::toolchain::TargetArch;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum DependencyStatus {
     Installed,
     NotFound,
+    /// Installed, but older than a board needs
+    Outdated,
     Error(String),
 }
 
@@ -16,40 +30,402 @@ impl DependencyStatus {
         match self {
             DependencyStatus::Installed => "Installed",
             DependencyStatus::NotFound => "Not Found",
+            DependencyStatus::Outdated => "Outdated",
             DependencyStatus::Error(_) => "Error",
         }
     }
 }
 
+/// How a requirement is checked
+#[derive(Debug, Clone, PartialEq)]
+pub enum Check {
+    /// An executable, asked for its version
+    Tool { program: PathBuf, version_args: Vec<String> },
+    RustupToolchain(String),
+    RustupTarget { toolchain: String, target: String },
+    RustupComponent { toolchain: String, component: String },
+}
+
+impl Check {
+    /// The command whose output tells whether the requirement is met
+    pub fn command(&self) -> (PathBuf, Vec<String>) {
+        let rustup = |args: &[&str]| (PathBuf::from("rustup"), args.iter().map(|a| a.to_string()).collect());
+        match self {
+            Check::Tool { program, version_args } => (program.clone(), version_args.clone()),
+            Check::RustupToolchain(_) => rustup(&["toolchain", "list"]),
+            Check::RustupTarget { toolchain, .. } => {
+                rustup(&["target", "list", "--installed", "--toolchain", toolchain])
+            }
+            Check::RustupComponent { toolchain, .. } => {
+                rustup(&["component", "list", "--installed", "--toolchain", toolchain])
+            }
+        }
+    }
+
+    /// Judge the command's output, or None if it couldn't be run
+    /// Returns whether the requirement is met and the version found, if any
+    fn evaluate(&self, output: Option<&str>) -> (bool, Option<String>) {
+        let Some(output) = output else {
+            return (false, None);
+        };
+        match self {
+            Check::Tool { .. } => (true, find_version(output)),
+            Check::RustupToolchain(channel) => (output.lines().any(|l| toolchain_matches(l, channel)), None),
+            Check::RustupTarget { target, .. } => (output.lines().any(|l| l.trim() == target), None),
+            Check::RustupComponent { component, .. } => (
+                output
+                    .lines()
+                    .any(|l| l.trim() == component || l.starts_with(&format!("{}-", component))),
+                None,
+            ),
+        }
+    }
+}
+
+/// Something a board needs installed to be built or flashed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Requirement {
+    pub name: String,
+    pub check: Check,
+    /// Oldest version that works, for tools
+    pub minimum: Option<String>,
+    /// Command that installs the requirement, if there is one to suggest
+    pub fix: Option<String>,
+    /// Boards that need it
+    pub required_by: Vec<String>,
+}
+
+/// What checking a requirement found
+#[derive(Debug, Clone, PartialEq)]
+pub struct DependencyReport {
+    pub requirement: Requirement,
+    pub status: DependencyStatus,
+    pub version: Option<String>,
+}
+
+#[derive(Default)]
 pub struct DependencyChecker {
-    pub cargo_generate: DependencyStatus,
-    pub espflash: DependencyStatus,
-    pub ravedude: DependencyStatus,
-    pub probe_rs: DependencyStatus,
+    pub reports: Vec<DependencyReport>,
 }
 
 impl DependencyChecker {
     pub fn new() -> Self {
-        Self {
-            cargo_generate: DependencyStatus::NotFound,
-            espflash: DependencyStatus::NotFound,
-            ravedude: DependencyStatus::NotFound,
-            probe_rs: DependencyStatus::NotFound,
+        Self { reports: vec![] }
+    }
+
+    /// Check every requirement by running the command that reports on it
+    pub fn check_all(&mut self, requirements: Vec<Requirement>) {
+        self.check_with(requirements, run_command);
+    }
+
+    /// Check every requirement, getting each command's output from `run`
+    pub fn check_with(&mut self, requirements: Vec<Requirement>, mut run: impl FnMut(&Path, &[String]) -> Option<String>) {
+        self.reports = requirements
+            .into_iter()
+            .map(|requirement| {
+                let (program, args) = requirement.check.command();
+                let output = run(&program, &args);
+                let (found, version) = requirement.check.evaluate(output.as_deref());
+                let status = match (&output, found) {
+                    (None, _) | (Some(_), false) => DependencyStatus::NotFound,
+                    (Some(_), true) => match (&version, &requirement.minimum) {
+                        (Some(version), Some(minimum)) if !version_at_least(version, minimum) => {
+                            DependencyStatus::Outdated
+                        }
+                        (None, Some(_)) if matches!(requirement.check, Check::Tool { .. }) => {
+                            DependencyStatus::Error("couldn't tell which version is installed".to_string())
+                        }
+                        _ => DependencyStatus::Installed,
+                    },
+                };
+                DependencyReport {
+                    requirement,
+                    status,
+                    version,
+                }
+            })
+            .collect();
+    }
+
+    pub fn missing(&self) -> impl Iterator<Item = &DependencyReport> {
+        self.reports.iter().filter(|r| !r.status.is_available())
+    }
+}
+
+/// Work out what the given boards need, merging what several of them share
+/// `boards` pairs each main board's name with its build profile
+pub fn requirements_for(boards: &[(String, BuildProfile)], redist: Option<&ToolchainManager>) -> Vec<Requirement> {
+    let mut requirements: Vec<Requirement> = vec![];
+    for (board, profile) in boards {
+        for mut requirement in profile_requirements(profile, redist) {
+            match requirements.iter_mut().find(|r| r.check == requirement.check) {
+                Some(existing) => {
+                    existing.required_by.push(board.clone());
+                    // the strictest minimum wins
+                    if let Some(minimum) = requirement.minimum.take() {
+                        if existing.minimum.as_ref().is_none_or(|m| !version_at_least(m, &minimum)) {
+                            existing.minimum = Some(minimum);
+                        }
+                    }
+                }
+                None => {
+                    requirement.required_by.push(board.clone());
+                    requirements.push(requirement);
+                }
+            }
+        }
+    }
+    requirements
+}
+
+fn profile_requirements(profile: &BuildProfile, redist: Option<&ToolchainManager>) -> Vec<Requirement> {
+    let requirement = |name: &str, check: Check, fix: Option<String>| Requirement {
+        name: name.to_string(),
+        minimum: profile.min_versions.get(name).cloned(),
+        check,
+        fix,
+        required_by: vec![],
+    };
+    let tool = |program: &str, version_args: &[&str]| Check::Tool {
+        program: PathBuf::from(program),
+        version_args: version_args.iter().map(|a| a.to_string()).collect(),
+    };
+    let mut requirements = vec![requirement(
+        "cargo",
+        tool("cargo", &["--version"]),
+        Some(rustup_install_command().to_string()),
+    )];
+
+    let toolchain = profile.channel.clone().unwrap_or_else(|| "stable".to_string());
+    // Xtensa's toolchain isn't in rustup's channels; espup installs it
+    let toolchain_fix = if toolchain == "esp" {
+        "cargo install espup --locked && espup install".to_string()
+    } else {
+        format!("rustup toolchain install {}", toolchain)
+    };
+    if profile.channel.is_some() {
+        requirements.push(requirement(
+            &format!("Rust {} toolchain", toolchain),
+            Check::RustupToolchain(toolchain.clone()),
+            Some(toolchain_fix),
+        ));
+    }
+
+    if let Some(target) = &profile.target {
+        let arch = TargetArch::of(target);
+        if !profile.build_std.is_empty() || arch.needs_build_std() {
+            requirements.push(requirement(
+                "rust-src",
+                Check::RustupComponent {
+                    toolchain: toolchain.clone(),
+                    component: "rust-src".to_string(),
+                },
+                Some(format!("rustup component add rust-src --toolchain {}", toolchain)),
+            ));
+        } else if !target.ends_with(".json") {
+            requirements.push(requirement(
+                target,
+                Check::RustupTarget {
+                    toolchain: toolchain.clone(),
+                    target: target.clone(),
+                },
+                Some(format!("rustup target add {} --toolchain {}", target, toolchain)),
+            ));
+        }
+    }
+
+    if let Some(runner) = profile.runner {
+        let (version_args, fix): (&[&str], &str) = match runner {
+            Runner::Ravedude => (&["--version"], "cargo install ravedude --locked"),
+            Runner::Espflash => (&["--version"], "cargo install espflash --locked"),
+            Runner::ProbeRs => (&["--version"], "cargo install probe-rs-tools --locked"),
+            Runner::Elf2uf2 => (&["--version"], "cargo install elf2uf2-rs --locked"),
+        };
+        let executable = runner.executable();
+        requirements.push(requirement(
+            executable,
+            tool(executable, version_args),
+            Some(fix.to_string()),
+        ));
+        if runner == Runner::Ravedude {
+            // ravedude flashes through avrdude, which the Arduino toolchain bundles
+            requirements.push(requirement(
+                "avrdude",
+                Check::Tool {
+                    program: bundled_or_path(redist, profile.redist.as_deref(), "avrdude"),
+                    version_args: vec!["-?".to_string()],
+                },
+                Some(avr_package_command("avrdude")),
+            ));
         }
     }
 
-    pub fn check_all(&mut self) {
-        self.cargo_generate = Self::check_tool("cargo-generate");
-        self.espflash = Self::check_tool("espflash");
-        self.ravedude = Self::check_tool("ravedude");
-        self.probe_rs = Self::check_tool("probe-rs");
+    if let Some(linker) = &profile.linker {
+        requirements.push(requirement(
+            linker,
+            Check::Tool {
+                program: bundled_or_path(redist, profile.redist.as_deref(), linker),
+                version_args: vec!["--version".to_string()],
+            },
+            Some(avr_package_command(linker)),
+        ));
     }
+    requirements
+}
+
+/// Prefer a tool from the board's Redist toolchain, falling back to the one on the PATH
+fn bundled_or_path(redist: Option<&ToolchainManager>, toolchain: Option<&str>, tool: &str) -> PathBuf {
+    redist
+        .zip(toolchain)
+        .and_then(|(redist, toolchain)| redist.tool(toolchain, tool))
+        .filter(|path| path.exists())
+        .or_else(|| which(tool).ok())
+        .unwrap_or_else(|| PathBuf::from(tool))
+}
+
+fn rustup_install_command() -> &'static str {
+    if cfg!(windows) {
+        "winget install Rustlang.Rustup"
+    } else {
+        "curl --proto '=https' --tlsv1.2 -sSf https://sh.rustup.rs | sh"
+    }
+}
 
-    fn check_tool(tool_name: &str) -> DependencyStatus {
-        match which(tool_name) {
-            Ok(_) => DependencyStatus::Installed,
-            Err(which::Error::CannotFindBinaryPath) => DependencyStatus::NotFound,
-            Err(e) => DependencyStatus::Error(format!("Error checking {}: {}", tool_name, e)),
+/// The command that installs an AVR tool with the platform's package manager
+fn avr_package_command(tool: &str) -> String {
+    if cfg!(target_os = "macos") {
+        format!("brew tap osx-cross/avr && brew install {}", tool)
+    } else if cfg!(windows) {
+        format!("winget install {}", tool)
+    } else {
+        let package = if tool == "avr-gcc" { "gcc-avr" } else { tool };
+        format!("sudo apt install {}", package)
+    }
+}
+
+/// Run a command, returning what it printed on stdout and stderr, or None if it couldn't start
+/// Tools differ in where they print their version and what they exit with, so neither matters
+pub fn run_command(program: &Path, args: &[String]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    let mut text = String::from_utf8_lossy(&output.stdout).to_string();
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    Some(text)
+}
+
+/// Whether a line of `rustup toolchain list` is the given channel, for this host
+/// `nightly` matches `nightly-x86_64-unknown-linux-gnu` but not `nightly-2025-04-27-x86_64-...`
+fn toolchain_matches(line: &str, channel: &str) -> bool {
+    let name = line.split_whitespace().next().unwrap_or_default();
+    match name.strip_prefix(channel) {
+        Some("") => true,
+        Some(rest) => rest.starts_with('-') && !rest[1..].starts_with(|c: char| c.is_ascii_digit()),
+        None => false,
+    }
+}
+
+/// Find the first dotted version number in a tool's output, such as "3.3.0" in "espflash 3.3.0"
+pub fn find_version(output: &str) -> Option<String> {
+    output
+        .split(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .map(|word| word.trim_matches('.'))
+        .find(|word| word.contains('.') && word.split('.').all(|part| !part.is_empty()))
+        .map(str::to_string)
+}
+
+/// Compare dotted version numbers, treating missing parts as zero
+pub fn version_at_least(version: &str, minimum: &str) -> bool {
+    let parse = |v: &str| -> Vec<u64> { v.split('.').map(|p| p.parse().unwrap_or(0)).collect() };
+    let (version, minimum) = (parse(version), parse(minimum));
+    for i in 0..version.len().max(minimum.len()) {
+        let (a, b) = (version.get(i).copied().unwrap_or(0), minimum.get(i).copied().unwrap_or(0));
+        if a != b {
+            return a > b;
         }
     }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::board::get_boards;
+    use std::path::Path;
+
+    #[test]
+    fn test_dependency_checker_follows_board_profiles() {
+        use crate::dependencies::checker::{self, Check, DependencyChecker, DependencyStatus};
+
+        assert_eq!(checker::find_version("espflash 3.3.0"), Some("3.3.0".to_string()));
+        assert_eq!(checker::find_version("cargo 1.90.0 (840b83a10 2025-07-30)"), Some("1.90.0".to_string()));
+        assert_eq!(checker::find_version("avrdude version 6.3-20190619"), Some("6.3".to_string()));
+        assert!(checker::version_at_least("3.10", "3.9.1"));
+        assert!(checker::version_at_least("7.3.0", "7.3"));
+        assert!(!checker::version_at_least("2.0.1", "3.0.0"));
+
+        let known_boards = get_boards(Path::new("./iron-coder-boards"));
+        let profile = |name: &str| {
+            let board = known_boards.iter().find(|b| b.get_name() == name).unwrap();
+            (name.to_string(), board.build_profile().clone())
+        };
+        let boards = vec![profile("Arduino Uno"), profile("ESP32-C6-DevKitM-1")];
+        let requirements = checker::requirements_for(&boards, None);
+        let names: Vec<&str> = requirements.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "cargo",
+                "Rust nightly-2025-04-27 toolchain",
+                "rust-src",
+                "ravedude",
+                "avrdude",
+                "avr-gcc",
+                "Rust stable toolchain",
+                "riscv32imac-unknown-none-elf",
+                "espflash",
+            ]
+        );
+        // both boards need cargo, so it's listed once for both
+        assert_eq!(requirements[0].required_by.len(), 2);
+        let target = requirements.iter().find(|r| r.name == "riscv32imac-unknown-none-elf").unwrap();
+        assert_eq!(
+            target.fix.as_deref(),
+            Some("rustup target add riscv32imac-unknown-none-elf --toolchain stable")
+        );
+
+        let mut dependency_checker = DependencyChecker::new();
+        dependency_checker.check_with(requirements, |program, args| {
+            let program = program.file_name()?.to_str()?;
+            match (program, args.first().map(String::as_str)) {
+                ("cargo", _) => Some("cargo 1.90.0 (840b83a10 2025-07-30)".to_string()),
+                ("rustup", Some("toolchain")) => Some(
+                    "stable-x86_64-unknown-linux-gnu (default)\nnightly-x86_64-unknown-linux-gnu\n".to_string(),
+                ),
+                ("rustup", Some("target")) => Some("x86_64-unknown-linux-gnu\n".to_string()),
+                ("rustup", Some("component")) => Some("rust-src\n".to_string()),
+                ("ravedude", _) => Some("ravedude 0.1.7\n".to_string()),
+                ("espflash", _) => Some("espflash 3.3.0\n".to_string()),
+                ("avr-gcc", _) => Some("avr-gcc (GCC) 7.3.0\n".to_string()),
+                _ => None,
+            }
+        });
+        let status = |name: &str| {
+            let report = dependency_checker.reports.iter().find(|r| r.requirement.name == name).unwrap();
+            (report.status.clone(), report.version.clone())
+        };
+        assert_eq!(status("cargo"), (DependencyStatus::Installed, Some("1.90.0".to_string())));
+        // a plain nightly isn't the dated nightly the Uno is pinned to
+        assert_eq!(status("Rust nightly-2025-04-27 toolchain").0, DependencyStatus::NotFound);
+        assert_eq!(status("Rust stable toolchain").0, DependencyStatus::Installed);
+        assert_eq!(status("rust-src").0, DependencyStatus::Installed);
+        assert_eq!(status("riscv32imac-unknown-none-elf").0, DependencyStatus::NotFound);
+        assert_eq!(status("ravedude"), (DependencyStatus::Outdated, Some("0.1.7".to_string())));
+        assert_eq!(status("avrdude").0, DependencyStatus::NotFound);
+        assert_eq!(status("espflash").0, DependencyStatus::Installed);
+        assert_eq!(status("avr-gcc").0, DependencyStatus::Installed);
+        let missing: Vec<_> = dependency_checker.missing().map(|r| r.requirement.name.as_str()).collect();
+        assert_eq!(missing.len(), 4);
+        assert!(matches!(
+            dependency_checker.reports[2].requirement.check,
+            Check::RustupComponent { ref toolchain, .. } if toolchain == "nightly-2025-04-27"
+        ));
+    }
 }
@@ -1 +1,2 @@
-pub mod checker;
\ No newline at end of file
+pub mod checker;
+pub mod redist;
@@ -0,0 +1,496 @@
+//! Toolchains bundled with the IDE in its `Redist` directory
+//!
+//! Each toolchain lives in `Redist/<name>/toolchain/<platform>/`, with a `bin` directory, the
+//! `builtin_tools_versions.txt` its vendor ships and a `SHA256SUMS` file listing every file in
+//! it. Toolchains that aren't bundled can be installed from local archives listed in
+//! `Redist/toolchains.toml`, which also records the checksum each archive must have.
+use log::{info, warn};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+pub const REDIST_DIR_NAME: &str = "Redist";
+/// Environment variable that overrides where the Redist directory is looked for
+pub const REDIST_ENV: &str = "IRON_CODER_REDIST";
+const INDEX_FILE: &str = "toolchains.toml";
+const VERSIONS_FILE: &str = "builtin_tools_versions.txt";
+const CHECKSUMS_FILE: &str = "SHA256SUMS";
+
+/// Name of the subdirectory toolchains keep this platform's binaries in
+pub fn platform_dir() -> Option<&'static str> {
+    if cfg!(target_os = "linux") {
+        Some("linux-x86_64")
+    } else if cfg!(target_os = "macos") {
+        if cfg!(target_arch = "aarch64") {
+            Some("macos-aarch64")
+        } else {
+            Some("macos-x86_64")
+        }
+    } else if cfg!(target_os = "windows") {
+        Some("windows-x86_64")
+    } else {
+        None
+    }
+}
+
+/// Find the Redist directory however the IDE was launched
+/// The environment variable wins; otherwise the directories holding the executable are searched
+/// upwards, which covers both an installed IDE and one run from cargo's target directory, and
+/// finally the working directory
+pub fn find_redist_dir(exe: Option<&Path>, working_dir: Option<&Path>) -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os(REDIST_ENV).map(PathBuf::from) {
+        if dir.is_dir() {
+            return Some(dir);
+        }
+        warn!("{} points at {}, which isn't a directory", REDIST_ENV, dir.display());
+    }
+    let exe = exe.map(|e| e.canonicalize().unwrap_or_else(|_| e.to_path_buf()));
+    let from_exe = exe.iter().flat_map(|e| e.ancestors().skip(1)).find_map(|dir| {
+        // a macOS app bundle keeps its data next to the executable's directory
+        [dir.join(REDIST_DIR_NAME), dir.join("Resources").join(REDIST_DIR_NAME)]
+            .into_iter()
+            .find(|candidate| candidate.is_dir())
+    });
+    from_exe.or_else(|| {
+        working_dir
+            .map(|d| d.join(REDIST_DIR_NAME))
+            .filter(|candidate| candidate.is_dir())
+    })
+}
+
+/// A toolchain installed for one platform
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstalledToolchain {
+    pub name: String,
+    pub platform: String,
+    pub dir: PathBuf,
+    /// Tools and their versions, from builtin_tools_versions.txt
+    pub versions: Vec<(String, String)>,
+}
+
+impl InstalledToolchain {
+    pub fn bin_dir(&self) -> PathBuf {
+        self.dir.join("bin")
+    }
+}
+
+/// An archive a toolchain can be installed from, as listed in toolchains.toml
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ToolchainArchive {
+    pub name: String,
+    pub platform: String,
+    /// Path of the archive, relative to the Redist directory unless absolute
+    pub archive: PathBuf,
+    /// Expected SHA-256 of the archive, in hex
+    pub sha256: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ToolchainIndex {
+    #[serde(default, rename = "toolchain")]
+    toolchains: Vec<ToolchainArchive>,
+}
+
+/// Outcome of checking an installed toolchain against its SHA256SUMS
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verification {
+    /// Every listed file is present and unchanged
+    Verified { files: usize },
+    /// The toolchain has no SHA256SUMS to check against
+    NoChecksums,
+    Mismatched { changed: Vec<PathBuf>, missing: Vec<PathBuf> },
+}
+
+#[derive(Debug)]
+pub enum RedistError {
+    Io(io::Error),
+    /// The archive isn't listed in toolchains.toml, so there's nothing to verify it against
+    UnknownArchive(PathBuf),
+    ChecksumMismatch { expected: String, actual: String },
+    /// Only .tar.gz, .tgz and .zip archives can be installed
+    UnsupportedArchive(PathBuf),
+    InvalidIndex(String),
+}
+
+impl fmt::Display for RedistError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RedistError::Io(e) => write!(f, "{}", e),
+            RedistError::UnknownArchive(path) => {
+                write!(f, "{} isn't listed in {}", path.display(), INDEX_FILE)
+            }
+            RedistError::ChecksumMismatch { expected, actual } => {
+                write!(f, "checksum mismatch: expected {}, got {}", expected, actual)
+            }
+            RedistError::UnsupportedArchive(path) => {
+                write!(f, "{} isn't a .tar.gz or .zip archive", path.display())
+            }
+            RedistError::InvalidIndex(message) => write!(f, "invalid {}: {}", INDEX_FILE, message),
+        }
+    }
+}
+
+impl From<io::Error> for RedistError {
+    fn from(e: io::Error) -> Self {
+        RedistError::Io(e)
+    }
+}
+
+/// Lists, verifies and installs the toolchains in a Redist directory
+#[derive(Debug, Clone)]
+pub struct ToolchainManager {
+    pub root: PathBuf,
+}
+
+impl ToolchainManager {
+    pub fn new(root: &Path) -> Self {
+        ToolchainManager {
+            root: root.to_path_buf(),
+        }
+    }
+
+    /// Manage the Redist directory the running IDE was installed with
+    pub fn locate() -> Option<Self> {
+        let exe = std::env::current_exe().ok();
+        let working_dir = std::env::current_dir().ok();
+        find_redist_dir(exe.as_deref(), working_dir.as_deref()).map(|root| Self::new(&root))
+    }
+
+    /// Get every installed toolchain, for any platform
+    pub fn installed(&self) -> Vec<InstalledToolchain> {
+        let mut installed = vec![];
+        let Ok(entries) = fs::read_dir(&self.root) else {
+            return installed;
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let Ok(platforms) = fs::read_dir(entry.path().join("toolchain")) else {
+                continue;
+            };
+            for platform in platforms.filter_map(|p| p.ok()) {
+                let platform_name = platform.file_name().to_string_lossy().to_string();
+                // installs in progress are unpacked into hidden directories
+                if platform_name.starts_with('.') || !platform.path().is_dir() {
+                    continue;
+                }
+                installed.push(Self::read_installed(&name, &platform_name, &platform.path()));
+            }
+        }
+        installed.sort_by(|a, b| (&a.name, &a.platform).cmp(&(&b.name, &b.platform)));
+        installed
+    }
+
+    /// Get a toolchain installed for the platform the IDE runs on
+    pub fn find(&self, name: &str) -> Option<InstalledToolchain> {
+        let platform = platform_dir()?;
+        let dir = self.toolchain_dir(name, platform);
+        dir.is_dir().then(|| Self::read_installed(name, platform, &dir))
+    }
+
+    /// Get the path of an executable in a toolchain installed for this platform
+    pub fn tool(&self, toolchain: &str, tool: &str) -> Option<PathBuf> {
+        let tool = if cfg!(windows) {
+            format!("{}.exe", tool)
+        } else {
+            tool.to_string()
+        };
+        Some(self.find(toolchain)?.bin_dir().join(tool))
+    }
+
+    /// Get the archives toolchains.toml lists
+    pub fn available(&self) -> Result<Vec<ToolchainArchive>, RedistError> {
+        let path = self.root.join(INDEX_FILE);
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+        let index: ToolchainIndex = toml::from_str(&text).map_err(|e| RedistError::InvalidIndex(e.to_string()))?;
+        Ok(index.toolchains)
+    }
+
+    /// Check every file an installed toolchain's SHA256SUMS lists
+    pub fn verify(&self, toolchain: &InstalledToolchain) -> io::Result<Verification> {
+        let sums = match fs::read_to_string(toolchain.dir.join(CHECKSUMS_FILE)) {
+            Ok(sums) => sums,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Verification::NoChecksums),
+            Err(e) => return Err(e),
+        };
+        let mut files = 0;
+        let mut changed = vec![];
+        let mut missing = vec![];
+        for line in sums.lines().filter(|l| !l.trim().is_empty()) {
+            // sha256sum's format: the hash, two spaces (or a space and a star) and the path
+            let Some((expected, file)) = line.split_once(' ') else {
+                continue;
+            };
+            let file = PathBuf::from(file.trim_start_matches([' ', '*']));
+            match sha256_file(&toolchain.dir.join(&file)) {
+                Ok(actual) if actual.eq_ignore_ascii_case(expected) => files += 1,
+                Ok(_) => changed.push(file),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => missing.push(file),
+                Err(e) => return Err(e),
+            }
+        }
+        if changed.is_empty() && missing.is_empty() {
+            Ok(Verification::Verified { files })
+        } else {
+            Ok(Verification::Mismatched { changed, missing })
+        }
+    }
+
+    /// Install a toolchain from a local archive listed in toolchains.toml
+    /// The archive's checksum is checked first, and the unpacked files are given a SHA256SUMS
+    /// so the install can be verified later. An existing install for the platform is replaced.
+    pub fn install_archive(&self, archive: &Path) -> Result<InstalledToolchain, RedistError> {
+        let file_name = archive.file_name().map(|n| n.to_os_string());
+        let entry = self
+            .available()?
+            .into_iter()
+            .find(|t| t.archive.file_name().map(|n| n.to_os_string()) == file_name)
+            .ok_or_else(|| RedistError::UnknownArchive(archive.to_path_buf()))?;
+        let actual = sha256_file(archive)?;
+        if !actual.eq_ignore_ascii_case(&entry.sha256) {
+            return Err(RedistError::ChecksumMismatch {
+                expected: entry.sha256,
+                actual,
+            });
+        }
+
+        let target = self.toolchain_dir(&entry.name, &entry.platform);
+        let staging = target.with_file_name(format!(".{}.partial", entry.platform));
+        if staging.exists() {
+            fs::remove_dir_all(&staging)?;
+        }
+        fs::create_dir_all(&staging)?;
+        if let Err(e) = unpack(archive, &staging) {
+            let _ = fs::remove_dir_all(&staging);
+            return Err(e);
+        }
+        // archives usually wrap everything in one top-level directory
+        let unpacked = single_subdirectory(&staging)?.unwrap_or_else(|| staging.clone());
+        write_checksums(&unpacked)?;
+
+        if target.exists() {
+            fs::remove_dir_all(&target)?;
+        }
+        fs::rename(&unpacked, &target)?;
+        if staging.exists() {
+            fs::remove_dir_all(&staging)?;
+        }
+        info!("installed toolchain {} for {} from {}", entry.name, entry.platform, archive.display());
+        Ok(Self::read_installed(&entry.name, &entry.platform, &target))
+    }
+
+    fn toolchain_dir(&self, name: &str, platform: &str) -> PathBuf {
+        self.root.join(name).join("toolchain").join(platform)
+    }
+
+    fn read_installed(name: &str, platform: &str, dir: &Path) -> InstalledToolchain {
+        let versions = fs::read_to_string(dir.join(VERSIONS_FILE))
+            .map(|text| parse_versions(&text))
+            .unwrap_or_default();
+        InstalledToolchain {
+            name: name.to_string(),
+            platform: platform.to_string(),
+            dir: dir.to_path_buf(),
+            versions,
+        }
+    }
+}
+
+/// Read the `vendor.tool=version` lines of builtin_tools_versions.txt into tools and versions
+pub fn parse_versions(text: &str) -> Vec<(String, String)> {
+    text.lines()
+        .filter_map(|line| line.trim().split_once('='))
+        .map(|(key, version)| {
+            let tool = key.split_once('.').map_or(key, |(_, tool)| tool);
+            (tool.trim().to_string(), version.trim().to_string())
+        })
+        .collect()
+}
+
+/// Get the SHA-256 of a file, in lowercase hex
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let read = reader.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+    }
+    Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Write a SHA256SUMS listing every regular file under a directory, in sha256sum's format
+/// Symlinks are left out, since they only point at files that are listed themselves
+/// Returns how many files were listed
+pub fn write_checksums(dir: &Path) -> io::Result<usize> {
+    let mut files = vec![];
+    collect_files(dir, dir, &mut files)?;
+    files.retain(|f| f != Path::new(CHECKSUMS_FILE));
+    files.sort();
+    let mut sums = String::new();
+    for file in &files {
+        let relative = file.to_string_lossy().replace('\\', "/");
+        sums.push_str(&format!("{}  {}\n", sha256_file(&dir.join(file))?, relative));
+    }
+    fs::write(dir.join(CHECKSUMS_FILE), sums)?;
+    Ok(files.len())
+}
+
+fn collect_files(root: &Path, dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            collect_files(root, &entry.path(), files)?;
+        } else if file_type.is_file() {
+            if let Ok(relative) = entry.path().strip_prefix(root) {
+                files.push(relative.to_path_buf());
+            }
+        }
+    }
+    Ok(())
+}
+
+fn unpack(archive: &Path, destination: &Path) -> Result<(), RedistError> {
+    let name = archive.file_name().map(|n| n.to_string_lossy().to_lowercase()).unwrap_or_default();
+    if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        let decoder = flate2::read::GzDecoder::new(File::open(archive)?);
+        tar::Archive::new(decoder).unpack(destination)?;
+    } else if name.ends_with(".zip") {
+        let mut zip = zip::ZipArchive::new(File::open(archive)?).map_err(|e| RedistError::Io(io::Error::other(e)))?;
+        zip.extract(destination).map_err(|e| RedistError::Io(io::Error::other(e)))?;
+    } else {
+        return Err(RedistError::UnsupportedArchive(archive.to_path_buf()));
+    }
+    Ok(())
+}
+
+/// Get the directory an unpacked archive wraps everything in, if it has one
+/// A lone `bin` is the toolchain's own, unless there's another `bin` inside it
+fn single_subdirectory(dir: &Path) -> io::Result<Option<PathBuf>> {
+    let entries: Vec<_> = fs::read_dir(dir)?.filter_map(|e| e.ok()).collect();
+    match entries.as_slice() {
+        [only] if only.file_type()?.is_dir() => {
+            let path = only.path();
+            let wraps = only.file_name() != "bin" || path.join("bin").is_dir();
+            Ok(wraps.then_some(path))
+        }
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    #[test]
+    fn test_redist_toolchains_are_found_verified_and_installed() {
+        use crate::dependencies::redist::{self, RedistError, ToolchainManager, Verification};
+
+        let temp = crate::test::temp_dir();
+        let root = temp.path().to_path_buf();
+        let redist_dir = root.join("Redist");
+        let exe_dir = root.join("target/debug/deps");
+        std::fs::create_dir_all(&exe_dir).unwrap();
+        std::fs::create_dir_all(&redist_dir).unwrap();
+        // found from the executable however deep cargo put it, before the working directory
+        let exe = exe_dir.join("iron_coder");
+        let found = redist::find_redist_dir(Some(&exe), Some(Path::new("/"))).unwrap();
+        assert_eq!(found.canonicalize().unwrap(), redist_dir.canonicalize().unwrap());
+        assert_eq!(redist::find_redist_dir(None, Some(&root)), Some(redist_dir.clone()));
+
+        assert_eq!(
+            redist::parse_versions("arduino.avrdude=6.3.0-arduino17\narduino.avr-gcc=7.3.0-atmel3.6.1-arduino7\n"),
+            vec![
+                ("avrdude".to_string(), "6.3.0-arduino17".to_string()),
+                ("avr-gcc".to_string(), "7.3.0-atmel3.6.1-arduino7".to_string()),
+            ]
+        );
+
+        // the bundled Arduino toolchain ships its versions and checksums
+        let bundled = ToolchainManager::new(Path::new("./Redist"));
+        let arduino = bundled
+            .installed()
+            .into_iter()
+            .find(|t| t.name == "Arduino" && t.platform == "linux-x86_64")
+            .unwrap();
+        assert!(arduino.versions.iter().any(|(tool, _)| tool == "avr-gcc"));
+        assert!(arduino.dir.join("SHA256SUMS").exists());
+
+        // a toolchain installed from an archive is checked against the index first
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast()));
+        for (path, contents) in [
+            ("blinky-gcc/bin/blinky-gcc", "#!/bin/sh\n"),
+            ("blinky-gcc/builtin_tools_versions.txt", "blinky.blinky-gcc=1.2.3\n"),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o755);
+            header.set_cksum();
+            builder.append_data(&mut header, path, contents.as_bytes()).unwrap();
+        }
+        let archive_bytes = builder.into_inner().unwrap().finish().unwrap();
+        let archive = root.join("blinky-gcc.tar.gz");
+        std::fs::write(&archive, &archive_bytes).unwrap();
+        let manager = ToolchainManager::new(&redist_dir);
+        assert!(matches!(manager.install_archive(&archive), Err(RedistError::UnknownArchive(_))));
+
+        let index = |sha256: &str| {
+            format!(
+                "[[toolchain]]\nname = \"Blinky\"\nplatform = \"linux-x86_64\"\narchive = \"{}\"\nsha256 = \"{}\"\n",
+                archive.display(),
+                sha256
+            )
+        };
+        std::fs::write(redist_dir.join("toolchains.toml"), index(&"0".repeat(64))).unwrap();
+        assert_eq!(manager.available().unwrap().len(), 1);
+        assert!(matches!(manager.install_archive(&archive), Err(RedistError::ChecksumMismatch { .. })));
+        assert!(manager.installed().is_empty());
+
+        let sha256 = redist::sha256_file(&archive).unwrap();
+        std::fs::write(redist_dir.join("toolchains.toml"), index(&sha256)).unwrap();
+        let blinky = manager.install_archive(&archive).unwrap();
+        assert_eq!(blinky.dir, redist_dir.join("Blinky/toolchain/linux-x86_64"));
+        assert_eq!(blinky.versions, vec![("blinky-gcc".to_string(), "1.2.3".to_string())]);
+        assert!(blinky.bin_dir().join("blinky-gcc").exists());
+        assert_eq!(manager.installed(), vec![blinky.clone()]);
+        assert_eq!(manager.verify(&blinky).unwrap(), Verification::Verified { files: 2 });
+
+        std::fs::write(blinky.bin_dir().join("blinky-gcc"), "tampered").unwrap();
+        std::fs::remove_file(blinky.dir.join("builtin_tools_versions.txt")).unwrap();
+        assert_eq!(
+            manager.verify(&blinky).unwrap(),
+            Verification::Mismatched {
+                changed: vec![std::path::PathBuf::from("bin/blinky-gcc")],
+                missing: vec![std::path::PathBuf::from("builtin_tools_versions.txt")],
+            }
+        );
+        std::fs::remove_file(blinky.dir.join("SHA256SUMS")).unwrap();
+        assert_eq!(manager.verify(&blinky).unwrap(), Verification::NoChecksums);
+
+        // an archive holding nothing but bin/ isn't unwrapped
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast()));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(10);
+        header.set_mode(0o755);
+        header.set_cksum();
+        builder.append_data(&mut header, "bin/blinky-gcc", "#!/bin/sh\n".as_bytes()).unwrap();
+        std::fs::write(&archive, builder.into_inner().unwrap().finish().unwrap()).unwrap();
+        let sha256 = redist::sha256_file(&archive).unwrap();
+        std::fs::write(redist_dir.join("toolchains.toml"), index(&sha256)).unwrap();
+        let blinky = manager.install_archive(&archive).unwrap();
+        assert!(blinky.bin_dir().join("blinky-gcc").exists());
+    }
+}
@@ -1,7 +1,9 @@
 pub mod app;
 pub mod board;
+pub mod cli;
 pub mod project;
 pub mod dependencies;
+pub mod snippets;
 
 #[cfg(test)]
 mod test;
@@ -4,6 +4,11 @@ use egui_extras::install_image_loaders;
 use iron_coder::MainWindow;
 
 pub fn main() -> eframe::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(code) = iron_coder::cli::run(&args) {
+        std::process::exit(code);
+    }
+
     let icon_data = include_bytes!("../assets/application-icon/icon.png");
     let icon = eframe::icon_data::from_png_bytes(icon_data).ok();
 
@@ -15,7 +20,7 @@ pub fn main() -> eframe::Result<()> {
     };
 
     eframe::run_native(
-        "IRON CODER",
+        iron_coder::app::APP_NAME,
         options,
         Box::new(|cc| {
             install_image_loaders(&cc.egui_ctx); // <-- call here
@@ -0,0 +1,62 @@
+//! Crash-safe file writes
+//!
+//! Contents are written to a temporary file next to the target, flushed to disk, and then
+//! renamed over the target. A crash at any point leaves either the old or the new file,
+//! never a truncated one.
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Atomically replace the contents of `path`
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let temp_path = temp_path_for(path);
+    let result = write_and_rename(path, &temp_path, contents);
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+fn write_and_rename(path: &Path, temp_path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut file = File::create(temp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    drop(file);
+
+    // keep the permissions of the file being replaced
+    if let Ok(metadata) = fs::metadata(path) {
+        fs::set_permissions(temp_path, metadata.permissions())?;
+    }
+    fs::rename(temp_path, path)?;
+
+    // make the rename itself durable
+    #[cfg(unix)]
+    if let Some(Ok(dir)) = path.parent().filter(|d| !d.as_os_str().is_empty()).map(File::open) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+/// The temporary file lives in the same directory so the rename never crosses filesystems
+fn temp_path_for(path: &Path) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.{}.tmp", file_name, std::process::id()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_atomic_write_replaces_the_file_without_leaving_a_temporary() {
+        let temp = crate::test::temp_dir();
+        let original = temp.path().join("main.rs");
+        fs::write(&original, "fn main() {}\n").unwrap();
+        write_atomic(&original, b"fn main() { loop {} }\n").unwrap();
+        assert_eq!(fs::read_to_string(&original).unwrap(), "fn main() { loop {} }\n");
+        assert_eq!(fs::read_dir(temp.path()).unwrap().count(), 1, "temporary file left behind");
+    }
+}
@@ -0,0 +1,209 @@
+//! Edits to a project's Cargo.toml, made with toml_edit so the user's formatting and comments survive
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use log::warn;
+use toml_edit::{Array, DocumentMut, InlineTable, Item, Table, value};
+
+use crate::board::Driver;
+
+/// Add a driver crate to the `[dependencies]` of a Cargo.toml
+/// Returns Ok(false) without touching the file if the crate is already a dependency
+pub fn add_dependency(manifest_path: &Path, driver: &Driver) -> io::Result<bool> {
+    let mut doc = read_manifest(manifest_path)?;
+
+    if doc.get("dependencies").is_none() {
+        doc["dependencies"] = Item::Table(Table::new());
+    }
+    let Some(dependencies) = doc["dependencies"].as_table_like_mut() else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "[dependencies] is not a table",
+        ));
+    };
+    if dependencies.contains_key(&driver.crate_name) {
+        return Ok(false);
+    }
+
+    if driver.features.is_empty() {
+        dependencies.insert(&driver.crate_name, value(driver.version.clone()));
+    } else {
+        let mut dependency = InlineTable::new();
+        dependency.insert("version", driver.version.clone().into());
+        let features: Array = driver.features.iter().map(|f| f.as_str()).collect();
+        dependency.insert("features", features.into());
+        dependencies.insert(&driver.crate_name, value(dependency));
+    }
+
+    fs::write(manifest_path, doc.to_string())?;
+    Ok(true)
+}
+
+/// Rename the package in a Cargo.toml, along with the binary target named after it
+/// A lone `[[bin]]` target is renamed whatever it was called, since it is the project's firmware
+/// Returns Ok(false) if the manifest has no `[package]` table
+pub fn rename_package(manifest_path: &Path, name: &str) -> io::Result<bool> {
+    let mut doc = read_manifest(manifest_path)?;
+
+    let Some(package) = doc.get_mut("package").and_then(|p| p.as_table_like_mut()) else {
+        return Ok(false);
+    };
+    let old_name = package.get("name").and_then(|n| n.as_str()).map(str::to_string);
+    set_keeping_decor(package, "name", name);
+
+    if let Some(bins) = doc.get_mut("bin").and_then(|b| b.as_array_of_tables_mut()) {
+        let lone = bins.len() == 1;
+        for bin in bins.iter_mut() {
+            let is_package_bin = old_name.is_some() && bin.get("name").and_then(|n| n.as_str()) == old_name.as_deref();
+            if lone || is_package_bin {
+                set_keeping_decor(bin, "name", name);
+            }
+        }
+    }
+
+    fs::write(manifest_path, doc.to_string())?;
+    Ok(true)
+}
+
+/// Add a crate to the `[workspace]` members of a Cargo.toml, making the package a workspace root
+/// `member` is the crate's path relative to the manifest, with forward slashes
+/// Returns Ok(false) without touching the file if the crate is already a member
+pub fn add_workspace_member(manifest_path: &Path, member: &str) -> io::Result<bool> {
+    let mut doc = read_manifest(manifest_path)?;
+    if doc.get("workspace").is_none() {
+        doc["workspace"] = Item::Table(Table::new());
+    }
+    let Some(workspace) = doc["workspace"].as_table_like_mut() else {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "[workspace] is not a table"));
+    };
+    if workspace.get("members").is_none() {
+        workspace.insert("members", value(Array::new()));
+    }
+    let Some(members) = workspace.get_mut("members").and_then(|m| m.as_array_mut()) else {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "workspace members is not a list"));
+    };
+    if members.iter().any(|m| m.as_str() == Some(member)) {
+        return Ok(false);
+    }
+    members.push(member);
+
+    fs::write(manifest_path, doc.to_string())?;
+    Ok(true)
+}
+
+/// Remove a crate from the `[workspace]` members of a Cargo.toml
+/// Returns Ok(false) if it wasn't a member
+pub fn remove_workspace_member(manifest_path: &Path, member: &str) -> io::Result<bool> {
+    let mut doc = read_manifest(manifest_path)?;
+    let Some(members) = doc
+        .get_mut("workspace")
+        .and_then(|w| w.get_mut("members"))
+        .and_then(|m| m.as_array_mut())
+    else {
+        return Ok(false);
+    };
+    let before = members.len();
+    members.retain(|m| m.as_str() != Some(member));
+    if members.len() == before {
+        return Ok(false);
+    }
+
+    fs::write(manifest_path, doc.to_string())?;
+    Ok(true)
+}
+
+/// Move the `[profile]` settings of a workspace member up to the workspace root
+/// Cargo ignores profiles outside the root, so settings the root doesn't have are copied up
+/// and the member's are removed; where both set a key the root's setting is kept, with a warning
+/// if the member's differed
+pub fn hoist_profiles(member_manifest: &Path, root_manifest: &Path) -> io::Result<()> {
+    let mut member = read_manifest(member_manifest)?;
+    let Some(Item::Table(profiles)) = member.remove("profile") else {
+        return Ok(());
+    };
+    let mut root = read_manifest(root_manifest)?;
+    if root.get("profile").is_none() {
+        let mut table = Table::new();
+        table.set_implicit(true);
+        root["profile"] = Item::Table(table);
+    }
+    let Some(root_profiles) = root["profile"].as_table_mut() else {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "[profile] is not a table"));
+    };
+    for (name, settings) in profiles.iter() {
+        let Some(settings) = settings.as_table() else {
+            continue;
+        };
+        if root_profiles.get(name).is_none() {
+            root_profiles.insert(name, Item::Table(Table::new()));
+        }
+        let Some(root_settings) = root_profiles[name].as_table_mut() else {
+            continue;
+        };
+        for (key, setting) in settings.iter() {
+            match root_settings.get(key) {
+                None => {
+                    root_settings.insert(key, setting.clone());
+                }
+                Some(kept) if kept.to_string().trim() != setting.to_string().trim() => warn!(
+                    "dropped profile.{}.{} = {} from {}, the workspace root sets it to {}",
+                    name,
+                    key,
+                    setting.to_string().trim(),
+                    member_manifest.display(),
+                    kept.to_string().trim()
+                ),
+                Some(_) => {}
+            }
+        }
+    }
+
+    fs::write(root_manifest, root.to_string())?;
+    fs::write(member_manifest, member.to_string())?;
+    Ok(())
+}
+
+fn read_manifest(manifest_path: &Path) -> io::Result<DocumentMut> {
+    fs::read_to_string(manifest_path)?
+        .parse::<DocumentMut>()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+}
+
+/// Set a string value, keeping any comment or alignment around the old one
+fn set_keeping_decor(table: &mut dyn toml_edit::TableLike, key: &str, new_value: &str) {
+    match table.get_mut(key).and_then(|v| v.as_value_mut()) {
+        Some(old) => {
+            let decor = old.decor().clone();
+            *old = new_value.into();
+            *old.decor_mut() = decor;
+        }
+        None => {
+            table.insert(key, value(new_value));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{BoardStandards, get_boards};
+
+    #[test]
+    fn test_driver_crate_is_added_to_cargo_toml_once() {
+        let known_boards = get_boards(Path::new("./iron-coder-boards"));
+        let oled = known_boards
+            .iter()
+            .find(|b| b.get_name() == "OLED Featherwing (128x64)")
+            .unwrap();
+        let driver = oled.get_driver("I2C", Some(&BoardStandards::ESP32)).unwrap();
+        let temp = crate::test::temp_dir();
+        let manifest = temp.path().join("Cargo.toml");
+        fs::write(&manifest, "[package]\nname = \"blink\" # keep me\n\n[dependencies]\npanic-halt = \"0.2.0\"\n").unwrap();
+        assert!(add_dependency(&manifest, driver).unwrap());
+        assert!(!add_dependency(&manifest, driver).unwrap());
+        let contents = fs::read_to_string(&manifest).unwrap();
+        assert!(contents.contains("# keep me"));
+        assert!(contents.contains("sh1107-driver = \"0.1\""));
+    }
+}
@@ -0,0 +1,94 @@
+//! Creating a project from one of a board's examples
+//!
+//! Examples live in `<board dir>/examples/`, either as a single source file that becomes the
+//! project's `src/main.rs`, or as a directory laid over the board's template like a starter.
+use log::info;
+
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::board::Board;
+use crate::project::Project;
+use crate::project::project::ProjectIOError;
+use crate::project::starter;
+
+/// Copy an example into a new project directory with the board as its main board
+/// The destination must not exist yet, or be empty. The new project is returned loaded.
+pub fn create_from_example(
+    board: &Rc<Board>,
+    example: &Path,
+    destination: &Path,
+    kb: &Vec<Rc<Board>>,
+) -> Result<Project, ProjectIOError> {
+    if !board.is_main_board() {
+        return Err(ProjectIOError::NoMainBoard);
+    }
+    let is_empty = fs::read_dir(destination).map_or(true, |mut d| d.next().is_none());
+    if !is_empty {
+        return Err(ProjectIOError::DestinationNotEmpty);
+    }
+    fs::create_dir_all(destination).map_err(|_| ProjectIOError::FilesystemError)?;
+
+    let name = destination
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| "example".to_string());
+    let mut project = Project {
+        name: name.clone(),
+        ..Default::default()
+    };
+    project.add_board(board);
+    project.set_location(destination.to_path_buf());
+    project.generate_cargo_template()?;
+
+    let copied = if example.is_dir() {
+        starter::copy_dir(example, destination)
+    } else {
+        fs::create_dir_all(destination.join("src"))
+            .and_then(|_| fs::copy(example, destination.join("src/main.rs")))
+            .map(|_| ())
+    };
+    copied.map_err(|_| ProjectIOError::FilesystemError)?;
+
+    // an example that brings its own wiring keeps it, under the new project's name
+    if project.project_file().is_some_and(|f| f.exists()) {
+        project.load_from(destination, kb)?;
+        project.name = name;
+    }
+    project.save().map_err(|_| ProjectIOError::FilesystemError)?;
+    project.load_from(destination, kb)?;
+
+    info!("created project {} from example {}", destination.display(), example.display());
+    Ok(project)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::board::get_boards;
+    use std::path::Path;
+
+    #[test]
+    fn test_board_example_becomes_a_project() {
+        let known_boards = get_boards(Path::new("./iron-coder-boards"));
+        let uno = known_boards.iter().find(|b| b.get_name() == "Arduino Uno").unwrap();
+        let example = uno.find_example("serial_counter").unwrap();
+
+        let temp = crate::test::temp_dir();
+        let dir = temp.path().to_path_buf();
+        let destination = dir.join("counter");
+        let project = crate::project::example::create_from_example(uno, example, &destination, &known_boards).unwrap();
+        assert_eq!(project.name, "counter");
+        assert_eq!(project.main_board.as_ref().unwrap().borrow().board_name(), "Arduino Uno");
+        assert_eq!(
+            std::fs::read_to_string(destination.join("src/main.rs")).unwrap(),
+            std::fs::read_to_string(example).unwrap()
+        );
+        assert!(destination.join("Cargo.toml").exists());
+        assert!(project.source_files.iter().any(|f| f.ends_with("src/main.rs")));
+
+        // the destination has to be empty
+        let err = crate::project::example::create_from_example(uno, example, &destination, &known_boards);
+        assert!(matches!(err, Err(crate::project::project::ProjectIOError::DestinationNotEmpty)));
+    }
+}
@@ -0,0 +1,296 @@
+//! Versioning and migration of the `.ironcoder.toml` project file
+//!
+//! Every project file records the format it was written in. Older files are upgraded one
+//! version at a time by the migrations below before being deserialized, and the original
+//! file is backed up next to it so nothing is lost if a migration gets something wrong.
+use log::{info, warn};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml_edit::{Array, DocumentMut, value};
+
+/// Key holding the format version in the project file
+pub const FORMAT_VERSION_KEY: &str = "format_version";
+
+/// Format written by this version of Iron Coder
+pub const CURRENT_FORMAT_VERSION: u32 = 3;
+
+/// Files written before the format was versioned have no version key
+const UNVERSIONED: u32 = 1;
+
+type Migration = fn(&mut DocumentMut) -> Result<(), String>;
+
+/// Migrations in order; entry `i` upgrades a file from version `i + 1` to `i + 2`
+const MIGRATIONS: &[Migration] = &[migrate_v1_to_v2, migrate_v2_to_v3];
+
+/// Why a project file couldn't be loaded
+#[derive(Debug)]
+pub enum LoadError {
+    /// The file isn't valid TOML, or doesn't match the project structure
+    Parse {
+        message: String,
+        /// The offending key, when it could be determined
+        field: Option<String>,
+        line: Option<usize>,
+        column: Option<usize>,
+    },
+    /// The file was written by a newer Iron Coder
+    UnsupportedVersion { found: u32, supported: u32 },
+    /// A migration couldn't upgrade the file
+    Migration { from: u32, message: String },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Parse {
+                message,
+                field,
+                line,
+                column,
+            } => {
+                if let Some(line) = line {
+                    write!(f, "line {}", line)?;
+                    if let Some(column) = column {
+                        write!(f, ", column {}", column)?;
+                    }
+                    write!(f, ": ")?;
+                }
+                if let Some(field) = field {
+                    write!(f, "field `{}`: ", field)?;
+                }
+                write!(f, "{}", message)
+            }
+            LoadError::UnsupportedVersion { found, supported } => write!(
+                f,
+                "project file format {} is newer than the supported format {}, please update Iron Coder",
+                found, supported
+            ),
+            LoadError::Migration { from, message } => write!(
+                f,
+                "couldn't upgrade project file from format {}: {}",
+                from, message
+            ),
+        }
+    }
+}
+
+impl LoadError {
+    /// Build a parse error from a toml error, locating it in the source text
+    pub fn from_toml(error: &toml::de::Error, source: &str) -> Self {
+        let message = error.message().trim().to_string();
+        let (line, column, line_text) = match error.span() {
+            Some(span) => {
+                let (line, column) = line_and_column(source, span.start);
+                let text = source.lines().nth(line - 1).unwrap_or("").to_string();
+                (Some(line), Some(column), text)
+            }
+            None => (None, None, String::new()),
+        };
+        let field = field_from_message(&message).or_else(|| field_from_line(&line_text));
+        LoadError::Parse {
+            message,
+            field,
+            line,
+            column,
+        }
+    }
+}
+
+/// Upgrade the text of a project file to the current format
+/// Returns the upgraded text along with the version the file was written in
+/// Migrations edit the document in place, so the upgraded text keeps the file's layout
+pub fn migrate(source: &str) -> Result<(String, u32), LoadError> {
+    let mut doc: DocumentMut = source.parse().map_err(|e: toml_edit::TomlError| {
+        let (line, column) = match e.span() {
+            Some(span) => {
+                let (line, column) = line_and_column(source, span.start);
+                (Some(line), Some(column))
+            }
+            None => (None, None),
+        };
+        LoadError::Parse {
+            message: e.message().trim().to_string(),
+            field: None,
+            line,
+            column,
+        }
+    })?;
+    let found = file_version(&doc)?;
+    if found > CURRENT_FORMAT_VERSION {
+        return Err(LoadError::UnsupportedVersion {
+            found,
+            supported: CURRENT_FORMAT_VERSION,
+        });
+    }
+    for version in found..CURRENT_FORMAT_VERSION {
+        let migration = MIGRATIONS[(version - UNVERSIONED) as usize];
+        migration(&mut doc).map_err(|message| LoadError::Migration {
+            from: version,
+            message,
+        })?;
+        doc[FORMAT_VERSION_KEY] = value((version + 1) as i64);
+        info!("migrated project file from format {} to {}", version, version + 1);
+    }
+    if found == CURRENT_FORMAT_VERSION {
+        return Ok((source.to_string(), found));
+    }
+    Ok((doc.to_string(), found))
+}
+
+/// Deserialize an upgraded project file, collecting the keys this version doesn't know
+/// Unknown keys are left out rather than failing the load, so files touched by a newer
+/// Iron Coder or edited by hand still open
+pub fn deserialize<T: serde::de::DeserializeOwned>(source: &str) -> Result<(T, Vec<String>), toml::de::Error> {
+    let mut unknown = Vec::new();
+    let value = serde_ignored::deserialize(toml::Deserializer::new(source), |path| unknown.push(path.to_string()))?;
+    Ok((value, unknown))
+}
+
+/// Project files are always written in the current format, whatever they were loaded from
+pub fn serialize_current_version<S: serde::Serializer>(_: &u32, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u32(CURRENT_FORMAT_VERSION)
+}
+
+/// Get the path an older project file is backed up to before it is upgraded
+pub fn backup_path(project_file: &Path, version: u32) -> PathBuf {
+    let file_name = project_file
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    project_file.with_file_name(format!("{}.v{}.bak", file_name, version))
+}
+
+/// Copy an older project file aside, keeping any backup that already exists
+pub fn backup_project_file(project_file: &Path, version: u32) -> std::io::Result<PathBuf> {
+    let backup = backup_path(project_file, version);
+    if backup.exists() {
+        warn!("keeping existing project backup {}", backup.display());
+    } else {
+        fs::copy(project_file, &backup)?;
+        info!("backed up project file to {}", backup.display());
+    }
+    Ok(backup)
+}
+
+fn file_version(doc: &DocumentMut) -> Result<u32, LoadError> {
+    let Some(item) = doc.get(FORMAT_VERSION_KEY) else {
+        return Ok(UNVERSIONED);
+    };
+    match item.as_integer() {
+        Some(v) if v >= UNVERSIONED as i64 => Ok(v as u32),
+        _ => Err(LoadError::Parse {
+            message: format!("expected a format version number, found `{}`", item.to_string().trim()),
+            field: Some(FORMAT_VERSION_KEY.to_string()),
+            line: None,
+            column: None,
+        }),
+    }
+}
+
+/// Unversioned files stored the project directory and source file list, which are
+/// machine-specific and are recomputed from the project location on load
+fn migrate_v1_to_v2(doc: &mut DocumentMut) -> Result<(), String> {
+    doc.remove("location");
+    doc.remove("source_files");
+    Ok(())
+}
+
+/// Protocol groups used to store a full copy of each of their connections, which are
+/// also stored in the project's connection list; they now reference them by id
+fn migrate_v2_to_v3(doc: &mut DocumentMut) -> Result<(), String> {
+    let Some(groups) = doc.get_mut("protocol_groups").and_then(|g| g.as_table_like_mut()) else {
+        return Ok(());
+    };
+    for (group_key, group) in groups.iter_mut() {
+        let group = group
+            .as_table_like_mut()
+            .ok_or_else(|| format!("protocol group {} is not a table", group_key))?;
+        let mut ids = Array::new();
+        if let Some(connections) = group.remove("connections") {
+            let connections = connections
+                .into_array_of_tables()
+                .map_err(|_| format!("connections of protocol group {} are not a list", group_key))?;
+            for connection in connections.iter() {
+                let id = connection
+                    .get("id")
+                    .and_then(|id| id.as_str())
+                    .ok_or_else(|| format!("a connection of protocol group {} has no id", group_key))?;
+                ids.push(id);
+            }
+        }
+        group.insert("connection_ids", value(ids));
+    }
+    Ok(())
+}
+
+fn line_and_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+    (line, column)
+}
+
+/// Serde names the field in messages like "missing field `name`"
+fn field_from_message(message: &str) -> Option<String> {
+    let start = message.find("field `")? + "field `".len();
+    let len = message[start..].find('`')?;
+    Some(message[start..start + len].to_string())
+}
+
+/// Otherwise take the key or table header on the offending line
+fn field_from_line(line: &str) -> Option<String> {
+    let line = line.trim();
+    if line.starts_with('[') {
+        let name = line.trim_matches(|c| c == '[' || c == ']').trim();
+        return (!name.is_empty()).then(|| name.to_string());
+    }
+    let (key, _) = line.split_once('=')?;
+    let key = key.trim().trim_matches('"');
+    (!key.is_empty()).then(|| key.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::board::get_boards;
+    use crate::project::Project;
+    use std::path::Path;
+
+    #[test]
+    fn test_unversioned_project_file_is_migrated_with_backup() {
+        let known_boards = get_boards(Path::new("./iron-coder-boards"));
+        let temp = crate::test::temp_dir();
+        let dir = temp.path().to_path_buf();
+        let project_file = dir.join(".ironcoder.toml");
+        std::fs::copy("./example-projects/ArduinoUno_blink_i2c/.ironcoder.toml", &project_file).unwrap();
+
+        let mut project = Project::default();
+        project.load_from(&dir, &known_boards).unwrap();
+        assert_eq!(project.location.as_deref(), Some(dir.as_path()));
+        assert!(project.has_unsaved_changes());
+        let backup = crate::project::format::backup_path(&project_file, 1);
+        assert!(std::fs::read_to_string(&backup).unwrap().contains("location ="));
+
+        project.save().unwrap();
+        let saved = std::fs::read_to_string(&project_file).unwrap();
+        assert!(saved.starts_with(&format!(
+            "format_version = {}",
+            crate::project::format::CURRENT_FORMAT_VERSION
+        )));
+        assert!(!saved.contains("location ="));
+
+        // unknown keys are skipped, while errors name the offending field and where it is
+        let (_, unknown) = crate::project::format::deserialize::<Project>(&saved.replacen("name = ", "nmae = ", 1)).unwrap();
+        assert_eq!(unknown, ["nmae"]);
+        std::fs::write(&project_file, saved.replacen("name = ", "name = 5 #", 1)).unwrap();
+        let err = Project::default().load_from(&dir, &known_boards).unwrap_err().to_string();
+        std::fs::write(
+            &project_file,
+            format!("format_version = {}\n", crate::project::format::CURRENT_FORMAT_VERSION + 1),
+        )
+        .unwrap();
+        let newer = Project::default().load_from(&dir, &known_boards).unwrap_err().to_string();
+        assert!(err.contains("line 2") && err.contains("field `name`"), "{}", err);
+        assert!(newer.contains("newer"), "{}", newer);
+    }
+}
@@ -0,0 +1,388 @@
+//! Load-time integrity checks for project files
+//!
+//! A project file can reference things that no longer exist: a board that was renamed or
+//! removed from the board library, a connection to a board that isn't in the project, a pin
+//! the board's manifest doesn't have, or a protocol group wire that was deleted. These are
+//! collected when a project is loaded so the user can decide how to repair each one.
+use log::info;
+use std::collections::HashSet;
+use std::fmt;
+use std::rc::Rc;
+use uuid::Uuid;
+
+use crate::board::Board;
+use crate::project::Project;
+
+/// A dangling reference found in a project file
+#[derive(Debug, Clone, PartialEq)]
+pub enum Issue {
+    /// A board whose name isn't in the board library
+    MissingBoard { board_id: Uuid, board_name: String },
+    /// A connection to a board id that isn't in the project
+    OrphanConnection {
+        connection_id: Uuid,
+        connection_name: String,
+        board_id: Uuid,
+    },
+    /// A connection to a pin the board's manifest doesn't declare
+    UnknownPin {
+        connection_id: Uuid,
+        connection_name: String,
+        board_name: String,
+        pin: u32,
+    },
+    /// A protocol group listing a connection that isn't in the project
+    MissingGroupConnection {
+        group_id: Uuid,
+        group_name: String,
+        connection_id: Uuid,
+    },
+}
+
+/// A way of repairing an issue
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fix {
+    /// Use another board from the library in place of a missing one
+    Substitute(String),
+    /// Remove the offending item from the project
+    Drop,
+    /// Leave the item as it is, as a placeholder that can be fixed later
+    Keep,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Issue::MissingBoard { board_name, .. } => {
+                write!(f, "board \"{}\" is not in the board library", board_name)
+            }
+            Issue::OrphanConnection {
+                connection_name,
+                board_id,
+                ..
+            } => write!(
+                f,
+                "connection \"{}\" is attached to board {} which is not in the project",
+                connection_name, board_id
+            ),
+            Issue::UnknownPin {
+                connection_name,
+                board_name,
+                pin,
+                ..
+            } => write!(
+                f,
+                "connection \"{}\" uses pin {} which board \"{}\" does not have",
+                connection_name, pin, board_name
+            ),
+            Issue::MissingGroupConnection {
+                group_name,
+                connection_id,
+                ..
+            } => write!(
+                f,
+                "protocol group \"{}\" references connection {} which is not in the project",
+                group_name, connection_id
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Fix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fix::Substitute(name) => write!(f, "substitute \"{}\"", name),
+            Fix::Drop => write!(f, "drop"),
+            Fix::Keep => write!(f, "keep as placeholder"),
+        }
+    }
+}
+
+impl Issue {
+    /// Get the fixes that can be applied to this issue
+    /// Missing boards can be substituted with any library board of the same kind
+    pub fn fixes(&self, project: &Project, kb: &[Rc<Board>]) -> Vec<Fix> {
+        match self {
+            Issue::MissingBoard { board_id, .. } => {
+                let is_main = project.main_boards_iter().any(|b| b.borrow().id == *board_id);
+                let mut fixes: Vec<Fix> = kb
+                    .iter()
+                    .filter(|b| b.is_main_board() == is_main)
+                    .map(|b| Fix::Substitute(b.get_name().to_string()))
+                    .collect();
+                if !holds_workspace(project, board_id) {
+                    fixes.push(Fix::Drop);
+                }
+                fixes.push(Fix::Keep);
+                fixes
+            }
+            Issue::OrphanConnection { .. } | Issue::UnknownPin { .. } => vec![Fix::Drop, Fix::Keep],
+            // groups only ever hold live connections, so the stale reference can only go
+            Issue::MissingGroupConnection { .. } => vec![Fix::Drop],
+        }
+    }
+}
+
+/// Check a deserialized project against the board library
+/// This must run before `Project::load_board_resources`, which quietly skips dangling references
+pub fn check(project: &Project, kb: &[Rc<Board>]) -> Vec<Issue> {
+    let mut issues = Vec::new();
+
+    let board_ids: HashSet<Uuid> = project.boards_iter().map(|b| b.borrow().id).collect();
+    for b in project.boards_iter() {
+        let b = b.borrow();
+        if !kb.iter().any(|k| k.get_name() == b.board_name()) {
+            issues.push(Issue::MissingBoard {
+                board_id: b.id,
+                board_name: b.board_name().to_string(),
+            });
+        }
+    }
+
+    let find_board = |id: &Uuid| {
+        let name = project
+            .boards_iter()
+            .find(|b| b.borrow().id == *id)?
+            .borrow()
+            .board_name()
+            .to_string();
+        kb.iter().find(|k| k.get_name() == name).cloned()
+    };
+
+    for c in project.connections_iter() {
+        let c = c.borrow();
+        let mut ends = vec![(c.start_board_id(), c.get_start_pin())];
+        if let Some(end_pin) = c.get_end_pin() {
+            ends.push((c.end_board_id(), end_pin));
+        }
+        for (board_id, pin) in ends {
+            if !board_ids.contains(&board_id) {
+                issues.push(Issue::OrphanConnection {
+                    connection_id: c.id,
+                    connection_name: c.name.clone(),
+                    board_id,
+                });
+                break;
+            }
+            // pins can only be checked against boards that are in the library
+            let Some(board) = find_board(&board_id) else {
+                continue;
+            };
+            if board.pinout.get_pin(&pin).is_none() {
+                issues.push(Issue::UnknownPin {
+                    connection_id: c.id,
+                    connection_name: c.name.clone(),
+                    board_name: board.get_name().to_string(),
+                    pin,
+                });
+            }
+        }
+    }
+
+    let connection_ids: HashSet<Uuid> = project.connections_iter().map(|c| c.borrow().id).collect();
+    let mut groups: Vec<_> = project.protocol_groups.values().collect();
+    groups.sort_by_key(|g| g.id);
+    for group in groups {
+        for c in &group.connections {
+            let connection_id = c.borrow().id;
+            if !connection_ids.contains(&connection_id) {
+                issues.push(Issue::MissingGroupConnection {
+                    group_id: group.id,
+                    group_name: group.name.clone(),
+                    connection_id,
+                });
+            }
+        }
+    }
+
+    issues
+}
+
+/// Apply a fix to a loaded project
+/// Dropping only changes the project model; generated code that referenced the item is left
+/// alone since it can't be regenerated reliably without the missing data
+pub fn apply_fix(project: &mut Project, issue: &Issue, fix: &Fix, kb: &[Rc<Board>]) {
+    info!("fixing \"{}\": {}", issue, fix);
+    match (issue, fix) {
+        (_, Fix::Keep) => {}
+        (Issue::MissingBoard { board_id, .. }, Fix::Substitute(name)) => {
+            let Some(board) = project.board_map.get(board_id).cloned() else {
+                return;
+            };
+            board.borrow_mut().set_board_name(name);
+            let kb = kb.to_vec();
+            board.borrow_mut().init_refs(&kb, project);
+        }
+        (Issue::MissingBoard { board_id, .. }, Fix::Drop) => {
+            if holds_workspace(project, board_id) {
+                return;
+            }
+            drop_board(project, board_id)
+        }
+        (Issue::OrphanConnection { connection_id, .. }, Fix::Drop)
+        | (Issue::UnknownPin { connection_id, .. }, Fix::Drop) => {
+            drop_connection(project, connection_id)
+        }
+        (Issue::MissingGroupConnection { group_id, connection_id, .. }, Fix::Drop) => {
+            if let Some(group) = project.protocol_groups.get_mut(group_id) {
+                group.connections.retain(|c| c.borrow().id != *connection_id);
+            }
+        }
+        _ => return,
+    }
+    project.integrity_issues.retain(|i| i != issue);
+    if *fix != Fix::Keep {
+        project.mark_unsaved();
+    }
+}
+
+/// Load a project without a user interface and report its issues
+/// With a fix given, every issue it applies to is fixed and the project is saved
+pub fn check_headless(
+    project_directory: &std::path::Path,
+    kb: &[Rc<Board>],
+    fix: Option<Fix>,
+) -> Result<Vec<Issue>, crate::project::project::ProjectIOError> {
+    let mut project = Project::default();
+    project.load_from(project_directory, &kb.to_vec())?;
+    let issues = project.integrity_issues.clone();
+    if let Some(fix) = fix {
+        for issue in &issues {
+            if issue.fixes(&project, kb).contains(&fix) {
+                apply_fix(&mut project, issue, &fix, kb);
+            }
+        }
+        if project.has_unsaved_changes() {
+            project
+                .save()
+                .map_err(|_| crate::project::project::ProjectIOError::FilesystemError)?;
+        }
+    }
+    Ok(issues)
+}
+
+/// Whether a board is the main board whose crate is the root of other main boards' workspace
+/// Dropping it would leave their crates as members of a workspace with no root firmware
+fn holds_workspace(project: &Project, board_id: &Uuid) -> bool {
+    !project.secondary_main_boards.is_empty()
+        && project.main_board.as_ref().is_some_and(|b| b.borrow().id == *board_id)
+}
+
+fn drop_board(project: &mut Project, board_id: &Uuid) {
+    let attached: Vec<Uuid> = project
+        .connections_iter()
+        .filter(|c| {
+            let c = c.borrow();
+            c.start_board_id() == *board_id || (c.get_end_pin().is_some() && c.end_board_id() == *board_id)
+        })
+        .map(|c| c.borrow().id)
+        .collect();
+    for connection_id in attached {
+        drop_connection(project, &connection_id);
+    }
+    if project
+        .main_board
+        .as_ref()
+        .is_some_and(|b| b.borrow().id == *board_id)
+    {
+        project.main_board = None;
+    }
+    project.secondary_main_boards.retain(|b| b.borrow().id != *board_id);
+    project.peripheral_boards.retain(|b| b.borrow().id != *board_id);
+    project.board_map.remove(board_id);
+    for group in project.protocol_groups.values_mut() {
+        group.devices.retain(|d| d.board_id != *board_id);
+    }
+}
+
+fn drop_connection(project: &mut Project, connection_id: &Uuid) {
+    let Some(connection) = project
+        .connections_iter()
+        .find(|c| c.borrow().id == *connection_id)
+        .cloned()
+    else {
+        return;
+    };
+    for b in project.boards_iter() {
+        b.borrow_mut().drop_connection(&connection);
+    }
+    project.connections.retain(|c| !Rc::ptr_eq(c, &connection));
+    for group in project.protocol_groups.values_mut() {
+        group.connections.retain(|c| !Rc::ptr_eq(c, &connection));
+    }
+    project.protocol_groups.retain(|_, g| !g.connections.is_empty());
+    project.integrity_issues.retain(|i| match i {
+        Issue::OrphanConnection { connection_id: id, .. }
+        | Issue::UnknownPin { connection_id: id, .. } => id != connection_id,
+        _ => true,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::board::get_boards;
+    use crate::project::Project;
+    use std::path::Path;
+
+    #[test]
+    fn test_dangling_references_are_reported_and_fixed() {
+        use crate::project::integrity::{self, Fix, Issue};
+
+        let known_boards = get_boards(Path::new("./iron-coder-boards"));
+        let original = std::fs::read_to_string("./example-projects/ArduinoUno_blink_i2c/.ironcoder.toml").unwrap();
+        let broken = original
+            .replace("board_name = \"LED\"", "board_name = \"Retired LED\"")
+            .replacen("end_board_id = \"09620132-011f-47c5-b13c-932c954efdf4\"", "end_board_id = \"00000000-0000-0000-0000-0000000000aa\"", 1)
+            .replace("start_pin = 13", "start_pin = 999")
+            .replace(
+                "cd822.connections]]\nname = \"12_to_4\"\nid = \"431cb051-9f70-4c85-8b37-41dd21129011\"",
+                "cd822.connections]]\nname = \"12_to_4\"\nid = \"00000000-0000-0000-0000-0000000000bb\"",
+            );
+        let temp = crate::test::temp_dir();
+        let dir = temp.path().to_path_buf();
+        std::fs::write(dir.join(".ironcoder.toml"), &broken).unwrap();
+
+        let mut project = Project::default();
+        project.load_from(&dir, &known_boards).unwrap();
+        let issues = project.integrity_issues.clone();
+        assert_eq!(issues.len(), 4, "{:?}", issues);
+        assert!(matches!(&issues[0], Issue::MissingBoard { board_name, .. } if board_name == "Retired LED"));
+        assert!(matches!(&issues[1], Issue::OrphanConnection { connection_name, .. } if connection_name == "18_to_0"));
+        assert!(matches!(&issues[2], Issue::UnknownPin { pin: 999, .. }));
+        assert!(matches!(&issues[3], Issue::MissingGroupConnection { .. }));
+
+        // the missing board stays on the canvas as a named placeholder until it is fixed
+        let retired = project.peripheral_boards[1].clone();
+        assert_eq!(retired.borrow().board.get_name(), "Retired LED");
+        assert!(issues[0].fixes(&project, &known_boards).contains(&Fix::Substitute("LED".to_string())));
+        integrity::apply_fix(&mut project, &issues[0], &Fix::Substitute("LED".to_string()), &known_boards);
+        assert!(retired.borrow().board.svg_board_info.is_some());
+
+        for issue in &issues[1..] {
+            integrity::apply_fix(&mut project, issue, &Fix::Drop, &known_boards);
+        }
+        assert!(project.integrity_issues.is_empty());
+        assert_eq!(project.connections.len(), 1);
+        // the bus lost both of its wires, so it went too
+        assert!(project.protocol_groups.is_empty());
+
+        // headless runs report the same issues, and can fix them all at once
+        std::fs::write(dir.join(".ironcoder.toml"), &broken).unwrap();
+        assert_eq!(integrity::check_headless(&dir, &known_boards, None).unwrap(), issues);
+        assert_eq!(integrity::check_headless(&dir, &known_boards, Some(Fix::Drop)).unwrap().len(), 4);
+        assert!(integrity::check_headless(&dir, &known_boards, None).unwrap().is_empty());
+
+        // the main board can't be dropped while other main boards' crates are its members
+        let esp = known_boards.iter().find(|b| b.get_name() == "ESP32-C6-DevKitM-1").unwrap();
+        project.add_board(esp).unwrap();
+        let main = project.main_board.clone().unwrap();
+        let missing_main = Issue::MissingBoard {
+            board_id: main.borrow().id,
+            board_name: "Retired Uno".to_string(),
+        };
+        assert!(!missing_main.fixes(&project, &known_boards).contains(&Fix::Drop));
+        integrity::apply_fix(&mut project, &missing_main, &Fix::Drop, &known_boards);
+        assert!(project.main_board.as_ref().is_some_and(|b| std::rc::Rc::ptr_eq(b, &main)));
+        assert_eq!(project.secondary_main_boards.len(), 1);
+    }
+}
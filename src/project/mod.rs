@@ -1,3 +1,12 @@
+pub mod atomic_write;
+pub mod cargo_manifest;
+pub mod example;
+pub mod format;
+pub mod integrity;
 pub mod project;
+pub mod relocate;
+pub mod starter;
+pub mod template;
+pub mod toolchain;
 
 pub use project::Project;
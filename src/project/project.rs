@@ -11,8 +11,17 @@ use std::io::BufRead;
 use std::path::{Path, PathBuf};
 
 use crate::app::connection_wizard::WizardType;
+use crate::app::canvas_protocol::{BusDevice, format_address, to_identifier};
 use crate::app::{CanvasBoard, CanvasConnection, CanvasProtocol, SharedState};
-use crate::board::{BoardStandards, GPIODirection, get_boards};
+use crate::board::{BoardStandards, BuildProfile, Driver, GPIODirection, get_boards};
+use crate::app::recovery;
+use crate::dependencies::redist::ToolchainManager;
+use crate::project::{atomic_write, cargo_manifest, toolchain};
+use crate::project::format::{self, LoadError};
+use crate::project::integrity::{self, Issue};
+use crate::project::template::{Template, TemplateError};
+
+use heck::ToKebabCase;
 
 use egui::Context;
 
@@ -35,6 +44,9 @@ use crate::board::pinout::RoleAssignment;
 
 const PROJECT_FILE_NAME: &'static str = ".ironcoder.toml";
 
+/// Directory holding the firmware crates of the main boards after the first
+pub const FIRMWARE_MEMBERS_DIR: &str = "firmware";
+
 pub type Result = core::result::Result<(), ProjectIOError>;
 
 #[non_exhaustive]
@@ -44,8 +56,59 @@ pub enum ProjectIOError {
     NoMainBoard,
     NoProjectTemplate,
     NoProjectDirectory,
+    DestinationNotEmpty,
     FilesystemError,
-    LoadToTomlError,
+    LoadToTomlError(LoadError),
+    TemplateError(TemplateError),
+}
+
+impl std::fmt::Display for ProjectIOError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProjectIOError::FilePickerAborted => write!(f, "no folder was picked"),
+            ProjectIOError::NoMainBoard => write!(f, "the project has no main board"),
+            ProjectIOError::NoProjectTemplate => write!(f, "the main board has no project template"),
+            ProjectIOError::NoProjectDirectory => write!(f, "the project has no directory"),
+            ProjectIOError::DestinationNotEmpty => write!(f, "the destination folder isn't empty"),
+            ProjectIOError::FilesystemError => write!(f, "couldn't access the project files"),
+            ProjectIOError::LoadToTomlError(e) => write!(f, "invalid {}: {}", PROJECT_FILE_NAME, e),
+            ProjectIOError::TemplateError(e) => write!(f, "couldn't generate the project template: {}", e),
+        }
+    }
+}
+
+/// A board's project template to expand into a crate of the project
+pub struct CargoTemplate {
+    pub template_dir: PathBuf,
+    pub destination: PathBuf,
+    pub crate_name: String,
+    /// The project directory and the crate's path in it, for a firmware crate that joins the
+    /// project's workspace
+    pub member: Option<(PathBuf, String)>,
+}
+
+impl CargoTemplate {
+    pub fn expand(&self) -> Result {
+        Template::load(&self.template_dir)
+            .and_then(|t| t.expand(&self.destination, &self.crate_name, &HashMap::new()))
+            .map_err(ProjectIOError::TemplateError)?;
+        let Some((location, member)) = &self.member else {
+            return Ok(());
+        };
+        let root_manifest = location.join("Cargo.toml");
+        if !root_manifest.exists() {
+            warn!("project has no Cargo.toml, {} isn't part of a workspace", member);
+            return Ok(());
+        }
+        cargo_manifest::add_workspace_member(&root_manifest, member)
+            .and_then(|_| cargo_manifest::hoist_profiles(&self.destination.join("Cargo.toml"), &root_manifest))
+            .map_err(|e| {
+                warn!("couldn't add {} to the workspace: {}", member, e);
+                ProjectIOError::FilesystemError
+            })?;
+        info!("created firmware crate {}", self.destination.display());
+        Ok(())
+    }
 }
 
 /// A Project represents the highest level of Iron Coder, which contains
@@ -54,13 +117,23 @@ pub enum ProjectIOError {
 #[derive(Serialize, Deserialize, Default)]
 #[serde(default)]
 pub struct Project {
+    /// Version of the project file format, see `project::format`
+    #[serde(serialize_with = "format::serialize_current_version")]
+    pub format_version: u32,
     pub name: String,
+    #[serde(skip)]
     pub location: Option<PathBuf>,
+    #[serde(skip)]
     pub source_files: Vec<PathBuf>,
 
     #[serde(with = "rc_refcell_option")]
     pub main_board: Option<Rc<RefCell<CanvasBoard>>>,
 
+    /// Further programmable boards, each with its own firmware crate as a member of the
+    /// Cargo workspace rooted at the main board's crate
+    #[serde(with = "rc_refcell_vec")]
+    pub secondary_main_boards: Vec<Rc<RefCell<CanvasBoard>>>,
+
     #[serde(with = "rc_refcell_vec")]
     pub peripheral_boards: Vec<Rc<RefCell<CanvasBoard>>>,
 
@@ -68,7 +141,7 @@ pub struct Project {
     pub connections: Vec<Rc<RefCell<CanvasConnection>>>,
 
     /// Protocol groups (I2C, SPI, UART) that bundle multiple connections together
-    #[serde(default)]
+    #[serde(default, with = "sorted_map")]
     pub protocol_groups: HashMap<Uuid, CanvasProtocol>,
 
     #[serde(skip)]
@@ -76,6 +149,10 @@ pub struct Project {
 
     #[serde(skip)]
     pub has_unsaved_changes: bool,
+
+    /// Dangling references found when the project was loaded, waiting for the user to fix them
+    #[serde(skip)]
+    pub integrity_issues: Vec<Issue>,
 }
 
 // backend functionality for Project struct
@@ -124,8 +201,7 @@ impl Project {
         match board.is_main_board() {
             true => {
                 if self.has_main_board() {
-                    info!("project already contains a main board! aborting.");
-                    return None;
+                    return self.add_secondary_main_board(board);
                 } else {
                     if let Some(b) = CanvasBoard::new(&board) {
                         let b_ref = Rc::new(RefCell::new(b));
@@ -153,9 +229,65 @@ impl Project {
         return None;
     }
 
+    /// Add another programmable board, giving it a firmware crate in the project's workspace
+    fn add_secondary_main_board(&mut self, board: &Rc<Board>) -> Option<Rc<RefCell<CanvasBoard>>> {
+        let mut b = CanvasBoard::new(board)?;
+        b.firmware_dir = Some(self.unique_firmware_dir(board));
+        let b_ref = Rc::new(RefCell::new(b));
+        self.board_map.insert(b_ref.borrow().id, b_ref.clone());
+        self.secondary_main_boards.push(b_ref.clone());
+        if self.location.is_some() {
+            if let Err(e) = self.generate_firmware_member(&b_ref) {
+                warn!("couldn't create the firmware crate for {}: {}", board.get_name(), e);
+            }
+            self.find_source_files();
+        }
+        self.mark_unsaved();
+        Some(b_ref)
+    }
+
+    /// Pick a directory for a board's firmware crate that no other board uses and that doesn't exist yet
+    fn unique_firmware_dir(&self, board: &Board) -> String {
+        let base = format!("{}/{}", FIRMWARE_MEMBERS_DIR, board.get_name().to_kebab_case());
+        let taken = |dir: &str| {
+            self.secondary_main_boards
+                .iter()
+                .any(|b| b.borrow().firmware_dir.as_deref() == Some(dir))
+                || self.location.as_ref().is_some_and(|l| l.join(dir).exists())
+        };
+        let mut candidate = base.clone();
+        let mut suffix = 2;
+        while taken(&candidate) {
+            candidate = format!("{}-{}", base, suffix);
+            suffix += 1;
+        }
+        candidate
+    }
+
+    /// Every programmable board in the project, the first main board first
+    pub fn main_boards_iter(&self) -> impl Iterator<Item = &Rc<RefCell<CanvasBoard>>> {
+        self.main_board.iter().chain(self.secondary_main_boards.iter())
+    }
+
+    /// Get the directory of the crate holding a main board's firmware
+    /// The first main board's firmware is the project itself, the others' are workspace members
+    pub fn firmware_crate_dir(&self, board_id: &Uuid) -> Option<PathBuf> {
+        let location = self.location.as_ref()?;
+        let member_dir = self
+            .secondary_main_boards
+            .iter()
+            .find(|b| b.borrow().id == *board_id)
+            .and_then(|b| b.borrow().firmware_dir.clone());
+        match member_dir {
+            Some(dir) => Some(location.join(dir)),
+            None => Some(location.clone()),
+        }
+    }
+
     /// Populate the project board list via the app-wide 'known boards' list
     pub fn load_board_resources(&mut self, kb: &Vec<Rc<Board>>) {
-        if let Some(b) = &self.main_board {
+        let main_boards: Vec<_> = self.main_boards_iter().cloned().collect();
+        for b in &main_boards {
             let board_id = b.borrow().id;
             self.board_map.insert(board_id, b.clone());
             b.borrow_mut().init_refs(kb, &self);
@@ -172,6 +304,31 @@ impl Project {
         for (_, protocol_group) in self.protocol_groups.iter_mut() {
             protocol_group.init_refs(&self.connections);
         }
+        // Groups saved before groups had names get a unique default one
+        let unnamed: Vec<Uuid> = self
+            .protocol_groups
+            .values()
+            .filter(|g| g.name.trim().is_empty())
+            .map(|g| g.id)
+            .collect();
+        for group_id in unnamed {
+            let Some(protocol_type) = self.protocol_groups.get(&group_id).map(|g| g.protocol_type) else {
+                continue;
+            };
+            let name = self.unique_group_name(&CanvasProtocol::default_name(&protocol_type), Some(group_id));
+            if let Some(group) = self.protocol_groups.get_mut(&group_id) {
+                group.name = name;
+            }
+        }
+        // Groups saved before buses tracked their peripherals get them from their wiring
+        let group_ids: Vec<Uuid> = self.protocol_groups.keys().cloned().collect();
+        for group_id in group_ids {
+            let Some(mut group) = self.protocol_groups.get(&group_id).cloned() else {
+                continue;
+            };
+            self.sync_bus_devices(&mut group);
+            self.protocol_groups.insert(group_id, group);
+        }
     }
 
     /// This method will reload the project based on the current project location
@@ -186,145 +343,93 @@ impl Project {
     /// Load a project from a specified directory, and sync the board assets.
     pub fn load_from(&mut self, project_directory: &Path, kb: &Vec<Rc<Board>>) -> Result {
         let project_file = project_directory.join(PROJECT_FILE_NAME);
-        let toml_str = match fs::read_to_string(project_file) {
+        let toml_str = match fs::read_to_string(&project_file) {
             Ok(s) => s,
             Err(e) => {
                 warn!("error reading project file: {:?}", e);
                 return Err(ProjectIOError::FilesystemError);
             }
         };
-        let p: Project = match toml::from_str(&toml_str) {
-            Ok(p) => p,
+        self.load_from_str(project_directory, &toml_str, kb)
+    }
+
+    /// Load a project from the contents of a project file, as if it were in the given directory
+    /// Used to restore a project from its crash-recovery copy
+    pub fn load_from_str(&mut self, project_directory: &Path, toml_str: &str, kb: &Vec<Rc<Board>>) -> Result {
+        let project_file = project_directory.join(PROJECT_FILE_NAME);
+        let (toml_str, found_version) = match format::migrate(toml_str) {
+            Ok(upgraded) => upgraded,
             Err(e) => {
                 eprintln!("Failed to parse TOML: {}", e);
-                return Err(ProjectIOError::LoadToTomlError);
+                return Err(ProjectIOError::LoadToTomlError(e));
             }
         };
+        let p: Project = match format::deserialize(&toml_str) {
+            Ok((p, unknown)) => {
+                for key in unknown {
+                    warn!("ignoring unknown key `{}` in {}", key, PROJECT_FILE_NAME);
+                }
+                p
+            }
+            Err(e) => {
+                let e = LoadError::from_toml(&e, &toml_str);
+                eprintln!("Failed to parse TOML: {}", e);
+                return Err(ProjectIOError::LoadToTomlError(e));
+            }
+        };
+        // keep the file as it was before the upgrade is saved over it
+        let migrated = found_version < format::CURRENT_FORMAT_VERSION;
+        if migrated {
+            if let Err(e) = format::backup_project_file(&project_file, found_version) {
+                warn!("couldn't back up project file before upgrading it: {:?}", e);
+                return Err(ProjectIOError::FilesystemError);
+            }
+        }
+        self.format_version = format::CURRENT_FORMAT_VERSION;
         self.name = p.name;
         self.location = Some(project_directory.to_path_buf());
         self.main_board = p.main_board;
+        self.secondary_main_boards = p.secondary_main_boards;
         self.peripheral_boards = p.peripheral_boards;
         self.connections = p.connections;
         self.protocol_groups = p.protocol_groups;
-        self.has_unsaved_changes = false; // just loaded from disk therefore no changes
+        self.board_map.clear();
+        self.integrity_issues = integrity::check(self, kb);
+        for issue in &self.integrity_issues {
+            warn!("project integrity: {}", issue);
+        }
+        // just loaded from disk therefore no changes, unless the file needs rewriting in the new format
+        self.has_unsaved_changes = migrated;
         self.load_board_resources(kb);
         self.find_source_files();
         self.update_toolchain_location();
         Ok(())
     }
-    /// Determine platform for toolchain selection
-    fn get_platform_toolchain_dir() -> &'static str {
-        if cfg!(target_os = "linux") {
-            "linux-x86_64"
-        } else if cfg!(target_os = "macos") {
-            if cfg!(target_arch = "aarch64") {
-                "macos-aarch64"
-            } else {
-                "macos-x86_64"
-            }
-        } else if cfg!(target_os = "windows") {
-            "windows-x86_64"
-        } else {
-            "unknown"
-        }
-    }
-    /// Select toolchain if necessary and update cargo config
+    /// Bring the cargo config of every firmware crate in line with its board's build profile
     pub fn update_toolchain_location(&mut self) {
-        if let Some(loc) = &self.location {
-            if let Some(mut ide_path) = std::env::current_exe()
-                .ok()
-                .map(|p| p.to_path_buf().parent().unwrap().to_path_buf())
-            {
-                if ide_path.ends_with("debug") || ide_path.ends_with("release") {
-                    println!(
-                        "Detected debug/release build, adjusting toolchain path respectively."
-                    );
-                    ide_path = ide_path.parent().unwrap().parent().unwrap().to_path_buf();
-                    // println!("IDE path: {}", ide_path.display());
-                }
-
-                if let Some(cpu_name) = self.main_board.as_ref().and_then(|b| {
-                    let b = b.borrow();
-                    b.board.cpu.clone()
-                }) {
-                    if cpu_name != "Microchip AVR" {
-                        return;
-                    }
-
-                    let toolchain_name = "Arduino";
-                    let os_str = Self::get_platform_toolchain_dir();
-                    if os_str == "unknown" {
-                        warn!("Unsupported OS for toolchain configuration.");
-                        return;
-                    }
-                    let toolchain_bin = ide_path
-                        .join("Redist")
-                        .join(toolchain_name)
-                        .join("toolchain")
-                        .join(os_str)
-                        .join("bin");
-
-                    // Point to avr-gcc for build, avrdude for upload
-                    let avr_gcc_path = if cfg!(windows) {
-                        toolchain_bin.join("avr-gcc.exe")
-                    } else {
-                        toolchain_bin.join("avr-gcc")
-                    };
-
-                    let cargo_config_dir = loc.join(".cargo");
-                    let cargo_config_file = cargo_config_dir.join("config.toml");
-
-                    // Ensure .cargo directory exists
-                    if let Err(e) = fs::create_dir_all(&cargo_config_dir) {
-                        warn!("Failed to create .cargo directory: {}", e);
-                        return;
-                    }
-
-                    let mut config = toml::value::Table::new();
-
-                    // If config.toml exists, read and parse it
-                    if cargo_config_file.exists() {
-                        if let Ok(contents) = fs::read_to_string(&cargo_config_file) {
-                            if let Ok(parsed) = contents.parse::<toml::Value>() {
-                                if let Some(table) = parsed.as_table() {
-                                    config = table.clone();
-                                }
-                            }
-                        }
-                    }
-
-                    // TOML is weird, target is a section, and that section contains the key cfg(target_arch = "avr")
-                    let target_section_key = "target";
-                    let target_cfg_key = "cfg(target_arch = \"avr\")";
-
-                    // Get or create the [target] section
-                    let target_section = config
-                        .entry(target_section_key.to_string())
-                        .or_insert_with(|| toml::Value::Table(toml::map::Map::new()));
-
-                    if let toml::Value::Table(target_map) = target_section {
-                        // Get or create the specific target config
-                        let target_table = target_map
-                            .entry(target_cfg_key.to_string())
-                            .or_insert_with(|| toml::Value::Table(toml::map::Map::new()));
-
-                        if let toml::Value::Table(target) = target_table {
-                            target.insert(
-                                "linker".to_string(),
-                                toml::Value::String(avr_gcc_path.to_string_lossy().to_string()),
-                            );
-                        }
-                    }
-
-                    // Write back to config.toml
-                    let toml_str = toml::to_string_pretty(&config).unwrap_or_default();
-                    if let Err(e) = fs::write(&cargo_config_file, toml_str) {
-                        warn!("Failed to write toolchain config: {}", e);
-                    }
+        let crates: Vec<(BuildProfile, PathBuf)> = self
+            .main_boards_iter()
+            .filter_map(|b| {
+                let b = b.borrow();
+                Some((b.board.build_profile().clone(), self.firmware_crate_dir(&b.id)?))
+            })
+            .collect();
+        let redist = ToolchainManager::locate();
+        for (profile, crate_dir) in crates {
+            let linker = match (&redist, &profile.redist, &profile.linker) {
+                (Some(redist), Some(toolchain), Some(linker)) => redist.tool(toolchain, linker),
+                (None, Some(toolchain), Some(_)) => {
+                    warn!("can't find the Redist directory holding the {} toolchain", toolchain);
+                    None
                 }
+                _ => None,
+            };
+            if let Err(e) = toolchain::update_cargo_config(&crate_dir, &profile, linker.as_deref()) {
+                warn!("couldn't update the cargo config in {}: {}", crate_dir.display(), e);
             }
         }
     }
+
     /// Prompt the user to select project directory to open
     pub fn open(&mut self, kb: &Vec<Rc<Board>>) -> Result {
         if let Some(project_directory) = FileDialog::new().pick_folder() {
@@ -377,9 +482,10 @@ impl Project {
                 project_file.display().to_string()
             );
 
-            match toml::to_string(self) {
+            match self.to_toml() {
                 Ok(contents) => {
-                    fs::write(project_file, contents)?;
+                    atomic_write::write_atomic(&project_file, contents.as_bytes())?;
+                    recovery::RecoveryStore::default().discard(&project_file);
                     // Mark as saved
                     self.has_unsaved_changes = false;
                 }
@@ -393,6 +499,16 @@ impl Project {
         }
     }
 
+    /// Get the contents of the project file for the project as it is now
+    pub fn to_toml(&self) -> core::result::Result<String, toml::ser::Error> {
+        toml::to_string(self)
+    }
+
+    /// Get the path of the project file, if the project has a location
+    pub fn project_file(&self) -> Option<PathBuf> {
+        self.location.as_ref().map(|l| l.join(PROJECT_FILE_NAME))
+    }
+
     pub fn mark_unsaved(&mut self) {
         self.has_unsaved_changes = true;
     }
@@ -401,40 +517,91 @@ impl Project {
         self.has_unsaved_changes
     }
 
+    /// Expand the main board's project template into the project directory
+    /// Expansion happens in-process, so cargo-generate doesn't need to be installed
     pub fn generate_cargo_template(&mut self) -> Result {
-        if let Some(mb) = &self.main_board {
-            if let Some(template_dir) = mb.borrow().board.get_template_dir() {
-                let destination = self.get_location();
-
-                let cmd = duct::cmd!(
-                    "cargo",
-                    "generate",
-                    "--path",
-                    template_dir.as_path().to_str().unwrap(),
-                    "--name",
-                    self.name.clone(),
-                    "--destination",
-                    destination.clone(),
-                    "--init",
-                );
-
-                match cmd.run() {
-                    Ok(output) => Ok(()),
-                    Err(e) => Err(ProjectIOError::FilesystemError),
-                }
-            } else {
-                return Err(ProjectIOError::NoProjectTemplate);
-            }
-        } else {
+        for template in self.cargo_templates()? {
+            template.expand()?;
+        }
+        Ok(())
+    }
+
+    /// Get the templates to expand for the main board's crate, and for the firmware crates of
+    /// other main boards that don't exist yet
+    /// They hold no reference to the project, so can be expanded off the UI thread
+    pub fn cargo_templates(&self) -> core::result::Result<Vec<CargoTemplate>, ProjectIOError> {
+        let Some(mb) = &self.main_board else {
             return Err(ProjectIOError::NoMainBoard);
+        };
+        let Some(template_dir) = mb.borrow().board.get_template_dir() else {
+            return Err(ProjectIOError::NoProjectTemplate);
+        };
+        let Some(destination) = &self.location else {
+            return Err(ProjectIOError::NoProjectDirectory);
+        };
+        let mut templates = vec![CargoTemplate {
+            template_dir,
+            destination: destination.clone(),
+            crate_name: self.name.clone(),
+            member: None,
+        }];
+        for b in &self.secondary_main_boards {
+            let missing = self
+                .firmware_crate_dir(&b.borrow().id)
+                .is_some_and(|dir| !dir.exists());
+            if missing {
+                templates.push(self.firmware_member_template(b)?);
+            }
         }
+        Ok(templates)
+    }
+
+    /// Expand a secondary main board's template into its firmware crate and add the crate
+    /// to the workspace
+    fn generate_firmware_member(&self, board: &Rc<RefCell<CanvasBoard>>) -> Result {
+        self.firmware_member_template(board)?.expand()
+    }
+
+    fn firmware_member_template(&self, board: &Rc<RefCell<CanvasBoard>>) -> core::result::Result<CargoTemplate, ProjectIOError> {
+        let Some(location) = &self.location else {
+            return Err(ProjectIOError::NoProjectDirectory);
+        };
+        let (template_dir, member) = {
+            let b = board.borrow();
+            (b.board.get_template_dir(), b.firmware_dir.clone())
+        };
+        let Some(template_dir) = template_dir else {
+            return Err(ProjectIOError::NoProjectTemplate);
+        };
+        let Some(member) = member else {
+            return Err(ProjectIOError::NoMainBoard);
+        };
+        let destination = location.join(&member);
+        let crate_name = format!(
+            "{}-{}",
+            self.name.to_kebab_case(),
+            destination.file_name().unwrap_or_default().to_string_lossy()
+        );
+        Ok(CargoTemplate {
+            template_dir,
+            destination,
+            crate_name,
+            member: Some((location.clone(), member)),
+        })
     }
 
     pub fn find_source_files(&mut self) {
         if let Some(loc) = &self.location {
-            let src_path = loc.join("src");
+            let mut src_paths = vec![loc.join("src")];
+            for b in &self.secondary_main_boards {
+                if let Some(dir) = &b.borrow().firmware_dir {
+                    src_paths.push(loc.join(dir).join("src"));
+                }
+            }
             let mut source_files = Vec::new();
-            self.recursive_add_source(&src_path, &mut source_files);
+            for src_path in src_paths {
+                self.recursive_add_source(&src_path, &mut source_files);
+            }
             self.source_files = source_files;
         }
     }
@@ -472,7 +639,8 @@ impl Project {
 
     pub fn remove_board(&mut self, board: &Rc<RefCell<CanvasBoard>>) {
         let mut b = board.borrow_mut();
-        if b.board.is_main_board() {
+        // the first main board's firmware is the project itself
+        if self.main_board.as_ref().is_some_and(|mb| Rc::ptr_eq(mb, board)) {
             return;
         }
 
@@ -484,40 +652,61 @@ impl Project {
             self.remove_connection(c);
         }
         self.peripheral_boards.retain(|c| !Rc::ptr_eq(c, board));
-    }
-
-    pub fn remove_connection(&mut self, connection: &Rc<RefCell<CanvasConnection>>) {
-        let main_file = self.source_files.iter().find(|path| {
-            path.file_name()
-                .map(|name| name == "main.rs")
-                .unwrap_or(false)
-        });
 
-        let conn = connection.borrow();
-
-        if let Some(path) = main_file {
-            self.remove_pin_from_source(path, &conn);
+        let member = board.borrow().firmware_dir.clone();
+        if let Some(member) = member {
+            self.secondary_main_boards.retain(|c| !Rc::ptr_eq(c, board));
+            // the crate itself stays on disk, only the workspace stops building it
+            if let Some(location) = &self.location {
+                let root_manifest = location.join("Cargo.toml");
+                if root_manifest.exists() {
+                    if let Err(e) = cargo_manifest::remove_workspace_member(&root_manifest, &member) {
+                        warn!("couldn't remove {} from the workspace: {}", member, e);
+                    }
+                }
+            }
+            self.find_source_files();
+            self.mark_unsaved();
         }
+    }
 
-        if let Some(group_id) = conn.protocol_group_id {
-            if let Some(group) = self.protocol_groups.get_mut(&group_id) {
-                group.connections.retain(|c| !Rc::ptr_eq(c, connection));
+    pub fn remove_connection(&mut self, connection: &Rc<RefCell<CanvasConnection>>) {
+        let group_id = connection.borrow().protocol_group_id;
+        match group_id.and_then(|id| self.protocol_groups.get(&id).cloned()) {
+            Some(old_group) => {
+                // bus code is generated per group, so regenerate the group without this wire
+                let mut new_group = old_group.clone();
+                new_group.connections.retain(|c| !Rc::ptr_eq(c, connection));
+                self.sync_bus_devices(&mut new_group);
+                self.update_group_source(&old_group, &new_group);
+                self.protocol_groups.insert(new_group.id, new_group);
+            }
+            None => {
+                let controller_id = connection.borrow().start_board_id();
+                if let Some(path) = self.source_file_for(&controller_id) {
+                    self.remove_pin_from_source(&path, &connection.borrow());
+                    if let Some((_, lines)) = self.generate_gpio_driver(&connection.borrow()) {
+                        self.remove_lines_from_source(&path, &lines);
+                    }
+                }
+                if let Some((path, stmt)) = self.linked_end_statement(&connection.borrow()) {
+                    self.remove_lines_from_source(&path, &[stmt]);
+                }
             }
         }
 
-        drop(conn);
-
         self.connections.retain(|c| !Rc::ptr_eq(c, connection));
     }
 
     pub fn boards_iter(&self) -> impl Iterator<Item = &Rc<RefCell<CanvasBoard>>> {
-        self.main_board.iter().chain(self.peripheral_boards.iter())
+        self.main_boards_iter().chain(self.peripheral_boards.iter())
     }
 
     pub fn boards_iter_rev(&self) -> impl Iterator<Item = &Rc<RefCell<CanvasBoard>>> {
         self.peripheral_boards
             .iter()
             .rev()
+            .chain(self.secondary_main_boards.iter().rev())
             .chain(self.main_board.iter().rev())
     }
 
@@ -528,43 +717,191 @@ impl Project {
     pub fn add_connection(&mut self, c: &Rc<RefCell<CanvasConnection>>) {
         self.connections.push(c.clone());
 
-        let main_file = self.source_files.iter().find(|path| {
-            path.file_name()
-                .map(|name| name == "main.rs")
-                .unwrap_or(false)
-        });
-
-        let conn = c.borrow();
+        let controller_id = c.borrow().start_board_id();
+        if let Some(path) = self.source_file_for(&controller_id) {
+            self.insert_pin_into_source(&path, &c.borrow());
+            if let Some((driver, lines)) = self.generate_gpio_driver(&c.borrow()) {
+                self.insert_lines_after_marker(&path, "INTERFACE_DEFINITIONS", &lines);
+                self.add_driver_dependency(&controller_id, &driver);
+            }
+        }
+        // a wire between two main boards shows up in the firmware of both
+        if let Some((path, stmt)) = self.linked_end_statement(&c.borrow()) {
+            self.insert_lines_after_marker(&path, "PIN_DEFINITIONS", &[stmt]);
+        }
 
-        if let Some(path) = main_file {
-            self.insert_pin_into_source(path, &conn);
+        // a wire re-added to a bus that still exists rejoins it
+        let group_id = c.borrow().protocol_group_id;
+        if let Some(group_id) = group_id {
+            if self.protocol_groups.contains_key(&group_id) {
+                self.add_connections_to_protocol_group(&group_id, vec![c.clone()]);
+            }
         }
     }
 
     // ===== Protocol Group Management =====
 
     /// Add a protocol group to the project
-    pub fn add_protocol_group(&mut self, group: CanvasProtocol) {
+    /// Assigns a unique name and a free peripheral instance if the group has none, registers the
+    /// peripherals wired to it, then replaces the wires' standalone pin code with the group's code
+    /// Returns false, leaving the project unchanged, if the board has no instance left for the group
+    pub fn add_protocol_group(&mut self, mut group: CanvasProtocol) -> bool {
+        let in_use = self.instances_in_use(&group, Some(group.id));
+        if group.peripheral_instance.as_ref().is_none_or(|i| in_use.contains(i)) {
+            group.peripheral_instance = self.next_free_instance(&group, Some(group.id));
+        }
+        if group.peripheral_instance.is_none() && !self.protocol_instances(&group).is_empty() {
+            warn!(
+                "the board has no free {} peripheral left for another bus",
+                group.protocol_type.interface_name()
+            );
+            return false;
+        }
+        let base_name = if group.name.trim().is_empty() {
+            CanvasProtocol::default_name(&group.protocol_type)
+        } else {
+            group.name.clone()
+        };
+        group.name = self.unique_group_name(&base_name, None);
+        group.assign_to_connections();
+        self.sync_bus_devices(&mut group);
+
+        if let Some(path) = self.group_source_file(&group) {
+            for conn in &group.connections {
+                self.remove_pin_from_source(&path, &conn.borrow());
+            }
+            self.insert_group_into_source(&path, &group);
+        }
+
         self.protocol_groups.insert(group.id, group);
+        self.mark_unsaved();
+        true
+    }
+
+    /// Add connections to an existing protocol group, either to complete missing roles
+    /// or to attach another peripheral to a shared bus
+    pub fn add_connections_to_protocol_group(
+        &mut self,
+        group_id: &Uuid,
+        connections: Vec<Rc<RefCell<CanvasConnection>>>,
+    ) {
+        let Some(old_group) = self.protocol_groups.get(group_id).cloned() else {
+            return;
+        };
+        let mut new_group = old_group.clone();
+        let path = self.group_source_file(&old_group);
+        for conn in connections {
+            if new_group.connections.iter().any(|c| Rc::ptr_eq(c, &conn)) {
+                continue;
+            }
+            if let Some(path) = &path {
+                self.remove_pin_from_source(path, &conn.borrow());
+            }
+            new_group.add_connection(conn);
+        }
+        new_group.assign_to_connections();
+        self.sync_bus_devices(&mut new_group);
+        self.update_group_source(&old_group, &new_group);
+        self.protocol_groups.insert(new_group.id, new_group);
+        self.mark_unsaved();
     }
 
     /// Remove a protocol group from the project
     pub fn remove_protocol_group(&mut self, group_id: &Uuid) {
-        let main_file = self.source_files.iter().find(|path| {
-            path.file_name()
-                .map(|name| name == "main.rs")
-                .unwrap_or(false)
-        });
-
         if let Some(protocol) = self.protocol_groups.get(group_id) {
-            if let Some(path) = main_file {
-                self.remove_bus_from_source(path, &protocol.protocol_type);
+            if let Some(path) = self.group_source_file(protocol) {
+                self.remove_group_from_source(&path, protocol);
             }
         }
 
         self.protocol_groups.remove(group_id);
     }
 
+    /// Rename a protocol group, regenerating its variable names in the source
+    /// Returns the name actually applied, which is made unique among the project's groups
+    pub fn rename_protocol_group(&mut self, group_id: &Uuid, new_name: &str) -> Option<String> {
+        let old_group = self.protocol_groups.get(group_id)?.clone();
+        let unique_name = self.unique_group_name(new_name, Some(*group_id));
+        let mut new_group = old_group.clone();
+        new_group.name = unique_name.clone();
+
+        self.update_group_source(&old_group, &new_group);
+        self.protocol_groups.insert(*group_id, new_group);
+        self.mark_unsaved();
+        Some(unique_name)
+    }
+
+    /// Rename a device on a shared bus, regenerating its handle in the source
+    /// Returns the name actually applied, which is made unique among the project's buses and devices
+    pub fn rename_bus_device(&mut self, group_id: &Uuid, board_id: &Uuid, new_name: &str) -> Option<String> {
+        let old_group = self.protocol_groups.get(group_id)?.clone();
+        old_group.get_device(board_id)?;
+        let unique_name = self.unique_identifier(new_name, Some(*board_id), &[]);
+        let mut new_group = old_group.clone();
+        if let Some(device) = new_group.devices.iter_mut().find(|d| d.board_id == *board_id) {
+            device.name = unique_name.clone();
+        }
+
+        self.update_group_source(&old_group, &new_group);
+        self.protocol_groups.insert(*group_id, new_group);
+        self.mark_unsaved();
+        Some(unique_name)
+    }
+
+    /// Select the bus address of a device on an I2C bus
+    pub fn set_bus_device_address(&mut self, group_id: &Uuid, board_id: &Uuid, address: u8) {
+        let Some(old_group) = self.protocol_groups.get(group_id).cloned() else {
+            return;
+        };
+        let mut new_group = old_group.clone();
+        let Some(device) = new_group.devices.iter_mut().find(|d| d.board_id == *board_id) else {
+            return;
+        };
+        if device.address == Some(address) {
+            return;
+        }
+        device.address = Some(address);
+
+        for (address, names) in new_group.address_conflicts() {
+            warn!("I2C address {} is used by {}", format_address(address), names.join(", "));
+        }
+        self.update_group_source(&old_group, &new_group);
+        self.protocol_groups.insert(*group_id, new_group);
+        self.mark_unsaved();
+    }
+
+    /// Get the addresses a peripheral board supports on a protocol's bus, default address first
+    pub fn device_addresses(&self, board_id: &Uuid, protocol_type: &WizardType) -> Vec<u8> {
+        match self.board_map.get(board_id) {
+            Some(b) => b
+                .borrow()
+                .board
+                .pinout
+                .get_interface_addresses(protocol_type.interface_name()),
+            None => vec![],
+        }
+    }
+
+    /// Select the peripheral instance (e.g. I2C1) that drives a protocol group
+    pub fn set_protocol_group_instance(&mut self, group_id: &Uuid, instance: &str) {
+        let Some(old_group) = self.protocol_groups.get(group_id).cloned() else {
+            return;
+        };
+        if old_group.peripheral_instance.as_deref() == Some(instance) {
+            return;
+        }
+        if self.instances_in_use(&old_group, Some(*group_id)).iter().any(|i| i == instance) {
+            warn!("{} already drives another bus", instance);
+            return;
+        }
+        let mut new_group = old_group.clone();
+        new_group.peripheral_instance = Some(instance.to_string());
+
+        self.update_group_source(&old_group, &new_group);
+        self.protocol_groups.insert(*group_id, new_group);
+        self.mark_unsaved();
+    }
+
     /// Get a protocol group by ID
     pub fn get_protocol_group(&self, group_id: &Uuid) -> Option<&CanvasProtocol> {
         self.protocol_groups.get(group_id)
@@ -579,82 +916,311 @@ impl Project {
         }
     }
 
-    pub fn add_connection_bus(&mut self, bus_type: &WizardType) {
-        let main_file = self.source_files.iter().find(|path| {
-            path.file_name()
-                .map(|name| name == "main.rs")
-                .unwrap_or(false)
-        });
+    /// Check if a pin is one of the shared lines (e.g. SDA, SCK) of an existing bus of this type,
+    /// in which case another peripheral may be wired to it
+    pub fn is_shared_bus_pin(&self, board_id: &Uuid, pin: u32, protocol_type: &WizardType) -> bool {
+        let shared_roles = protocol_type.shared_roles();
+        self.protocol_groups
+            .values()
+            .filter(|g| g.protocol_type == *protocol_type)
+            .flat_map(|g| g.connections.iter())
+            .any(|c| {
+                let c = c.borrow();
+                c.get_start_board().borrow().id == *board_id
+                    && c.get_start_pin() == pin
+                    && c.role.as_ref().is_some_and(|r| shared_roles.contains(r))
+            })
+    }
+
+    /// Find the bus whose shared lines the given connections were wired to
+    /// Returns None if the connections form a new bus
+    pub fn find_shared_bus_group(
+        &self,
+        protocol_type: &WizardType,
+        connections: &[Rc<RefCell<CanvasConnection>>],
+    ) -> Option<Uuid> {
+        let shared_roles = protocol_type.shared_roles();
+        if shared_roles.is_empty() {
+            return None;
+        }
+        let bus_pins = |conns: &[Rc<RefCell<CanvasConnection>>]| -> Vec<Option<(Uuid, u32)>> {
+            shared_roles
+                .iter()
+                .map(|role| {
+                    conns
+                        .iter()
+                        .find(|c| c.borrow().role.as_ref() == Some(role))
+                        .map(|c| {
+                            let c = c.borrow();
+                            (c.get_start_board().borrow().id, c.get_start_pin())
+                        })
+                })
+                .collect()
+        };
+        let new_pins = bus_pins(connections);
+        if new_pins.iter().any(|p| p.is_none()) {
+            return None;
+        }
+        self.protocol_groups
+            .values()
+            .filter(|g| g.protocol_type == *protocol_type)
+            .find(|g| bus_pins(&g.connections) == new_pins)
+            .map(|g| g.id)
+    }
 
-        if let Some(path) = main_file {
-            self.insert_bus_into_source(path, bus_type);
+    /// Get the peripheral instances the main board driving a group offers for its protocol
+    pub fn protocol_instances(&self, group: &CanvasProtocol) -> Vec<String> {
+        match self.group_controller(group) {
+            Some(mb) => mb
+                .borrow()
+                .board
+                .pinout
+                .get_interface_instances(group.protocol_type.interface_name()),
+            None => vec![],
         }
     }
 
-    fn insert_bus_into_source(&self, path: &PathBuf, bus_type: &WizardType) {
-        let marker = "INTERFACE_DEFINITIONS".to_string();
+    /// Get the main board driving a group, which is where its wires start
+    pub fn group_controller(&self, group: &CanvasProtocol) -> Option<Rc<RefCell<CanvasBoard>>> {
+        match group.connections.first() {
+            Some(c) => Some(c.borrow().get_start_board().clone()),
+            None => self.main_board.clone(),
+        }
+    }
 
-        let code = read_to_string(&path).unwrap();
-        let mut output = Vec::new();
-        let mut inserted = false;
+    /// Get the peripheral instances other groups of the same type on the same board already use
+    fn instances_in_use(&self, group: &CanvasProtocol, exclude: Option<Uuid>) -> Vec<String> {
+        let controller_id = self.group_controller(group).map(|b| b.borrow().id);
+        self.protocol_groups
+            .values()
+            .filter(|g| g.protocol_type == group.protocol_type && Some(g.id) != exclude)
+            .filter(|g| self.group_controller(g).map(|b| b.borrow().id) == controller_id)
+            .filter_map(|g| g.peripheral_instance.clone())
+            .collect()
+    }
 
-        let Some(new_stmt_str) = self.generate_bus_statement(bus_type) else {
-            return;
+    /// Pick the first peripheral instance not already used by another group of the same type on the same board
+    fn next_free_instance(&self, group: &CanvasProtocol, exclude: Option<Uuid>) -> Option<String> {
+        let used = self.instances_in_use(group, exclude);
+        self.protocol_instances(group).into_iter().find(|i| !used.contains(i))
+    }
+
+    /// Make a group name unique by appending a numeric suffix if its identifier is taken
+    pub fn unique_group_name(&self, base_name: &str, exclude: Option<Uuid>) -> String {
+        self.unique_identifier(base_name, exclude, &[])
+    }
+
+    /// Make a bus or device name unique among every bus and device in the project
+    /// `exclude` is the id of the group or device board being renamed, `extra_taken`
+    /// holds identifiers not yet stored in the project
+    fn unique_identifier(&self, base_name: &str, exclude: Option<Uuid>, extra_taken: &[String]) -> String {
+        let base_name = if base_name.trim().is_empty() {
+            "bus"
+        } else {
+            base_name.trim()
         };
-        println!("HERES WHAT WIZ WANTS: {}", new_stmt_str);
+        let mut taken: Vec<String> = extra_taken.to_vec();
+        for g in self.protocol_groups.values() {
+            if Some(g.id) != exclude {
+                taken.push(g.variable_name());
+            }
+            for d in &g.devices {
+                if Some(d.board_id) != exclude {
+                    taken.push(d.variable_name());
+                }
+            }
+        }
 
-        for line in code.lines() {
-            output.push(line.to_string());
+        let mut candidate = base_name.to_string();
+        let mut suffix = 2;
+        while taken.contains(&to_identifier(&candidate)) {
+            candidate = format!("{}_{}", base_name, suffix);
+            suffix += 1;
+        }
+        candidate
+    }
 
-            if !inserted && line.contains(&marker) {
-                let indent = line
-                    .chars()
-                    .take_while(|c| c.is_whitespace())
-                    .collect::<String>();
-                output.push(format!("{}{}", indent, new_stmt_str));
-                inserted = true;
+    /// Keep a group's device list in step with the peripherals its connections reach
+    /// New peripherals are named after their board and get the first bus address not taken
+    /// on the bus, devices without wires are dropped
+    fn sync_bus_devices(&self, group: &mut CanvasProtocol) {
+        let mut wired: Vec<(Uuid, String, Vec<u8>)> = Vec::new();
+        for conn in &group.connections {
+            if let Some(eb) = conn.borrow().get_end_board() {
+                let eb = eb.borrow();
+                if !wired.iter().any(|(id, _, _)| *id == eb.id) {
+                    let addresses = eb
+                        .board
+                        .pinout
+                        .get_interface_addresses(group.protocol_type.interface_name());
+                    wired.push((eb.id, eb.board.get_name().to_string(), addresses));
+                }
             }
         }
-        let code = output.join("\n");
-        write(path, code);
+        group.devices.retain(|d| wired.iter().any(|(id, _, _)| *id == d.board_id));
+
+        for (board_id, board_name, addresses) in wired {
+            if group.get_device(&board_id).is_some() {
+                continue;
+            }
+            let mut taken = vec![group.variable_name()];
+            taken.extend(group.devices.iter().map(|d| d.variable_name()));
+            let base_name = self.device_base_name(group, &board_id, &board_name);
+            let name = self.unique_identifier(&base_name, Some(group.id), &taken);
+
+            let used: Vec<u8> = group.devices.iter().filter_map(|d| d.address).collect();
+            let address = addresses
+                .iter()
+                .find(|a| !used.contains(a))
+                .or(addresses.first())
+                .copied();
+            if let Some(address) = address.filter(|a| used.contains(a)) {
+                warn!("{} has no free I2C address, {} is already in use", name, format_address(address));
+            }
+            group.devices.push(BusDevice { board_id, name, address });
+        }
+    }
+
+    /// Name a new device on a bus after its board, unless the board's name reads like a bus
+    /// itself (the generic I2C/SPI boards); those go by their driver crate or get a suffix
+    fn device_base_name(&self, group: &CanvasProtocol, board_id: &Uuid, board_name: &str) -> String {
+        let is_bus_name = [WizardType::None, WizardType::I2C, WizardType::SPI, WizardType::UART]
+            .iter()
+            .any(|t| to_identifier(board_name) == CanvasProtocol::default_name(t));
+        if !is_bus_name {
+            return board_name.to_string();
+        }
+        match self.device_driver(group, board_id) {
+            Some(driver) => driver.crate_name,
+            None => format!("{board_name} device"),
+        }
+    }
+
+    /// Get the source file a main board's generated code goes into
+    fn source_file_for(&self, board_id: &Uuid) -> Option<PathBuf> {
+        let is_member = self.secondary_main_boards.iter().any(|b| b.borrow().id == *board_id);
+        if !is_member {
+            return self.main_source_file();
+        }
+        let path = self.firmware_crate_dir(board_id)?.join("src").join("main.rs");
+        path.exists().then_some(path)
+    }
+
+    fn group_source_file(&self, group: &CanvasProtocol) -> Option<PathBuf> {
+        match self.group_controller(group) {
+            Some(controller) => self.source_file_for(&controller.borrow().id),
+            None => self.main_source_file(),
+        }
     }
 
-    fn remove_bus_from_source(&self, path: &PathBuf, bus_type: &WizardType) {
-        let Some(stmt_to_remove) = self.generate_bus_statement(bus_type) else {
+    fn main_source_file(&self) -> Option<PathBuf> {
+        self.source_files
+            .iter()
+            .find(|path| {
+                path.file_name()
+                    .map(|name| name == "main.rs")
+                    .unwrap_or(false)
+            })
+            .cloned()
+    }
+
+    /// Replace a group's generated code after the group changed
+    fn update_group_source(&self, old_group: &CanvasProtocol, new_group: &CanvasProtocol) {
+        if let Some(path) = self.group_source_file(old_group) {
+            self.remove_group_from_source(&path, old_group);
+            self.insert_group_into_source(&path, new_group);
+        }
+    }
+
+    fn insert_group_into_source(&self, path: &PathBuf, group: &CanvasProtocol) {
+        let (pin_lines, interface_lines) = self.generate_group_code(group);
+        self.insert_lines_after_marker(path, "PIN_DEFINITIONS", &pin_lines);
+        self.insert_lines_after_marker(path, "INTERFACE_DEFINITIONS", &interface_lines);
+        let Some(controller_id) = self.group_controller(group).map(|b| b.borrow().id) else {
             return;
         };
+        for device in &group.devices {
+            if let Some(driver) = self.device_driver(group, &device.board_id) {
+                self.add_driver_dependency(&controller_id, &driver);
+            }
+        }
+    }
+
+    fn remove_group_from_source(&self, path: &PathBuf, group: &CanvasProtocol) {
+        let (pin_lines, interface_lines) = self.generate_group_code(group);
+        self.remove_lines_from_source(path, &pin_lines);
+        self.remove_lines_from_source(path, &interface_lines);
+    }
+
+    /// Remove generated lines from a source file, ignoring indentation
+    /// The lines are removed as the block they were inserted as when it is still intact,
+    /// otherwise each line is removed once
+    fn remove_lines_from_source(&self, path: &PathBuf, lines: &[String]) {
+        if lines.is_empty() {
+            return;
+        }
 
         let Ok(code) = read_to_string(&path) else {
             return;
         };
 
-        let mut output = Vec::new();
-        let lines: Vec<&str> = code.lines().collect();
-        let mut skip_next = false;
+        let lines_to_remove: Vec<&str> = lines.iter().map(|l| l.trim()).collect();
+        let code_lines: Vec<&str> = code.lines().collect();
+        let block_start = code_lines.windows(lines_to_remove.len()).position(|window| {
+            window
+                .iter()
+                .zip(&lines_to_remove)
+                .all(|(line, to_remove)| line.trim() == *to_remove)
+        });
 
-        for line in lines {
-            if skip_next {
-                skip_next = false;
-                continue;
+        let mut output = Vec::new();
+        if let Some(start) = block_start {
+            for (i, line) in code_lines.iter().enumerate() {
+                if i < start || i >= start + lines_to_remove.len() {
+                    output.push(line.to_string());
+                }
             }
-
-            let trimmed = line.trim();
-
-            if stmt_to_remove.contains('\n') {
-                let parts: Vec<&str> = stmt_to_remove.split('\n').collect();
-                if parts.len() == 2 && trimmed == parts[0].trim() {
-                    skip_next = true;
+        } else {
+            // every generated line names a group or device variable, so each is removed once
+            let mut lines_to_remove = lines_to_remove;
+            for line in code_lines {
+                let trimmed = line.trim();
+                if let Some(pos) = lines_to_remove.iter().position(|l| *l == trimmed) {
+                    lines_to_remove.remove(pos);
                     continue;
                 }
+                output.push(line.to_string());
             }
+        }
 
-            if trimmed == stmt_to_remove.trim() {
-                continue;
-            }
+        let code = output.join("\n");
+        write(path, code);
+    }
 
-            output.push(line.to_string());
+    fn insert_lines_after_marker(&self, path: &PathBuf, marker: &str, lines: &[String]) {
+        if lines.is_empty() {
+            return;
         }
+        let Ok(code) = read_to_string(&path) else {
+            return;
+        };
+        let mut output = Vec::new();
+        let mut inserted = false;
+
+        for line in code.lines() {
+            output.push(line.to_string());
 
+            if !inserted && line.contains(marker) {
+                let indent = line
+                    .chars()
+                    .take_while(|c| c.is_whitespace())
+                    .collect::<String>();
+                for stmt_line in lines {
+                    output.push(format!("{}{}", indent, stmt_line));
+                }
+                inserted = true;
+            }
+        }
         let code = output.join("\n");
         write(path, code);
     }
@@ -666,7 +1232,7 @@ impl Project {
         let mut output = Vec::new();
         let mut inserted = false;
 
-        let Some(new_stmt_str) = self.generate_pin_statement(conn) else {
+        let Some(new_stmt_str) = self.generate_pin_statement(conn, None) else {
             return;
         };
         println!("HERES WHAT CONN WANTS {}", new_stmt_str);
@@ -688,7 +1254,7 @@ impl Project {
     }
 
     fn remove_pin_from_source(&self, path: &PathBuf, conn: &CanvasConnection) {
-        let Some(stmt_to_remove) = self.generate_pin_statement(conn) else {
+        let Some(stmt_to_remove) = self.generate_pin_statement(conn, None) else {
             return;
         };
 
@@ -712,32 +1278,262 @@ impl Project {
         write(path, code);
     }
 
-    fn generate_bus_statement(&self, bus_type: &WizardType) -> Option<String> {
-        let Some(sb_rc) = &self.main_board else {
+    /// Generate a protocol group's code, split into pin definitions and interface definitions
+    /// Shared bus lines are defined once; once peripherals are attached every device gets its own
+    /// handle on the bus, from embedded-hal-bus or, on HALs still on embedded-hal 0.2, shared-bus
+    pub(crate) fn generate_group_code(&self, group: &CanvasProtocol) -> (Vec<String>, Vec<String>) {
+        let mut pin_lines = Vec::new();
+        let mut interface_lines = Vec::new();
+        let v = group.variable_name();
+        let shared_roles = group.protocol_type.shared_roles();
+
+        if shared_roles.is_empty() {
+            for conn in &group.connections {
+                if let Some(stmt) = self.generate_pin_statement(&conn.borrow(), Some(&v)) {
+                    pin_lines.push(stmt);
+                }
+            }
+        } else {
+            for role in &shared_roles {
+                if let Some(conn) = group.connection_for_role(role) {
+                    if let Some(stmt) = self.generate_pin_statement(&conn.borrow(), Some(&v)) {
+                        pin_lines.push(stmt);
+                    }
+                }
+            }
+        }
+
+        let Some(bus_stmt) = self.generate_bus_statement(group) else {
+            return (pin_lines, interface_lines);
+        };
+        interface_lines.extend(bus_stmt.lines().map(|l| l.to_string()));
+        if shared_roles.is_empty() || group.devices.is_empty() {
+            return (pin_lines, interface_lines);
+        }
+
+        let shared_bus = self.uses_shared_bus(group);
+        if shared_bus {
+            interface_lines.push(format!("let {v}_bus = shared_bus::BusManagerSimple::new({v});"));
+        } else {
+            interface_lines.push(format!("let {v}_bus = core::cell::RefCell::new({v});"));
+        }
+        for device in &group.devices {
+            let d = device.variable_name();
+            match group.protocol_type {
+                WizardType::I2C => {
+                    // drivers take the address alongside the bus handle
+                    let driver = self.device_driver(group, &device.board_id);
+                    let takes_address = driver.as_ref().is_some_and(|d| d.uses_placeholder("address"));
+                    if let Some(address) = device.address.filter(|_| takes_address) {
+                        interface_lines.push(format!("let {d}_address: u8 = {};", format_address(address)));
+                    }
+                    if shared_bus {
+                        interface_lines.push(format!("let mut {d} = {v}_bus.acquire_i2c();"));
+                    } else {
+                        interface_lines.push(format!(
+                            "let mut {d} = embedded_hal_bus::i2c::RefCellDevice::new(&{v}_bus);"
+                        ));
+                    }
+                    if let Some(driver) = driver {
+                        interface_lines.extend(driver.instantiate(&[
+                            ("name", d.clone()),
+                            ("bus", d.clone()),
+                            ("address", format!("{d}_address")),
+                        ]));
+                    }
+                }
+                WizardType::SPI => {
+                    let cs_stmt = group
+                        .device_connections(&device.board_id)
+                        .iter()
+                        .find(|c| c.borrow().role.as_deref() == Some("CS"))
+                        .and_then(|c| self.generate_cs_statement(&c.borrow(), &v, &d));
+                    // a device can't get a handle until its chip select is wired
+                    let Some(cs_stmt) = cs_stmt else {
+                        continue;
+                    };
+                    interface_lines.push(cs_stmt);
+                    if shared_bus {
+                        // embedded-hal 0.2 drivers toggle their chip select themselves
+                        interface_lines.push(format!("let mut {d} = {v}_bus.acquire_spi();"));
+                    } else {
+                        interface_lines.push(format!(
+                            "let mut {d} = embedded_hal_bus::spi::RefCellDevice::new_no_delay(&{v}_bus, {d}_cs).unwrap();"
+                        ));
+                    }
+                    if let Some(driver) = self.device_driver(group, &device.board_id) {
+                        interface_lines.extend(driver.instantiate(&[
+                            ("name", d.clone()),
+                            ("bus", d.clone()),
+                            ("cs", format!("{d}_cs")),
+                        ]));
+                    }
+                }
+                _ => {}
+            }
+        }
+        (pin_lines, interface_lines)
+    }
+
+    /// Whether the HAL of the board driving a group is still on embedded-hal 0.2, where devices
+    /// share a bus through shared-bus proxies rather than embedded-hal-bus handles
+    fn uses_shared_bus(&self, group: &CanvasProtocol) -> bool {
+        self.group_controller(group)
+            .is_some_and(|c| c.borrow().board.get_board_standard() == Some(BoardStandards::Feather))
+    }
+
+    /// Find the driver a peripheral board declares for an interface, matching the standard of
+    /// the main board it is wired to
+    fn peripheral_driver(&self, controller: &Board, board: &Board, interface: &str) -> Option<Driver> {
+        let standard = controller.get_board_standard();
+        board.get_driver(interface, standard.as_ref()).cloned()
+    }
+
+    /// Find the driver of a device on a bus
+    fn device_driver(&self, group: &CanvasProtocol, board_id: &Uuid) -> Option<Driver> {
+        let controller = self.group_controller(group)?.borrow().board.clone();
+        let board = self.board_map.get(board_id)?.borrow().board.clone();
+        self.peripheral_driver(&controller, &board, group.protocol_type.interface_name())
+    }
+
+    /// Generate the driver for a peripheral wired straight to a main board pin, if it declares one
+    fn generate_gpio_driver(&self, conn: &CanvasConnection) -> Option<(Driver, Vec<String>)> {
+        let eb_rc = conn.get_end_board()?;
+        let eb = &eb_rc.borrow().board;
+        let controller = conn.get_start_board().borrow().board.clone();
+        let driver = self.peripheral_driver(&controller, eb, "GPIO")?;
+        let end_pin = conn.get_end_pin()?;
+        if driver.pin.is_some_and(|p| p != end_pin) {
+            return None;
+        }
+
+        let start_pin = conn.get_start_pin();
+        let sb_rc = conn.get_start_board();
+        let pin_alias = sb_rc
+            .borrow()
+            .board
+            .pinout
+            .get_pin_alias(&start_pin, &"GPIO".to_string())?;
+        let name = to_identifier(&format!("{} {}", eb.get_name(), pin_alias));
+        let pin = format!("pin_c_{}_to_{}", start_pin, end_pin);
+        let lines = driver.instantiate(&[("name", name), ("pin", pin)]);
+        Some((driver, lines))
+    }
+
+    /// Add the crates of every driver the project's wiring uses to its Cargo.toml
+    /// Used when a project arrives already wired, such as one created from a starter
+    pub fn add_wired_driver_dependencies(&self) {
+        for c in self.connections_iter() {
+            if let Some((driver, _)) = self.generate_gpio_driver(&c.borrow()) {
+                self.add_driver_dependency(&c.borrow().start_board_id(), &driver);
+            }
+        }
+        for group in self.protocol_groups.values() {
+            let Some(controller_id) = self.group_controller(group).map(|b| b.borrow().id) else {
+                continue;
+            };
+            for device in &group.devices {
+                if let Some(driver) = self.device_driver(group, &device.board_id) {
+                    self.add_driver_dependency(&controller_id, &driver);
+                }
+            }
+        }
+    }
+
+    /// Add a driver's crate to the Cargo.toml of a main board's firmware if it isn't a dependency yet
+    fn add_driver_dependency(&self, controller_id: &Uuid, driver: &Driver) {
+        let Some(crate_dir) = self.firmware_crate_dir(controller_id) else {
+            return;
+        };
+        let manifest_path = crate_dir.join("Cargo.toml");
+        if !manifest_path.exists() {
+            return;
+        }
+        match cargo_manifest::add_dependency(&manifest_path, driver) {
+            Ok(true) => info!("added {} {} to Cargo.toml", driver.crate_name, driver.version),
+            Ok(false) => {}
+            Err(e) => warn!("couldn't add {} to Cargo.toml: {}", driver.crate_name, e),
+        }
+    }
+
+    /// Generate the chip select output for one device on a shared SPI bus
+    fn generate_cs_statement(&self, conn: &CanvasConnection, bus_var: &str, device_var: &str) -> Option<String> {
+        let sb_rc = conn.get_start_board();
+        let sb = &sb_rc.borrow().board;
+        let start_pin = conn.get_start_pin();
+        let pin_alias = sb.pinout.get_pin_alias(&start_pin, &"GPIO".to_string())?;
+
+        let fmt = match sb.get_board_standard() {
+            Some(BoardStandards::Arduino) => {
+                // setup_spi! takes ownership of the hardware SS pin and hands it back
+                if Self::hardware_ss_pin(sb) == Some(start_pin) {
+                    format!("let {device_var}_cs = {bus_var}_ss;")
+                } else {
+                    format!("let {device_var}_cs = pins.{pin_alias}.into_output();")
+                }
+            }
+            Some(BoardStandards::ESP32) => format!(
+                "let {device_var}_cs = Output::new(peripherals.{pin_alias}, Level::High, OutputConfig::default());"
+            ),
+            Some(BoardStandards::Feather) => {
+                format!("let {device_var}_cs = pins.{pin_alias}.into_push_pull_output();")
+            }
+            _ => return None,
+        };
+        Some(fmt)
+    }
+
+    /// Physical pin of the main board's hardware SPI slave select, if it has one
+    fn hardware_ss_pin(board: &Board) -> Option<u32> {
+        board
+            .pinout
+            .get_pins_from_role(&"SS".to_string())
+            .and_then(|pins| pins.iter().min().copied())
+    }
+
+    pub(crate) fn generate_bus_statement(&self, group: &CanvasProtocol) -> Option<String> {
+        let Some(sb_rc) = &self.group_controller(group) else {
             return None;
         };
         let sb = &sb_rc.borrow().board;
+        let v = group.variable_name();
+        // boards with more than one block say which in their manifest, first one by default
+        let instance = || {
+            group
+                .peripheral_instance
+                .clone()
+                .or_else(|| self.protocol_instances(group).first().cloned())
+        };
         let fmt = match sb.get_board_standard() {
-            Some(BoardStandards::Arduino) => match bus_type {
-                WizardType::I2C => format!("let mut i2c = setup_i2c!(dp, sda, scl, 10_000);"),
-                WizardType::SPI => format!("let mut i2c = setup_spi!(dp, sck, mosi, miso, ss);"),
+            Some(BoardStandards::Arduino) => match group.protocol_type {
+                WizardType::I2C => format!("let mut {v} = setup_i2c!(dp, {v}_sda, {v}_scl, 10_000);"),
+                WizardType::SPI => {
+                    let ss_alias = Self::hardware_ss_pin(sb)
+                        .and_then(|pin| sb.pinout.get_pin_alias(&pin, &"GPIO".to_string()))
+                        .unwrap_or("d10".to_string());
+                    format!("let (mut {v}, {v}_ss) = setup_spi!(dp, {v}_sck, {v}_mosi, {v}_miso, pins.{ss_alias});")
+                }
                 _ => return None,
             },
-            Some(BoardStandards::ESP32) => match bus_type {
+            Some(BoardStandards::ESP32) => match group.protocol_type {
                 WizardType::I2C => format!(
-                    "let i2c_peripheral = peripherals.I2C0;\n    let mut i2c = setup_i2c!(i2c_peripheral, sda, scl, 10);"
+                    "let {v}_peripheral = peripherals.{};\nlet mut {v} = setup_i2c!({v}_peripheral, {v}_sda, {v}_scl, 10);",
+                    instance()?
                 ),
                 WizardType::SPI => format!(
-                    "let spi_peripheral = peripherals.SPI2;\n    let mut spi = setup_spi!(spi_peripheral, sck, mosi, miso, 10);"
+                    "let {v}_peripheral = peripherals.{};\nlet mut {v} = setup_spi!({v}_peripheral, {v}_sck, {v}_mosi, {v}_miso, 10);",
+                    instance()?
                 ),
                 _ => return None,
             },
-            Some(BoardStandards::Feather) => match bus_type {
+            Some(BoardStandards::Feather) => match group.protocol_type {
                 WizardType::I2C => format!(
-                    "let i2c_peripheral = pac.I2C1;\n    let mut i2c = setup_i2c!(pac, clocks, 100_000, i2c_peripheral, sda, scl);"
+                    "let {v}_peripheral = pac.{};\nlet mut {v} = setup_i2c!(pac, clocks, 100_000, {v}_peripheral, {v}_sda, {v}_scl);",
+                    instance()?
                 ),
                 WizardType::SPI => format!(
-                    "let spi_peripheral = pac.SPI0;\n   let spi_mode = Mode {{polarity: Polarity::IdleLow, phase: Phase::CaptureOnFirstTransition}};\n    let mut spi = setup_spi!(pac, miso, mosi, sck, clocks, 100_000, spi_peripheral, spi_mode);"
+                    "let {v}_peripheral = pac.{};\nlet {v}_mode = Mode {{ polarity: Polarity::IdleLow, phase: Phase::CaptureOnFirstTransition }};\nlet mut {v} = setup_spi!(pac, {v}_miso, {v}_mosi, {v}_sck, clocks, 100_000, {v}_peripheral, {v}_mode);",
+                    instance()?
                 ),
                 _ => return None,
             },
@@ -746,7 +1542,33 @@ impl Project {
         return Some(fmt);
     }
 
-    fn generate_pin_statement(&self, conn: &CanvasConnection) -> Option<String> {
+    /// Generate the pin of a wire between two main boards, seen from one of them
+    /// The pin is handed over as it is, since the firmware on each side decides which way it drives
+    fn generate_link_statement(board: &Board, pin: u32, other_pin: u32) -> Option<String> {
+        let pin_alias = board.pinout.get_pin_alias(&pin, &"GPIO".to_string())?;
+        let var_name = format!("c_{}_to_{}", pin, other_pin);
+        let fmt = match board.get_board_standard() {
+            Some(BoardStandards::Arduino) | Some(BoardStandards::Feather) => {
+                format!("let pin_{} = pins.{};", var_name, pin_alias)
+            }
+            Some(BoardStandards::ESP32) => format!("let pin_{} = peripherals.{};", var_name, pin_alias),
+            _ => return None,
+        };
+        Some(fmt)
+    }
+
+    /// Get the source file and pin statement a wire needs at its end, when it ends on another main board
+    fn linked_end_statement(&self, conn: &CanvasConnection) -> Option<(PathBuf, String)> {
+        let eb_rc = conn.get_end_board()?;
+        let eb = eb_rc.borrow();
+        if !eb.board.is_main_board() {
+            return None;
+        }
+        let stmt = Self::generate_link_statement(&eb.board, conn.get_end_pin()?, conn.get_start_pin())?;
+        Some((self.source_file_for(&eb.id)?, stmt))
+    }
+
+    fn generate_pin_statement(&self, conn: &CanvasConnection, bus_var: Option<&str>) -> Option<String> {
         let sb_rc = conn.get_start_board();
         let sb = &sb_rc.borrow().board;
         let eb_rc = conn.get_end_board()?;
@@ -754,6 +1576,9 @@ impl Project {
 
         let start_pin = conn.get_start_pin();
         let end_pin = conn.get_end_pin().unwrap();
+        if eb.is_main_board() {
+            return Self::generate_link_statement(sb, start_pin, end_pin);
+        }
         if let Some((pin_interface, pin_role)) = eb.get_peripheral_pin_interface(&end_pin) {
             if let Some(possible_pins) = sb.pinout.get_pins_from_role(&pin_interface) {
                 if !possible_pins.contains(&start_pin) {
@@ -770,6 +1595,11 @@ impl Project {
                 .get_pin_alias(&end_pin, &pin_interface)
                 .unwrap_or_default()
                 .to_lowercase();
+            // bus pins are prefixed with their group's variable so several buses can coexist
+            let conn_type = match bus_var {
+                Some(v) => format!("{}_{}", v, conn_type),
+                None => conn_type,
+            };
 
             let var_name = format!("c_{}_to_{}", start_pin, end_pin);
 
@@ -875,6 +1705,32 @@ pub mod rc_refcell_vec {
     }
 }
 
+/// Serialize a HashMap with its keys in order, so saving the same project gives the same file
+pub mod sorted_map {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::collections::{BTreeMap, HashMap};
+    use std::hash::Hash;
+
+    pub fn serialize<K, V, S>(map: &HashMap<K, V>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        K: Ord + Serialize,
+        V: Serialize,
+        S: Serializer,
+    {
+        let sorted: BTreeMap<&K, &V> = map.iter().collect();
+        sorted.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, K, V, D>(deserializer: D) -> Result<HashMap<K, V>, D::Error>
+    where
+        K: Eq + Hash + Deserialize<'de>,
+        V: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        HashMap::deserialize(deserializer)
+    }
+}
+
 mod rc_refcell_option {
     use serde::{Deserialize, Deserializer, Serialize, Serializer};
     use std::{cell::RefCell, rc::Rc};
@@ -899,3 +1755,379 @@ mod rc_refcell_option {
         Ok(opt.map(|v| Rc::new(RefCell::new(v))))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{add_peripheral, project_with_main_board, wire};
+    use egui::Color32;
+
+    #[test]
+    fn test_protocol_groups_get_unique_names_and_instances() {
+        let mut project = project_with_main_board("Feather RP2040");
+
+        let first = CanvasProtocol::new(WizardType::I2C);
+        let second = CanvasProtocol::new(WizardType::I2C);
+        let (first_id, second_id) = (first.id, second.id);
+        project.add_protocol_group(first);
+        project.add_protocol_group(second);
+
+        let first = project.get_protocol_group(&first_id).unwrap();
+        let second = project.get_protocol_group(&second_id).unwrap();
+        assert_eq!(first.variable_name(), "i2c");
+        assert_eq!(second.variable_name(), "i2c_2");
+        assert_eq!(first.peripheral_instance.as_deref(), Some("I2C1"));
+        assert_eq!(second.peripheral_instance.as_deref(), Some("I2C0"));
+
+        let stmt = project.generate_bus_statement(second).unwrap();
+        assert!(stmt.contains("let i2c_2_peripheral = pac.I2C0;\nlet mut i2c_2 = setup_i2c!(pac, clocks, 100_000, i2c_2_peripheral, i2c_2_sda, i2c_2_scl);"));
+
+        // both I2C blocks are taken now
+        assert!(!project.add_protocol_group(CanvasProtocol::new(WizardType::I2C)));
+        assert_eq!(project.protocol_groups.len(), 2);
+
+        // renaming follows the identifier rules and stays unique
+        let applied = project.rename_protocol_group(&second_id, "i2c").unwrap();
+        assert_eq!(applied, "i2c_2");
+        let applied = project.rename_protocol_group(&second_id, "OLED Bus").unwrap();
+        assert_eq!(applied, "OLED Bus");
+        let second = project.get_protocol_group(&second_id).unwrap();
+        assert_eq!(second.variable_name(), "oled_bus");
+    }
+
+    #[test]
+    fn test_single_twi_board_refuses_a_second_i2c_bus() {
+        let mut project = project_with_main_board("Arduino Uno");
+
+        let first = CanvasProtocol::new(WizardType::I2C);
+        let first_id = first.id;
+        assert!(project.add_protocol_group(first));
+        let first = project.get_protocol_group(&first_id).unwrap();
+        assert_eq!(first.peripheral_instance.as_deref(), Some("TWI"));
+
+        assert!(!project.add_protocol_group(CanvasProtocol::new(WizardType::I2C)));
+        assert_eq!(project.protocol_groups.len(), 1);
+    }
+
+    #[test]
+    fn test_spi_bus_statement_uses_spi_binding() {
+        let mut project = project_with_main_board("Arduino Uno");
+        let group = CanvasProtocol::new(WizardType::SPI);
+        let group_id = group.id;
+        project.add_protocol_group(group);
+
+        let group = project.get_protocol_group(&group_id).unwrap();
+        let stmt = project.generate_bus_statement(group).unwrap();
+        assert_eq!(stmt, "let (mut spi, spi_ss) = setup_spi!(dp, spi_sck, spi_mosi, spi_miso, pins.d10);");
+    }
+
+    #[test]
+    fn test_i2c_bus_fans_out_to_several_devices() {
+        let mut project = project_with_main_board("Arduino Uno");
+        let sensor = add_peripheral(&mut project, "I2C");
+        let display = add_peripheral(&mut project, "I2C");
+        let main_id = project.main_board.as_ref().unwrap().borrow().id;
+
+        let first = wire(&mut project, &sensor, &[(12, 0, "SDA"), (13, 1, "SCL")]);
+        let mut group = CanvasProtocol::new(WizardType::I2C);
+        for conn in first {
+            group.add_connection(conn);
+        }
+        let group_id = group.id;
+        project.add_protocol_group(group);
+        assert!(project.is_shared_bus_pin(&main_id, 12, &WizardType::I2C));
+
+        // wiring a second peripheral to the same SDA/SCL pins joins the existing bus
+        let second = wire(&mut project, &display, &[(12, 0, "SDA"), (13, 1, "SCL")]);
+        assert_eq!(project.find_shared_bus_group(&WizardType::I2C, &second), Some(group_id));
+        project.add_connections_to_protocol_group(&group_id, second);
+
+        let group = project.get_protocol_group(&group_id).unwrap();
+        assert_eq!(group.devices.len(), 2);
+        assert!(group.is_complete());
+        let (pins, interfaces) = project.generate_group_code(group);
+        assert_eq!(
+            pins,
+            vec![
+                "let i2c_sda = pins.a4.into_floating_input().into_pull_up_input();",
+                "let i2c_scl = pins.a5.into_floating_input().into_pull_up_input();",
+            ]
+        );
+        assert_eq!(
+            interfaces,
+            vec![
+                "let mut i2c = setup_i2c!(dp, i2c_sda, i2c_scl, 10_000);",
+                "let i2c_bus = core::cell::RefCell::new(i2c);",
+                "let mut i2c_device = embedded_hal_bus::i2c::RefCellDevice::new(&i2c_bus);",
+                "let mut i2c_device_2 = embedded_hal_bus::i2c::RefCellDevice::new(&i2c_bus);",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_i2c_address_collisions_are_detected() {
+        let mut project = project_with_main_board("Feather RP2040");
+        let expander = add_peripheral(&mut project, "I2C");
+        let oled = add_peripheral(&mut project, "OLED Featherwing (128x64)");
+        let expander_id = expander.borrow().id;
+        let oled_id = oled.borrow().id;
+        assert_eq!(project.device_addresses(&oled_id, &WizardType::I2C), vec![0x3C]);
+
+        let mut group = CanvasProtocol::new(WizardType::I2C);
+        for conn in wire(&mut project, &expander, &[(27, 0, "SDA"), (26, 1, "SCL")]) {
+            group.add_connection(conn);
+        }
+        for conn in wire(&mut project, &oled, &[(27, 4, "SDA"), (26, 3, "SCL")]) {
+            group.add_connection(conn);
+        }
+        let group_id = group.id;
+        project.add_protocol_group(group);
+
+        let group = project.get_protocol_group(&group_id).unwrap();
+        assert_eq!(group.get_device(&oled_id).unwrap().address, Some(0x3C));
+        assert!(group.address_conflicts().is_empty());
+
+        // a second OLED can only answer on the same address
+        let oled_2 = add_peripheral(&mut project, "OLED Featherwing (128x64)");
+        let second = wire(&mut project, &oled_2, &[(27, 4, "SDA"), (26, 3, "SCL")]);
+        project.add_connections_to_protocol_group(&group_id, second);
+        let conflicts = project.get_protocol_group(&group_id).unwrap().address_conflicts();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].0, 0x3C);
+        assert_eq!(conflicts[0].1.len(), 2);
+
+        project.set_bus_device_address(&group_id, &expander_id, 0x27);
+        project.set_bus_device_address(&group_id, &oled_id, 0x3D);
+        let group = project.get_protocol_group(&group_id).unwrap();
+        let (_, interfaces) = project.generate_group_code(group);
+        // neither device has a Feather driver to hand its address to
+        assert!(!interfaces.iter().any(|l| l.contains("_address")));
+        // rp2040-hal is on embedded-hal 0.2, so the devices share the bus through shared-bus
+        assert!(interfaces.contains(&"let i2c_bus = shared_bus::BusManagerSimple::new(i2c);".to_string()));
+        assert!(interfaces.contains(&"let mut i2c_device = i2c_bus.acquire_i2c();".to_string()));
+    }
+
+    #[test]
+    fn test_spi_bus_gives_each_device_its_own_chip_select() {
+        let mut project = project_with_main_board("Arduino Uno");
+        let display = add_peripheral(&mut project, "SPI");
+        let sd_card = add_peripheral(&mut project, "SPI");
+        let display_id = display.borrow().id;
+        let sd_card_id = sd_card.borrow().id;
+
+        let mut group = CanvasProtocol::new(WizardType::SPI);
+        for conn in wire(&mut project, &display, &[(18, 0, "SCK"), (20, 1, "MOSI"), (19, 2, "MISO"), (21, 3, "CS")]) {
+            group.add_connection(conn);
+        }
+        let group_id = group.id;
+        project.add_protocol_group(group);
+        let second = wire(&mut project, &sd_card, &[(18, 0, "SCK"), (20, 1, "MOSI"), (19, 2, "MISO"), (22, 3, "CS")]);
+        project.add_connections_to_protocol_group(&group_id, second);
+
+        project.rename_bus_device(&group_id, &display_id, "display").unwrap();
+        project.rename_bus_device(&group_id, &sd_card_id, "SD Card").unwrap();
+
+        let group = project.get_protocol_group(&group_id).unwrap();
+        let (pins, interfaces) = project.generate_group_code(group);
+        assert_eq!(pins.len(), 3);
+        assert_eq!(
+            interfaces,
+            vec![
+                "let (mut spi, spi_ss) = setup_spi!(dp, spi_sck, spi_mosi, spi_miso, pins.d10);",
+                "let spi_bus = core::cell::RefCell::new(spi);",
+                "let display_cs = spi_ss;",
+                "let mut display = embedded_hal_bus::spi::RefCellDevice::new_no_delay(&spi_bus, display_cs).unwrap();",
+                "let sd_card_cs = pins.d9.into_output();",
+                "let mut sd_card = embedded_hal_bus::spi::RefCellDevice::new_no_delay(&spi_bus, sd_card_cs).unwrap();",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_peripheral_driver_is_instantiated() {
+        let mut project = project_with_main_board("ESP32-C6-DevKitM-1");
+        let oled = add_peripheral(&mut project, "OLED Featherwing (128x64)");
+
+        let mut group = CanvasProtocol::new(WizardType::I2C);
+        for conn in wire(&mut project, &oled, &[(2, 4, "SDA"), (3, 3, "SCL")]) {
+            group.add_connection(conn);
+        }
+        let group_id = group.id;
+        project.add_protocol_group(group);
+
+        let group = project.get_protocol_group(&group_id).unwrap();
+        let (_, interfaces) = project.generate_group_code(group);
+        let name = group.devices[0].variable_name();
+        assert!(interfaces.contains(&format!("let mut {name} = sh1107_driver::SH1107::new({name});")));
+        // the driver has its address built in
+        assert!(!interfaces.iter().any(|l| l.contains("_address")));
+    }
+
+    #[test]
+    #[ignore = "fetches the driver crates from crates.io and runs cargo check"]
+    fn test_generated_driver_code_type_checks() {
+        let mut project = project_with_main_board("ESP32-C6-DevKitM-1");
+        let oled = add_peripheral(&mut project, "OLED Featherwing (128x64)");
+        let mut group = CanvasProtocol::new(WizardType::I2C);
+        for conn in wire(&mut project, &oled, &[(2, 4, "SDA"), (3, 3, "SCL")]) {
+            group.add_connection(conn);
+        }
+        let group_id = group.id;
+        project.add_protocol_group(group);
+        let group = project.get_protocol_group(&group_id).unwrap();
+        let (_, interfaces) = project.generate_group_code(group);
+
+        // stand in for the HAL's bus so the rest of the generated code checks on the host
+        let mut main = String::from(
+            "#![allow(unused)]\n\
+             struct Bus;\n\
+             impl embedded_hal::i2c::ErrorType for Bus {\n\
+                 type Error = core::convert::Infallible;\n\
+             }\n\
+             impl embedded_hal::i2c::I2c for Bus {\n\
+                 fn transaction(&mut self, _: u8, _: &mut [embedded_hal::i2c::Operation<'_>]) -> Result<(), Self::Error> { Ok(()) }\n\
+             }\n\
+             fn main() {\n\
+                 let i2c = Bus;\n",
+        );
+        for line in interfaces.iter().filter(|l| !l.contains("setup_i2c!") && !l.contains("peripherals.")) {
+            main.push_str(line);
+            main.push('\n');
+        }
+        main.push_str("}\n");
+
+        let temp = crate::test::temp_dir();
+        let dir = temp.path();
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::write(dir.join("src/main.rs"), main).unwrap();
+        std::fs::write(
+            dir.join("Cargo.toml"),
+            "[package]\nname = \"driver_check\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n\
+             [dependencies]\nembedded-hal = \"1.0\"\nembedded-hal-bus = \"0.2\"\n",
+        )
+        .unwrap();
+        project.location = Some(dir.to_path_buf());
+        project.add_wired_driver_dependencies();
+
+        let output = std::process::Command::new(env!("CARGO"))
+            .arg("check")
+            .arg("--quiet")
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    }
+
+    #[test]
+    fn test_project_saves_are_deterministic_and_groups_reference_connections() {
+        let known_boards = get_boards(Path::new("./iron-coder-boards"));
+        let temp = crate::test::temp_dir();
+        let dir = temp.path().to_path_buf();
+        let project_file = dir.join(".ironcoder.toml");
+        std::fs::copy("./example-projects/ArduinoUno_blink_i2c/.ironcoder.toml", &project_file).unwrap();
+
+        let mut project = Project::default();
+        project.load_from(&dir, &known_boards).unwrap();
+        let group = project.protocol_groups.values().next().unwrap();
+        assert_eq!(group.connections.len(), 2);
+        assert!(group.connections.iter().all(|c| project.connections.iter().any(|pc| Rc::ptr_eq(pc, c))));
+
+        // a second group makes the map order matter
+        let mut extra = CanvasProtocol::new(WizardType::SPI);
+        extra.name = project.unique_group_name("spi", None);
+        project.protocol_groups.insert(extra.id, extra);
+
+        project.save().unwrap();
+        let first = std::fs::read_to_string(&project_file).unwrap();
+        let mut reloaded = Project::default();
+        reloaded.load_from(&dir, &known_boards).unwrap();
+        reloaded.save().unwrap();
+        let second = std::fs::read_to_string(&project_file).unwrap();
+
+        assert_eq!(first, second);
+        assert!(!first.contains(".connections]]"));
+        assert!(first.contains("connection_ids = [\"431cb051-9f70-4c85-8b37-41dd21129011\""));
+        let mut group_ids: Vec<_> = project.protocol_groups.keys().map(|id| id.to_string()).collect();
+        group_ids.sort();
+        let positions: Vec<_> = group_ids
+            .iter()
+            .map(|id| first.find(&format!("[protocol_groups.{}]", id)).unwrap())
+            .collect();
+        assert!(positions[0] < positions[1]);
+    }
+
+    #[test]
+    fn test_second_main_board_gets_its_own_workspace_member() {
+        let known_boards = get_boards(Path::new("./iron-coder-boards"));
+        let find = |name: &str| known_boards.iter().find(|b| b.get_name() == name).unwrap().clone();
+        let temp = crate::test::temp_dir();
+        let dir = temp.path().to_path_buf();
+        let mut project = Project {
+            name: "Dual Boards".to_string(),
+            ..Default::default()
+        };
+        project.add_board(&find("Arduino Uno")).unwrap();
+        project.set_location(dir.clone());
+        project.generate_cargo_template().unwrap();
+
+        let esp = project.add_board(&find("ESP32-C6-DevKitM-1")).unwrap();
+        let member = esp.borrow().firmware_dir.clone().unwrap();
+        assert!(member.starts_with("firmware/"));
+        let member_dir = project.firmware_crate_dir(&esp.borrow().id).unwrap();
+        assert_eq!(member_dir, dir.join(&member));
+        let main_id = project.main_board.as_ref().unwrap().borrow().id;
+        assert_eq!(project.firmware_crate_dir(&main_id).unwrap(), dir);
+
+        // cargo only reads profiles at the workspace root, so the member's move up without
+        // overriding the root's
+        let root: toml::Table = std::fs::read_to_string(dir.join("Cargo.toml")).unwrap().parse().unwrap();
+        let members = root["workspace"]["members"].as_array().unwrap();
+        assert_eq!(members.iter().filter_map(|m| m.as_str()).collect::<Vec<_>>(), vec![member.as_str()]);
+        assert_eq!(root["profile"]["release"]["debug"].as_bool(), Some(true));
+        assert_eq!(root["profile"]["release"]["overflow-checks"].as_bool(), Some(false));
+        let member_manifest: toml::Table = std::fs::read_to_string(member_dir.join("Cargo.toml")).unwrap().parse().unwrap();
+        assert!(member_manifest.get("profile").is_none());
+        let package_name = member_manifest["package"]["name"].as_str().unwrap();
+        assert!(package_name.starts_with("dual-boards-"));
+
+        // a wire from the second board generates code in its own firmware
+        let led = add_peripheral(&mut project, "LED");
+        let conn = Rc::new(RefCell::new(CanvasConnection::new(esp.clone(), 2, Color32::RED)));
+        conn.borrow_mut().end(led.clone(), 0);
+        project.add_connection(&conn);
+        let member_main = std::fs::read_to_string(member_dir.join("src/main.rs")).unwrap();
+        let root_main = std::fs::read_to_string(dir.join("src/main.rs")).unwrap();
+        assert!(member_main.contains("let mut pin_c_2_to_0 = Output::new(peripherals.GPIO2"));
+        assert!(!root_main.contains("pin_c_2_to_0"));
+
+        // a wire between the two boards shows up in both firmwares, and goes from both again
+        let uno = project.main_board.clone().unwrap();
+        let link = Rc::new(RefCell::new(CanvasConnection::new(uno.clone(), 22, Color32::RED)));
+        link.borrow_mut().end(esp.clone(), 3);
+        project.add_connection(&link);
+        let member_main = std::fs::read_to_string(member_dir.join("src/main.rs")).unwrap();
+        let root_main = std::fs::read_to_string(dir.join("src/main.rs")).unwrap();
+        assert!(member_main.contains("let pin_c_3_to_22 = peripherals.GPIO3;"), "{}", member_main);
+        assert!(root_main.contains("let pin_c_22_to_3 = pins.d9;"), "{}", root_main);
+        project.remove_connection(&link);
+        let member_main = std::fs::read_to_string(member_dir.join("src/main.rs")).unwrap();
+        let root_main = std::fs::read_to_string(dir.join("src/main.rs")).unwrap();
+        assert!(!member_main.contains("pin_c_3_to_22") && !root_main.contains("pin_c_22_to_3"));
+
+        project.save().unwrap();
+        let mut reloaded = Project::default();
+        reloaded.load_from(&dir, &known_boards).unwrap();
+        assert!(reloaded.integrity_issues.is_empty());
+        assert_eq!(reloaded.main_boards_iter().count(), 2);
+        let reloaded_esp = reloaded.secondary_main_boards[0].clone();
+        assert_eq!(reloaded_esp.borrow().firmware_dir.as_deref(), Some(member.as_str()));
+        assert!(reloaded.source_files.contains(&member_dir.join("src/main.rs")));
+
+        // removing the board takes it out of the workspace but leaves its crate alone
+        reloaded.remove_board(&reloaded_esp);
+        assert!(reloaded.secondary_main_boards.is_empty());
+        let root: toml::Table = std::fs::read_to_string(dir.join("Cargo.toml")).unwrap().parse().unwrap();
+        assert!(root["workspace"]["members"].as_array().unwrap().is_empty());
+        assert!(member_dir.join("Cargo.toml").exists());
+    }
+}
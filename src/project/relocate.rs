@@ -0,0 +1,194 @@
+//! Renaming a project and moving its directory
+//!
+//! A project's name shows up in its project file and, as the package and binary name, in its
+//! Cargo.toml. Renaming keeps these in step, and moving takes the whole directory along.
+//! Whatever holds paths into the old directory (open tabs, settings) has to be re-pointed
+//! by the caller using the old location this returns.
+use heck::ToKebabCase;
+use log::{info, warn};
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::project::Project;
+use crate::project::cargo_manifest;
+use crate::project::project::ProjectIOError;
+
+/// Rename a project and move its directory to `destination`, which may be where it already is
+/// Returns the project's previous location
+/// The project is renamed in place before anything is moved, and undone if the move fails,
+/// so on error the project and its files are left as they were
+pub fn relocate(project: &mut Project, new_name: &str, destination: &Path) -> Result<PathBuf, ProjectIOError> {
+    let (Some(old_location), Some(project_file)) = (project.get_location_path(), project.project_file()) else {
+        return Err(ProjectIOError::NoProjectDirectory);
+    };
+    let new_name = new_name.trim();
+
+    let old_canonical = old_location.canonicalize().map_err(|_| ProjectIOError::FilesystemError)?;
+    let moving = destination.canonicalize().map_or(true, |d| d != old_canonical);
+
+    let manifest_path = old_location.join("Cargo.toml");
+    let old_manifest = fs::read_to_string(&manifest_path).ok();
+    let old_project_file = fs::read_to_string(&project_file).ok();
+    let old_name = project.name.clone();
+    let was_unsaved = project.has_unsaved_changes();
+    let restore = |project: &mut Project| {
+        for (path, contents) in [(&manifest_path, &old_manifest), (&project_file, &old_project_file)] {
+            if let Some(contents) = contents {
+                if let Err(e) = fs::write(path, contents) {
+                    warn!("couldn't restore {}: {}", path.display(), e);
+                }
+            }
+        }
+        project.name = old_name.clone();
+        if was_unsaved {
+            project.mark_unsaved();
+        }
+    };
+
+    if old_manifest.is_some() {
+        if let Err(e) = cargo_manifest::rename_package(&manifest_path, &new_name.to_kebab_case()) {
+            warn!("couldn't rename the package in Cargo.toml: {}", e);
+            restore(project);
+            return Err(ProjectIOError::FilesystemError);
+        }
+    }
+    project.name = new_name.to_string();
+    if project.save().is_err() {
+        restore(project);
+        return Err(ProjectIOError::FilesystemError);
+    }
+    if moving {
+        if let Err(e) = move_dir(&old_location, destination) {
+            restore(project);
+            return Err(e);
+        }
+    }
+
+    project.set_location(destination.to_path_buf());
+    project.find_source_files();
+    info!(
+        "renamed project to \"{}\" at {} (was {})",
+        new_name,
+        destination.display(),
+        old_location.display()
+    );
+    Ok(old_location)
+}
+
+/// Get where a path inside the old project directory ends up after a move
+pub fn relocated_path(path: &Path, old_location: &Path, new_location: &Path) -> Option<PathBuf> {
+    if let Ok(relative) = path.strip_prefix(old_location) {
+        return Some(new_location.join(relative));
+    }
+    // paths may have been canonicalized when the old location wasn't, or the other way round
+    let old_canonical = old_location.canonicalize().ok()?;
+    path.strip_prefix(&old_canonical).ok().map(|r| new_location.join(r))
+}
+
+fn move_dir(from: &Path, to: &Path) -> Result<(), ProjectIOError> {
+    let old_canonical = from.canonicalize().map_err(|_| ProjectIOError::FilesystemError)?;
+    let to_parent = to.parent().ok_or(ProjectIOError::FilesystemError)?;
+    fs::create_dir_all(to_parent).map_err(|_| ProjectIOError::FilesystemError)?;
+    if to_parent
+        .canonicalize()
+        .is_ok_and(|p| p.starts_with(&old_canonical))
+    {
+        warn!("can't move a project into itself");
+        return Err(ProjectIOError::FilesystemError);
+    }
+    if to.exists() {
+        let is_empty = fs::read_dir(to).is_ok_and(|mut d| d.next().is_none());
+        if !is_empty {
+            return Err(ProjectIOError::DestinationNotEmpty);
+        }
+        fs::remove_dir(to).map_err(|_| ProjectIOError::FilesystemError)?;
+    }
+
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    // renaming fails across filesystems, so fall back to copying
+    copy_dir_all(from, to)
+        .and_then(|_| fs::remove_dir_all(from))
+        .map_err(|e| {
+            warn!("couldn't move {} to {}: {}", from.display(), to.display(), e);
+            ProjectIOError::FilesystemError
+        })
+}
+
+fn copy_dir_all(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir_all(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::board::get_boards;
+    use crate::project::Project;
+    use std::path::Path;
+
+    #[test]
+    fn test_project_rename_and_move_updates_manifest_and_tabs() {
+        use crate::app::tabs::file_tab::FileTab;
+        use crate::project::relocate;
+
+        let known_boards = get_boards(Path::new("./iron-coder-boards"));
+        let uno = known_boards.iter().find(|b| b.get_name() == "Arduino Uno").unwrap();
+        let temp = crate::test::temp_dir();
+        let dir = temp.path().to_path_buf();
+        let old_location = dir.join("counter");
+        let example = uno.find_example("serial_counter").unwrap();
+        let mut project = crate::project::example::create_from_example(uno, example, &old_location, &known_boards).unwrap();
+
+        let mut tab = FileTab::default();
+        tab.load_from_file(&old_location.join("src/main.rs")).unwrap();
+        tab.insert_at_cursor("// unsaved\n");
+
+        let destination = dir.join("moved").join("Blinky Lights");
+        let returned = relocate::relocate(&mut project, "Blinky Lights", &destination).unwrap();
+        assert_eq!(returned, old_location);
+        assert!(!old_location.exists());
+        assert_eq!(project.name, "Blinky Lights");
+        assert_eq!(project.get_location_path().unwrap(), destination);
+        assert!(project.source_files.iter().all(|f| f.starts_with(&destination)));
+
+        let manifest: toml::Table = std::fs::read_to_string(destination.join("Cargo.toml")).unwrap().parse().unwrap();
+        assert_eq!(manifest["package"]["name"].as_str(), Some("blinky-lights"));
+        assert_eq!(manifest["bin"][0]["name"].as_str(), Some("blinky-lights"));
+        let mut reloaded = Project::default();
+        reloaded.load_from(&destination, &known_boards).unwrap();
+        assert_eq!(reloaded.name, "Blinky Lights");
+
+        let new_path = relocate::relocated_path(tab.path().unwrap(), &old_location, &destination).unwrap();
+        assert_eq!(new_path, destination.join("src/main.rs"));
+        tab.relocate(&new_path).unwrap();
+        assert!(!tab.is_synced());
+        tab.save().unwrap();
+        assert!(std::fs::read_to_string(&new_path).unwrap().contains("// unsaved"));
+
+        // a non-empty destination is refused
+        std::fs::create_dir_all(dir.join("taken")).unwrap();
+        std::fs::write(dir.join("taken/file"), "").unwrap();
+        let err = relocate::relocate(&mut project, "taken", &dir.join("taken"));
+        assert!(matches!(err, Err(crate::project::project::ProjectIOError::DestinationNotEmpty)));
+        // and leaves the project as it was
+        assert_eq!(project.name, "Blinky Lights");
+        assert_eq!(project.get_location_path().unwrap(), destination);
+        let manifest: toml::Table = std::fs::read_to_string(destination.join("Cargo.toml")).unwrap().parse().unwrap();
+        assert_eq!(manifest["package"]["name"].as_str(), Some("blinky-lights"));
+        let mut reloaded = Project::default();
+        reloaded.load_from(&destination, &known_boards).unwrap();
+        assert_eq!(reloaded.name, "Blinky Lights");
+    }
+}
@@ -0,0 +1,140 @@
+//! Starter projects shipped alongside a board's manifest
+//!
+//! A starter lives in `<board dir>/starters/<name>/` and bundles a `.ironcoder.toml` (boards,
+//! their placement on the canvas and the wiring between them) with the source files that go
+//! with it. Creating a project from a starter lays these over the board's cargo template.
+use log::{info, warn};
+use serde::Deserialize;
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use toml_edit::{DocumentMut, value};
+
+use crate::project::atomic_write::write_atomic;
+
+/// Name of the manifest describing a starter
+pub const STARTER_MANIFEST: &str = "starter.toml";
+
+/// Name of the project file bundled with a starter
+const PROJECT_FILE: &str = ".ironcoder.toml";
+
+/// A starter project a new project can be created from
+#[derive(Debug, Clone, Deserialize)]
+pub struct Starter {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Directory holding the starter's files
+    #[serde(skip)]
+    pub path: PathBuf,
+}
+
+impl Starter {
+    /// Copy the starter's files into a project directory, overwriting what the template put there
+    /// The bundled project file is renamed to the new project's name
+    pub fn apply(&self, destination: &Path, project_name: &str) -> io::Result<()> {
+        copy_dir(&self.path, destination)?;
+
+        let project_file = destination.join(PROJECT_FILE);
+        let contents = fs::read_to_string(&project_file)?;
+        let mut doc = contents
+            .parse::<DocumentMut>()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        doc["name"] = value(project_name);
+        write_atomic(&project_file, doc.to_string().as_bytes())?;
+
+        info!("applied starter \"{}\" to {}", self.name, destination.display());
+        Ok(())
+    }
+}
+
+/// Load the starters in a board's starters directory, one per subdirectory with a starter.toml
+pub fn load_starters(dir: &Path) -> Vec<Starter> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut subdirs: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_dir())
+        .collect();
+    subdirs.sort();
+    subdirs
+        .into_iter()
+        .filter_map(|subdir| {
+            let manifest_path = subdir.join(STARTER_MANIFEST);
+            let toml_str = fs::read_to_string(&manifest_path).ok()?;
+            let mut starter: Starter = match toml::from_str(&toml_str) {
+                Ok(s) => s,
+                Err(e) => {
+                    warn!("error parsing starter manifest {}: {}", manifest_path.display(), e);
+                    return None;
+                }
+            };
+            if !subdir.join(PROJECT_FILE).exists() {
+                warn!("starter {} has no {}", subdir.display(), PROJECT_FILE);
+                return None;
+            }
+            starter.path = subdir;
+            Some(starter)
+        })
+        .collect()
+}
+
+/// Copy a directory's contents into another, leaving out any starter manifest
+pub(crate) fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let source = entry.path();
+        let target = to.join(entry.file_name());
+        if source.is_dir() {
+            copy_dir(&source, &target)?;
+        } else if entry.file_name() != STARTER_MANIFEST {
+            fs::copy(&source, &target)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::board::get_boards;
+    use crate::project::Project;
+    use std::path::Path;
+
+    #[test]
+    fn test_starter_projects_load_with_their_wiring() {
+        let known_boards = get_boards(Path::new("./iron-coder-boards"));
+        for board_name in ["Arduino Uno", "Feather RP2040"] {
+            let board = known_boards.iter().find(|b| b.get_name() == board_name).unwrap();
+            let names: Vec<&str> = board.starters().iter().map(|s| s.name.as_str()).collect();
+            assert_eq!(names, ["Blink", "I2C OLED"]);
+
+            for starter in board.starters() {
+                let temp = crate::test::temp_dir();
+                let dir = temp.path().to_path_buf();
+                std::fs::create_dir_all(dir.join("src")).unwrap();
+                std::fs::write(dir.join("src/main.rs"), "fn main() {}\n").unwrap();
+                std::fs::write(dir.join("Cargo.toml"), "[package]\nname = \"my_project\"\n\n[dependencies]\n").unwrap();
+                starter.apply(&dir, "my_project").unwrap();
+                assert!(!dir.join(crate::project::starter::STARTER_MANIFEST).exists());
+
+                let mut project = Project::default();
+                project.load_from(&dir, &known_boards).unwrap();
+                assert_eq!(project.borrow_name(), "my_project");
+                assert!(project.integrity_issues.is_empty(), "{}: {:?}", starter.name, project.integrity_issues);
+                assert_eq!(project.peripheral_boards.len(), 1);
+                assert!(project.connections_iter().count() > 0);
+                assert!(std::fs::read_to_string(dir.join("src/main.rs")).unwrap().contains("// INTERFACE_DEFINITIONS"));
+
+                // the OLED driver is only declared for the ESP32
+                project.add_wired_driver_dependencies();
+                let manifest = std::fs::read_to_string(dir.join("Cargo.toml")).unwrap();
+                assert!(!manifest.contains("sh1107"));
+            }
+        }
+    }
+}
@@ -0,0 +1,466 @@
+//! In-process expansion of a board's cargo-generate project template
+//!
+//! Board templates are written for cargo-generate, but new projects shouldn't depend on it
+//! being installed. This follows the parts of its behaviour the templates rely on: the
+//! `[template]` ignore, exclude and include lists and `[placeholders]` of
+//! `cargo-generate.toml`, Liquid `{{ variable | filter }}` substitution in file contents and
+//! names, `.liquid` file suffixes, `{% raw %}` blocks, and renaming the package in Cargo.toml.
+use heck::{ToKebabCase, ToLowerCamelCase, ToShoutySnakeCase, ToSnakeCase, ToTitleCase, ToUpperCamelCase};
+use log::{info, warn};
+use serde::Deserialize;
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::project::cargo_manifest;
+
+/// Name of the file configuring a template
+pub const TEMPLATE_CONFIG: &str = "cargo-generate.toml";
+
+/// Suffix marking a file whose name should lose it once expanded
+const LIQUID_SUFFIX: &str = ".liquid";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct TemplateConfig {
+    template: TemplateSection,
+    placeholders: HashMap<String, Placeholder>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct TemplateSection {
+    /// Files that aren't copied at all
+    ignore: Vec<String>,
+    /// Files copied as they are, without substitution
+    exclude: Vec<String>,
+    /// When given, the only files that get substitution
+    include: Option<Vec<String>>,
+}
+
+/// A value a template asks for in its `[placeholders]` table
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Placeholder {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub prompt: String,
+    pub default: Option<toml::Value>,
+    pub choices: Vec<String>,
+}
+
+impl Placeholder {
+    /// Get the value used when none is supplied
+    fn default_value(&self) -> Option<String> {
+        match &self.default {
+            Some(toml::Value::String(s)) => Some(s.clone()),
+            Some(v) => Some(v.to_string()),
+            None if self.kind == "bool" => Some("false".to_string()),
+            None => self.choices.first().cloned(),
+        }
+    }
+}
+
+/// Why a template couldn't be expanded
+#[derive(Debug)]
+pub enum TemplateError {
+    /// The template or destination couldn't be read or written
+    Io(io::Error),
+    /// cargo-generate.toml isn't valid
+    Config(String),
+    /// A placeholder has no default and wasn't given a value
+    MissingValue(String),
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::Io(e) => write!(f, "{}", e),
+            TemplateError::Config(message) => write!(f, "invalid {}: {}", TEMPLATE_CONFIG, message),
+            TemplateError::MissingValue(name) => write!(f, "template placeholder \"{}\" has no value", name),
+        }
+    }
+}
+
+impl From<io::Error> for TemplateError {
+    fn from(e: io::Error) -> Self {
+        TemplateError::Io(e)
+    }
+}
+
+/// A project template directory along with its configuration
+#[derive(Debug, Clone)]
+pub struct Template {
+    dir: PathBuf,
+    config: TemplateConfig,
+}
+
+impl Template {
+    /// Read a template directory, which doesn't need a cargo-generate.toml
+    pub fn load(dir: &Path) -> Result<Self, TemplateError> {
+        if !dir.is_dir() {
+            return Err(TemplateError::Io(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("template {} not found", dir.display()),
+            )));
+        }
+        let config = match fs::read_to_string(dir.join(TEMPLATE_CONFIG)) {
+            Ok(toml_str) => toml::from_str(&toml_str).map_err(|e| TemplateError::Config(e.message().to_string()))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => TemplateConfig::default(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Template {
+            dir: dir.to_path_buf(),
+            config,
+        })
+    }
+
+    /// Get the placeholders the template declares, sorted by name
+    pub fn placeholders(&self) -> Vec<(&String, &Placeholder)> {
+        let mut placeholders: Vec<_> = self.config.placeholders.iter().collect();
+        placeholders.sort_by_key(|(name, _)| *name);
+        placeholders
+    }
+
+    /// Expand the template into a destination directory
+    /// Declared placeholders take their value from `values`, falling back to their default
+    pub fn expand(
+        &self,
+        destination: &Path,
+        project_name: &str,
+        values: &HashMap<String, String>,
+    ) -> Result<(), TemplateError> {
+        let variables = self.variables(project_name, values)?;
+        fs::create_dir_all(destination)?;
+        self.expand_dir(&self.dir, destination, &variables)?;
+        // cargo-generate names the package after the project, whatever the template calls it
+        let manifest_path = destination.join("Cargo.toml");
+        if manifest_path.exists() {
+            cargo_manifest::rename_package(&manifest_path, &variables["project-name"])?;
+        }
+        info!("expanded template {} into {}", self.dir.display(), destination.display());
+        Ok(())
+    }
+
+    /// Collect the built-in variables cargo-generate provides along with the declared placeholders
+    fn variables(
+        &self,
+        project_name: &str,
+        values: &HashMap<String, String>,
+    ) -> Result<HashMap<String, String>, TemplateError> {
+        let project_name = project_name.to_kebab_case();
+        let mut variables = HashMap::from([
+            ("crate_name".to_string(), project_name.to_snake_case()),
+            ("project-name".to_string(), project_name),
+            ("crate_type".to_string(), "bin".to_string()),
+            ("is_init".to_string(), "true".to_string()),
+            ("authors".to_string(), author()),
+            ("os-arch".to_string(), format!("{}-{}", std::env::consts::OS, std::env::consts::ARCH)),
+        ]);
+        for (name, placeholder) in &self.config.placeholders {
+            let value = values
+                .get(name)
+                .cloned()
+                .or_else(|| placeholder.default_value())
+                .ok_or_else(|| TemplateError::MissingValue(name.clone()))?;
+            variables.insert(name.clone(), value);
+        }
+        Ok(variables)
+    }
+
+    fn expand_dir(&self, from: &Path, to: &Path, variables: &HashMap<String, String>) -> Result<(), TemplateError> {
+        let mut entries: Vec<PathBuf> = fs::read_dir(from)?.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+        entries.sort();
+        for source in entries {
+            let relative = source.strip_prefix(&self.dir).unwrap_or(&source);
+            let relative_str = relative.to_string_lossy().replace('\\', "/");
+            if relative_str == TEMPLATE_CONFIG || relative_str == ".git" || self.is_ignored(&relative_str) {
+                continue;
+            }
+
+            let file_name = source.file_name().unwrap_or_default().to_string_lossy();
+            let mut target_name = render(&file_name, variables);
+            if let Some(stripped) = target_name.strip_suffix(LIQUID_SUFFIX) {
+                target_name = stripped.to_string();
+            }
+            let target = to.join(target_name);
+
+            if source.is_dir() {
+                fs::create_dir_all(&target)?;
+                self.expand_dir(&source, &target, variables)?;
+            } else if !self.is_templated(&relative_str) {
+                fs::copy(&source, &target)?;
+            } else {
+                match fs::read_to_string(&source) {
+                    Ok(contents) => fs::write(&target, render(&contents, variables))?,
+                    // binary files can't hold placeholders
+                    Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                        fs::copy(&source, &target)?;
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn is_ignored(&self, path: &str) -> bool {
+        self.config.template.ignore.iter().any(|pattern| glob_matches(pattern, path))
+    }
+
+    fn is_templated(&self, path: &str) -> bool {
+        if self.config.template.exclude.iter().any(|pattern| glob_matches(pattern, path)) {
+            return false;
+        }
+        match &self.config.template.include {
+            Some(include) => include.iter().any(|pattern| glob_matches(pattern, path)),
+            None => true,
+        }
+    }
+}
+
+/// Substitute `{{ variable | filter }}` expressions, leaving `{% raw %}` blocks as they are
+/// Expressions naming an unknown variable are left untouched rather than emptied, since
+/// Rust code can contain `{{` of its own
+pub fn render(text: &str, variables: &HashMap<String, String>) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    loop {
+        let next_expression = rest.find("{{");
+        let next_raw = find_tag(rest, "raw");
+        match (next_expression, next_raw) {
+            (_, Some((raw_start, raw_end))) if next_expression.is_none_or(|e| raw_start < e) => {
+                out.push_str(&rest[..raw_start]);
+                let body = &rest[raw_end..];
+                match find_tag(body, "endraw") {
+                    Some((end_start, end_end)) => {
+                        out.push_str(&body[..end_start]);
+                        rest = &body[end_end..];
+                    }
+                    None => {
+                        out.push_str(body);
+                        return out;
+                    }
+                }
+            }
+            (Some(start), _) => {
+                let Some(len) = rest[start + 2..].find("}}") else {
+                    out.push_str(rest);
+                    return out;
+                };
+                let expression = &rest[start + 2..start + 2 + len];
+                out.push_str(&rest[..start]);
+                match evaluate(expression, variables) {
+                    Some(value) => out.push_str(&value),
+                    None => out.push_str(&rest[start..start + 2 + len + 2]),
+                }
+                rest = &rest[start + 2 + len + 2..];
+            }
+            (None, _) => {
+                out.push_str(rest);
+                return out;
+            }
+        }
+    }
+}
+
+/// Find a `{% name %}` tag, returning where it starts and ends
+fn find_tag(text: &str, name: &str) -> Option<(usize, usize)> {
+    let mut offset = 0;
+    while let Some(start) = text[offset..].find("{%") {
+        let start = offset + start;
+        let len = text[start + 2..].find("%}")?;
+        let end = start + 2 + len + 2;
+        if text[start + 2..start + 2 + len].trim_matches(|c: char| c == '-' || c.is_whitespace()) == name {
+            return Some((start, end));
+        }
+        offset = end;
+    }
+    None
+}
+
+fn evaluate(expression: &str, variables: &HashMap<String, String>) -> Option<String> {
+    let mut parts = expression.split('|').map(str::trim);
+    let mut value = variables.get(parts.next()?)?.clone();
+    for filter in parts {
+        value = match filter {
+            "snake_case" => value.to_snake_case(),
+            "kebab_case" => value.to_kebab_case(),
+            "pascal_case" | "upper_camel_case" => value.to_upper_camel_case(),
+            "lower_camel_case" => value.to_lower_camel_case(),
+            "shouty_snake_case" => value.to_shouty_snake_case(),
+            "title_case" => value.to_title_case(),
+            "upcase" => value.to_uppercase(),
+            "downcase" => value.to_lowercase(),
+            "capitalize" => {
+                let mut chars = value.chars();
+                chars
+                    .next()
+                    .map(|c| c.to_uppercase().chain(chars).collect())
+                    .unwrap_or_default()
+            }
+            _ => {
+                warn!("unsupported template filter \"{}\"", filter);
+                return None;
+            }
+        };
+    }
+    Some(value)
+}
+
+/// Match a path against a gitignore-style glob, where `*` stays within a path segment,
+/// `**` spans segments and a pattern without a slash matches a file name anywhere
+pub fn glob_matches(pattern: &str, path: &str) -> bool {
+    let pattern = pattern.trim_start_matches("./").trim_end_matches('/');
+    if !pattern.contains('/') {
+        return path.split('/').any(|segment| segment_matches(pattern.as_bytes(), segment.as_bytes()));
+    }
+    let pattern: Vec<&str> = pattern.split('/').collect();
+    let path: Vec<&str> = path.split('/').collect();
+    segments_match(&pattern, &path)
+}
+
+fn segments_match(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => (0..=path.len()).any(|skip| segments_match(rest, &path[skip..])),
+        Some((first, rest)) => match path.split_first() {
+            Some((segment, path_rest)) => {
+                segment_matches(first.as_bytes(), segment.as_bytes()) && segments_match(rest, path_rest)
+            }
+            None => false,
+        },
+    }
+}
+
+fn segment_matches(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) => (0..=text.len()).any(|skip| segment_matches(rest, &text[skip..])),
+        Some((b'?', rest)) => !text.is_empty() && segment_matches(rest, &text[1..]),
+        Some((c, rest)) => text.first() == Some(c) && segment_matches(rest, &text[1..]),
+    }
+}
+
+/// List everything under a directory, to tell later what an expansion into it added
+pub fn list_entries(dir: &Path) -> HashSet<PathBuf> {
+    let mut entries = HashSet::new();
+    let Ok(read) = fs::read_dir(dir) else {
+        return entries;
+    };
+    for entry in read.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if path.is_dir() {
+            entries.extend(list_entries(&path));
+        }
+        entries.insert(path);
+    }
+    entries
+}
+
+/// Remove what was added under a directory since `existing` was listed with `list_entries`
+/// Files that were there already are left alone, even if they were written over
+pub fn remove_added(dir: &Path, existing: &HashSet<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if existing.contains(&path) {
+            if path.is_dir() {
+                remove_added(&path, existing)?;
+            }
+        } else if path.is_dir() {
+            fs::remove_dir_all(&path)?;
+        } else {
+            fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+fn author() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::board::get_boards;
+    use std::path::Path;
+
+    #[test]
+    fn test_templates_expand_without_cargo_generate() {
+        use crate::project::template::{self, Template};
+        use std::collections::HashMap;
+
+        let temp = crate::test::temp_dir();
+        let dir = temp.path().to_path_buf();
+        let template_dir = dir.join("template");
+        std::fs::create_dir_all(template_dir.join("src")).unwrap();
+        std::fs::create_dir_all(template_dir.join("target")).unwrap();
+        std::fs::write(
+            template_dir.join(template::TEMPLATE_CONFIG),
+            "[template]\nignore = [\"target/**\", \"*.bak\"]\nexclude = [\"src/raw.rs\"]\n\n\
+             [placeholders.baud]\ntype = \"string\"\nprompt = \"Baud rate?\"\ndefault = \"57600\"\n",
+        )
+        .unwrap();
+        std::fs::write(template_dir.join("Cargo.toml"), "[package]\nname = \"blink\" # renamed\n").unwrap();
+        std::fs::write(
+            template_dir.join("src/main.rs.liquid"),
+            "// {{ project-name | pascal_case }} at {{baud}}\nfn {{ crate_name }}() { {% raw %}{{baud}}{% endraw %} {{ unknown }} }\n",
+        )
+        .unwrap();
+        std::fs::write(template_dir.join("src/raw.rs"), "{{ crate_name }}").unwrap();
+        std::fs::write(template_dir.join("notes.bak"), "").unwrap();
+        std::fs::write(template_dir.join("target/junk"), "").unwrap();
+
+        let destination = dir.join("My Project");
+        let template = Template::load(&template_dir).unwrap();
+        template.expand(&destination, "My Project", &HashMap::new()).unwrap();
+
+        let manifest = std::fs::read_to_string(destination.join("Cargo.toml")).unwrap();
+        assert_eq!(manifest, "[package]\nname = \"my-project\" # renamed\n");
+        assert_eq!(
+            std::fs::read_to_string(destination.join("src/main.rs")).unwrap(),
+            "// MyProject at 57600\nfn my_project() { {{baud}} {{ unknown }} }\n"
+        );
+        assert_eq!(std::fs::read_to_string(destination.join("src/raw.rs")).unwrap(), "{{ crate_name }}");
+        assert!(!destination.join(template::TEMPLATE_CONFIG).exists());
+        assert!(!destination.join("notes.bak").exists());
+        assert!(!destination.join("target/junk").exists());
+
+        // every board template expands offline
+        for board in get_boards(Path::new("./iron-coder-boards")).iter().filter(|b| b.is_main_board()) {
+            let Some(template_dir) = board.get_template_dir() else {
+                continue;
+            };
+            let destination = dir.join(board.get_name());
+            Template::load(&template_dir).unwrap().expand(&destination, "blinky", &HashMap::new()).unwrap();
+            assert!(destination.join("src/main.rs").exists());
+            let manifest: toml::Table = std::fs::read_to_string(destination.join("Cargo.toml")).unwrap().parse().unwrap();
+            assert_eq!(manifest["package"]["name"].as_str(), Some("blinky"));
+        }
+    }
+    #[test]
+    fn test_only_what_an_expansion_added_is_removed() {
+        use crate::project::template;
+
+        let temp = crate::test::temp_dir();
+        let dir = temp.path().to_path_buf();
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::write(dir.join("notes.txt"), "mine").unwrap();
+        std::fs::write(dir.join("src/lib.rs"), "mine").unwrap();
+        let existing = template::list_entries(&dir);
+
+        std::fs::create_dir_all(dir.join("src/bin")).unwrap();
+        std::fs::write(dir.join("src/bin/main.rs"), "generated").unwrap();
+        std::fs::write(dir.join("src/main.rs"), "generated").unwrap();
+        std::fs::write(dir.join("Cargo.toml"), "generated").unwrap();
+        template::remove_added(&dir, &existing).unwrap();
+
+        assert_eq!(template::list_entries(&dir), existing);
+        assert_eq!(std::fs::read_to_string(dir.join("src/lib.rs")).unwrap(), "mine");
+    }
+}
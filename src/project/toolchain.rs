@@ -1,108 +1,262 @@
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::path::{Path, PathBuf};
+//! The toolchain settings of a firmware crate, kept in its `.cargo/config.toml`
+//!
+//! A board's build profile says which target, runner and standard library crates its firmware
+//! needs. Those are filled into the crate's cargo config where it doesn't already set them, so
+//! anything the user changed by hand wins. The linker is the exception: it points into the
+//! IDE's own bundled toolchain, which differs between machines, so it is always refreshed.
+//! The config is edited with toml_edit, keeping the user's comments and formatting.
+use log::info;
 use std::fs;
+use std::io;
+use std::path::Path;
 
-#[derive(Debug, Serialize, Deserialize)]
-struct CargoConfig {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    build: Option<BuildConfig>,
-    
-    #[serde(rename = "target")]
-    #[serde(skip_serializing_if = "Option::is_none")]
-    targets: Option<HashMap<String, TargetConfig>>,
-    
-    #[serde(skip_serializing_if = "Option::is_none")]
-    unstable: Option<UnstableConfig>,
-}
+use toml_edit::{Array, DocumentMut, Item, Table, value};
+
+use crate::board::BuildProfile;
+use crate::project::atomic_write::write_atomic;
 
-#[derive(Debug, Serialize, Deserialize)]
-struct BuildConfig {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    target: Option<String>,
+/// Processor family of a target, which decides what its firmware needs beyond the profile
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetArch {
+    Avr,
+    /// ESP32, ESP32-S2 and ESP32-S3
+    Xtensa,
+    /// ESP32-C and ESP32-H series, among others
+    RiscV,
+    CortexM,
+    Other,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct TargetConfig {
-    #[serde(rename = "PATH")]
-    #[serde(skip_serializing_if = "Option::is_none")]
-    path: Option<String>,
-    
-    #[serde(skip_serializing_if = "Option::is_none")]
-    runner: Option<String>,
+impl TargetArch {
+    /// Work out the family from a target triple or the path of a JSON target spec
+    pub fn of(target: &str) -> Self {
+        let triple = target_key(target);
+        if triple.starts_with("avr") {
+            TargetArch::Avr
+        } else if triple.starts_with("xtensa") {
+            TargetArch::Xtensa
+        } else if triple.starts_with("riscv") {
+            TargetArch::RiscV
+        } else if triple.starts_with("thumbv") {
+            TargetArch::CortexM
+        } else {
+            TargetArch::Other
+        }
+    }
+
+    /// Whether the target ships without a prebuilt core library
+    pub fn needs_build_std(&self) -> bool {
+        matches!(self, TargetArch::Avr | TargetArch::Xtensa)
+    }
+
+    /// Linker flags the family's runtime crates expect, used when the profile gives none
+    fn default_rustflags(&self) -> &'static [&'static str] {
+        match self {
+            TargetArch::Xtensa => &["-C", "link-arg=-nostartfiles", "-C", "link-arg=-Tlinkall.x"],
+            TargetArch::RiscV => &["-C", "link-arg=-Tlinkall.x"],
+            TargetArch::CortexM => &["-C", "link-arg=-Tlink.x"],
+            TargetArch::Avr | TargetArch::Other => &[],
+        }
+    }
+
+    /// The `[target.'cfg(...)']` table templates commonly use for the family instead of the triple
+    fn cfg_key(&self) -> Option<&'static str> {
+        match self {
+            TargetArch::Avr => Some("cfg(target_arch = \"avr\")"),
+            TargetArch::CortexM => Some("cfg(all(target_arch = \"arm\", target_os = \"none\"))"),
+            _ => None,
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct UnstableConfig {
-    #[serde(rename = "build-std")]
-    #[serde(skip_serializing_if = "Option::is_none")]
-    build_std: Option<Vec<String>>,
+/// Bring a crate's `.cargo/config.toml` in line with its board's build profile
+/// `linker` is the linker to always point the target at, if the board needs a particular one
+/// Returns Ok(false) without touching the file if nothing needed changing
+pub fn update_cargo_config(crate_dir: &Path, profile: &BuildProfile, linker: Option<&Path>) -> io::Result<bool> {
+    let config_path = crate_dir.join(".cargo").join("config.toml");
+    let contents = match fs::read_to_string(&config_path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+    let mut doc = contents
+        .parse::<DocumentMut>()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+
+    apply_profile(&mut doc, profile, linker)?;
+
+    let updated = doc.to_string();
+    if updated == contents {
+        return Ok(false);
+    }
+    fs::create_dir_all(crate_dir.join(".cargo"))?;
+    write_atomic(&config_path, updated.as_bytes())?;
+    info!("updated {}", config_path.display());
+    Ok(true)
 }
 
-fn update_config_toml_simple(
-    project_path: &Path,
-    ide_installation_path: &Path,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let config_path = project_path.join(".cargo").join("config.toml");
-    
-    // Determine toolchain path
-    let toolchain_subdir = get_platform_toolchain_dir();
-    let toolchain_bin = ide_installation_path
-        .join("toolchain")
-        .join(toolchain_subdir)
-        .join("bin");
-    
-    let path_separator = if cfg!(windows) { ";" } else { ":" };
-    let path_value = format!("{}{}$PATH", toolchain_bin.display(), path_separator);
-    
-    // Read existing or create new
-    let mut config: CargoConfig = if config_path.exists() {
-        let content = fs::read_to_string(&config_path)?;
-        toml::from_str(&content)?
+fn apply_profile(doc: &mut DocumentMut, profile: &BuildProfile, linker: Option<&Path>) -> io::Result<()> {
+    let Some(target) = profile.target.as_deref() else {
+        return Ok(());
+    };
+    let arch = TargetArch::of(target);
+
+    let build = table_mut(doc.as_table_mut(), "build")?;
+    set_if_missing(build, "target", value(target));
+    let build_has_rustflags = build.contains_key("rustflags");
+
+    let build_std: Vec<&str> = if profile.build_std.is_empty() && arch.needs_build_std() {
+        vec!["core"]
     } else {
-        CargoConfig {
-            build: Some(BuildConfig {
-                target: Some("avr-atmega328p.json".to_string()),
-            }),
-            targets: None,
-            unstable: Some(UnstableConfig {
-                build_std: Some(vec!["core".to_string()]),
-            }),
-        }
+        profile.build_std.iter().map(String::as_str).collect()
     };
-    
-    // Update target config
-    let target_key = "cfg(target_arch = \"avr\")".to_string();
-    let mut targets = config.targets.unwrap_or_default();
-    
-    let target_config = targets.entry(target_key).or_insert(TargetConfig {
-        path: None,
-        runner: Some("ravedude".to_string()),
-    });
-    
-    target_config.path = Some(path_value);
-    config.targets = Some(targets);
-    
-    // Write back
-    fs::create_dir_all(config_path.parent().unwrap())?;
-    let toml_string = toml::to_string_pretty(&config)?;
-    fs::write(&config_path, toml_string)?;
-    
-    Ok(())
-}
+    if !build_std.is_empty() {
+        let unstable = table_mut(doc.as_table_mut(), "unstable")?;
+        set_if_missing(unstable, "build-std", value(build_std.into_iter().collect::<Array>()));
+    }
+
+    // keep using a cfg(...) table the config already has for the family
+    let target_key = arch
+        .cfg_key()
+        .filter(|key| doc.get("target").and_then(|t| t.get(key)).is_some())
+        .map(str::to_string)
+        .unwrap_or_else(|| target_key(target).to_string());
+    let targets = table_mut(doc.as_table_mut(), "target")?;
+    targets.set_implicit(true);
+    let target_table = table_mut(targets, &target_key)?;
 
-fn get_platform_toolchain_dir() -> &'static str {
-    if cfg!(target_os = "linux") {
-        "linux-x86_64"
-    } else if cfg!(target_os = "macos") {
-        if cfg!(target_arch = "aarch64") {
-            "macos-aarch64"
+    // cargo ignores build.rustflags when the target sets its own, so only one place gets them
+    if !build_has_rustflags {
+        let rustflags: Vec<&str> = if profile.rustflags.is_empty() {
+            arch.default_rustflags().to_vec()
         } else {
-            "macos-x86_64"
+            profile.rustflags.iter().map(String::as_str).collect()
+        };
+        if !rustflags.is_empty() {
+            set_if_missing(target_table, "rustflags", value(rustflags.into_iter().collect::<Array>()));
         }
-    } else if cfg!(target_os = "windows") {
-        "windows-x86_64"
-    } else {
-        panic!("Unsupported platform")
     }
-}
\ No newline at end of file
+    if let Some(runner) = profile.runner {
+        let command: Array = runner
+            .command()
+            .iter()
+            .copied()
+            .chain(profile.flash_args.iter().map(String::as_str))
+            .collect();
+        set_if_missing(target_table, "runner", value(command));
+    }
+    if let Some(linker) = linker {
+        let linker = linker.to_string_lossy().to_string();
+        match target_table.get_mut("linker").and_then(|l| l.as_value_mut()) {
+            Some(old) => {
+                let decor = old.decor().clone();
+                *old = linker.into();
+                *old.decor_mut() = decor;
+            }
+            None => {
+                target_table.insert("linker", value(linker));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The key of a target's table, which for a JSON target spec is the spec's file stem
+fn target_key(target: &str) -> &str {
+    match target.strip_suffix(".json") {
+        Some(spec) => Path::new(spec).file_name().and_then(|n| n.to_str()).unwrap_or(spec),
+        None => target,
+    }
+}
+
+fn table_mut<'a>(parent: &'a mut Table, key: &str) -> io::Result<&'a mut Table> {
+    if parent.get(key).is_none() {
+        parent.insert(key, Item::Table(Table::new()));
+    }
+    parent[key]
+        .as_table_mut()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("[{}] is not a table", key)))
+}
+
+fn set_if_missing(table: &mut Table, key: &str, item: Item) {
+    if !table.contains_key(key) {
+        table.insert(key, item);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    #[test]
+    fn test_cargo_config_is_filled_in_from_the_board_profile() {
+        use crate::board::{BuildProfile, Runner};
+        use crate::project::toolchain::{self, TargetArch};
+
+        assert_eq!(TargetArch::of("avr-atmega328p.json"), TargetArch::Avr);
+        assert_eq!(TargetArch::of("xtensa-esp32s3-none-elf"), TargetArch::Xtensa);
+        assert_eq!(TargetArch::of("riscv32imac-unknown-none-elf"), TargetArch::RiscV);
+        assert_eq!(TargetArch::of("thumbv7em-none-eabihf"), TargetArch::CortexM);
+
+        let temp = crate::test::temp_dir();
+        let dir = temp.path().to_path_buf();
+        std::fs::create_dir_all(dir.join(".cargo")).unwrap();
+        let config_path = dir.join(".cargo/config.toml");
+
+        // an old AVR project: the user's runner, comment and JSON target spec stay
+        std::fs::write(
+            &config_path,
+            "[build]\ntarget = \"avr-atmega328p.json\" # custom spec\n\n[target.'cfg(target_arch = \"avr\")']\nrunner = \"ravedude uno\"\n",
+        )
+        .unwrap();
+        let uno = BuildProfile {
+            target: Some("avr-none".to_string()),
+            runner: Some(Runner::Ravedude),
+            ..Default::default()
+        };
+        let linker = Path::new("/opt/avr/bin/avr-gcc");
+        assert!(toolchain::update_cargo_config(&dir, &uno, Some(linker)).unwrap());
+        let text = std::fs::read_to_string(&config_path).unwrap();
+        assert!(text.contains("target = \"avr-atmega328p.json\" # custom spec"));
+        let config: toml::Table = text.parse().unwrap();
+        let avr = &config["target"]["cfg(target_arch = \"avr\")"];
+        assert_eq!(avr["runner"].as_str(), Some("ravedude uno"));
+        assert_eq!(avr["linker"].as_str(), Some("/opt/avr/bin/avr-gcc"));
+        // AVR has no prebuilt core
+        assert_eq!(config["unstable"]["build-std"].as_array().unwrap()[0].as_str(), Some("core"));
+        // nothing left to change the second time round
+        assert!(!toolchain::update_cargo_config(&dir, &uno, Some(linker)).unwrap());
+
+        // a new Xtensa ESP crate gets the whole profile
+        std::fs::remove_file(&config_path).unwrap();
+        let esp = BuildProfile {
+            target: Some("xtensa-esp32s3-none-elf".to_string()),
+            runner: Some(Runner::Espflash),
+            flash_args: vec!["--monitor".to_string()],
+            ..Default::default()
+        };
+        assert!(toolchain::update_cargo_config(&dir, &esp, None).unwrap());
+        let config: toml::Table = std::fs::read_to_string(&config_path).unwrap().parse().unwrap();
+        assert_eq!(config["build"]["target"].as_str(), Some("xtensa-esp32s3-none-elf"));
+        let target = &config["target"]["xtensa-esp32s3-none-elf"];
+        let runner: Vec<_> = target["runner"].as_array().unwrap().iter().filter_map(|v| v.as_str()).collect();
+        assert_eq!(runner, vec!["espflash", "flash", "--monitor"]);
+        assert!(target["rustflags"].as_array().unwrap().iter().any(|f| f.as_str() == Some("link-arg=-Tlinkall.x")));
+        assert!(target.get("linker").is_none());
+
+        // Cortex-M rustflags go with the build table's when it already has some
+        std::fs::write(&config_path, "[build]\nrustflags = [\"-C\", \"link-arg=-Tlink.x\"]\n").unwrap();
+        let feather = BuildProfile {
+            target: Some("thumbv6m-none-eabi".to_string()),
+            runner: Some(Runner::ProbeRs),
+            flash_args: vec!["--chip".to_string(), "RP2040".to_string()],
+            ..Default::default()
+        };
+        toolchain::update_cargo_config(&dir, &feather, None).unwrap();
+        let config: toml::Table = std::fs::read_to_string(&config_path).unwrap().parse().unwrap();
+        let target = &config["target"]["thumbv6m-none-eabi"];
+        assert!(target.get("rustflags").is_none());
+        assert_eq!(target["runner"].as_array().unwrap().len(), 4);
+        assert!(config.get("unstable").is_none());
+    }
+}
@@ -0,0 +1,240 @@
+//! Code snippets that can be inserted into the editor, either shipped with Iron Coder
+//! or alongside a board's manifest
+use log::warn;
+use serde::Deserialize;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::project::Project;
+
+/// Name of the manifest listing the snippets in a directory
+pub const SNIPPET_MANIFEST: &str = "snippets.toml";
+
+#[derive(Deserialize, Default)]
+struct SnippetManifest {
+    #[serde(default)]
+    snippet: Vec<Snippet>,
+}
+
+/// A snippet of code with {{placeholder}} slots that are filled from project data when inserted
+#[derive(Debug, Clone, Deserialize)]
+pub struct Snippet {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// The snippet code, read from `file` when it isn't given inline
+    #[serde(default)]
+    pub code: String,
+    /// File holding the snippet code, relative to the manifest
+    #[serde(default)]
+    file: Option<PathBuf>,
+    /// Where the snippet came from (Iron Coder or a board name)
+    #[serde(skip)]
+    pub source: String,
+}
+
+impl Snippet {
+    /// Get the names of the placeholders used by this snippet, in order of first use
+    pub fn placeholders(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        let mut rest = self.code.as_str();
+        while let Some(start) = rest.find("{{") {
+            let Some(len) = rest[start + 2..].find("}}") else {
+                break;
+            };
+            let name = rest[start + 2..start + 2 + len].trim().to_string();
+            if !name.is_empty() && !names.contains(&name) {
+                names.push(name);
+            }
+            rest = &rest[start + 2 + len + 2..];
+        }
+        names
+    }
+
+    /// Fill in the placeholders, leaving any without a value untouched
+    pub fn render(&self, values: &HashMap<String, String>) -> String {
+        let mut code = self.code.clone();
+        for name in self.placeholders() {
+            if let Some(value) = values.get(&name).filter(|v| !v.is_empty()) {
+                code = code.replace(&format!("{{{{{}}}}}", name), value);
+            }
+        }
+        code
+    }
+
+    /// Check if the snippet matches a search query by name, description, tag or source
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return true;
+        }
+        self.name.to_lowercase().contains(&query)
+            || self.description.to_lowercase().contains(&query)
+            || self.source.to_lowercase().contains(&query)
+            || self.tags.iter().any(|t| t.to_lowercase().contains(&query))
+    }
+}
+
+/// Load the snippets listed in a directory's snippets.toml and in those of its direct subdirectories
+pub fn load_snippets(dir: &Path, source: &str) -> Vec<Snippet> {
+    let mut snippets = load_manifest(&dir.join(SNIPPET_MANIFEST), source);
+    if let Ok(entries) = fs::read_dir(dir) {
+        let mut subdirs: Vec<PathBuf> = entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.is_dir())
+            .collect();
+        subdirs.sort();
+        for subdir in subdirs {
+            snippets.extend(load_manifest(&subdir.join(SNIPPET_MANIFEST), source));
+        }
+    }
+    snippets
+}
+
+fn load_manifest(manifest_path: &Path, source: &str) -> Vec<Snippet> {
+    let Ok(toml_str) = fs::read_to_string(manifest_path) else {
+        return vec![];
+    };
+    let manifest: SnippetManifest = match toml::from_str(&toml_str) {
+        Ok(m) => m,
+        Err(e) => {
+            warn!("error parsing snippet manifest {}: {}", manifest_path.display(), e);
+            return vec![];
+        }
+    };
+    let dir = manifest_path.parent().unwrap_or(Path::new("."));
+    manifest
+        .snippet
+        .into_iter()
+        .filter_map(|mut snippet| {
+            if let Some(file) = &snippet.file {
+                match fs::read_to_string(dir.join(file)) {
+                    Ok(code) => snippet.code = code,
+                    Err(e) => {
+                        warn!("error reading snippet {}: {}", snippet.name, e);
+                        return None;
+                    }
+                }
+            }
+            snippet.source = source.to_string();
+            Some(snippet)
+        })
+        .collect()
+}
+
+/// Collect the values placeholders can be bound to from the current project
+/// `board` lists the project's boards, `pin` the aliases of wired main board pins,
+/// `bus` the bus variables and `device` the variables of devices on buses
+pub fn project_bindings(project: &Project) -> HashMap<String, Vec<String>> {
+    let mut bindings: HashMap<String, Vec<String>> = HashMap::new();
+
+    let boards = project
+        .boards_iter()
+        .map(|b| b.borrow().board.get_name().to_string())
+        .collect();
+    bindings.insert("board".to_string(), boards);
+
+    let mut pins = Vec::new();
+    for conn in project.connections_iter() {
+        let conn = conn.borrow();
+        let sb = conn.get_start_board().borrow();
+        let alias = sb.board.pinout.get_pin_alias(&conn.get_start_pin(), &"GPIO".to_string());
+        if let Some(alias) = alias.filter(|a| !pins.contains(a)) {
+            pins.push(alias);
+        }
+    }
+    pins.sort();
+    bindings.insert("pin".to_string(), pins);
+
+    let mut groups: Vec<_> = project.protocol_groups.values().collect();
+    groups.sort_by_key(|g| g.variable_name());
+    bindings.insert(
+        "bus".to_string(),
+        groups.iter().map(|g| g.variable_name()).collect(),
+    );
+    bindings.insert(
+        "device".to_string(),
+        groups
+            .iter()
+            .flat_map(|g| g.devices.iter().map(|d| d.variable_name()))
+            .collect(),
+    );
+
+    bindings
+}
+
+/// Insert a snippet into code, replacing the characters between `start` and `end`
+/// Lines after the first are indented to match the line the cursor is on
+/// Returns the new code and the character index just after the inserted snippet
+pub fn insert_snippet(code: &str, start: usize, end: usize, snippet: &str) -> (String, usize) {
+    let byte_index = |char_index: usize| {
+        code.char_indices()
+            .nth(char_index)
+            .map(|(i, _)| i)
+            .unwrap_or(code.len())
+    };
+    let (start, end) = (byte_index(start.min(end)), byte_index(start.max(end)));
+
+    let line_start = code[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let indent: String = code[line_start..]
+        .chars()
+        .take_while(|c| *c == ' ' || *c == '\t')
+        .collect();
+
+    let snippet = snippet.trim_end_matches(['\n', '\r']);
+    let inserted = snippet
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            if i == 0 || line.is_empty() {
+                line.to_string()
+            } else {
+                format!("{}{}", indent, line)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let new_code = format!("{}{}{}", &code[..start], inserted, &code[end..]);
+    let cursor = code[..start].chars().count() + inserted.chars().count();
+    (new_code, cursor)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use crate::test::project_with_main_board;
+
+    #[test]
+    fn test_snippets_are_loaded_bound_and_inserted_at_the_cursor() {
+        let arduino = project_with_main_board("Arduino Uno");
+        let board = arduino.boards_iter().next().unwrap().borrow().board.clone();
+        let blink = board
+            .snippets()
+            .iter()
+            .find(|s| s.name == "Blink pin")
+            .expect("board snippet missing");
+        assert_eq!(blink.source, "Arduino Uno");
+        assert!(blink.matches("LED"));
+        assert!(!blink.matches("spi"));
+
+        let builtin = crate::snippets::load_snippets(Path::new("./assets/code-snippets"), "Iron Coder");
+        let ws2812 = builtin.iter().find(|s| s.name == "WS2812 constructor").unwrap();
+        assert_eq!(ws2812.placeholders(), vec!["pin".to_string()]);
+
+        let values = [("pin".to_string(), "d5".to_string())].into_iter().collect();
+        let code = blink.render(&values);
+        assert!(code.starts_with("let mut led = pins.d5.into_output();"));
+
+        // continuation lines pick up the indentation of the line the cursor is on
+        let source = "fn main() {\n    \n}\n";
+        let (inserted, cursor) = crate::snippets::insert_snippet(source, 16, 16, "let a = 1;\nlet b = 2;\n");
+        assert_eq!(inserted, "fn main() {\n    let a = 1;\n    let b = 2;\n}\n");
+        assert_eq!(&inserted[cursor..], "\n}\n");
+    }
+}
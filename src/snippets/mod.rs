@@ -0,0 +1,3 @@
+pub mod library;
+
+pub use library::{Snippet, insert_snippet, load_snippets, project_bindings};
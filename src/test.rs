@@ -1,22 +1,77 @@
+//! Fixtures shared by the unit tests kept next to the code they cover
+
+use egui::Color32;
+
+use crate::app::{CanvasBoard, CanvasConnection};
+use crate::board::{Board, get_boards, svg_reader::SvgBoardInfo};
+use crate::project::Project;
+use std::cell::RefCell;
+use std::path::Path;
+use std::rc::Rc;
+
+impl Board {
+    pub fn dummy_svg() -> Self {
+        let mut board = Board::default();
+        board.svg_board_info = Some(SvgBoardInfo::default());
+        board
+    }
+}
+
+/// A fresh directory under the system temp dir, removed when dropped even if the test fails
+pub(crate) fn temp_dir() -> tempfile::TempDir {
+    tempfile::Builder::new()
+        .prefix("iron_coder_test_")
+        .tempdir()
+        .expect("couldn't create a temporary directory")
+}
+
+pub(crate) fn project_with_main_board(board_name: &str) -> Project {
+    let mut project = Project::default();
+    add_peripheral(&mut project, board_name);
+    project
+}
+
+/// Add a board from the bundled manifests to the project
+pub(crate) fn add_peripheral(project: &mut Project, board_name: &str) -> Rc<RefCell<CanvasBoard>> {
+    let known_boards = get_boards(Path::new("./iron-coder-boards"));
+    let board = known_boards
+        .iter()
+        .find(|b| b.get_name() == board_name)
+        .expect("board manifest missing")
+        .clone();
+    project.add_board(&board).unwrap()
+}
+
+/// Wire main board pins to a peripheral's pins, each connection taking the given role
+pub(crate) fn wire(
+    project: &mut Project,
+    peripheral: &Rc<RefCell<CanvasBoard>>,
+    wires: &[(u32, u32, &str)],
+) -> Vec<Rc<RefCell<CanvasConnection>>> {
+    let main = project.main_board.clone().unwrap();
+    let mut connections = vec![];
+    for (main_pin, peripheral_pin, role) in wires {
+        let conn = Rc::new(RefCell::new(CanvasConnection::new(main.clone(), *main_pin, Color32::RED)));
+        conn.borrow_mut().end(peripheral.clone(), *peripheral_pin);
+        conn.borrow_mut().role = Some(role.to_string());
+        project.add_connection(&conn);
+        connections.push(conn);
+    }
+    connections
+}
+
 #[cfg(test)]
 mod tests {
     use egui::Color32;
 
     use crate::app::CanvasConnection;
-    use crate::board::{Board, svg_reader::SvgBoardInfo};
+    use crate::board::Board;
     use crate::project::Project;
     use std::cell::RefCell;
     use std::rc::Rc;
 
-    impl Board {
-        pub fn dummy_svg() -> Self {
-            let mut board = Board::default();
-            board.svg_board_info = Some(SvgBoardInfo::default());
-            board
-        }
-    }
-
     #[test]
+    #[allow(clippy::needless_borrow)]
     fn test_board_connection_references() {
         let mut project = Project::default();
 